@@ -0,0 +1,35 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use motteseed::util::bencode::bencode_encodable::BencodeEncodable;
+use motteseed::util::bencode::parser::{Node, Value, parse, parse_strict};
+
+//check that every node spans exactly its raw bytes of buf
+fn check_spans(node: &Node, buf: &[u8]) {
+    assert_eq!(node.raw, &buf[node.span.clone()]);
+    match &node.value {
+        Value::List(list) => list.iter().for_each(|item| check_spans(item, buf)),
+        Value::Dict(dict) => dict.values().for_each(|value| check_spans(value, buf)),
+        _ => {}
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(lenient) = parse(data) else {
+        //strict mode never accepts what lenient mode rejects
+        assert!(parse_strict(data).is_err());
+        return;
+    };
+    check_spans(&lenient, data);
+
+    //re-encoding gives the canonical form, which strict mode accepts and encodes the same way
+    let encoded = lenient.encode();
+    let canonical = parse_strict(&encoded).expect("re-encoded value is canonical");
+    assert_eq!(canonical.encode(), encoded);
+
+    //canonical input survives a round trip byte for byte
+    if let Ok(strict) = parse_strict(data) {
+        assert_eq!(encoded, data);
+        assert_eq!(strict, lenient);
+    }
+});
@@ -0,0 +1,503 @@
+use motteseed::core::session::listener::parse_ports;
+use motteseed::core::session::session_config::{SessionConfig, TorrentConfig};
+use motteseed::core::torrent::create::{
+    CreateOptions, MAX_AUTO_PIECE_LENGTH, MIN_AUTO_PIECE_LENGTH,
+};
+use motteseed::util::hex;
+use motteseed::util::units::parse_bytes;
+
+use clap::{ArgAction, Args, Parser, Subcommand};
+use std::io::{self, IsTerminal};
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
+
+//command line of the client, downloading is the default action
+#[derive(Debug, Parser)]
+#[command(
+    name = "motteseed",
+    version,
+    about = "A BitTorrent client",
+    after_help = "Exit codes: 1 other failure, 2 invalid command line, 3 file not readable or writable, 4 invalid torrent, 5 tracker or web seed failure, 6 data does not match the torrent, 130 interrupted while stopping",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>, //action to run, download when None
+
+    #[command(flatten)]
+    pub download: DownloadArgs, //arguments of the default download action
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        action = ArgAction::Count,
+        help = "Log more details on stderr, repeat for more, RUST_LOG takes precedence"
+    )]
+    pub verbose: u8,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Config file with default settings, ~/.config/motteseed/config.toml if not given"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[cfg(feature = "serde")]
+    #[arg(
+        long,
+        global = true,
+        help = "Print JSON instead of text, one event per line while downloading"
+    )]
+    pub json: bool,
+}
+
+impl Cli {
+    //get the action to run, falling back to downloading the torrents given without a subcommand
+    //global flags are handed to the arguments of the action
+    pub fn into_command(self) -> Command {
+        #[cfg_attr(not(feature = "serde"), allow(unused_mut))]
+        let mut command = self.command.unwrap_or(Command::Download(self.download));
+        #[cfg(feature = "serde")]
+        match &mut command {
+            Command::Download(args) => args.json = self.json,
+            Command::Info(args) => args.json = self.json,
+            Command::Verify(args) => args.json = self.json,
+            Command::Scrape(args) => args.json = self.json,
+            Command::Create(args) => args.json = self.json,
+            Command::Daemon(args) => args.json = self.json,
+        }
+        command
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Download torrents, also the action when no subcommand is given")]
+    Download(DownloadArgs),
+
+    #[command(about = "Show the metadata of a torrent file without contacting trackers")]
+    Info(InfoArgs),
+
+    #[command(about = "Check downloaded data against the piece hashes of a torrent")]
+    Verify(VerifyArgs),
+
+    #[command(about = "Ask trackers for the seeders, leechers and completed downloads of torrents")]
+    Scrape(ScrapeArgs),
+
+    #[command(about = "Create a torrent from a file or directory")]
+    Create(CreateArgs),
+
+    #[command(about = "Keep running, downloading every torrent file dropped into a directory")]
+    Daemon(DaemonArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct InfoArgs {
+    #[arg(value_name = "TORRENT", help = "Torrent file to inspect")]
+    pub path: PathBuf,
+
+    #[cfg(feature = "serde")]
+    #[arg(skip)]
+    pub json: bool, //set from the global --json flag
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    #[arg(value_name = "TORRENT", help = "Torrent file the data belongs to")]
+    pub path: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = ".",
+        help = "Directory the torrent was downloaded to, as given to --out-dir"
+    )]
+    pub data: PathBuf,
+
+    #[arg(
+        long,
+        help = "Also hash every complete file that has an md5sum in the torrent and compare it"
+    )]
+    pub md5: bool,
+
+    #[cfg(feature = "serde")]
+    #[arg(skip)]
+    pub json: bool, //set from the global --json flag
+}
+
+#[derive(Debug, Args)]
+pub struct ScrapeArgs {
+    #[arg(
+        value_name = "TORRENT",
+        required_unless_present = "info_hashes",
+        help = "Torrent files to scrape the trackers of"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    #[arg(long = "info-hash", value_name = "HEX", value_parser = parse_info_hash, requires = "trackers", help = "Info hash to scrape, as 40 hex digits, needs --tracker")]
+    pub info_hashes: Vec<[u8; 20]>,
+
+    #[arg(
+        long = "tracker",
+        value_name = "URL",
+        help = "Tracker to scrape instead of the trackers in the torrent files"
+    )]
+    pub trackers: Vec<String>,
+
+    #[cfg(feature = "serde")]
+    #[arg(skip)]
+    pub json: bool, //set from the global --json flag
+}
+
+#[derive(Debug, Args)]
+pub struct CreateArgs {
+    #[arg(
+        value_name = "PATH",
+        help = "File or directory to create the torrent from"
+    )]
+    pub path: PathBuf,
+
+    #[arg(
+        long = "announce",
+        value_name = "URL",
+        help = "Tracker URL, repeat for backup trackers that are tried in order"
+    )]
+    pub trackers: Vec<String>,
+
+    #[arg(long, value_name = "SIZE", default_value = "auto", value_parser = parse_piece_length, help = "Piece length, a power of two from 16k to 16m, or auto to pick one from the size")]
+    pub piece_length: PieceLength,
+
+    #[arg(long, help = "Comment stored in the torrent")]
+    pub comment: Option<String>,
+
+    #[arg(
+        long,
+        help = "Only get peers from the trackers of the torrent (BEP 27)"
+    )]
+    pub private: bool,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Site the torrent is made for, gives it an info hash of its own"
+    )]
+    pub source: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Where to write the torrent, NAME.torrent in the current directory by default"
+    )]
+    pub out: Option<PathBuf>,
+
+    #[arg(long, help = "Overwrite the output file if it exists")]
+    pub force: bool,
+
+    #[cfg(feature = "serde")]
+    #[arg(skip)]
+    pub json: bool, //set from the global --json flag
+}
+
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Directory to pick up torrent files from, a file named <INFO_HASH>.pause in it pauses that torrent"
+    )]
+    pub watch: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Directory the downloaded data is saved in [default: .]"
+    )]
+    pub out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Move picked up torrent files into the added folder of the watch directory"
+    )]
+    pub move_added: bool,
+
+    #[arg(long, value_name = "SECS", default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..), help = "Seconds between two looks at the watch directory")]
+    pub scan_interval: u64,
+
+    #[cfg(feature = "serde")]
+    #[arg(skip)]
+    pub json: bool, //set from the global --json flag
+}
+
+impl DaemonArgs {
+    //build the session settings the flags describe on top of config, e.g. from the config file
+    pub fn session_config(&self, mut config: SessionConfig) -> SessionConfig {
+        if let Some(out) = &self.out {
+            config.out_dir = out.clone();
+        }
+        config
+    }
+}
+
+//piece length of a new torrent
+#[derive(Debug, Clone, Copy)]
+pub enum PieceLength {
+    Auto,       //picked from the total size
+    Bytes(u64), //fixed length in bytes
+}
+
+impl CreateArgs {
+    //build the torrent settings the flags describe
+    pub fn create_options(&self) -> CreateOptions {
+        CreateOptions {
+            piece_length: match self.piece_length {
+                PieceLength::Auto => None,
+                PieceLength::Bytes(bytes) => Some(bytes),
+            },
+            trackers: self.trackers.clone(),
+            comment: self.comment.clone(),
+            private: self.private,
+            source: self.source.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DownloadArgs {
+    #[arg(
+        required = true,
+        value_name = "TORRENT",
+        help = "Torrent files to download"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    #[arg(short, long, value_name = "PORTS", value_parser = parse_port_range, help = "Port or range like 6881-6889 to listen on, the first free one is announced, 0 lets the system pick [default: 6881-6889]")]
+    pub port: Option<RangeInclusive<u16>>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "DIR",
+        help = "Directory the downloaded data is saved in [default: .]"
+    )]
+    pub out_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Most peers per torrent, also the number asked from trackers [default: 50]"
+    )]
+    pub max_peers: Option<u32>,
+
+    #[arg(long, help = "Stop once the download is complete instead of seeding")]
+    pub no_seed: bool,
+
+    #[arg(
+        long,
+        help = "Check all data on disk against the piece hashes, ignoring resume data and file sizes"
+    )]
+    pub force_recheck: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "force_recheck",
+        help = "Download over files that are in the way instead of checking them"
+    )]
+    pub overwrite: bool,
+
+    #[arg(long, value_name = "RATIO", value_parser = parse_seed_ratio, help = "Stop seeding once this much of the torrent was uploaded per byte downloaded, 0 for no limit [default: 1.0]")]
+    pub seed_ratio: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "MINUTES",
+        help = "Stop seeding after this many minutes, 0 for no limit [default: 0]"
+    )]
+    pub seed_time: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Print status lines every few seconds instead of progress bars, the default when the output is not a terminal"
+    )]
+    pub no_progress: bool,
+
+    #[arg(
+        long,
+        help = "External address to announce instead of the one trackers see"
+    )]
+    pub ip: Option<IpAddr>,
+
+    #[arg(
+        long,
+        help = "Print a magnet link for sharing the torrent instead of downloading"
+    )]
+    pub magnet: bool,
+
+    #[cfg(feature = "serde")]
+    #[arg(skip)]
+    pub json: bool, //set from the global --json flag
+
+    #[arg(long, help = "Print every tracker response in readable form")]
+    pub debug_response: bool,
+}
+
+impl DownloadArgs {
+    //build the session settings the flags describe on top of config, e.g. from the config file
+    //flags that are not given keep the value of config
+    pub fn session_config(&self, mut config: SessionConfig) -> SessionConfig {
+        if let Some(ports) = &self.port {
+            config.ports = ports.clone();
+        }
+        if let Some(out_dir) = &self.out_dir {
+            config.out_dir = out_dir.clone();
+        }
+        if let Some(max_peers) = self.max_peers {
+            config.max_peers = max_peers;
+        }
+        if self.ip.is_some() {
+            config.announce_ip = self.ip;
+        }
+        config.seed &= !self.no_seed;
+        if let Some(ratio) = self.seed_ratio {
+            config.seed_policy.ratio = (ratio > 0.0).then_some(ratio);
+        }
+        if let Some(minutes) = self.seed_time {
+            config.seed_policy.time = (minutes > 0).then(|| Duration::from_secs(minutes * 60));
+        }
+        config.tracker.debug_responses = self.debug_response;
+        config
+    }
+
+    //build the settings of each torrent the flags describe
+    pub fn torrent_config(&self) -> TorrentConfig {
+        TorrentConfig {
+            force_recheck: self.force_recheck,
+            overwrite: self.overwrite,
+            ..TorrentConfig::default()
+        }
+    }
+
+    //check if progress is drawn as bars, which needs a terminal that is not taken by JSON events
+    pub fn draws_bars(&self) -> bool {
+        #[cfg(feature = "serde")]
+        if self.json {
+            return false;
+        }
+        io::stdout().is_terminal() && !self.no_progress
+    }
+}
+
+//parse an info hash given as hex on the command line
+fn parse_info_hash(text: &str) -> Result<[u8; 20], String> {
+    let bytes = hex::decode(text.as_bytes()).map_err(|e| e.to_string())?;
+    <[u8; 20]>::try_from(bytes.as_slice())
+        .map_err(|_| format!("expected 40 hex digits, got {}", text.len()))
+}
+
+//parse a port or a range of ports given on the command line, e.g. 6881-6889
+fn parse_port_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    parse_ports(text).ok_or_else(|| "expected a port or a range like 6881-6889".to_string())
+}
+
+//parse a share ratio given on the command line, e.g. 1.5
+fn parse_seed_ratio(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(ratio) if ratio.is_finite() && ratio >= 0.0 => Ok(ratio),
+        _ => Err("expected a ratio like 1.5, or 0 for no limit".to_string()),
+    }
+}
+
+//parse a piece length given on the command line, e.g. 256k or auto
+fn parse_piece_length(text: &str) -> Result<PieceLength, String> {
+    if text.eq_ignore_ascii_case("auto") {
+        return Ok(PieceLength::Auto);
+    }
+    match parse_bytes(text) {
+        Some(bytes)
+            if bytes.is_power_of_two()
+                && (MIN_AUTO_PIECE_LENGTH..=MAX_AUTO_PIECE_LENGTH).contains(&bytes) =>
+        {
+            Ok(PieceLength::Bytes(bytes))
+        }
+        _ => Err("expected a power of two from 16k to 16m, or auto".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::cli_error::EXIT_USAGE;
+    use crate::cli::config_file::ConfigFile;
+
+    #[test]
+    fn flags_reach_the_session_config() {
+        let cli = Cli::try_parse_from([
+            "motteseed",
+            "a.torrent",
+            "--port",
+            "7000",
+            "--out-dir",
+            "downloads",
+            "--max-peers",
+            "9",
+            "--no-seed",
+            "--seed-ratio",
+            "2.5",
+            "--seed-time",
+            "90",
+            "-v",
+        ])
+        .unwrap();
+        assert_eq!(cli.verbose, 1);
+        let Command::Download(args) = cli.into_command() else {
+            panic!("not a download");
+        };
+        let config = args.session_config(SessionConfig::default());
+        assert_eq!(config.ports, 7000..=7000);
+        assert_eq!(config.out_dir, PathBuf::from("downloads"));
+        assert_eq!(config.max_peers, 9);
+        assert!(!config.seed);
+        assert_eq!(config.seed_policy.ratio, Some(2.5));
+        assert_eq!(config.seed_policy.time, Some(Duration::from_secs(90 * 60)));
+
+        //bad command lines exit with the usage code, like clap itself
+        for line in [
+            &["motteseed", "a.torrent", "--frobnicate"][..],
+            &["motteseed", "a.torrent", "--max-peers", "many"],
+            &["motteseed", "--no-seed"],
+            &["motteseed", "a.torrent", "--overwrite", "--force-recheck"],
+        ] {
+            let err = Cli::try_parse_from(line).unwrap_err();
+            assert_eq!(err.exit_code(), i32::from(EXIT_USAGE), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let (file, _) = ConfigFile::parse("port = 7000\nmax_peers = 9\nseed = false\n").unwrap();
+        let cli = Cli::try_parse_from(["motteseed", "a.torrent", "--port", "7100"]).unwrap();
+        let Command::Download(args) = cli.into_command() else {
+            panic!("not a download");
+        };
+        let config = args.session_config(file.session_config());
+        //flag over file, file over default
+        assert_eq!(config.ports, 7100..=7100);
+        assert_eq!(config.max_peers, 9);
+        assert!(!config.seed);
+        assert_eq!(config.out_dir, SessionConfig::default().out_dir);
+    }
+
+    #[test]
+    fn verbose_is_counted_on_any_subcommand() {
+        let cli = Cli::try_parse_from(["motteseed", "info", "a.torrent", "-vv"]).unwrap();
+        assert_eq!(cli.verbose, 2);
+        let cli =
+            Cli::try_parse_from(["motteseed", "-v", "info", "a.torrent", "-v", "-v"]).unwrap();
+        assert_eq!(cli.verbose, 3);
+    }
+}
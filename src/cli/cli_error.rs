@@ -0,0 +1,239 @@
+use motteseed::core::session::listen_error::ListenError;
+use motteseed::core::session::session_error::{SessionError, TorrentError};
+use motteseed::core::storage::storage_error::StorageError;
+use motteseed::core::torrent::torrent_error::{CreateTorrentError, ReadTorrentError};
+use motteseed::core::tracker::tracker_error::TrackerError;
+use motteseed::core::webseed::webseed_error::WebSeedError;
+
+use std::error::Error as _;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use thiserror::Error;
+
+//exit codes, so scripts can tell why a run failed
+pub const EXIT_FAILURE: u8 = 1; //failure not covered by the codes below
+pub const EXIT_USAGE: u8 = 2; //invalid command line, the code clap exits with
+pub const EXIT_IO: u8 = 3; //file or directory that can not be read or written
+pub const EXIT_PARSE: u8 = 4; //torrent file that is not a valid torrent
+pub const EXIT_TRACKER: u8 = 5; //tracker or web seed that could not be reached or refused
+pub const EXIT_MISMATCH: u8 = 6; //data on disk that is missing or does not match the torrent
+pub const EXIT_INTERRUPTED: u8 = 130; //second Ctrl-C while stopping, 128 + SIGINT like a shell
+
+//custom error enum for the command line, with the file each failure is about
+#[derive(Error, Debug)]
+pub enum CliError {
+    //config file that was asked for but could not be read
+    #[error("cannot read config '{}'", path.display())]
+    ReadConfig {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    //config file that is not valid TOML or has a setting of the wrong type
+    #[error("invalid config '{}' at line {line}, column {column}: {message}", path.display())]
+    ParseConfig {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    //torrent file that could not be opened or read
+    #[error("cannot read '{}'", path.display())]
+    ReadTorrent {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    //torrent file that was read but is not a valid torrent
+    #[error("invalid torrent '{}'", path.display())]
+    ParseTorrent {
+        path: PathBuf,
+        #[source]
+        source: ReadTorrentError,
+    },
+
+    //torrent that could not be created from the files at path
+    #[error("cannot create torrent from '{}'", path.display())]
+    CreateTorrent {
+        path: PathBuf,
+        #[source]
+        source: CreateTorrentError,
+    },
+
+    //output file that exists and may not be overwritten
+    #[error("'{}' already exists, use --force to overwrite it", path.display())]
+    OutputExists { path: PathBuf },
+
+    //output file that could not be written
+    #[error("cannot write '{}'", path.display())]
+    WriteFile {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    //download directory that could not be prepared for the torrent
+    #[error("cannot prepare '{}'", path.display())]
+    Storage {
+        path: PathBuf,
+        #[source]
+        source: StorageError,
+    },
+
+    //data of the torrent at path that could not be read for checking
+    #[error("cannot check the data of '{}'", path.display())]
+    Verify {
+        path: PathBuf,
+        #[source]
+        source: StorageError,
+    },
+
+    //tracker that could not be scraped, e.g. because it has no scrape support
+    #[error("scrape of '{tracker}' failed")]
+    Scrape {
+        tracker: String,
+        #[source]
+        source: TrackerError,
+    },
+
+    //session that could not listen for incoming peer connections
+    #[error("cannot listen for peers")]
+    Listen(#[from] ListenError),
+
+    //torrent at path that the session could not run, e.g. because it runs already
+    #[error("cannot add '{}'", path.display())]
+    AddTorrent {
+        path: PathBuf,
+        #[source]
+        source: SessionError,
+    },
+
+    //web seeds of the torrent at path that could not serve its data
+    #[error("web seed download for '{}' failed", path.display())]
+    WebSeed {
+        path: PathBuf,
+        #[source]
+        source: WebSeedError,
+    },
+
+    #[cfg(feature = "serde")]
+    #[error("cannot encode torrent as JSON")]
+    Json(#[from] serde_json::Error),
+}
+
+impl CliError {
+    //split an error reading a torrent file into a read failure and an invalid torrent
+    pub fn read_torrent(path: PathBuf, err: ReadTorrentError) -> Self {
+        match err {
+            ReadTorrentError::IOError(source) => CliError::ReadTorrent { path, source },
+            source => CliError::ParseTorrent { path, source },
+        }
+    }
+
+    //name the torrent file in the error that stopped its torrent
+    pub fn torrent(path: PathBuf, err: TorrentError) -> Self {
+        match err {
+            TorrentError::Storage { dir, source } => CliError::Storage { path: dir, source },
+            TorrentError::Verify(source) => CliError::Verify { path, source },
+            TorrentError::WebSeed(source) => CliError::WebSeed { path, source },
+            TorrentError::SaveResume { dir, source } => CliError::WriteFile { path: dir, source },
+        }
+    }
+
+    //get the exit code for the error, see the EXIT_ constants
+    pub fn exit_code(&self) -> ExitCode {
+        let code = match self {
+            CliError::ReadConfig { .. }
+            | CliError::ReadTorrent { .. }
+            | CliError::OutputExists { .. }
+            | CliError::WriteFile { .. } => EXIT_IO,
+            //a broken config file is as much a usage mistake as a broken command line
+            CliError::ParseConfig { .. } => EXIT_USAGE,
+            CliError::ParseTorrent { .. } => EXIT_PARSE,
+            CliError::CreateTorrent { source, .. } => match source {
+                CreateTorrentError::IOError(_) => EXIT_IO,
+                _ => EXIT_FAILURE,
+            },
+            CliError::Storage { source, .. } | CliError::Verify { source, .. } => match source {
+                StorageError::IOError(_) | StorageError::InsufficientSpace { .. } => EXIT_IO,
+                StorageError::ExistingFileMismatch { .. } => EXIT_MISMATCH,
+                _ => EXIT_PARSE,
+            },
+            CliError::Scrape { .. } => EXIT_TRACKER,
+            CliError::Listen(_) | CliError::AddTorrent { .. } => EXIT_FAILURE,
+            CliError::WebSeed { source, .. } => match source {
+                WebSeedError::StorageError(StorageError::IOError(_)) => EXIT_IO,
+                _ => EXIT_TRACKER,
+            },
+            #[cfg(feature = "serde")]
+            CliError::Json(_) => EXIT_FAILURE,
+        };
+        ExitCode::from(code)
+    }
+
+    //render the error and its sources on one line, e.g. "cannot read 'a.torrent': No such file or directory"
+    pub fn message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = self.source();
+        while let Some(err) = source {
+            //most errors of this crate already show their source in their own message
+            let text = err.to_string();
+            if !message.contains(&text) {
+                message.push_str(&format!(": {}", text));
+            }
+            source = err.source();
+        }
+        message
+    }
+}
+
+//print the error of a failed action as a single line and get the exit code for the result
+pub fn report(result: Result<(), CliError>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e.message());
+            e.exit_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use motteseed::util::errors::BencodeParseError;
+
+    use std::io;
+
+    #[test]
+    fn messages_show_the_file_and_the_cause() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "No such file or directory");
+        let err = CliError::read_torrent("a.torrent".into(), ReadTorrentError::IOError(missing));
+        assert_eq!(
+            err.message(),
+            "cannot read 'a.torrent': No such file or directory"
+        );
+        assert_eq!(err.exit_code(), ExitCode::from(EXIT_IO));
+
+        let corrupt = ReadTorrentError::ParseError(BencodeParseError::UnexpectedEnd(3));
+        let err = CliError::read_torrent("b.torrent".into(), corrupt);
+        assert_eq!(
+            err.message(),
+            "invalid torrent 'b.torrent': Parse error: Unexpected end of bencode at offset 3"
+        );
+        assert_eq!(err.exit_code(), ExitCode::from(EXIT_PARSE));
+
+        let err = CliError::Scrape {
+            tracker: "http://t.example/announce".into(),
+            source: TrackerError::Failure("nope".into()),
+        };
+        assert_eq!(
+            err.message(),
+            "scrape of 'http://t.example/announce' failed: Tracker failure: nope"
+        );
+        assert_eq!(err.exit_code(), ExitCode::from(EXIT_TRACKER));
+    }
+}
@@ -0,0 +1,386 @@
+use crate::cli::args::{CreateArgs, InfoArgs, ScrapeArgs, VerifyArgs};
+use crate::cli::cli_error::{CliError, EXIT_MISMATCH, report};
+use motteseed::core::storage::file_storage::FileStorage;
+use motteseed::core::storage::md5_check::{Md5Status, verify_md5};
+use motteseed::core::storage::recheck::recheck;
+use motteseed::core::torrent::create::create_with_progress;
+use motteseed::core::torrent::torrent::{FileDetails, TorrentFile};
+use motteseed::core::tracker::scrape::scrape as scrape_tracker;
+use motteseed::core::tracker::tracker_config::TrackerConfig;
+use motteseed::util::hex;
+use motteseed::util::units::format_bytes;
+
+use futures_util::future::join_all;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+//inputs smaller than this hash in moments, so no progress is shown for them
+const MIN_PROGRESS_BYTES: u64 = 64 * 1024 * 1024;
+
+//create a torrent from the files at the given path and print its info hash and magnet link
+pub fn create(args: &CreateArgs) -> Result<(), CliError> {
+    let create_error = |source| CliError::CreateTorrent {
+        path: args.path.clone(),
+        source,
+    };
+    //the torrent is named after the last path component, which "." or "dir/.." do not have
+    let source = fs::canonicalize(&args.path).map_err(|e| create_error(e.into()))?;
+    let out = match &args.out {
+        Some(out) => out.clone(),
+        None => {
+            let name = source.file_name().unwrap_or(source.as_os_str());
+            PathBuf::from(name).with_added_extension("torrent")
+        }
+    };
+    //checked again when writing, this saves hashing a large input for nothing
+    if !args.force && out.exists() {
+        return Err(CliError::OutputExists { path: out });
+    }
+
+    //progress is only worth showing for inputs that take a while to hash
+    let show_progress = io::stderr().is_terminal();
+    let mut shown = false;
+    let bytes = create_with_progress(&source, &args.create_options(), |hashed, total| {
+        if show_progress && total >= MIN_PROGRESS_BYTES {
+            eprint!(
+                "\rHashing {} of {} ({}%)",
+                format_bytes(hashed),
+                format_bytes(total),
+                hashed * 100 / total
+            );
+            shown = true;
+        }
+    })
+    .map_err(create_error)?;
+    if shown {
+        eprintln!();
+    }
+
+    //a file created while hashing is not overwritten either
+    let write_error = |source| CliError::WriteFile {
+        path: out.clone(),
+        source,
+    };
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!args.force)
+        .open(&out)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => CliError::OutputExists { path: out.clone() },
+            _ => write_error(e),
+        })?;
+    file.write_all(&bytes).map_err(write_error)?;
+
+    let torrent_file =
+        TorrentFile::from_bytes(bytes).map_err(|e| CliError::read_torrent(out.clone(), e))?;
+    let torrent = torrent_file.torrent();
+    #[cfg(feature = "serde")]
+    if args.json {
+        let json = serde_json::json!({
+            "path": out,
+            "info_hash": hex::encode(&torrent.info_hash),
+            "magnet": torrent.to_magnet(),
+        });
+        println!("{}", json);
+        return Ok(());
+    }
+    println!("Created {}", out.display());
+    println!("Info hash: {}", hex::encode(&torrent.info_hash));
+    println!("Magnet:    {}", torrent.to_magnet());
+    Ok(())
+}
+
+//print the metadata of a torrent file, every file is listed unlike in the summary shown before downloading
+pub fn info(args: &InfoArgs) -> Result<(), CliError> {
+    let torrent_file = TorrentFile::from_file(&args.path)
+        .map_err(|e| CliError::read_torrent(args.path.clone(), e))?;
+    let torrent = torrent_file.torrent();
+    #[cfg(feature = "serde")]
+    if args.json {
+        let json = serde_json::to_string(&torrent.to_owned())?;
+        println!("{}", json);
+        return Ok(());
+    }
+    println!("{:#}", torrent);
+    for warning in torrent.info.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    Ok(())
+}
+
+//hash the downloaded data of a torrent and report which pieces and files are complete
+//succeeds only if every piece matches, missing or damaged data exits with EXIT_MISMATCH
+pub fn verify(args: &VerifyArgs) -> Result<ExitCode, CliError> {
+    let torrent_file = TorrentFile::from_file(&args.path)
+        .map_err(|e| CliError::read_torrent(args.path.clone(), e))?;
+    let torrent = torrent_file.torrent();
+    let info = &torrent.info;
+    let storage_error = |source| CliError::Storage {
+        path: args.data.clone(),
+        source,
+    };
+    let mut storage = FileStorage::new(info, &args.data).map_err(storage_error)?;
+
+    //progress is redrawn whenever the percentage changes
+    let show_progress = io::stderr().is_terminal();
+    let mut shown = None;
+    let result = recheck(info, &mut storage, |checked, count| {
+        let percent = checked * 100 / count;
+        if show_progress && shown != Some(percent) {
+            eprint!("\rChecking piece {} of {} ({}%)", checked, count, percent);
+            shown = Some(percent);
+        }
+    })
+    .map_err(|source| CliError::Verify {
+        path: args.path.clone(),
+        source,
+    })?;
+    if shown.is_some() {
+        eprintln!();
+    }
+
+    //files with an md5sum are hashed once more if asked, which needs all of their pieces
+    let md5 = if args.md5 {
+        for (index, _) in result
+            .pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, valid)| **valid)
+        {
+            storage.mark_have(index).map_err(storage_error)?;
+        }
+        verify_md5(info, &mut storage).map_err(storage_error)?
+    } else {
+        Vec::new()
+    };
+    let md5_of = |index: usize| md5.iter().find(|check| check.file == index);
+    let md5_matches = !md5
+        .iter()
+        .any(|check| matches!(check.status, Md5Status::Mismatch { .. }));
+
+    //path of every file shown to the user, None for padding files, which are not stored
+    let paths: Vec<Option<String>> = match &info.file_details {
+        FileDetails::SingleFile { .. } => vec![Some(info.name.to_string())],
+        FileDetails::MultiFile { files } => files
+            .iter()
+            .map(|file| {
+                let components = file.path.iter().map(|c| String::from_utf8_lossy(c));
+                (!file.is_padding()).then(|| components.collect::<Vec<_>>().join("/"))
+            })
+            .collect(),
+    };
+    //empty files are never written, so they are not missing even if they do not exist
+    let missing = |index: usize| {
+        result.files[index].length > 0
+            && storage.file_path(index).is_some_and(|path| !path.exists())
+    };
+
+    let exit_code = if result.is_complete() && md5_matches {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_MISMATCH)
+    };
+
+    #[cfg(feature = "serde")]
+    if args.json {
+        let files: Vec<_> = result
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(index, file)| {
+                Some(serde_json::json!({
+                    "path": paths[index].as_ref()?,
+                    "length": file.length,
+                    "valid_bytes": file.valid_bytes,
+                    "missing": missing(index),
+                    "md5": md5_of(index).map(|check| md5_label(&check.status)),
+                }))
+            })
+            .collect();
+        let json = serde_json::json!({
+            "pieces": result.pieces.len(),
+            "valid_pieces": result.valid_pieces(),
+            "bad_pieces": result.bad_pieces(),
+            "complete": result.is_complete() && md5_matches,
+            "files": files,
+        });
+        println!("{}", serde_json::to_string(&json)?);
+        return Ok(exit_code);
+    }
+
+    println!(
+        "{}/{} pieces OK",
+        result.valid_pieces(),
+        result.pieces.len()
+    );
+    //only files that need attention are listed
+    for (index, file) in result.files.iter().enumerate() {
+        let Some(path) = &paths[index] else {
+            continue;
+        };
+        if missing(index) {
+            println!("{} missing", path);
+        } else if !file.is_complete() {
+            println!(
+                "{} {}% complete",
+                path,
+                file.valid_bytes * 100 / file.length
+            );
+        } else if let Some(check) = md5_of(index)
+            && check.status != Md5Status::Match
+        {
+            println!("{} md5 {}", path, md5_label(&check.status));
+        }
+    }
+    if args.md5 {
+        let matched = md5
+            .iter()
+            .filter(|check| check.status == Md5Status::Match)
+            .count();
+        println!("{}/{} md5sums OK", matched, md5.len());
+    }
+
+    Ok(exit_code)
+}
+
+//word for the outcome of an md5 check, as printed and in JSON
+fn md5_label(status: &Md5Status) -> &'static str {
+    match status {
+        Md5Status::Match => "match",
+        Md5Status::Mismatch { .. } => "mismatch",
+        Md5Status::Incomplete => "incomplete",
+    }
+}
+
+//print the swarm counts of torrents, with one scrape request per tracker for all torrents on it
+//a tracker that fails is reported and does not stop the others
+pub async fn scrape(args: &ScrapeArgs, config: &TrackerConfig) -> Result<ExitCode, CliError> {
+    //torrents by name and info hash, with the trackers to ask about them
+    let given: Vec<Vec<u8>> = args
+        .trackers
+        .iter()
+        .map(|url| url.as_bytes().to_vec())
+        .collect();
+    let mut torrents: Vec<(String, [u8; 20], Vec<Vec<u8>>)> = Vec::new();
+    for path in &args.paths {
+        let torrent_file = TorrentFile::from_file_async(path)
+            .await
+            .map_err(|e| CliError::read_torrent(path.clone(), e))?;
+        let torrent = torrent_file.torrent();
+        let trackers = if given.is_empty() {
+            let urls = torrent.trackers().into_iter().flatten();
+            urls.map(<[u8]>::to_vec).collect()
+        } else {
+            given.clone()
+        };
+        if trackers.is_empty() {
+            eprintln!("Warning: {} has no trackers to scrape", path.display());
+        }
+        torrents.push((torrent.info.name.to_string(), torrent.info_hash, trackers));
+    }
+    for info_hash in &args.info_hashes {
+        torrents.push((hex::encode(info_hash), *info_hash, given.clone()));
+    }
+
+    //info hashes by tracker, so every tracker gets a single request
+    let mut requests: Vec<(&[u8], Vec<[u8; 20]>)> = Vec::new();
+    for (_, info_hash, trackers) in &torrents {
+        for tracker in trackers {
+            match requests.iter_mut().find(|(url, _)| url == tracker) {
+                Some((_, info_hashes)) if info_hashes.contains(info_hash) => {}
+                Some((_, info_hashes)) => info_hashes.push(*info_hash),
+                None => requests.push((tracker, vec![*info_hash])),
+            }
+        }
+    }
+
+    let responses = join_all(
+        requests
+            .iter()
+            .map(|(tracker, info_hashes)| scrape_tracker(tracker, info_hashes, config)),
+    )
+    .await;
+
+    //trackers that failed are reported here and left out of the table
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut counts = HashMap::new();
+    for ((tracker, _), response) in requests.iter().zip(responses) {
+        match response {
+            Ok(response) => {
+                counts.insert(*tracker, response.files);
+            }
+            Err(source) => {
+                exit_code = report(Err(CliError::Scrape {
+                    tracker: String::from_utf8_lossy(tracker).into_owned(),
+                    source,
+                }));
+            }
+        }
+    }
+
+    //one row per torrent and tracker, a torrent the tracker does not know has no counts
+    let rows: Vec<_> = torrents
+        .iter()
+        .flat_map(|(name, info_hash, trackers)| {
+            let counts = &counts;
+            trackers.iter().filter_map(move |tracker| {
+                let files = counts.get(tracker.as_slice())?;
+                Some((name, info_hash, tracker, files.get(info_hash)))
+            })
+        })
+        .collect();
+
+    #[cfg(feature = "serde")]
+    if args.json {
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|(name, info_hash, tracker, stats)| {
+                serde_json::json!({
+                    "name": name,
+                    "info_hash": hex::encode(*info_hash),
+                    "tracker": String::from_utf8_lossy(tracker),
+                    "seeders": stats.map(|stats| stats.seeders),
+                    "leechers": stats.map(|stats| stats.leechers),
+                    "completed": stats.map(|stats| stats.completed),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string(&rows)?);
+        return Ok(exit_code);
+    }
+
+    if !rows.is_empty() {
+        let width = rows
+            .iter()
+            .map(|(name, ..)| name.chars().count())
+            .max()
+            .unwrap_or_default();
+        println!(
+            "{:>8}  {:>8}  {:>9}  {:<width$}  Tracker",
+            "Seeders", "Leechers", "Completed", "Torrent"
+        );
+        for (name, _, tracker, stats) in &rows {
+            let [seeders, leechers, completed] = match stats {
+                Some(stats) => {
+                    [stats.seeders, stats.leechers, stats.completed].map(|n| n.to_string())
+                }
+                None => ["-"; 3].map(String::from),
+            };
+            println!(
+                "{:>8}  {:>8}  {:>9}  {:<width$}  {}",
+                seeders,
+                leechers,
+                completed,
+                name,
+                String::from_utf8_lossy(tracker)
+            );
+        }
+    }
+
+    Ok(exit_code)
+}
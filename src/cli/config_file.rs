@@ -0,0 +1,379 @@
+use crate::cli::cli_error::CliError;
+use motteseed::core::peer_id::{PeerId, PeerIdBuilder};
+use motteseed::core::session::listener::parse_ports;
+use motteseed::core::session::session_config::SessionConfig;
+use motteseed::core::tracker::proxy::{ProxyConfig, ProxyKind};
+use motteseed::util::units::parse_bytes;
+
+use serde::{Deserialize, Deserializer};
+use std::env;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+//config file of the client, relative to the user's config directory
+const CONFIG_PATH: &str = "motteseed/config.toml";
+
+//settings read from a config file, each one that is left out keeps its value
+//e.g.
+//  out_dir = "/data/torrents"
+//  port = 6881
+//  seed_ratio = 2
+//  [proxy]
+//  kind = "socks5"
+//  host = "127.0.0.1"
+//  port = 1080
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    pub out_dir: Option<PathBuf>, //directory downloaded torrents are saved in
+    #[serde(default, deserialize_with = "port_range")]
+    pub port: Option<RangeInclusive<u16>>, //port to listen on, a number or a range like "6881-6889"
+    pub max_peers: Option<u32>,   //most peers per torrent
+    pub seed: Option<bool>,       //keep seeding a torrent once it is complete
+    pub seed_ratio: Option<f64>,  //share ratio seeding stops at, 0 for no limit
+    pub seed_time: Option<u64>,   //minutes seeding stops after, 0 for no limit
+    //settings that are read but do nothing yet, a warning says so when they are set
+    #[serde(default, deserialize_with = "byte_rate")]
+    pub download_limit: Option<u64>, //bytes per second, a number or a size like "2m"
+    #[serde(default, deserialize_with = "byte_rate")]
+    pub upload_limit: Option<u64>, //bytes per second, a number or a size like "512k"
+    pub dht: Option<bool>,              //find peers through the DHT
+    pub pex: Option<bool>,              //exchange peers with connected peers
+    pub lsd: Option<bool>,              //find peers on the local network
+    pub part_files: Option<bool>,       //write incomplete files as <name>.part
+    pub proxy: Option<ProxySection>,    //proxy tracker connections are tunneled through
+    pub peer_id: Option<PeerIdSection>, //prefix of the peer id
+}
+
+//[proxy] table of the config file
+#[derive(Debug, Deserialize)]
+pub struct ProxySection {
+    pub kind: ProxyProtocol,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub remote_dns: Option<bool>, //let the proxy resolve tracker host names, true if left out
+}
+
+//proxy kind as written in the config file
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyProtocol {
+    Http,
+    Socks5,
+}
+
+//[peer_id] table of the config file, checked while parsing so mistakes point at the table
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "RawPeerId")]
+pub struct PeerIdSection(pub PeerId);
+
+#[derive(Deserialize)]
+struct RawPeerId {
+    client_code: Option<String>, //two letter client code, e.g. "MS"
+    version: Option<String>,     //four version characters, e.g. "0100"
+}
+
+impl TryFrom<RawPeerId> for PeerIdSection {
+    type Error = String;
+
+    fn try_from(raw: RawPeerId) -> Result<Self, Self::Error> {
+        let mut builder = PeerIdBuilder::new();
+        if let Some(client_code) = &raw.client_code {
+            builder = builder.client_code(client_code);
+        }
+        if let Some(version) = &raw.version {
+            builder = builder.version(version);
+        }
+        builder.build().map(Self).map_err(|e| e.to_string())
+    }
+}
+
+impl ConfigFile {
+    //get the default location of the config file, under $XDG_CONFIG_HOME or ~/.config
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join(CONFIG_PATH))
+    }
+
+    //read the config file at path, or the default one if path is None
+    //a missing default file gives an empty config, a missing file that was asked for is an error
+    pub fn load(path: Option<&Path>) -> Result<Self, CliError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default());
+            }
+            Err(source) => return Err(CliError::ReadConfig { path, source }),
+        };
+
+        let (config, unknown) = Self::parse(&text).map_err(|e| {
+            //the span is missing only for errors about the file as a whole
+            let offset = e.span().map_or(0, |span| span.start);
+            let (line, column) = line_column(&text, offset);
+            CliError::ParseConfig {
+                path: path.clone(),
+                line,
+                column,
+                message: e.message().to_string(),
+            }
+        })?;
+        //keys of newer or older versions should not stop the client from starting
+        for key in unknown {
+            warn!(path = %path.display(), key, "ignoring unknown config key");
+        }
+        for key in config.unsupported() {
+            warn!(path = %path.display(), key, "ignoring config key that is not supported yet");
+        }
+        Ok(config)
+    }
+
+    //get the keys the file sets that the client does not act on yet
+    pub fn unsupported(&self) -> Vec<&'static str> {
+        [
+            ("download_limit", self.download_limit.is_some()),
+            ("upload_limit", self.upload_limit.is_some()),
+            ("dht", self.dht.is_some()),
+            ("pex", self.pex.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(key, set)| set.then_some(key))
+        .collect()
+    }
+
+    //parse the text of a config file, also getting the keys that are not settings
+    pub fn parse(text: &str) -> Result<(Self, Vec<String>), toml::de::Error> {
+        let mut unknown = Vec::new();
+        let config = serde_ignored::deserialize(toml::Deserializer::new(text), |path| {
+            unknown.push(path.to_string())
+        })?;
+        Ok((config, unknown))
+    }
+
+    //overwrite the settings of config that the file sets
+    pub fn apply(&self, config: &mut SessionConfig) {
+        if let Some(out_dir) = &self.out_dir {
+            config.out_dir = out_dir.clone();
+        }
+        if let Some(ports) = &self.port {
+            config.ports = ports.clone();
+        }
+        if let Some(max_peers) = self.max_peers {
+            config.max_peers = max_peers;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = seed;
+        }
+        if let Some(ratio) = self.seed_ratio {
+            config.seed_policy.ratio = (ratio > 0.0).then_some(ratio);
+        }
+        if let Some(minutes) = self.seed_time {
+            config.seed_policy.time = (minutes > 0).then(|| Duration::from_secs(minutes * 60));
+        }
+        if let Some(lsd) = self.lsd {
+            config.lsd = lsd;
+        }
+        if let Some(part_files) = self.part_files {
+            config.part_files = part_files;
+        }
+        if let Some(proxy) = &self.proxy {
+            let kind = match proxy.kind {
+                ProxyProtocol::Http => ProxyKind::Http,
+                ProxyProtocol::Socks5 => ProxyKind::Socks5,
+            };
+            let mut proxy_config = ProxyConfig::new(kind, &proxy.host, proxy.port);
+            if let Some(username) = &proxy.username {
+                let password = proxy.password.clone().unwrap_or_default();
+                proxy_config.credentials = Some((username.clone(), password));
+            }
+            if let Some(remote_dns) = proxy.remote_dns {
+                proxy_config.remote_dns = remote_dns;
+            }
+            config.tracker.proxy = Some(proxy_config);
+        }
+        if let Some(peer_id) = &self.peer_id {
+            config.peer_id = peer_id.0;
+        }
+    }
+
+    //get the session settings of the file, with defaults for the ones it leaves out
+    pub fn session_config(&self) -> SessionConfig {
+        let mut config = SessionConfig::default();
+        self.apply(&mut config);
+        config
+    }
+}
+
+//deserialize a rate given as bytes per second or as a size like "512k"
+fn byte_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Rate {
+        Bytes(u64),
+        Size(String),
+    }
+
+    match Rate::deserialize(deserializer)? {
+        Rate::Bytes(bytes) => Ok(Some(bytes)),
+        Rate::Size(text) => parse_bytes(&text).map(Some).ok_or_else(|| {
+            serde::de::Error::custom(format!("expected a size like 512k, got '{}'", text))
+        }),
+    }
+}
+
+//deserialize a port given as a number or as a range like "6881-6889"
+fn port_range<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<RangeInclusive<u16>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Ports {
+        Port(u16),
+        Range(String),
+    }
+
+    match Ports::deserialize(deserializer)? {
+        Ports::Port(port) => Ok(Some(port..=port)),
+        Ports::Range(text) => parse_ports(&text).map(Some).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "expected a port range like 6881-6889, got '{}'",
+                text
+            ))
+        }),
+    }
+}
+
+//get the 1-based line and column of a byte offset in text
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |last| last.chars().count())
+        + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_values_replace_the_defaults() {
+        let text = r#"
+            out_dir = "/data/torrents"
+            port = 7000
+            seed = false
+            seed_ratio = 0
+            seed_time = 120
+            upload_limit = "512k"
+            download_limit = 1000
+            dht = false
+            part_files = false
+            future_option = 1
+
+            [proxy]
+            kind = "socks5"
+            host = "127.0.0.1"
+            port = 1080
+            username = "me"
+
+            [peer_id]
+            client_code = "qB"
+            version = "4610"
+        "#;
+        let (file, unknown) = ConfigFile::parse(text).unwrap();
+        assert_eq!(unknown, ["future_option"]);
+        //limits and the DHT are told to do nothing yet
+        assert_eq!(
+            file.unsupported(),
+            ["download_limit", "upload_limit", "dht"]
+        );
+        assert_eq!(file.upload_limit, Some(512 * 1024));
+
+        let config = file.session_config();
+        assert_eq!(config.out_dir, PathBuf::from("/data/torrents"));
+        assert_eq!(config.ports, 7000..=7000);
+        assert!(!config.seed && !config.part_files);
+        assert_eq!(config.seed_policy.ratio, None);
+        assert_eq!(config.seed_policy.time, Some(Duration::from_secs(2 * 3600)));
+        let proxy = config.tracker.proxy.unwrap();
+        assert_eq!(proxy.kind, ProxyKind::Socks5);
+        assert_eq!((proxy.host.as_str(), proxy.port), ("127.0.0.1", 1080));
+        assert_eq!(proxy.credentials, Some(("me".into(), String::new())));
+        assert_eq!(&config.peer_id.0[..8], b"-qB4610-");
+
+        //what the file leaves out keeps its default
+        let defaults = SessionConfig::default();
+        assert_eq!(config.max_peers, defaults.max_peers);
+        assert_eq!(config.lsd, defaults.lsd);
+    }
+
+    #[test]
+    fn malformed_files_point_at_the_mistake() {
+        let dir = std::env::temp_dir().join(format!("motteseed-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+
+        fs::write(&path, "port = 6881\nmax_peers = \"many\"\n").unwrap();
+        let err = ConfigFile::load(Some(&path)).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CliError::ParseConfig {
+                    line: 2,
+                    column: 13,
+                    ..
+                }
+            ),
+            "{:?}",
+            err
+        );
+        assert!(
+            err.message().contains("line 2, column 13"),
+            "{}",
+            err.message()
+        );
+
+        fs::write(&path, "port = \"6889-6881\"\n").unwrap();
+        let err = ConfigFile::load(Some(&path)).unwrap_err();
+        assert!(
+            err.message().contains("got '6889-6881'"),
+            "{}",
+            err.message()
+        );
+
+        fs::write(&path, "upload_limit = \"fast\"\n").unwrap();
+        let err = ConfigFile::load(Some(&path)).unwrap_err();
+        assert!(err.message().contains("got 'fast'"), "{}", err.message());
+
+        fs::write(&path, "[peer_id]\nclient_code = \"M\"\n").unwrap();
+        let err = ConfigFile::load(Some(&path)).unwrap_err();
+        assert!(
+            matches!(err, CliError::ParseConfig { line: 1, .. }),
+            "{:?}",
+            err
+        );
+
+        //a config file that was asked for has to exist
+        fs::remove_file(&path).unwrap();
+        let err = ConfigFile::load(Some(&path)).unwrap_err();
+        assert!(matches!(err, CliError::ReadConfig { .. }), "{:?}", err);
+        fs::remove_dir(&dir).unwrap();
+    }
+}
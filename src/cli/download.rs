@@ -0,0 +1,253 @@
+use crate::cli::args::{DaemonArgs, DownloadArgs};
+use crate::cli::cli_error::{CliError, report};
+#[cfg(feature = "serde")]
+use crate::cli::events::EventWriter;
+use crate::cli::progress::ProgressDisplay;
+use motteseed::core::session::resume::{save_torrent_file, saved_torrent_files};
+use motteseed::core::session::session::Session;
+use motteseed::core::session::session_config::TorrentConfig;
+use motteseed::core::session::watch_dir::WatchDir;
+use motteseed::core::torrent::magnet::MagnetOptions;
+use motteseed::core::torrent::torrent::TorrentFile;
+use motteseed::util::hex;
+
+use indicatif::MultiProgress;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+//how the torrents of one invocation are shown
+#[derive(Debug, Clone, Copy)]
+pub struct Output<'a> {
+    pub bars: Option<&'a MultiProgress>, //bars drawn on a terminal, status lines are printed if None
+    pub tagged: bool, //status lines start with the torrent name, for several torrents at once
+    #[cfg(feature = "serde")]
+    pub json: bool, //JSON events are printed instead of text
+}
+
+//download the torrent at file_path with the settings of session
+pub async fn download(
+    file_path: &Path,
+    args: &DownloadArgs,
+    session: &Session,
+    output: Output<'_>,
+) -> Result<(), CliError> {
+    let torrent_file = TorrentFile::from_file_async(file_path)
+        .await
+        .map_err(|e| CliError::read_torrent(file_path.to_path_buf(), e));
+    //a torrent file that can not be read has no info hash for the error event
+    #[cfg(feature = "serde")]
+    if args.json
+        && let Err(e) = &torrent_file
+    {
+        EventWriter::new(None).error(e);
+    }
+    let torrent_file = torrent_file?;
+    //print a magnet link for sharing the torrent instead of downloading
+    if args.magnet {
+        let options = MagnetOptions {
+            length: true,
+            web_seeds: true,
+        };
+        println!("{}", torrent_file.torrent().to_magnet_with(options));
+        return Ok(());
+    }
+    run(
+        file_path,
+        torrent_file,
+        session,
+        args.torrent_config(),
+        output,
+    )
+    .await
+}
+
+//run a torrent in session until it is done or the session shuts down, showing its progress
+async fn run(
+    file_path: &Path,
+    torrent_file: TorrentFile,
+    session: &Session,
+    torrent_config: TorrentConfig,
+    output: Output<'_>,
+) -> Result<(), CliError> {
+    let torrent = torrent_file.torrent();
+    //status lines of concurrent torrents are told apart by the torrent name
+    let tag = match output.tagged {
+        true => format!("{}: ", torrent.info.name),
+        false => String::new(),
+    };
+    //the bars of other torrents are hidden while printing, so they do not overwrite the output
+    let display = ProgressDisplay::new(&torrent.info.name, output.bars);
+    //with --json stdout only carries the events of the torrent
+    #[cfg(feature = "serde")]
+    let display = if output.json {
+        ProgressDisplay::with_events(
+            &torrent.info.name,
+            EventWriter::new(Some(&torrent.info_hash)),
+        )
+    } else {
+        display
+    };
+    display.added(torrent);
+    display.print(|| println!("{}", torrent));
+    display.suspend(|| {
+        for warning in torrent.info.warnings() {
+            eprintln!("{}Warning: {}", tag, warning);
+        }
+    });
+    if !torrent.has_peer_source() {
+        display.suspend(|| {
+            eprintln!(
+                "{}Torrent has no trackers, DHT nodes or web seeds to get peers from",
+                tag
+            )
+        });
+        display.finish();
+        return Ok(());
+    }
+    let has_trackers = !torrent.trackers().is_empty();
+
+    //the display is refreshed from snapshots while the session runs the torrent, and shows the events
+    //of the torrent as they come, until it stops either way
+    let mut events = session.subscribe();
+    let result = match session.add_torrent_with(torrent_file, torrent_config) {
+        Ok(handle) => {
+            let ticker = tokio::spawn(display.clone().run(handle.clone()));
+            let wait = handle.wait();
+            tokio::pin!(wait);
+            let result = loop {
+                tokio::select! {
+                    biased;
+                    event = events.recv() => match event {
+                        Ok(event) if event.info_hash() == handle.info_hash() => display.show(&event, &handle),
+                        Ok(_) => {}
+                        Err(RecvError::Lagged(missed)) => warn!(missed, "display fell behind the events of the torrent"),
+                        Err(RecvError::Closed) => break wait.await,
+                    },
+                    result = &mut wait => break result,
+                }
+            };
+            //events sent right before the torrent stopped are still queued
+            while let Ok(event) = events.try_recv() {
+                if event.info_hash() == handle.info_hash() {
+                    display.show(&event, &handle);
+                }
+            }
+            ticker.abort();
+            result
+                .unwrap_or(Ok(()))
+                .map_err(|e| CliError::torrent(file_path.to_path_buf(), e))
+        }
+        Err(source) => Err(CliError::AddTorrent {
+            path: file_path.to_path_buf(),
+            source,
+        }),
+    };
+    //trackerless torrents rely on DHT nodes, which are not supported yet
+    if result.is_ok() && !has_trackers && !session.is_shutting_down() {
+        display.suspend(|| eprintln!("{}Torrent has no trackers, DHT is not supported yet", tag));
+    }
+    display.finish();
+    if let Err(e) = &result {
+        display.error(e);
+    }
+    result
+}
+
+//run session until it shuts down, starting every torrent file dropped into the watch directory
+//torrent files are kept next to the resume data, so a restarted daemon runs its torrents again
+//a torrent is paused while a file named by its info hash with the extension .pause is in the
+//watch directory
+pub async fn daemon(args: &DaemonArgs, session: Arc<Session>) -> Result<(), CliError> {
+    //held until every torrent is done, so a shutdown also waits for torrents that just started
+    let mut stop = session.stop_signal();
+    let state_dir = session.config().resume_dir();
+    let output = Output {
+        bars: None,
+        tagged: true,
+        #[cfg(feature = "serde")]
+        json: args.json,
+    };
+    let mut watch = WatchDir::new(&args.watch, args.move_added);
+    let mut torrents = JoinSet::new();
+    //torrents held by a pause file, which are resumed once it is deleted
+    let mut paused = HashSet::new();
+
+    let saved = saved_torrent_files(&state_dir).map_err(|source| CliError::ReadTorrent {
+        path: state_dir.clone(),
+        source,
+    })?;
+    for path in saved {
+        match TorrentFile::from_file(&path) {
+            Ok(torrent_file) => {
+                watch.add_known(torrent_file.torrent().info_hash);
+                torrents.spawn(run_file(path, torrent_file, session.clone(), output));
+            }
+            Err(e) => warn!(path = %path.display(), error = %e, "cannot restore torrent"),
+        }
+    }
+
+    loop {
+        let added = watch.scan().map_err(|source| CliError::ReadTorrent {
+            path: args.watch.clone(),
+            source,
+        })?;
+        for torrent_file in added {
+            let torrent = torrent_file.torrent();
+            let path = save_torrent_file(&state_dir, &torrent.info_hash, torrent_file.as_bytes())
+                .map_err(|source| CliError::WriteFile {
+                path: state_dir.clone(),
+                source,
+            })?;
+            info!(name = %torrent.info.name, info_hash = %hex::encode(&torrent.info_hash), "added torrent");
+            torrents.spawn(run_file(path, torrent_file, session.clone(), output));
+        }
+        let pause_files = watch.paused().map_err(|source| CliError::ReadTorrent {
+            path: args.watch.clone(),
+            source,
+        })?;
+        for torrent in session.list() {
+            let info_hash = torrent.info_hash();
+            if pause_files.contains(info_hash) && paused.insert(*info_hash) {
+                info!(name = %torrent.name(), "pausing torrent");
+                torrent.pause();
+            } else if !pause_files.contains(info_hash) && paused.remove(info_hash) {
+                info!(name = %torrent.name(), "resuming torrent");
+                torrent.resume();
+            }
+        }
+        //torrents that ended already reported how, the daemon keeps going either way
+        while torrents.try_join_next().is_some() {}
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(args.scan_interval)) => {}
+            _ = stop.stopped() => break,
+        }
+    }
+    while torrents.join_next().await.is_some() {}
+    Ok(())
+}
+
+//run a torrent of the daemon, reporting a failure like the download command does
+async fn run_file(
+    path: PathBuf,
+    torrent_file: TorrentFile,
+    session: Arc<Session>,
+    output: Output<'static>,
+) -> ExitCode {
+    report(
+        run(
+            &path,
+            torrent_file,
+            &session,
+            TorrentConfig::default(),
+            output,
+        )
+        .await,
+    )
+}
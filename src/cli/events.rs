@@ -0,0 +1,155 @@
+use crate::cli::cli_error::CliError;
+use motteseed::core::session::progress::Progress;
+use motteseed::core::torrent::torrent::Torrent;
+use motteseed::util::hex;
+
+use serde_json::{Value, json};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//writes what happens to a torrent as JSON events on stdout, one object per line
+//every event has its name, the time in seconds since the unix epoch and the info hash of the torrent
+#[derive(Debug, Clone)]
+pub struct EventWriter {
+    info_hash: Option<String>, //info hash as hex, None if the torrent file could not be read
+}
+
+impl EventWriter {
+    //write the events of the torrent with info_hash, None for a torrent file that could not be read
+    pub fn new(info_hash: Option<&[u8; 20]>) -> Self {
+        Self {
+            info_hash: info_hash.map(|hash| hex::encode(hash)),
+        }
+    }
+
+    //the torrent was read and is about to start
+    pub fn torrent_added(&self, torrent: &Torrent) {
+        self.emit(
+            "torrent_added",
+            json!({
+                "name": torrent.info.name,
+                "total_bytes": torrent.info.total_length(),
+                "piece_length": torrent.info.piece_length,
+                "pieces": torrent.info.num_pieces(),
+            }),
+        );
+    }
+
+    //a tracker answered an announce, seeders and leechers are null if it did not say
+    pub fn tracker_announce(
+        &self,
+        tracker: &str,
+        peers: usize,
+        seeders: Option<u64>,
+        leechers: Option<u64>,
+    ) {
+        self.emit(
+            "tracker_announce",
+            json!({
+                "tracker": tracker,
+                "peers": peers,
+                "seeders": seeders,
+                "leechers": leechers,
+            }),
+        );
+    }
+
+    //a piece passed its hash check, either downloaded or found on disk while checking
+    pub fn piece_verified(&self, index: usize) {
+        self.emit("piece_verified", json!({ "index": index }));
+    }
+
+    //periodic snapshot of the transfer, eta_secs is null if it can not be told
+    pub fn progress(&self, progress: &Progress) {
+        self.emit(
+            "progress",
+            json!({
+                "state": progress.state.to_string(),
+                "percent": progress.percent(),
+                "total_bytes": progress.total_bytes,
+                "verified_bytes": progress.verified_bytes,
+                "downloaded": progress.downloaded,
+                "uploaded": progress.uploaded,
+                "download_rate": progress.download_rate,
+                "upload_rate": progress.upload_rate,
+                "peers": progress.peers,
+                "eta_secs": progress.eta().map(|eta| eta.as_secs()),
+                "elapsed_secs": progress.elapsed.as_secs_f64(),
+            }),
+        );
+    }
+
+    //every piece of the torrent passed its hash check
+    pub fn completed(&self, progress: &Progress) {
+        self.emit(
+            "completed",
+            json!({
+                "total_bytes": progress.total_bytes,
+                "verified_bytes": progress.verified_bytes,
+                "downloaded": progress.downloaded,
+                "average_download_rate": progress.average_download_rate(),
+                "elapsed_secs": progress.elapsed.as_secs_f64(),
+            }),
+        );
+    }
+
+    //the torrent met its seed policy and left the swarm
+    pub fn seeding_stopped(&self, progress: &Progress) {
+        self.emit(
+            "seeding_stopped",
+            json!({
+                "uploaded": progress.uploaded,
+                "ratio": progress.ratio(),
+                "seeding_secs": progress.seeding_time.as_secs_f64(),
+            }),
+        );
+    }
+
+    //the torrent stopped because of error, the same message is printed on stderr
+    pub fn error(&self, error: &CliError) {
+        self.emit("error", json!({ "message": error.message() }));
+    }
+
+    //build the event name with the common fields followed by the fields of the event
+    pub fn event(&self, name: &str, fields: Value) -> Value {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since| since.as_secs_f64());
+        let mut event = json!({
+            "event": name,
+            "time": time,
+            "info_hash": self.info_hash,
+        });
+        if let (Some(event), Value::Object(fields)) = (event.as_object_mut(), fields) {
+            event.extend(fields);
+        }
+        event
+    }
+
+    //print an event as a single line, which is written in one go so lines of torrents do not mix
+    fn emit(&self, name: &str, fields: Value) {
+        println!("{}", self.event(name, fields));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_single_lines_with_the_common_fields() {
+        let writer = EventWriter::new(Some(&[0xab; 20]));
+        let line = writer
+            .event("piece_verified", json!({ "index": 3 }))
+            .to_string();
+        assert!(!line.contains('\n'));
+        let event: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["event"], "piece_verified");
+        assert_eq!(event["index"], 3);
+        assert_eq!(event["info_hash"], "ab".repeat(20));
+        assert!(event["time"].as_f64().unwrap() > 1.6e9);
+
+        let event = EventWriter::new(None).event("error", json!({ "message": "x" }));
+        assert!(event["info_hash"].is_null());
+        assert_eq!(event["message"], "x");
+    }
+}
@@ -0,0 +1,50 @@
+use indicatif::MultiProgress;
+use std::io::{self, IsTerminal, Write};
+use tracing_subscriber::EnvFilter;
+
+//target of the events carrying tracker responses, logged with --debug-response
+const RESPONSE_TARGET: &str = concat!(env!("CARGO_CRATE_NAME"), "::core::tracker::http_transport");
+
+//send the log events of the client to stderr, hiding bars while a line is written
+//RUST_LOG picks what is logged if set, otherwise verbose does: warnings, then info, debug and trace
+pub fn init(verbose: u8, debug_responses: bool, bars: Option<MultiProgress>) {
+    let level = match verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let mut filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    if debug_responses {
+        filter = filter.add_directive(
+            format!("{}=info", RESPONSE_TARGET)
+                .parse()
+                .expect("directive is valid"),
+        );
+    }
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(move || LogWriter { bars: bars.clone() })
+        .init();
+}
+
+//writes log lines to stderr, with the bars cleared so the next redraw does not overwrite them
+struct LogWriter {
+    bars: Option<MultiProgress>, //bars drawn on the same terminal, None if there are none
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.bars {
+            Some(bars) => bars.suspend(|| io::stderr().write(buf)),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
@@ -0,0 +1,9 @@
+pub mod args;
+pub mod cli_error;
+pub mod commands;
+pub mod config_file;
+pub mod download;
+#[cfg(feature = "serde")]
+pub mod events;
+pub mod logging;
+pub mod progress;
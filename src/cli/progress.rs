@@ -0,0 +1,338 @@
+use crate::cli::cli_error::CliError;
+#[cfg(feature = "serde")]
+use crate::cli::events::EventWriter;
+use motteseed::core::session::event::Event;
+use motteseed::core::session::progress::{Progress, TorrentState};
+use motteseed::core::session::torrent_handle::TorrentHandle;
+use motteseed::core::torrent::torrent::Torrent;
+use motteseed::util::units::{format_bytes, format_duration};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::time::Duration;
+
+//how often the bars are redrawn
+pub const BAR_REFRESH: Duration = Duration::from_millis(500);
+//how often a status line is printed when no bars are drawn, e.g. when the output goes to a file
+pub const LOG_INTERVAL: Duration = Duration::from_secs(10);
+//how often a progress event is written with --json
+#[cfg(feature = "serde")]
+pub const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+//number of characters between the brackets of a bar
+const BAR_WIDTH: usize = 30;
+
+//how the progress of a torrent is shown
+#[derive(Debug, Clone)]
+enum View {
+    Bar(ProgressBar), //bar redrawn in place on a terminal
+    Lines,            //status line printed every LOG_INTERVAL
+    #[cfg(feature = "serde")]
+    Events(EventWriter), //JSON events, with nothing else printed on stdout
+}
+
+//shows the progress of a torrent, as a bar redrawn in place, periodic status lines or JSON events
+#[derive(Debug, Clone)]
+pub struct ProgressDisplay {
+    name: String, //torrent name shown in front of the status
+    view: View,
+}
+
+impl ProgressDisplay {
+    //show the progress of the torrent name as a bar among bars, or as status lines if bars is None
+    pub fn new(name: &str, bars: Option<&MultiProgress>) -> Self {
+        let view = match bars {
+            Some(bars) => {
+                //the whole line is rendered by format_bar, so the bar only draws the message
+                let style = ProgressStyle::with_template("{msg}").expect("template is valid");
+                View::Bar(bars.add(ProgressBar::no_length().with_style(style)))
+            }
+            None => View::Lines,
+        };
+
+        Self {
+            name: name.to_string(),
+            view,
+        }
+    }
+
+    //show the progress of the torrent name as events written by events
+    #[cfg(feature = "serde")]
+    pub fn with_events(name: &str, events: EventWriter) -> Self {
+        Self {
+            name: name.to_string(),
+            view: View::Events(events),
+        }
+    }
+
+    //show a snapshot of the progress, replacing the bar or printing a new status line or event
+    pub fn update(&self, progress: &Progress) {
+        match &self.view {
+            View::Bar(bar) => bar.set_message(format_bar(&self.name, progress)),
+            View::Lines => println!("{}", format_line(&self.name, progress)),
+            #[cfg(feature = "serde")]
+            View::Events(events) => events.progress(progress),
+        }
+    }
+
+    //print with the bars hidden, so the output is not overwritten by the next redraw
+    //used for diagnostics on stderr, which are printed in every view
+    pub fn suspend<R>(&self, print: impl FnOnce() -> R) -> R {
+        match &self.view {
+            View::Bar(bar) => bar.suspend(print),
+            _ => print(),
+        }
+    }
+
+    //print human-readable output on stdout, which is left out when stdout carries JSON events
+    pub fn print(&self, print: impl FnOnce()) {
+        #[cfg(feature = "serde")]
+        if let View::Events(_) = self.view {
+            return;
+        }
+        self.suspend(print);
+    }
+
+    //announce the torrent once it is read
+    #[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+    pub fn added(&self, torrent: &Torrent) {
+        #[cfg(feature = "serde")]
+        if let View::Events(events) = &self.view {
+            events.torrent_added(torrent);
+        }
+    }
+
+    //report the error that stopped the torrent, which is printed on stderr in every view
+    #[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+    pub fn error(&self, error: &CliError) {
+        #[cfg(feature = "serde")]
+        if let View::Events(events) = &self.view {
+            events.error(error);
+        }
+    }
+
+    //remove the bar once the torrent is stopped
+    pub fn finish(&self) {
+        if let View::Bar(bar) = &self.view {
+            bar.finish_and_clear();
+        }
+    }
+
+    //show snapshots of the progress of torrent until the task running this is aborted
+    pub async fn run(self, torrent: TorrentHandle) {
+        let period = match self.view {
+            View::Bar(_) => BAR_REFRESH,
+            View::Lines => LOG_INTERVAL,
+            #[cfg(feature = "serde")]
+            View::Events(_) => EVENT_INTERVAL,
+        };
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            self.update(&torrent.progress());
+        }
+    }
+}
+
+impl ProgressDisplay {
+    //show an event of the torrent, what happens between events is shown by the snapshots of run
+    pub fn show(&self, event: &Event, torrent: &TorrentHandle) {
+        match event {
+            Event::PieceVerified { index, .. } => self.piece_verified(*index),
+            Event::TrackerAnnounce {
+                tracker,
+                ok: true,
+                peers,
+                seeders,
+                leechers,
+                ..
+            } => self.announced(tracker, *peers, *seeders, *leechers),
+            Event::TorrentCompleted { .. } => self.completed(&torrent.progress()),
+            Event::StateChanged {
+                state: TorrentState::Stopped,
+                ..
+            } => self.seeding_stopped(&torrent.progress()),
+            //the torrent is shown once it is read, and the error that ends it once it is reported
+            _ => {}
+        }
+    }
+
+    //report a piece that passed its hash check, only events show single pieces
+    #[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+    fn piece_verified(&self, index: usize) {
+        #[cfg(feature = "serde")]
+        if let View::Events(events) = &self.view {
+            events.piece_verified(index);
+        }
+    }
+
+    //report an answer of a tracker, the peer count is shown with the next snapshot otherwise
+    #[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+    fn announced(&self, tracker: &str, peers: usize, seeders: Option<u64>, leechers: Option<u64>) {
+        #[cfg(feature = "serde")]
+        if let View::Events(events) = &self.view {
+            events.tracker_announce(tracker, peers, seeders, leechers);
+        }
+    }
+
+    //print the summary of a download that is complete
+    fn completed(&self, progress: &Progress) {
+        match &self.view {
+            //the bar is drawn again below the summary, so it should not show an older state
+            View::Bar(bar) => bar.set_message(format_bar(&self.name, progress)),
+            View::Lines => {}
+            #[cfg(feature = "serde")]
+            View::Events(events) => {
+                events.completed(progress);
+                return;
+            }
+        }
+        self.suspend(|| {
+            for line in format_summary(&self.name, progress) {
+                println!("{}", line);
+            }
+        });
+    }
+
+    //report a torrent that met its seed policy and left the swarm
+    fn seeding_stopped(&self, progress: &Progress) {
+        #[cfg(feature = "serde")]
+        if let View::Events(events) = &self.view {
+            events.seeding_stopped(progress);
+            return;
+        }
+        self.suspend(|| {
+            println!(
+                "{}: seeding done at ratio {:.2} after {}, stopped",
+                self.name,
+                progress.ratio(),
+                format_duration(progress.seeding_time)
+            )
+        });
+    }
+}
+
+//render the bar of a torrent, e.g. "name [#####-----]  50.0% downloading, 1.0 MiB/s down, ..."
+pub fn format_bar(name: &str, progress: &Progress) -> String {
+    let filled = ((progress.percent() / 100.0 * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+    format!(
+        "{} [{}{}] {}",
+        name,
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        format_status(progress)
+    )
+}
+
+//render the status line of a torrent printed when no bar is drawn
+pub fn format_line(name: &str, progress: &Progress) -> String {
+    format!("{}: {}", name, format_status(progress))
+}
+
+//render the lines printed once a download is complete: time taken, average speed and data verified
+pub fn format_summary(name: &str, progress: &Progress) -> Vec<String> {
+    vec![
+        format!(
+            "{}: complete after {}",
+            name,
+            format_duration(progress.elapsed)
+        ),
+        format!(
+            "Downloaded {} at {}/s on average",
+            format_bytes(progress.downloaded),
+            format_bytes(progress.average_download_rate())
+        ),
+        format!(
+            "Verified {} of {}",
+            format_bytes(progress.verified_bytes),
+            format_bytes(progress.total_bytes)
+        ),
+    ]
+}
+
+//render percentage, state, rates, peers and ETA, leaving out what does not apply to the state
+fn format_status(progress: &Progress) -> String {
+    let mut status = format!("{:5.1}% {}", progress.percent(), progress.state);
+    //nothing is transferred while checking
+    if progress.state != TorrentState::Checking {
+        status.push_str(&format!(
+            ", {}/s down, {}/s up, {} peers",
+            format_bytes(progress.download_rate),
+            format_bytes(progress.upload_rate),
+            progress.peers
+        ));
+    }
+    if let Some(eta) = progress.eta() {
+        status.push_str(&format!(", ETA {}", format_duration(eta)));
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //progress of a 100 MiB torrent that is half verified, in state
+    fn progress(state: TorrentState) -> Progress {
+        Progress {
+            state,
+            total_bytes: 100 << 20,
+            checked_bytes: 0,
+            verified_bytes: 50 << 20,
+            downloaded: 40 << 20,
+            uploaded: 0,
+            download_rate: 1 << 20,
+            upload_rate: 0,
+            peers: 3,
+            elapsed: Duration::from_secs(40),
+            seeding_time: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn bars_and_lines_show_the_state() {
+        assert_eq!(
+            format_bar("ubuntu.iso", &progress(TorrentState::Downloading)),
+            "ubuntu.iso [###############---------------]  50.0% downloading, \
+            1.0 MiB/s down, 0 B/s up, 3 peers, ETA 50s"
+        );
+
+        //nothing is transferred while checking
+        let mut checking = progress(TorrentState::Checking);
+        checking.checked_bytes = 25 << 20;
+        assert_eq!(format_line("a", &checking), "a:  25.0% checking");
+
+        let mut seeding = progress(TorrentState::Seeding);
+        seeding.verified_bytes = seeding.total_bytes;
+        assert_eq!(
+            format_line("a", &seeding),
+            "a: 100.0% seeding, 1.0 MiB/s down, 0 B/s up, 3 peers"
+        );
+        let bar = format_bar("a", &seeding);
+        assert!(
+            bar.starts_with("a [##############################] 100.0%"),
+            "{}",
+            bar
+        );
+
+        //an empty torrent is done from the start
+        let mut empty = progress(TorrentState::Downloading);
+        (empty.total_bytes, empty.verified_bytes) = (0, 0);
+        let line = format_line("e", &empty);
+        assert!(
+            line.starts_with("e: 100.0% downloading") && line.ends_with("ETA 0s"),
+            "{}",
+            line
+        );
+    }
+
+    #[test]
+    fn summary_shows_time_speed_and_data() {
+        assert_eq!(
+            format_summary("a", &progress(TorrentState::Downloading)),
+            [
+                "a: complete after 40s",
+                "Downloaded 40.0 MiB at 1.0 MiB/s on average",
+                "Verified 50.0 MiB of 100.0 MiB",
+            ]
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::core::lsd::lsd_error::LsdError;
+use crate::core::peer::peer::Peer;
+
+use rand::{Rng, rng};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, sleep_until};
+
+//multicast groups and port used for local service discovery (BEP 14)
+pub const LSD_GROUP_V4: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 192, 152, 143)), 6771);
+pub const LSD_GROUP_V6: SocketAddr = SocketAddr::new(
+    IpAddr::V6(Ipv6Addr::new(0xff15, 0, 0, 0, 0, 0, 0xefc0, 0x988f)),
+    6771,
+);
+
+//time between two announcements of the same torrents
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+//largest announcement we expect
+const MAX_PACKET_SIZE: usize = 1400;
+
+//a BT-SEARCH announcement sent to the multicast group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsdMessage {
+    pub port: u16,                  //port the announcing peer listens on
+    pub info_hashes: Vec<[u8; 20]>, //torrents the peer is interested in
+    pub cookie: Option<String>,     //opaque value used to recognise our own announcements
+}
+
+impl LsdMessage {
+    //encode the announcement for the given multicast group
+    pub fn encode(&self, group: SocketAddr) -> Vec<u8> {
+        let mut message = format!(
+            "BT-SEARCH * HTTP/1.1\r\nHost: {}\r\nPort: {}\r\n",
+            group, self.port
+        );
+        for info_hash in &self.info_hashes {
+            message.push_str("Infohash: ");
+            for b in info_hash {
+                message.push_str(&format!("{:02x}", b));
+            }
+            message.push_str("\r\n");
+        }
+        if let Some(cookie) = &self.cookie {
+            message.push_str(&format!("cookie: {}\r\n", cookie));
+        }
+        message.push_str("\r\n\r\n");
+
+        message.into_bytes()
+    }
+
+    //parse an announcement, header names are case-insensitive
+    pub fn parse(bytes: &[u8]) -> Result<Self, LsdError> {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|_| LsdError::Malformed("announcement is not UTF-8".into()))?;
+        let mut lines = text.split("\r\n");
+
+        if lines.next() != Some("BT-SEARCH * HTTP/1.1") {
+            return Err(LsdError::Malformed("not a BT-SEARCH request".into()));
+        }
+
+        let mut port = None;
+        let mut info_hashes = Vec::new();
+        let mut cookie = None;
+        for line in lines.take_while(|line| !line.is_empty()) {
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(LsdError::Malformed(format!(
+                    "invalid header line {:?}",
+                    line
+                )));
+            };
+            let value = value.trim();
+
+            match name.trim().to_ascii_lowercase().as_str() {
+                "port" => {
+                    port =
+                        Some(value.parse::<u16>().map_err(|_| {
+                            LsdError::Malformed(format!("invalid port {:?}", value))
+                        })?)
+                }
+                "infohash" => info_hashes.push(Self::parse_info_hash(value)?),
+                "cookie" => cookie = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        let port = port.ok_or_else(|| LsdError::Malformed("missing port".into()))?;
+        if info_hashes.is_empty() {
+            return Err(LsdError::Malformed("missing infohash".into()));
+        }
+
+        Ok(Self {
+            port,
+            info_hashes,
+            cookie,
+        })
+    }
+
+    //parse a 40 character hex info hash
+    fn parse_info_hash(value: &str) -> Result<[u8; 20], LsdError> {
+        let invalid = || LsdError::Malformed(format!("invalid infohash {:?}", value));
+        if value.len() != 40 || !value.is_ascii() {
+            return Err(invalid());
+        }
+
+        let mut info_hash = [0u8; 20];
+        for (i, b) in info_hash.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(info_hash)
+    }
+}
+
+//a peer found on the local network
+#[derive(Debug, Clone)]
+pub struct LocalPeer {
+    pub info_hash: [u8; 20], //torrent the peer announced
+    pub peer: Peer,          //address of the peer
+}
+
+//announces our torrents on the local network and reports peers announcing the same ones
+#[derive(Debug)]
+pub struct LocalDiscovery {
+    socket: UdpSocket,          //socket joined to the multicast group
+    group: SocketAddr,          //multicast group announcements are sent to
+    port: u16,                  //port we accept peer connections on
+    cookie: String,             //sent with our announcements so we can ignore them
+    info_hashes: Vec<[u8; 20]>, //torrents we announce and accept peers for
+    next_announce: Instant,     //time of the next announcement
+}
+
+impl LocalDiscovery {
+    //join the IPv4 group, announcing that we accept connections on port
+    pub fn new(port: u16) -> Result<Self, LsdError> {
+        Self::with_group(LSD_GROUP_V4, port)
+    }
+
+    //join the given multicast group, LSD_GROUP_V4 or LSD_GROUP_V6
+    pub fn with_group(group: SocketAddr, port: u16) -> Result<Self, LsdError> {
+        let domain = if group.is_ipv4() {
+            Domain::IPV4
+        } else {
+            Domain::IPV6
+        };
+        let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
+        //several clients on the same host share the LSD port
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+
+        match group.ip() {
+            IpAddr::V4(ip) => {
+                socket.bind(&SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), group.port()).into())?;
+                socket.join_multicast_v4(&ip, &Ipv4Addr::UNSPECIFIED)?;
+                socket.set_multicast_loop_v4(true)?;
+            }
+            IpAddr::V6(ip) => {
+                socket.set_only_v6(true)?;
+                socket.bind(&SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), group.port()).into())?;
+                socket.join_multicast_v6(&ip, 0)?;
+                socket.set_multicast_loop_v6(true)?;
+            }
+        }
+
+        Ok(Self {
+            socket: UdpSocket::from_std(socket.into())?,
+            group,
+            port,
+            cookie: format!("{:08x}", rng().random::<u32>()),
+            info_hashes: Vec::new(),
+            next_announce: Instant::now(),
+        })
+    }
+
+    //start announcing a torrent, private torrents (Info::is_private) are never announced
+    //returns whether the torrent was added
+    pub fn add_torrent(&mut self, info_hash: [u8; 20], private: bool) -> bool {
+        if private || self.info_hashes.contains(&info_hash) {
+            return false;
+        }
+        self.info_hashes.push(info_hash);
+        //let the new torrent be found right away
+        self.next_announce = Instant::now();
+        true
+    }
+
+    //stop announcing a torrent and accepting local peers for it
+    pub fn remove_torrent(&mut self, info_hash: &[u8; 20]) {
+        self.info_hashes.retain(|hash| hash != info_hash);
+    }
+
+    //announce all torrents to the multicast group
+    pub async fn announce(&mut self) -> Result<(), LsdError> {
+        self.next_announce = Instant::now() + ANNOUNCE_INTERVAL;
+        if self.info_hashes.is_empty() {
+            return Ok(());
+        }
+
+        let message = LsdMessage {
+            port: self.port,
+            info_hashes: self.info_hashes.clone(),
+            cookie: Some(self.cookie.clone()),
+        };
+        self.socket
+            .send_to(&message.encode(self.group), self.group)
+            .await?;
+
+        Ok(())
+    }
+
+    //wait for peers announcing our torrents, announcing ourselves on schedule meanwhile
+    //malformed announcements, our own ones and ones for other torrents are skipped
+    pub async fn next_peers(&mut self) -> Result<Vec<LocalPeer>, LsdError> {
+        let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+        loop {
+            let (len, from) = tokio::select! {
+                _ = sleep_until(self.next_announce) => {
+                    self.announce().await?;
+                    continue;
+                }
+                received = self.socket.recv_from(&mut buffer) => received?,
+            };
+
+            let Ok(message) = LsdMessage::parse(&buffer[..len]) else {
+                continue;
+            };
+            if message.cookie.as_deref() == Some(self.cookie.as_str()) {
+                continue;
+            }
+
+            let peers: Vec<LocalPeer> = message
+                .info_hashes
+                .into_iter()
+                .filter(|info_hash| self.info_hashes.contains(info_hash))
+                .map(|info_hash| LocalPeer {
+                    info_hash,
+                    peer: Peer::new(from.ip(), message.port, None),
+                })
+                .collect();
+            if !peers.is_empty() {
+                return Ok(peers);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announcements_roundtrip() {
+        let message = LsdMessage {
+            port: 6881,
+            info_hashes: vec![[0xab; 20], [1; 20]],
+            cookie: Some("c".into()),
+        };
+        let bytes = message.encode(LSD_GROUP_V4);
+        let text = String::from_utf8(bytes.clone()).unwrap();
+        assert!(text.starts_with(
+            "BT-SEARCH * HTTP/1.1\r\nHost: 239.192.152.143:6771\r\nPort: 6881\r\nInfohash: abab"
+        ));
+        assert!(text.ends_with("\r\ncookie: c\r\n\r\n\r\n"));
+        assert_eq!(LsdMessage::parse(&bytes).unwrap(), message);
+
+        //header names are case-insensitive and hex digits may be upper case
+        let upper = format!(
+            "BT-SEARCH * HTTP/1.1\r\nport: 1\r\nINFOHASH: {}\r\n\r\n\r\n",
+            "AB".repeat(20)
+        );
+        assert_eq!(
+            LsdMessage::parse(upper.as_bytes()).unwrap().info_hashes,
+            [[0xab; 20]]
+        );
+    }
+
+    #[test]
+    fn malformed_announcements_are_refused() {
+        for bytes in [
+            &b"BT-SEARCH * HTTP/1.1\r\nPort: 1\r\n\r\n\r\n"[..],
+            b"M-SEARCH * HTTP/1.1\r\nPort: 1\r\n\r\n\r\n",
+            b"BT-SEARCH * HTTP/1.1\r\nPort: 70000\r\nInfohash: 00\r\n\r\n\r\n",
+            b"BT-SEARCH * HTTP/1.1\r\nInfohash: 0101010101010101010101010101010101010101\r\n\r\n",
+            b"BT-SEARCH * HTTP/1.1\r\nPort: 1\r\nInfohash: zz01010101010101010101010101010101010101\r\n\r\n",
+            b"BT-SEARCH * HTTP/1.1\r\nbroken\r\n\r\n",
+            b"\xff",
+        ] {
+            assert!(LsdMessage::parse(bytes).is_err(), "{:?}", bytes);
+        }
+    }
+
+    #[tokio::test]
+    async fn private_torrents_are_never_announced() {
+        let group = "239.192.152.143:16790".parse().unwrap();
+        let mut discovery = LocalDiscovery::with_group(group, 1).unwrap();
+        assert!(!discovery.add_torrent([1; 20], true));
+        assert!(discovery.add_torrent([2; 20], false));
+        assert!(!discovery.add_torrent([2; 20], false));
+        assert_eq!(discovery.info_hashes, [[2; 20]]);
+    }
+}
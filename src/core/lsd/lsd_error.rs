@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+//custom error enum for local service discovery
+#[derive(Error, Debug)]
+pub enum LsdError {
+    //announcement that does not follow BEP 14
+    #[error("Malformed announcement: {0}")]
+    Malformed(String),
+
+    //io error with a display message
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+}
@@ -0,0 +1,2 @@
+pub mod lsd;
+pub mod lsd_error;
@@ -1,4 +1,9 @@
+pub mod lsd;
 pub mod peer;
 pub mod peer_id;
+pub mod peer_id_error;
+pub mod session;
+pub mod storage;
 pub mod torrent;
 pub mod tracker;
+pub mod webseed;
@@ -0,0 +1,180 @@
+use crate::core::peer::message::{HANDSHAKE_LEN, Handshake, MAX_MESSAGE_LEN, Message};
+use crate::core::peer::peer_error::PeerError;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+//time a peer has to send its handshake once connected
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+//a connection to a peer whose handshake was exchanged
+#[derive(Debug)]
+pub struct PeerConnection {
+    stream: TcpStream,
+    buffer: Vec<u8>,      //bytes read that do not make up a whole message yet
+    handshake: Handshake, //handshake the peer sent
+}
+
+impl PeerConnection {
+    //connect to the peer at addr and exchange handshakes for the torrent of ours
+    pub async fn connect(addr: SocketAddr, ours: &Handshake) -> Result<Self, PeerError> {
+        let mut stream = timeout(HANDSHAKE_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| PeerError::Timeout)??;
+        stream.write_all(&ours.encode()).await?;
+        let handshake = read_handshake(&mut stream).await?;
+        if handshake.info_hash != ours.info_hash {
+            return Err(PeerError::Handshake(
+                "the peer answered for another torrent".into(),
+            ));
+        }
+        Ok(Self::new(stream, handshake))
+    }
+
+    //read the handshake of a peer that connected to us, without answering it yet
+    pub async fn read_handshake(stream: &mut TcpStream) -> Result<Handshake, PeerError> {
+        read_handshake(stream).await
+    }
+
+    //answer the handshake a peer sent over stream with ours
+    pub async fn accept(
+        mut stream: TcpStream,
+        theirs: Handshake,
+        ours: &Handshake,
+    ) -> Result<Self, PeerError> {
+        stream.write_all(&ours.encode()).await?;
+        Ok(Self::new(stream, theirs))
+    }
+
+    fn new(stream: TcpStream, handshake: Handshake) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+            handshake,
+        }
+    }
+
+    //get the handshake the peer sent
+    pub fn handshake(&self) -> &Handshake {
+        &self.handshake
+    }
+
+    //get the address of the peer
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.peer_addr().ok()
+    }
+
+    //send message to the peer
+    pub async fn send(&mut self, message: &Message) -> Result<(), PeerError> {
+        self.stream.write_all(&message.encode()).await?;
+        Ok(())
+    }
+
+    //wait for the next message of the peer
+    //cancel safe, bytes read before the future is dropped are kept for the next call
+    pub async fn recv(&mut self) -> Result<Message, PeerError> {
+        loop {
+            if let Some(message) = self.buffered()? {
+                return Ok(message);
+            }
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+
+    //take the first message out of the buffer, None if it is not all there yet
+    fn buffered(&mut self) -> Result<Option<Message>, PeerError> {
+        let Some(prefix) = self.buffer.get(..4) else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(prefix.try_into().expect("prefix is 4 bytes"));
+        if len > MAX_MESSAGE_LEN {
+            return Err(PeerError::Malformed(format!("message of {} bytes", len)));
+        }
+        let end = 4 + len as usize;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+        let message = Message::decode(&self.buffer[4..end]);
+        self.buffer.drain(..end);
+        message.map(Some)
+    }
+}
+
+//read a handshake from stream, which has to come within HANDSHAKE_TIMEOUT
+async fn read_handshake(stream: &mut TcpStream) -> Result<Handshake, PeerError> {
+    let mut bytes = [0; HANDSHAKE_LEN];
+    timeout(HANDSHAKE_TIMEOUT, stream.read_exact(&mut bytes))
+        .await
+        .map_err(|_| PeerError::Timeout)??;
+    Handshake::decode(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn messages_split_across_reads_are_put_together() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ours = Handshake::new([1; 20], [2; 20]);
+        let peer = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let theirs = PeerConnection::read_handshake(&mut stream).await.unwrap();
+            assert_eq!(theirs.peer_id, [2; 20]);
+            let ours = Handshake::new([1; 20], [3; 20]);
+            let stream = PeerConnection::accept(stream, theirs, &ours).await.unwrap();
+            let mut stream = stream.stream;
+            //a have sent a byte at a time, then two messages at once
+            for byte in Message::Have(5).encode() {
+                stream.write_all(&[byte]).await.unwrap();
+                stream.flush().await.unwrap();
+            }
+            let mut both = Message::Unchoke.encode();
+            both.extend(Message::KeepAlive.encode());
+            stream.write_all(&both).await.unwrap();
+        });
+
+        let mut connection = PeerConnection::connect(addr, &ours).await.unwrap();
+        assert_eq!(connection.handshake().peer_id, [3; 20]);
+        assert_eq!(connection.recv().await.unwrap(), Message::Have(5));
+        assert_eq!(connection.recv().await.unwrap(), Message::Unchoke);
+        assert_eq!(connection.recv().await.unwrap(), Message::KeepAlive);
+        peer.await.unwrap();
+        assert!(matches!(
+            connection.recv().await,
+            Err(PeerError::IOError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn oversized_messages_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ours = Handshake::new([1; 20], [2; 20]);
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let theirs = PeerConnection::read_handshake(&mut stream).await.unwrap();
+            let ours = Handshake::new(theirs.info_hash, [3; 20]);
+            let mut connection = PeerConnection::accept(stream, theirs, &ours).await.unwrap();
+            let len = MAX_MESSAGE_LEN + 1;
+            connection
+                .stream
+                .write_all(&len.to_be_bytes())
+                .await
+                .unwrap();
+        });
+        let mut connection = PeerConnection::connect(addr, &ours).await.unwrap();
+        assert!(matches!(
+            connection.recv().await,
+            Err(PeerError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::core::peer::peer_error::PeerError;
+
+//protocol name every handshake starts with
+const PROTOCOL: &[u8; 19] = b"BitTorrent protocol";
+
+//length of a handshake: protocol name and its length, reserved bytes, info hash and peer id
+pub const HANDSHAKE_LEN: usize = 68;
+
+//longest block served, clients ask for blocks of 16 KiB and some older ones for 32 KiB
+pub const MAX_REQUEST: u32 = 32 * 1024;
+
+//longest message read from a peer, which fits the bitfield of a torrent of 8 million pieces
+pub const MAX_MESSAGE_LEN: u32 = 1024 * 1024;
+
+//byte and bit of the reserved bytes that tell the Fast extension is supported (BEP 6)
+const FAST_BYTE: usize = 7;
+const FAST_BIT: u8 = 0x04;
+
+//the first thing both ends of a connection send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub reserved: [u8; 8],   //extensions the sender supports
+    pub info_hash: [u8; 20], //torrent the connection is for
+    pub peer_id: [u8; 20],   //id of the sender
+}
+
+impl Handshake {
+    //create a handshake for info_hash that tells the extensions we support
+    pub fn new(info_hash: [u8; 20], peer_id: [u8; 20]) -> Self {
+        let mut reserved = [0; 8];
+        reserved[FAST_BYTE] |= FAST_BIT;
+        Self {
+            reserved,
+            info_hash,
+            peer_id,
+        }
+    }
+
+    //check if the sender supports the Fast extension
+    pub fn supports_fast(&self) -> bool {
+        self.reserved[FAST_BYTE] & FAST_BIT != 0
+    }
+
+    //encode the handshake as it is sent
+    pub fn encode(&self) -> [u8; HANDSHAKE_LEN] {
+        let mut bytes = [0; HANDSHAKE_LEN];
+        bytes[0] = PROTOCOL.len() as u8;
+        bytes[1..20].copy_from_slice(PROTOCOL);
+        bytes[20..28].copy_from_slice(&self.reserved);
+        bytes[28..48].copy_from_slice(&self.info_hash);
+        bytes[48..68].copy_from_slice(&self.peer_id);
+        bytes
+    }
+
+    //decode a handshake, which has to be of the BitTorrent protocol
+    pub fn decode(bytes: &[u8; HANDSHAKE_LEN]) -> Result<Self, PeerError> {
+        if bytes[0] as usize != PROTOCOL.len() || &bytes[1..20] != PROTOCOL {
+            return Err(PeerError::Handshake("not the BitTorrent protocol".into()));
+        }
+        let mut handshake = Self::new([0; 20], [0; 20]);
+        handshake.reserved.copy_from_slice(&bytes[20..28]);
+        handshake.info_hash.copy_from_slice(&bytes[28..48]);
+        handshake.peer_id.copy_from_slice(&bytes[48..68]);
+        Ok(handshake)
+    }
+}
+
+//a message of the peer wire protocol (BEP 3) and its Fast extension (BEP 6)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    KeepAlive,
+    Choke,
+    Unchoke,
+    Interested,
+    NotInterested,
+    Have(u32),
+    Bitfield(Vec<u8>),
+    Request {
+        index: u32,
+        begin: u32,
+        length: u32,
+    },
+    Piece {
+        index: u32,
+        begin: u32,
+        data: Vec<u8>,
+    },
+    Cancel {
+        index: u32,
+        begin: u32,
+        length: u32,
+    },
+    Port(u16),
+    SuggestPiece(u32),
+    HaveAll,
+    HaveNone,
+    RejectRequest {
+        index: u32,
+        begin: u32,
+        length: u32,
+    },
+    AllowedFast(u32),
+    //message of an extension we do not support
+    Unknown {
+        id: u8,
+        payload: Vec<u8>,
+    },
+}
+
+impl Message {
+    //encode the message with its length prefix
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        let three = |body: &mut Vec<u8>, id: u8, index: u32, begin: u32, length: u32| {
+            body.push(id);
+            body.extend(index.to_be_bytes());
+            body.extend(begin.to_be_bytes());
+            body.extend(length.to_be_bytes());
+        };
+        match self {
+            Message::KeepAlive => {}
+            Message::Choke => body.push(0),
+            Message::Unchoke => body.push(1),
+            Message::Interested => body.push(2),
+            Message::NotInterested => body.push(3),
+            Message::Have(index) => {
+                body.push(4);
+                body.extend(index.to_be_bytes());
+            }
+            Message::Bitfield(bits) => {
+                body.push(5);
+                body.extend(bits);
+            }
+            Message::Request {
+                index,
+                begin,
+                length,
+            } => three(&mut body, 6, *index, *begin, *length),
+            Message::Piece { index, begin, data } => {
+                body.push(7);
+                body.extend(index.to_be_bytes());
+                body.extend(begin.to_be_bytes());
+                body.extend(data);
+            }
+            Message::Cancel {
+                index,
+                begin,
+                length,
+            } => three(&mut body, 8, *index, *begin, *length),
+            Message::Port(port) => {
+                body.push(9);
+                body.extend(port.to_be_bytes());
+            }
+            Message::SuggestPiece(index) => {
+                body.push(0x0d);
+                body.extend(index.to_be_bytes());
+            }
+            Message::HaveAll => body.push(0x0e),
+            Message::HaveNone => body.push(0x0f),
+            Message::RejectRequest {
+                index,
+                begin,
+                length,
+            } => three(&mut body, 0x10, *index, *begin, *length),
+            Message::AllowedFast(index) => {
+                body.push(0x11);
+                body.extend(index.to_be_bytes());
+            }
+            Message::Unknown { id, payload } => {
+                body.push(*id);
+                body.extend(payload);
+            }
+        }
+        let mut bytes = (body.len() as u32).to_be_bytes().to_vec();
+        bytes.extend(body);
+        bytes
+    }
+
+    //decode a message from its body, the bytes after the length prefix
+    pub fn decode(body: &[u8]) -> Result<Self, PeerError> {
+        let Some((&id, payload)) = body.split_first() else {
+            return Ok(Message::KeepAlive);
+        };
+        let malformed =
+            || PeerError::Malformed(format!("message {} of {} bytes", id, payload.len()));
+        let u32_at = |at: usize| -> Result<u32, PeerError> {
+            let bytes = payload.get(at..at + 4).ok_or_else(malformed)?;
+            Ok(u32::from_be_bytes(
+                bytes.try_into().map_err(|_| malformed())?,
+            ))
+        };
+        let exact = |len: usize| {
+            if payload.len() == len {
+                Ok(())
+            } else {
+                Err(malformed())
+            }
+        };
+        let message = match id {
+            0 => exact(0).map(|_| Message::Choke)?,
+            1 => exact(0).map(|_| Message::Unchoke)?,
+            2 => exact(0).map(|_| Message::Interested)?,
+            3 => exact(0).map(|_| Message::NotInterested)?,
+            4 => exact(4).and_then(|_| u32_at(0)).map(Message::Have)?,
+            5 => Message::Bitfield(payload.to_vec()),
+            6 | 8 | 0x10 => {
+                exact(12)?;
+                let (index, begin, length) = (u32_at(0)?, u32_at(4)?, u32_at(8)?);
+                match id {
+                    6 => Message::Request {
+                        index,
+                        begin,
+                        length,
+                    },
+                    8 => Message::Cancel {
+                        index,
+                        begin,
+                        length,
+                    },
+                    _ => Message::RejectRequest {
+                        index,
+                        begin,
+                        length,
+                    },
+                }
+            }
+            7 => Message::Piece {
+                index: u32_at(0)?,
+                begin: u32_at(4)?,
+                data: payload[8..].to_vec(),
+            },
+            9 => {
+                exact(2)?;
+                Message::Port(u16::from_be_bytes([payload[0], payload[1]]))
+            }
+            0x0d => exact(4)
+                .and_then(|_| u32_at(0))
+                .map(Message::SuggestPiece)?,
+            0x0e => exact(0).map(|_| Message::HaveAll)?,
+            0x0f => exact(0).map(|_| Message::HaveNone)?,
+            0x11 => exact(4).and_then(|_| u32_at(0)).map(Message::AllowedFast)?,
+            _ => Message::Unknown {
+                id,
+                payload: payload.to_vec(),
+            },
+        };
+        Ok(message)
+    }
+}
+
+//get the bitfield of num_pieces pieces, with the high bit of the first byte for piece 0
+pub fn bitfield(num_pieces: usize, have: impl Fn(usize) -> bool) -> Vec<u8> {
+    let mut bits = vec![0; num_pieces.div_ceil(8)];
+    for index in (0..num_pieces).filter(|index| have(*index)) {
+        bits[index / 8] |= 0x80 >> (index % 8);
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshakes_round_trip_and_tell_the_fast_extension() {
+        let handshake = Handshake::new([1; 20], [2; 20]);
+        assert!(handshake.supports_fast());
+        let bytes = handshake.encode();
+        assert_eq!(&bytes[..20], b"\x13BitTorrent protocol");
+        assert_eq!(bytes[27], 0x04);
+        assert_eq!(Handshake::decode(&bytes).unwrap(), handshake);
+
+        let mut plain = bytes;
+        plain[27] = 0;
+        assert!(!Handshake::decode(&plain).unwrap().supports_fast());
+        let mut other = bytes;
+        other[1] = b'b';
+        assert!(matches!(
+            Handshake::decode(&other),
+            Err(PeerError::Handshake(_))
+        ));
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            Message::KeepAlive,
+            Message::Choke,
+            Message::Unchoke,
+            Message::Interested,
+            Message::NotInterested,
+            Message::Have(7),
+            Message::Bitfield(vec![0b1010_0000]),
+            Message::Request {
+                index: 1,
+                begin: 16384,
+                length: 16384,
+            },
+            Message::Piece {
+                index: 1,
+                begin: 0,
+                data: vec![1, 2, 3],
+            },
+            Message::Cancel {
+                index: 1,
+                begin: 0,
+                length: 3,
+            },
+            Message::Port(6881),
+            Message::SuggestPiece(3),
+            Message::HaveAll,
+            Message::HaveNone,
+            Message::RejectRequest {
+                index: 2,
+                begin: 0,
+                length: 16384,
+            },
+            Message::AllowedFast(4),
+            Message::Unknown {
+                id: 20,
+                payload: b"d1:md6:ut_pexi1eee".to_vec(),
+            },
+        ];
+        for message in messages {
+            let bytes = message.encode();
+            let len = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+            assert_eq!(len, bytes.len() - 4);
+            assert_eq!(Message::decode(&bytes[4..]).unwrap(), message);
+        }
+        assert_eq!(
+            Message::Request {
+                index: 1,
+                begin: 2,
+                length: 3
+            }
+            .encode(),
+            [0, 0, 0, 13, 6, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3]
+        );
+    }
+
+    #[test]
+    fn messages_of_the_wrong_length_are_malformed() {
+        assert!(matches!(
+            Message::decode(&[4, 0, 0]),
+            Err(PeerError::Malformed(_))
+        ));
+        assert!(Message::decode(&[6, 0, 0, 0, 1]).is_err());
+        assert!(Message::decode(&[1, 0]).is_err());
+        assert!(Message::decode(&[7, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn bitfields_start_with_the_high_bit() {
+        assert_eq!(bitfield(10, |index| index == 0 || index == 9), [0x80, 0x40]);
+        assert_eq!(bitfield(0, |_| true), Vec::<u8>::new());
+    }
+}
@@ -1 +1,6 @@
+pub mod connection;
+pub mod message;
 pub mod peer;
+pub mod peer_error;
+pub mod peer_set;
+pub mod remote_client;
@@ -1,16 +1,67 @@
+use crate::core::peer::remote_client::RemoteClient;
+
 use std::array::TryFromSliceError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Peer {
-    peer_ip: [u8; 4], //ip address of peer
-    peer_port: u16,   //connection port for peer
+    peer_ip: IpAddr,           //ip address of peer
+    peer_port: u16,            //connection port for peer
+    peer_id: Option<[u8; 20]>, //peer id, when the tracker sent one
 }
 
 impl Peer {
+    //create a peer from its address parts
+    pub fn new(peer_ip: IpAddr, peer_port: u16, peer_id: Option<[u8; 20]>) -> Self {
+        Self {
+            peer_ip,
+            peer_port,
+            peer_id,
+        }
+    }
+
+    //decode a compact IPv4 peer entry
     pub fn decode(bytes: &[u8; 6]) -> Result<Self, TryFromSliceError> {
+        let ip: [u8; 4] = bytes[0..4].try_into()?;
         Ok(Self {
-            peer_ip: bytes[0..4].try_into()?,
+            peer_ip: IpAddr::V4(Ipv4Addr::from(ip)),
             peer_port: u16::from_be_bytes(bytes[4..6].try_into()?),
+            peer_id: None,
         })
     }
+
+    //decode a compact IPv6 peer entry
+    pub fn decode_v6(bytes: &[u8; 18]) -> Result<Self, TryFromSliceError> {
+        let ip: [u8; 16] = bytes[0..16].try_into()?;
+        Ok(Self {
+            peer_ip: IpAddr::V6(Ipv6Addr::from(ip)),
+            peer_port: u16::from_be_bytes(bytes[16..18].try_into()?),
+            peer_id: None,
+        })
+    }
+
+    //get ip address of peer
+    pub fn ip(&self) -> IpAddr {
+        self.peer_ip
+    }
+
+    //get connection port of peer
+    pub fn port(&self) -> u16 {
+        self.peer_port
+    }
+
+    //get socket address of peer
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.peer_ip, self.peer_port)
+    }
+
+    //get peer id, if known
+    pub fn peer_id(&self) -> Option<&[u8; 20]> {
+        self.peer_id.as_ref()
+    }
+
+    //get the client of the peer as far as its peer id tells, if the id is known
+    pub fn client(&self) -> Option<RemoteClient> {
+        self.peer_id.as_ref().map(RemoteClient::parse)
+    }
 }
@@ -0,0 +1,27 @@
+use crate::core::storage::storage_error::StorageError;
+
+use thiserror::Error;
+
+//custom error enum for connections to peers
+#[derive(Error, Debug)]
+pub enum PeerError {
+    //handshake of another protocol, or for a torrent we do not have
+    #[error("Handshake failed: {0}")]
+    Handshake(String),
+
+    //message that does not follow the peer wire protocol
+    #[error("Malformed message: {0}")]
+    Malformed(String),
+
+    //peer that sent nothing for too long
+    #[error("Peer timed out")]
+    Timeout,
+
+    //block asked for by the peer that could not be read
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    //io error with a display message
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+}
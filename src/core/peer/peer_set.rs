@@ -0,0 +1,122 @@
+use crate::core::peer::peer::Peer;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::time::Instant;
+
+//peers gathered over several announces, one entry per socket address
+#[derive(Debug, Default)]
+pub struct PeerSet {
+    peers: Vec<Peer>, //known peers, in the order they were first seen
+    last_seen: HashMap<SocketAddr, Instant>, //when each address was last announced
+}
+
+impl PeerSet {
+    //create an empty peer set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //merge announced peers in, refreshing the ones already known
+    //a known peer keeps its position, its peer id is filled in if it was missing
+    pub fn merge(&mut self, peers: impl IntoIterator<Item = Peer>) {
+        let now = Instant::now();
+        for peer in peers {
+            if self.last_seen.insert(peer.addr(), now).is_none() {
+                self.peers.push(peer);
+            } else if peer.peer_id().is_some()
+                && let Some(known) = self.peers.iter_mut().find(|p| p.addr() == peer.addr())
+                && known.peer_id().is_none()
+            {
+                *known = peer;
+            }
+        }
+    }
+
+    //drop peers that were not announced within max_age
+    pub fn expire(&mut self, max_age: Duration) {
+        let last_seen = &mut self.last_seen;
+        self.peers.retain(|peer| {
+            let addr = peer.addr();
+            let fresh = last_seen
+                .get(&addr)
+                .is_some_and(|seen| seen.elapsed() <= max_age);
+            if !fresh {
+                last_seen.remove(&addr);
+            }
+            fresh
+        });
+    }
+
+    //get all known peers
+    pub fn peers(&self) -> &[Peer] {
+        &self.peers
+    }
+
+    //get when a peer was last announced
+    pub fn last_seen(&self, addr: &SocketAddr) -> Option<Instant> {
+        self.last_seen.get(addr).copied()
+    }
+
+    //get the number of known peers
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    //check if no peers are known
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //peer on 10.0.0.last:port
+    fn peer(last: u8, port: u16, peer_id: Option<[u8; 20]>) -> Peer {
+        Peer::new([10, 0, 0, last].into(), port, peer_id)
+    }
+
+    #[test]
+    fn peers_are_merged_by_address() {
+        let mut set = PeerSet::new();
+        set.merge([peer(1, 1, None), peer(2, 2, None), peer(1, 1, None)]);
+        set.merge([
+            peer(2, 2, Some([7; 20])),
+            peer(3, 3, None),
+            peer(1, 2, None),
+        ]);
+        let addrs: Vec<String> = set.peers().iter().map(|p| p.addr().to_string()).collect();
+        assert_eq!(
+            addrs,
+            ["10.0.0.1:1", "10.0.0.2:2", "10.0.0.3:3", "10.0.0.1:2"]
+        );
+        //a known peer gets its id filled in, but never replaced
+        assert_eq!(set.peers()[1].peer_id(), Some(&[7; 20]));
+        set.merge([peer(2, 2, Some([8; 20]))]);
+        assert_eq!(set.peers()[1].peer_id(), Some(&[7; 20]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn peers_not_announced_again_expire() {
+        let mut set = PeerSet::new();
+        set.merge([peer(1, 1, None), peer(2, 2, None)]);
+        tokio::time::advance(Duration::from_secs(60)).await;
+        set.merge([peer(2, 2, None)]);
+        assert_eq!(
+            set.last_seen(&peer(2, 2, None).addr()),
+            Some(Instant::now())
+        );
+
+        set.expire(Duration::from_secs(60));
+        assert_eq!(set.len(), 2);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        set.expire(Duration::from_secs(60));
+        assert_eq!(set.peers()[0].addr(), peer(2, 2, None).addr());
+        assert_eq!(set.last_seen(&peer(1, 1, None).addr()), None);
+        set.expire(Duration::ZERO);
+        assert!(set.is_empty());
+    }
+}
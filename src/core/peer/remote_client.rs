@@ -0,0 +1,302 @@
+use std::fmt;
+
+//convention a peer id follows, naming the client that sent it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientFamily {
+    Azureus([u8; 2]), //-XX1234-, two character client code and four version characters
+    Shadow(u8),       //S58B--, one character client code and three version characters
+    Mainline(u8),     //M4-3-6--, one letter client code and dash separated version numbers
+    BitComet,         //exbc followed by two version bytes
+    Unknown,          //none of the above, e.g. random bytes
+}
+
+//client of a remote peer, as far as its peer id tells
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteClient {
+    pub family: ClientFamily,    //convention the peer id follows
+    pub version: Option<String>, //dotted version, None if the id carries none
+    pub raw: [u8; 20],           //peer id as received
+}
+
+impl RemoteClient {
+    //identify the client from a peer id, anything unrecognised is Unknown
+    pub fn parse(peer_id: &[u8; 20]) -> Self {
+        let (family, version) = parse_azureus(peer_id)
+            .or_else(|| parse_bitcomet(peer_id))
+            .or_else(|| parse_mainline(peer_id))
+            .or_else(|| parse_shadow(peer_id))
+            .unwrap_or((ClientFamily::Unknown, None));
+
+        Self {
+            family,
+            version,
+            raw: *peer_id,
+        }
+    }
+
+    //get the name of the client, None if the family or code is not known
+    pub fn name(&self) -> Option<&'static str> {
+        match self.family {
+            ClientFamily::Azureus(code) => azureus_name(&code),
+            ClientFamily::Shadow(code) => shadow_name(code),
+            ClientFamily::Mainline(b'M') => Some("Mainline"),
+            ClientFamily::Mainline(b'Q') => Some("Queen Bee"),
+            ClientFamily::Mainline(_) => None,
+            ClientFamily::BitComet => Some("BitComet"),
+            ClientFamily::Unknown => None,
+        }
+    }
+}
+
+impl fmt::Display for RemoteClient {
+    //name and version, or the client code for clients not in the tables
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.name(), self.family) {
+            (Some(name), _) => write!(f, "{}", name)?,
+            (None, ClientFamily::Azureus(code)) => {
+                write!(f, "Unknown client {}", String::from_utf8_lossy(&code))?
+            }
+            (None, ClientFamily::Shadow(code) | ClientFamily::Mainline(code)) => {
+                write!(f, "Unknown client {}", char::from(code))?
+            }
+            (None, _) => return write!(f, "Unknown client"),
+        }
+        if let Some(version) = &self.version {
+            write!(f, " {}", version)?;
+        }
+        Ok(())
+    }
+}
+
+//-XX1234-, every version character is one number: 0-9, then A-Z for 10 to 35
+//clients differ in how they use the four characters, so all of them are shown
+fn parse_azureus(id: &[u8; 20]) -> Option<(ClientFamily, Option<String>)> {
+    if id[0] != b'-' || id[7] != b'-' {
+        return None;
+    }
+    //codes are mostly letters, a few use symbols, e.g. A~ for Ares
+    let code = [id[1], id[2]];
+    if !code.iter().all(|b| b.is_ascii_graphic() && *b != b'-') {
+        return None;
+    }
+    let version = id[3..7]
+        .iter()
+        .map(|&b| decode_digit(b).map(|digit| digit.to_string()))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((ClientFamily::Azureus(code), Some(version.join("."))))
+}
+
+//exbc followed by the major and minor version as raw bytes, e.g. exbc\x00\x38 is 0.56
+fn parse_bitcomet(id: &[u8; 20]) -> Option<(ClientFamily, Option<String>)> {
+    if !id.starts_with(b"exbc") {
+        return None;
+    }
+    let version = format!("{}.{:02}", id[4], id[5]);
+    Some((ClientFamily::BitComet, Some(version)))
+}
+
+//M4-3-6-- or M10-0-0-, an uppercase letter and three dash terminated decimal numbers
+fn parse_mainline(id: &[u8; 20]) -> Option<(ClientFamily, Option<String>)> {
+    if !id[0].is_ascii_uppercase() {
+        return None;
+    }
+    let mut numbers = Vec::with_capacity(3);
+    let mut rest = &id[1..];
+    for _ in 0..3 {
+        let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+        if !(1..=2).contains(&digits) || rest.get(digits) != Some(&b'-') {
+            return None;
+        }
+        numbers.push(String::from_utf8_lossy(&rest[..digits]).into_owned());
+        rest = &rest[digits + 1..];
+    }
+
+    Some((ClientFamily::Mainline(id[0]), Some(numbers.join("."))))
+}
+
+//S58B--, a known client letter, three version characters and two dashes
+//only known letters are accepted, random ids match the layout too often
+fn parse_shadow(id: &[u8; 20]) -> Option<(ClientFamily, Option<String>)> {
+    shadow_name(id[0])?;
+    if &id[4..6] != b"--" {
+        return None;
+    }
+    let version = id[1..4]
+        .iter()
+        .map(|&b| decode_shadow_digit(b).map(|digit| digit.to_string()))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((ClientFamily::Shadow(id[0]), Some(version.join("."))))
+}
+
+//number of an Azureus-style version character: 0-9, then A-Z or a-z for 10 to 35
+fn decode_digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'A'..=b'Z' => Some(b - b'A' + 10),
+        b'a'..=b'z' => Some(b - b'a' + 10),
+        _ => None,
+    }
+}
+
+//number of a Shadow-style version character: 0-9, A-Z for 10 to 35, a-z for 36 to 61, . for 62
+fn decode_shadow_digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'A'..=b'Z' => Some(b - b'A' + 10),
+        b'a'..=b'z' => Some(b - b'a' + 36),
+        b'.' => Some(62),
+        _ => None,
+    }
+}
+
+//client names of Shadow-style letters
+fn shadow_name(code: u8) -> Option<&'static str> {
+    match code {
+        b'A' => Some("ABC"),
+        b'O' => Some("Osprey Permaseed"),
+        b'Q' => Some("BTQueue"),
+        b'R' => Some("Tribler"),
+        b'S' => Some("Shadow"),
+        b'T' => Some("BitTornado"),
+        b'U' => Some("UPnP NAT Bit Torrent"),
+        _ => None,
+    }
+}
+
+//client names of Azureus-style codes
+fn azureus_name(code: &[u8; 2]) -> Option<&'static str> {
+    match code {
+        b"7T" => Some("aTorrent"),
+        b"A~" => Some("Ares"),
+        b"AG" => Some("Ares"),
+        b"AR" => Some("Arctic"),
+        b"AZ" => Some("Vuze"),
+        b"BB" => Some("BitBuddy"),
+        b"BC" => Some("BitComet"),
+        b"BF" => Some("Bitflu"),
+        b"BI" => Some("BiglyBT"),
+        b"BN" => Some("Baidu Netdisk"),
+        b"BT" => Some("BitTorrent"),
+        b"BW" => Some("BitWombat"),
+        b"DE" => Some("Deluge"),
+        b"FD" => Some("Free Download Manager"),
+        b"FW" => Some("FrostWire"),
+        b"FX" => Some("Freebox BitTorrent"),
+        b"HL" => Some("Halite"),
+        b"KT" => Some("KTorrent"),
+        b"LH" => Some("LH-ABC"),
+        b"LT" => Some("libtorrent (Rasterbar)"),
+        b"lt" => Some("libTorrent (rakshasa)"),
+        b"LW" => Some("LimeWire"),
+        b"MS" => Some("MotteSeed"),
+        b"PI" => Some("PicoTorrent"),
+        b"qB" => Some("qBittorrent"),
+        b"SD" => Some("Xunlei"),
+        b"ST" => Some("SymTorrent"),
+        b"TL" => Some("Tribler"),
+        b"TR" => Some("Transmission"),
+        b"UM" => Some("µTorrent for Mac"),
+        b"UT" => Some("µTorrent"),
+        b"UW" => Some("µTorrent Web"),
+        b"WD" => Some("WebTorrent Desktop"),
+        b"WW" => Some("WebTorrent"),
+        b"XL" => Some("Xunlei"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::peer::peer::Peer;
+
+    use rand::Rng;
+
+    //peer id starting with prefix, padded with filler
+    fn id(prefix: &[u8]) -> [u8; 20] {
+        let mut id = *b"0123456789abcdefghij";
+        id[..prefix.len()].copy_from_slice(prefix);
+        id
+    }
+
+    #[test]
+    fn real_peer_ids_are_recognized() {
+        let table: &[(&[u8], &str)] = &[
+            (b"-qB4610-", "qBittorrent 4.6.1.0"),
+            (b"-TR2940-", "Transmission 2.9.4.0"),
+            (b"-TR400B-", "Transmission 4.0.0.11"),
+            (b"-UT355W-", "µTorrent 3.5.5.32"),
+            (b"-UM1870-", "µTorrent for Mac 1.8.7.0"),
+            (b"-lt0D80-", "libTorrent (rakshasa) 0.13.8.0"),
+            (b"-LT1270-", "libtorrent (Rasterbar) 1.2.7.0"),
+            (b"-DE13F0-", "Deluge 1.3.15.0"),
+            (b"-AZ5770-", "Vuze 5.7.7.0"),
+            (b"-BI3600-", "BiglyBT 3.6.0.0"),
+            (b"-BC0151-", "BitComet 0.1.5.1"),
+            (b"-KT5100-", "KTorrent 5.1.0.0"),
+            (b"-A~0120-", "Ares 0.1.2.0"),
+            (b"-WW0105-", "WebTorrent 0.1.0.5"),
+            (b"-MS0100-", "MotteSeed 0.1.0.0"),
+            (b"-ZZ1234-", "Unknown client ZZ 1.2.3.4"),
+            (b"S58B-----", "Shadow 5.8.11"),
+            (b"T03I--", "BitTornado 0.3.18"),
+            (b"A310--", "ABC 3.1.0"),
+            (b"R9a.--", "Tribler 9.36.62"),
+            (b"M7-4-3--", "Mainline 7.4.3"),
+            (b"M10-0-0-", "Mainline 10.0.0"),
+            (b"Q1-10-0-", "Queen Bee 1.10.0"),
+            (b"X1-2-3--", "Unknown client X 1.2.3"),
+            (b"exbc\x00\x38", "BitComet 0.56"),
+            (b"exbc\x01\x05LORD", "BitComet 1.05"),
+            (&[0; 20], "Unknown client"),
+            (b"\xff\xfe\x01-qB4610-", "Unknown client"),
+            (b"-qB46!0-", "Unknown client"),
+            (b"-q-4610-", "Unknown client"),
+            (b"Z58B--", "Unknown client"),
+            (b"S58B-x", "Unknown client"),
+            (b"M123-4-5-", "Unknown client"),
+            (b"m7-4-3--", "Unknown client"),
+        ];
+        for (prefix, display) in table {
+            let raw = id(prefix);
+            let client = RemoteClient::parse(&raw);
+            assert_eq!(client.to_string(), *display);
+            //unknown ids have no version, every other one is shown with it
+            let version = client.version.as_deref();
+            assert_eq!(version.is_none(), *display == "Unknown client");
+            assert!(version.is_none_or(|version| display.ends_with(version)));
+            assert_eq!(client.raw, raw);
+        }
+
+        let family = |prefix| RemoteClient::parse(&id(prefix)).family;
+        assert_eq!(family(b"-ZZ1234-"), ClientFamily::Azureus(*b"ZZ"));
+        assert_eq!(family(b"S58B-----"), ClientFamily::Shadow(b'S'));
+        assert_eq!(family(b"X1-2-3--"), ClientFamily::Mainline(b'X'));
+        assert_eq!(family(b"exbc\x00\x38"), ClientFamily::BitComet);
+        assert_eq!(family(b"-qB46!0-"), ClientFamily::Unknown);
+
+        let ip = "1.2.3.4".parse().unwrap();
+        let peer = Peer::new(ip, 1, Some(id(b"-TR2940-")));
+        assert_eq!(peer.client().unwrap().name(), Some("Transmission"));
+        assert!(Peer::new(ip, 1, None).client().is_none());
+    }
+
+    #[test]
+    fn random_peer_ids_never_panic() {
+        let mut rng = rand::rng();
+        for _ in 0..20_000 {
+            let mut raw = [0u8; 20];
+            rng.fill(&mut raw);
+            //bias towards the prefixes that are parsed
+            match rng.random_range(0..4) {
+                0 => raw[0] = b'-',
+                1 => raw[..4].copy_from_slice(b"exbc"),
+                2 => raw[0] = b'M',
+                _ => {}
+            }
+            let _ = RemoteClient::parse(&raw).to_string();
+        }
+    }
+}
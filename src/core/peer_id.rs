@@ -1,36 +1,296 @@
+use crate::core::peer_id_error::PeerIdError;
+
 use once_cell::sync::Lazy;
 use rand::{Rng, rng};
 
-//static peer_id that gets generated once per client session
-static PEER_ID: Lazy<[u8; 20]> = Lazy::new(|| {
-    let mut id = [0u8; 20];
-    //create an Azureus-style peer_id
-    //-MS0100-[13 random bytes]
+//client code and version of the peer ids we generate, -MS0100- for version 0.1.0
+pub const DEFAULT_CLIENT_CODE: &str = "MS";
+pub const DEFAULT_VERSION: &str = match std::str::from_utf8(&VERSION_CHARS) {
+    Ok(version) => version,
+    Err(_) => panic!("version characters are ASCII"),
+};
+
+//version characters derived from the crate version, so a release needs no manual edit
+const VERSION_CHARS: [u8; 4] = version_chars(env!("CARGO_PKG_VERSION"));
+
+//encode a semver version as four peer id characters
+//major, minor and patch become one character each: 0-9, then A-Z for 10 to 35,
+//larger numbers are clamped to Z. Missing parts are 0 and the fourth character is always 0,
+//so 0.1.0 is 0100 and 1.4.12 is 14C0. Pre-release and build metadata are ignored
+const fn version_chars(version: &str) -> [u8; 4] {
+    let bytes = version.as_bytes();
+    let mut chars = [b'0'; 4];
+    let mut part = 0;
+    let mut number: u32 = 0;
+    let mut i = 0;
+    while part < 3 && i <= bytes.len() {
+        if i == bytes.len() || !bytes[i].is_ascii_digit() {
+            chars[part] = version_char(number);
+            part += 1;
+            number = 0;
+            //anything but a dot ends the numeric part, e.g. 1.0.0-beta
+            if i < bytes.len() && bytes[i] != b'.' {
+                break;
+            }
+        } else {
+            number = number
+                .saturating_mul(10)
+                .saturating_add((bytes[i] - b'0') as u32);
+        }
+        i += 1;
+    }
+    chars
+}
+
+//encode a version number as one character, 0-9 then A-Z, clamped to Z
+const fn version_char(number: u32) -> u8 {
+    match number {
+        0..=9 => b'0' + number as u8,
+        10..=35 => b'A' + (number - 10) as u8,
+        _ => b'Z',
+    }
+}
+
+//20 byte id we identify ourselves with to trackers and peers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerId(pub [u8; 20]);
+
+impl PeerId {
+    //generate an id starting with prefix, the rest is random
+    //the random bytes are printable so the id survives trackers that treat it as text
+    //a prefix longer than 20 bytes is cut off
+    pub fn generate(prefix: &[u8]) -> Self {
+        let mut id = [0u8; 20];
+        let prefix_len = prefix.len().min(id.len());
+        id[..prefix_len].copy_from_slice(&prefix[..prefix_len]);
+
+        let mut rng = rng();
+        for byte in &mut id[prefix_len..] {
+            *byte = rng.random_range(33..=126);
+        }
+        Self(id)
+    }
+
+    //start building an id with a custom client code, version or a verbatim value
+    pub fn builder() -> PeerIdBuilder {
+        PeerIdBuilder::new()
+    }
 
-    //client identifier part
-    id[0] = b'-';
-    id[1] = b'M';
-    id[2] = b'S';
+    //get the raw bytes of the id
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+}
+
+//builds a peer id, Azureus-style from a client code and version unless given verbatim
+//-XXVVVV-[12 random bytes], e.g. -MS0100-
+#[derive(Debug, Clone)]
+pub struct PeerIdBuilder {
+    client_code: String,       //two letter client identifier, checked when building
+    version: String,           //four version characters, checked when building
+    verbatim: Option<Vec<u8>>, //full peer id used as is, e.g. for trackers that whitelist clients
+}
+
+impl Default for PeerIdBuilder {
+    fn default() -> Self {
+        Self {
+            client_code: DEFAULT_CLIENT_CODE.to_string(),
+            version: DEFAULT_VERSION.to_string(),
+            verbatim: None,
+        }
+    }
+}
+
+impl PeerIdBuilder {
+    //start building a peer id with our own client code and version
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //set the two letter client code
+    pub fn client_code(mut self, client_code: &str) -> Self {
+        self.client_code = client_code.to_string();
+        self
+    }
+
+    //set the four version characters
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
 
-    //version (v1.0.0)
-    id[3] = b'0';
-    id[4] = b'1';
-    id[5] = b'0';
-    id[6] = b'0';
+    //use a full 20 byte peer id as is instead of generating one
+    pub fn verbatim(mut self, peer_id: &[u8]) -> Self {
+        self.verbatim = Some(peer_id.to_vec());
+        self
+    }
 
-    //separator
-    id[7] = b'-';
+    //build a peer id, failing if a part has the wrong length or characters
+    //every call gives new random bytes, so keeping the builder allows rotating the id
+    pub fn build(&self) -> Result<PeerId, PeerIdError> {
+        if let Some(verbatim) = &self.verbatim {
+            return verbatim
+                .as_slice()
+                .try_into()
+                .map(PeerId)
+                .map_err(|_| PeerIdError::Length(verbatim.len()));
+        }
+        if !is_id_part(&self.client_code, 2) {
+            return Err(PeerIdError::ClientCode(self.client_code.clone()));
+        }
+        if !is_id_part(&self.version, 4) {
+            return Err(PeerIdError::Version(self.version.clone()));
+        }
 
-    //random bytes
-    let mut rng = rng();
-    for i in 8..20 {
-        id[i] = rng.random_range(33..=126);
+        let prefix = format!("-{}{}-", self.client_code, self.version);
+        Ok(PeerId::generate(prefix.as_bytes()))
     }
+}
+
+//check that part is len ASCII letters or digits
+fn is_id_part(part: &str, len: usize) -> bool {
+    part.len() == len && part.bytes().all(|b| b.is_ascii_alphanumeric())
+}
 
-    id
+//peer id of the default session, generated once per process
+//sessions that want their own id, or one per torrent, generate it with PeerId::generate
+static PEER_ID: Lazy<PeerId> = Lazy::new(|| {
+    PeerIdBuilder::new()
+        .build()
+        .expect("default client code and version are valid")
 });
 
-//get peer id
-pub fn get_peer_id() -> &'static [u8; 20] {
+//get the peer id of the default session
+pub fn get_peer_id() -> &'static PeerId {
     &PEER_ID
 }
+
+//static tracker key that gets generated once per client session
+static TRACKER_KEY: Lazy<String> = Lazy::new(|| {
+    //random 32-bit value, hex-encoded so it is safe to put in a URL as is
+    let key: u32 = rng().random();
+    format!("{:08X}", key)
+});
+
+//get tracker key
+pub fn get_tracker_key() -> &'static str {
+    &TRACKER_KEY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //check that the random part of id is printable ASCII
+    fn is_printable(id: &PeerId) -> bool {
+        id.0[8..].iter().all(|b| (33..=126).contains(b))
+    }
+
+    #[test]
+    fn default_ids_use_the_motteseed_prefix() {
+        let id = get_peer_id();
+        assert_eq!(&id.0[..8], b"-MS0100-");
+        assert!(is_printable(id));
+        assert_eq!(get_peer_id(), id);
+        assert_eq!(&PeerIdBuilder::new().build().unwrap().0[..8], b"-MS0100-");
+    }
+
+    #[test]
+    fn prefixes_can_be_chosen() {
+        let id = PeerIdBuilder::new()
+            .client_code("qB")
+            .version("4610")
+            .build()
+            .unwrap();
+        assert_eq!(&id.0[..8], b"-qB4610-");
+        assert!(is_printable(&id));
+
+        //a verbatim id wins over the prefix
+        let verbatim = *b"-TR3000-abcdefghijkl";
+        let id = PeerIdBuilder::new()
+            .client_code("x")
+            .verbatim(&verbatim)
+            .build();
+        assert_eq!(id, Ok(PeerId(verbatim)));
+    }
+
+    #[test]
+    fn bad_prefixes_are_refused() {
+        for code in ["M", "MSS", "M-", "é"] {
+            let id = PeerIdBuilder::new().client_code(code).build();
+            assert_eq!(id, Err(PeerIdError::ClientCode(code.into())));
+        }
+        for version in ["010", "01 0", "01000"] {
+            let id = PeerIdBuilder::new().version(version).build();
+            assert_eq!(id, Err(PeerIdError::Version(version.into())));
+        }
+        let err = PeerIdBuilder::new().verbatim(b"short").build().unwrap_err();
+        assert_eq!(err, PeerIdError::Length(5));
+        assert_eq!(
+            err.to_string(),
+            "Invalid peer id length 5, expected 20 bytes"
+        );
+    }
+
+    #[test]
+    fn version_comes_from_the_crate_version() {
+        let version = env!("CARGO_PKG_VERSION");
+        let expected: String = version
+            .split(['-', '+'])
+            .next()
+            .unwrap()
+            .split('.')
+            .map(|part| {
+                let number: u32 = part.parse().unwrap();
+                char::from_digit(number.min(35), 36)
+                    .unwrap()
+                    .to_ascii_uppercase()
+            })
+            .chain(std::iter::repeat('0'))
+            .take(4)
+            .collect();
+        assert_eq!(DEFAULT_VERSION, expected);
+        assert_eq!(
+            &get_peer_id().0[..8],
+            format!("-MS{}-", expected).as_bytes()
+        );
+    }
+
+    #[test]
+    fn versions_are_encoded_one_character_per_part() {
+        for (version, chars) in [
+            ("0.1.0", b"0100"),
+            ("1.4.12", b"14C0"),
+            ("1.4", b"1400"),
+            ("2", b"2000"),
+            ("1.35.36", b"1ZZ0"),
+            ("99999999999.0.0", b"Z000"),
+            ("1.2.3-beta.4", b"1230"),
+            ("1.2-rc1", b"1200"),
+            ("1.2.3+build.9", b"1230"),
+            ("", b"0000"),
+        ] {
+            assert_eq!(&version_chars(version), chars, "{}", version);
+        }
+    }
+
+    #[test]
+    fn generated_ids_are_distinct() {
+        //two sessions of one process generate their own ids
+        let (a, b) = (PeerId::generate(b"-MS0100-"), PeerId::generate(b"-MS0100-"));
+        assert_ne!(a, b);
+        assert_eq!(&a.0[..8], b"-MS0100-");
+        assert_ne!(&a, get_peer_id());
+
+        //a kept builder rotates the id on every build, a verbatim id never changes
+        let builder = PeerId::builder().client_code("UT").version("3550");
+        let (c, d) = (builder.build().unwrap(), builder.build().unwrap());
+        assert_ne!(c, d);
+        assert_eq!(c.0[..8], d.0[..8]);
+        let verbatim = PeerId::builder().verbatim(&[7; 20]);
+        assert_eq!(verbatim.build(), verbatim.build());
+
+        assert_eq!(PeerId::generate(&[b'x'; 30]).0, [b'x'; 20]);
+        assert!(is_printable(&PeerId::generate(b"")));
+    }
+}
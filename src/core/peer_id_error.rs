@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+//custom error enum for building peer ids
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PeerIdError {
+    //client code that is not two ASCII letters or digits
+    #[error("Invalid client code {0:?}, expected two ASCII letters or digits")]
+    ClientCode(String),
+
+    //version that is not four ASCII letters or digits
+    #[error("Invalid version {0:?}, expected four ASCII letters or digits")]
+    Version(String),
+
+    //verbatim peer id of the wrong length, with the length given
+    #[error("Invalid peer id length {0}, expected 20 bytes")]
+    Length(usize),
+}
@@ -0,0 +1,93 @@
+use crate::core::session::progress::TorrentState;
+
+use std::net::SocketAddr;
+
+//events a subscriber of a session may fall behind by before it loses the oldest ones
+pub const EVENT_CAPACITY: usize = 1024;
+
+//something that happened to a torrent of a session, sent to every subscriber of the session
+//every event names the torrent by its info hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    //the torrent was added and is about to start
+    TorrentAdded {
+        info_hash: [u8; 20],
+        name: String,
+    },
+
+    //the metadata of a torrent added from a magnet link was found
+    MetadataReceived {
+        info_hash: [u8; 20],
+    },
+
+    //a downloaded piece passed its hash check
+    PieceVerified {
+        info_hash: [u8; 20],
+        index: usize,
+    },
+
+    //every piece is downloaded and verified, sent after the PieceVerified of the last one
+    TorrentCompleted {
+        info_hash: [u8; 20],
+    },
+
+    //an announce to tracker got an answer if ok, a failed one is followed by a TrackerError
+    //seeders and leechers are None if the tracker did not count them
+    TrackerAnnounce {
+        info_hash: [u8; 20],
+        tracker: String,
+        ok: bool,
+        peers: usize,
+        seeders: Option<u64>,
+        leechers: Option<u64>,
+    },
+
+    //why an announce to tracker failed
+    TrackerError {
+        info_hash: [u8; 20],
+        tracker: String,
+        message: String,
+    },
+
+    //a peer connected to us and exchanged handshakes for the torrent
+    PeerConnected {
+        info_hash: [u8; 20],
+        addr: SocketAddr,
+    },
+
+    //a connection to a peer was closed
+    PeerDisconnected {
+        info_hash: [u8; 20],
+        addr: SocketAddr,
+    },
+
+    //the torrent went from one state to another, e.g. Stopped once its seed policy is met
+    StateChanged {
+        info_hash: [u8; 20],
+        state: TorrentState,
+    },
+
+    //the torrent stopped running because of the failure with this message
+    Error {
+        info_hash: [u8; 20],
+        message: String,
+    },
+}
+
+impl Event {
+    //get the info hash of the torrent the event is about
+    pub fn info_hash(&self) -> &[u8; 20] {
+        match self {
+            Event::TorrentAdded { info_hash, .. }
+            | Event::MetadataReceived { info_hash }
+            | Event::PieceVerified { info_hash, .. }
+            | Event::TorrentCompleted { info_hash }
+            | Event::TrackerAnnounce { info_hash, .. }
+            | Event::TrackerError { info_hash, .. }
+            | Event::PeerConnected { info_hash, .. }
+            | Event::PeerDisconnected { info_hash, .. }
+            | Event::StateChanged { info_hash, .. }
+            | Event::Error { info_hash, .. } => info_hash,
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use thiserror::Error;
+
+//custom error enum for listening for incoming peer connections
+#[derive(Error, Debug)]
+pub enum ListenError {
+    //every port of the configured range is taken or may not be used
+    #[error("no port from {first} to {last} is free to listen on")]
+    NoFreePort {
+        first: u16,
+        last: u16,
+        #[source]
+        source: std::io::Error,
+    },
+}
@@ -0,0 +1,78 @@
+use crate::core::session::listen_error::ListenError;
+use crate::core::tracker::tracker::DEFAULT_PORT;
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use tokio::net::TcpListener;
+use tracing::debug;
+
+//ports tried for incoming connections unless configured otherwise, the range clients commonly use
+pub const DEFAULT_PORTS: RangeInclusive<u16> = DEFAULT_PORT..=6889;
+
+//listen for incoming peer connections on the first port of ports that is free
+//a range of just 0 lets the system pick a port, the port that was bound is the listener's local_addr
+pub async fn bind_listener(ports: RangeInclusive<u16>) -> Result<TcpListener, ListenError> {
+    let mut last_error = None;
+    for port in ports.clone() {
+        match TcpListener::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))).await {
+            Ok(listener) => return Ok(listener),
+            //another client owns the port, the next one may be free
+            Err(e) => {
+                debug!(port, error = %e, "cannot listen on port");
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(ListenError::NoFreePort {
+        first: *ports.start(),
+        last: *ports.end(),
+        source: last_error.unwrap_or_else(|| io::Error::other("the port range is empty")),
+    })
+}
+
+//parse a port such as "6881" or a range such as "6881-6889"
+//None if a port does not fit in u16, or a range is empty or includes 0
+pub fn parse_ports(text: &str) -> Option<RangeInclusive<u16>> {
+    let text = text.trim();
+    let Some((first, last)) = text.split_once('-') else {
+        let port = text.parse().ok()?;
+        return Some(port..=port);
+    };
+    let first: u16 = first.trim().parse().ok()?;
+    let last: u16 = last.trim().parse().ok()?;
+    (first != 0 && first <= last).then_some(first..=last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_and_ranges_parse() {
+        assert_eq!(parse_ports("6881"), Some(6881..=6881));
+        assert_eq!(parse_ports("6881-6889"), Some(6881..=6889));
+        assert_eq!(parse_ports(" 7000 - 7001 "), Some(7000..=7001));
+        assert_eq!(parse_ports("0"), Some(0..=0));
+        assert_eq!(parse_ports("6889-6881"), None);
+        assert_eq!(parse_ports("0-10"), None);
+        assert_eq!(parse_ports("70000"), None);
+        assert_eq!(parse_ports("any"), None);
+    }
+
+    #[tokio::test]
+    async fn port_zero_lets_the_system_pick() {
+        let listener = bind_listener(0..=0).await.unwrap();
+        assert_ne!(listener.local_addr().unwrap().port(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_range_without_free_ports_is_an_error() {
+        let busy = std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let port = busy.local_addr().unwrap().port();
+        let err = bind_listener(port..=port).await.unwrap_err();
+        assert!(
+            matches!(err, ListenError::NoFreePort { first, last, .. } if first == port && last == port)
+        );
+    }
+}
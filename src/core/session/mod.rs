@@ -0,0 +1,13 @@
+pub mod event;
+pub mod listen_error;
+pub mod listener;
+pub mod progress;
+pub mod resume;
+pub mod seed_policy;
+pub mod session;
+pub mod session_config;
+pub mod session_error;
+pub mod torrent_handle;
+mod torrent_task;
+mod upload;
+pub mod watch_dir;
@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+//transfer rates are averaged over this long, so a single slow or fast piece does not swing them
+pub const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+//what a torrent is busy with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TorrentState {
+    Checking,    //hashing data left on disk by an earlier run
+    Downloading, //fetching the pieces that are missing
+    Seeding,     //complete and serving the data to others
+    Paused,      //held by its handle, out of the swarm until resumed
+    Stopped,     //done seeding, out of the swarm
+}
+
+impl fmt::Display for TorrentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TorrentState::Checking => "checking",
+            TorrentState::Downloading => "downloading",
+            TorrentState::Seeding => "seeding",
+            TorrentState::Paused => "paused",
+            TorrentState::Stopped => "stopped",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//state of a torrent at one moment, taken for display
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub state: TorrentState,
+    pub total_bytes: u64,       //size of the torrent data
+    pub checked_bytes: u64,     //bytes hashed so far while checking
+    pub verified_bytes: u64,    //bytes in pieces that passed their hash check
+    pub downloaded: u64,        //bytes downloaded since the torrent was started
+    pub uploaded: u64,          //bytes uploaded since the torrent was started
+    pub download_rate: u64,     //bytes per second over the last RATE_WINDOW
+    pub upload_rate: u64,       //bytes per second over the last RATE_WINDOW
+    pub peers: usize,           //peers of the swarm known so far
+    pub elapsed: Duration,      //time since the torrent was started
+    pub seeding_time: Duration, //time spent seeding, zero until the torrent is complete
+}
+
+impl Progress {
+    //get the percentage of the current state that is done, hashed bytes while checking and verified ones otherwise
+    pub fn percent(&self) -> f64 {
+        let done = match self.state {
+            TorrentState::Checking => self.checked_bytes,
+            _ => self.verified_bytes,
+        };
+        if self.total_bytes == 0 {
+            return 100.0;
+        }
+        done as f64 * 100.0 / self.total_bytes as f64
+    }
+
+    //get the time until the download is complete at the current rate, None if it can not be told
+    pub fn eta(&self) -> Option<Duration> {
+        if self.state != TorrentState::Downloading || self.download_rate == 0 {
+            return None;
+        }
+        let left = self.total_bytes.saturating_sub(self.verified_bytes);
+        Some(Duration::from_secs(left.div_ceil(self.download_rate)))
+    }
+
+    //get the share ratio, bytes uploaded per byte downloaded
+    //a torrent that was complete from the start is measured against its size instead
+    pub fn ratio(&self) -> f64 {
+        let base = match self.downloaded {
+            0 => self.total_bytes,
+            downloaded => downloaded,
+        };
+        if base == 0 {
+            return 0.0;
+        }
+        self.uploaded as f64 / base as f64
+    }
+
+    //get the average download rate in bytes per second since the torrent was started
+    pub fn average_download_rate(&self) -> u64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0;
+        }
+        (self.downloaded as f64 / secs) as u64
+    }
+}
+
+//counts the transfer of a torrent as it happens and hands out Progress snapshots
+#[derive(Debug)]
+pub struct ProgressTracker {
+    state: TorrentState,
+    total_bytes: u64,
+    checked_bytes: u64,
+    verified_bytes: u64,
+    downloaded: u64,
+    uploaded: u64,
+    peers: usize,
+    started: Instant,                       //when the torrent was started
+    seeding_since: Option<Instant>,         //when the torrent started seeding
+    samples: VecDeque<(Instant, u64, u64)>, //download and upload totals at recent points in time, oldest first
+}
+
+impl ProgressTracker {
+    //start tracking a torrent of total_bytes, which has nothing verified yet
+    pub fn new(total_bytes: u64) -> Self {
+        Self {
+            state: TorrentState::Downloading,
+            total_bytes,
+            checked_bytes: 0,
+            verified_bytes: 0,
+            downloaded: 0,
+            uploaded: 0,
+            peers: 0,
+            started: Instant::now(),
+            seeding_since: None,
+            samples: VecDeque::new(),
+        }
+    }
+
+    //get what the torrent is busy with
+    pub fn state(&self) -> TorrentState {
+        self.state
+    }
+
+    //switch to state, checking starts over with nothing hashed
+    pub fn set_state(&mut self, state: TorrentState) {
+        if state == TorrentState::Checking {
+            self.checked_bytes = 0;
+        }
+        //the rate of a download starts from the moment it starts
+        if state == TorrentState::Downloading {
+            self.record(Instant::now());
+        }
+        //a torrent resumed after a pause keeps seeding since it started to
+        if state == TorrentState::Seeding && self.seeding_since.is_none() {
+            self.seeding_since = Some(Instant::now());
+        }
+        self.state = state;
+    }
+
+    //count a piece of bytes that was hashed while checking
+    pub fn checked(&mut self, bytes: u64) {
+        self.checked_bytes += bytes;
+    }
+
+    //count a piece of bytes already on disk that passed its hash check
+    pub fn verified(&mut self, bytes: u64) {
+        self.verified_bytes += bytes;
+    }
+
+    //count a piece of bytes that was downloaded and passed its hash check
+    pub fn downloaded(&mut self, bytes: u64) {
+        self.downloaded += bytes;
+        self.verified_bytes += bytes;
+        self.record(Instant::now());
+    }
+
+    //count a block of bytes that was uploaded to a peer
+    pub fn uploaded(&mut self, bytes: u64) {
+        self.uploaded += bytes;
+        self.record(Instant::now());
+    }
+
+    //set the number of peers of the swarm known so far
+    pub fn set_peers(&mut self, peers: usize) {
+        self.peers = peers;
+    }
+
+    //take a snapshot of the progress so far
+    pub fn snapshot(&self) -> Progress {
+        self.snapshot_at(Instant::now())
+    }
+
+    //take a snapshot as of now, which is passed in so rates do not depend on when this runs
+    fn snapshot_at(&self, now: Instant) -> Progress {
+        Progress {
+            state: self.state,
+            total_bytes: self.total_bytes,
+            checked_bytes: self.checked_bytes,
+            verified_bytes: self.verified_bytes,
+            downloaded: self.downloaded,
+            uploaded: self.uploaded,
+            download_rate: self.rate(now, self.downloaded, |&(_, downloaded, _)| downloaded),
+            upload_rate: self.rate(now, self.uploaded, |&(_, _, uploaded)| uploaded),
+            peers: self.peers,
+            elapsed: now.duration_since(self.started),
+            seeding_time: self
+                .seeding_since
+                .map_or(Duration::ZERO, |since| now.duration_since(since)),
+        }
+    }
+
+    //remember the transfer totals at now, keeping the newest sample older than the window as a baseline
+    fn record(&mut self, now: Instant) {
+        self.samples
+            .push_back((now, self.downloaded, self.uploaded));
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|&(time, _, _)| now.duration_since(time) >= RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    //bytes per second moved since the oldest sample that is still needed for the window,
+    //total is the count so far and bytes picks the count of a sample
+    fn rate(&self, now: Instant, total: u64, bytes: impl Fn(&(Instant, u64, u64)) -> u64) -> u64 {
+        let baseline = self
+            .samples
+            .iter()
+            .rev()
+            .find(|&&(time, _, _)| now.duration_since(time) >= RATE_WINDOW)
+            .or(self.samples.front());
+        let Some(sample) = baseline else {
+            return 0;
+        };
+        let secs = now.duration_since(sample.0).as_secs_f64();
+        if secs == 0.0 {
+            return 0;
+        }
+        ((total - bytes(sample)) as f64 / secs) as u64
+    }
+}
@@ -0,0 +1,230 @@
+use crate::core::storage::storage::Storage;
+use crate::core::storage::storage_error::StorageError;
+use crate::core::torrent::torrent::Info;
+use crate::util::bencode::bencode_decodable::BencodeDecodable;
+use crate::util::bencode::bencode_decodable_error::BencodeDecodableError;
+use crate::util::bencode::bencode_encodable::{BencodeEncodable, encode_dict};
+use crate::util::bencode::parser::{Node, parse};
+use crate::util::hex;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+//state of a torrent saved when it stops, so the next start knows which pieces it has
+//without hashing all the data again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeData {
+    pub info_hash: [u8; 20], //torrent the data belongs to
+    pub pieces: Vec<bool>,   //whether each piece is complete and verified, by piece index
+    pub downloaded: u64,     //bytes downloaded over all runs
+    pub uploaded: u64,       //bytes uploaded over all runs
+}
+
+impl ResumeData {
+    //collect the pieces of info that storage has, pushing buffered data down first
+    //so the pieces recorded are on disk when the file is written
+    pub fn from_storage<S: Storage>(
+        info_hash: &[u8; 20],
+        info: &Info,
+        storage: &mut S,
+    ) -> Result<Self, StorageError> {
+        storage.flush()?;
+        Ok(Self {
+            info_hash: *info_hash,
+            pieces: (0..info.num_pieces())
+                .map(|index| storage.have(index))
+                .collect(),
+            downloaded: 0,
+            uploaded: 0,
+        })
+    }
+
+    //get the location of the resume data of info_hash in dir
+    pub fn path(dir: &Path, info_hash: &[u8; 20]) -> PathBuf {
+        dir.join(format!("{}.resume", hex::encode(info_hash)))
+    }
+
+    //write the resume data into dir, creating dir if needed
+    //the file is replaced in one rename, so a crash while saving leaves the previous one intact
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = Self::path(dir, &self.info_hash);
+        let partial = path.with_extension("resume.part");
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(&self.encode())?;
+        file.sync_all()?;
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    //read the resume data of info_hash from dir, None if there is none
+    //data that is unreadable, damaged or does not fit the torrent is ignored with a warning,
+    //the torrent is then checked like one without resume data
+    pub fn load(dir: &Path, info_hash: &[u8; 20], info: &Info) -> Option<Self> {
+        let path = Self::path(dir, info_hash);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "cannot read resume data");
+                return None;
+            }
+        };
+        let decoded = parse(&bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|node| Self::decode(&node).map_err(|e| e.to_string()));
+        match decoded {
+            Ok(data) if data.info_hash == *info_hash && data.pieces.len() == info.num_pieces() => {
+                Some(data)
+            }
+            Ok(_) => {
+                warn!(path = %path.display(), "resume data is for another torrent, ignoring it");
+                None
+            }
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "damaged resume data, ignoring it");
+                None
+            }
+        }
+    }
+
+    //get the number of complete pieces
+    pub fn have_count(&self) -> usize {
+        self.pieces.iter().filter(|&&have| have).count()
+    }
+}
+
+//keep a copy of a torrent file next to its resume data, so a restarted session finds its torrents again
+pub fn save_torrent_file(dir: &Path, info_hash: &[u8; 20], bytes: &[u8]) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = saved_torrent_path(dir, info_hash);
+    fs::write(&path, bytes)?;
+    Ok(path)
+}
+
+//get the location of the torrent file of info_hash saved in dir by save_torrent_file
+pub fn saved_torrent_path(dir: &Path, info_hash: &[u8; 20]) -> PathBuf {
+    dir.join(format!("{}.torrent", hex::encode(info_hash)))
+}
+
+//get the torrent files saved in dir by save_torrent_file, sorted by name
+//a missing dir means nothing was saved yet
+pub fn saved_torrent_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "torrent") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+//pieces are stored as a bitfield, highest bit of the first byte for piece 0 like the peer protocol
+impl BencodeEncodable for ResumeData {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        let mut bitfield = vec![0u8; self.pieces.len().div_ceil(8)];
+        for (index, _) in self.pieces.iter().enumerate().filter(|(_, have)| **have) {
+            bitfield[index / 8] |= 0x80 >> (index % 8);
+        }
+        let piece_count = self.pieces.len();
+        encode_dict(
+            w,
+            [
+                (
+                    b"downloaded".as_slice(),
+                    &self.downloaded as &dyn BencodeEncodable,
+                ),
+                (b"info hash".as_slice(), &self.info_hash),
+                (b"piece count".as_slice(), &piece_count),
+                (b"pieces".as_slice(), &bitfield),
+                (b"uploaded".as_slice(), &self.uploaded),
+            ],
+        )
+    }
+}
+
+impl<'a> BencodeDecodable<'a> for ResumeData {
+    fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError> {
+        let dict = Self::get_struct(b)?;
+        let info_hash = Self::get_str(Self::get_struct_value("info hash", dict)?)?;
+        let info_hash = info_hash.try_into().map_err(|_| {
+            BencodeDecodableError::WrongType(format!(
+                "expected a 20 byte info hash, got {} bytes",
+                info_hash.len()
+            ))
+        })?;
+        let piece_count = Self::get_u64(Self::get_struct_value("piece count", dict)?)? as usize;
+        let bitfield = Self::get_str(Self::get_struct_value("pieces", dict)?)?;
+        if bitfield.len() != piece_count.div_ceil(8) {
+            return Err(BencodeDecodableError::WrongType(format!(
+                "expected a bitfield of {} pieces, got {} bytes",
+                piece_count,
+                bitfield.len()
+            )));
+        }
+        let pieces = (0..piece_count)
+            .map(|index| bitfield[index / 8] & (0x80 >> (index % 8)) != 0)
+            .collect();
+
+        Ok(Self {
+            info_hash,
+            pieces,
+            downloaded: Self::get_optional_u64("downloaded", dict)?.unwrap_or(0),
+            uploaded: Self::get_optional_u64("uploaded", dict)?.unwrap_or(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::storage::memory_storage::MemoryStorage;
+    use crate::core::torrent::torrent::TorrentFile;
+
+    use sha1::{Digest, Sha1};
+
+    #[test]
+    fn saved_data_loads_back() {
+        //three pieces of 16 bytes, the last one short
+        let data: Vec<u8> = (0..40).collect();
+        let hashes: Vec<u8> = data.chunks(16).flat_map(Sha1::digest).collect();
+        let mut bytes = b"d4:infod6:lengthi40e4:name1:f12:piece lengthi16e6:pieces60:".to_vec();
+        bytes.extend_from_slice(&hashes);
+        bytes.extend_from_slice(b"ee");
+        let torrent_file = TorrentFile::from_bytes(bytes).unwrap();
+        let torrent = torrent_file.torrent();
+        let info = &torrent.info;
+
+        let mut storage = MemoryStorage::empty(info);
+        storage.write_block(0, 0, &data[..16]).unwrap();
+        storage.write_block(2, 0, &data[32..]).unwrap();
+        let mut resume = ResumeData::from_storage(&torrent.info_hash, info, &mut storage).unwrap();
+        assert_eq!(resume.pieces, [true, false, true]);
+        resume.downloaded = 24;
+
+        let dir = std::env::temp_dir().join(format!("motteseed-resume-{}", std::process::id()));
+        let path = resume.save(&dir).unwrap();
+        assert_eq!(path, ResumeData::path(&dir, &torrent.info_hash));
+        assert_eq!(
+            ResumeData::load(&dir, &torrent.info_hash, info),
+            Some(resume.clone())
+        );
+        assert_eq!(resume.have_count(), 2);
+
+        //resume data of another torrent, or a damaged file, is not used
+        assert_eq!(ResumeData::load(&dir, &[0; 20], info), None);
+        fs::write(&path, b"d6:piecese").unwrap();
+        assert_eq!(ResumeData::load(&dir, &torrent.info_hash, info), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use crate::core::session::progress::Progress;
+
+use std::time::Duration;
+
+//share ratio a torrent seeds up to unless configured otherwise
+pub const DEFAULT_SEED_RATIO: f64 = 1.0;
+
+//when a complete torrent stops seeding, whichever limit is met first ends it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeedPolicy {
+    pub ratio: Option<f64>, //stop once the share ratio reaches this, None for no limit
+    pub time: Option<Duration>, //stop after seeding this long, None for no limit
+}
+
+impl Default for SeedPolicy {
+    fn default() -> Self {
+        Self {
+            ratio: Some(DEFAULT_SEED_RATIO),
+            time: None,
+        }
+    }
+}
+
+impl SeedPolicy {
+    //seed until the torrent or the session is stopped
+    pub const FOREVER: Self = Self {
+        ratio: None,
+        time: None,
+    };
+
+    //check if a torrent that is seeding with progress should stop
+    pub fn is_met(&self, progress: &Progress) -> bool {
+        self.ratio.is_some_and(|ratio| progress.ratio() >= ratio)
+            || self.time.is_some_and(|time| progress.seeding_time >= time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::session::progress::{ProgressTracker, TorrentState};
+
+    #[test]
+    fn seeding_stops_at_the_ratio() {
+        let mut tracker = ProgressTracker::new(1000);
+        tracker.downloaded(1000);
+        tracker.set_state(TorrentState::Seeding);
+        let policy = SeedPolicy::default();
+        assert!(!policy.is_met(&tracker.snapshot()));
+        tracker.uploaded(999);
+        assert!(!policy.is_met(&tracker.snapshot()));
+        tracker.uploaded(1);
+        assert!(policy.is_met(&tracker.snapshot()));
+        assert!(!SeedPolicy::FOREVER.is_met(&tracker.snapshot()));
+
+        //a torrent that was complete from the start measures the ratio against its size
+        let mut tracker = ProgressTracker::new(1000);
+        tracker.verified(1000);
+        tracker.set_state(TorrentState::Seeding);
+        tracker.uploaded(500);
+        let half = SeedPolicy {
+            ratio: Some(0.5),
+            time: None,
+        };
+        assert!(half.is_met(&tracker.snapshot()));
+    }
+
+    #[test]
+    fn seeding_stops_after_the_time() {
+        let mut tracker = ProgressTracker::new(1000);
+        tracker.verified(1000);
+        tracker.set_state(TorrentState::Seeding);
+        let mut progress = tracker.snapshot();
+        let policy = SeedPolicy {
+            ratio: None,
+            time: Some(Duration::from_secs(3600)),
+        };
+        assert!(!policy.is_met(&progress));
+        progress.seeding_time = Duration::from_secs(3600);
+        assert!(policy.is_met(&progress));
+    }
+}
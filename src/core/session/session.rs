@@ -0,0 +1,1582 @@
+use crate::core::lsd::lsd::LocalDiscovery;
+use crate::core::peer::connection::PeerConnection;
+use crate::core::peer::message::Handshake;
+use crate::core::peer::peer::Peer;
+use crate::core::session::event::{EVENT_CAPACITY, Event};
+use crate::core::session::listen_error::ListenError;
+use crate::core::session::listener::bind_listener;
+use crate::core::session::progress::ProgressTracker;
+use crate::core::session::resume::{ResumeData, saved_torrent_path};
+use crate::core::session::session_config::{SessionConfig, TorrentConfig};
+use crate::core::session::session_error::SessionError;
+use crate::core::session::torrent_handle::TorrentHandle;
+use crate::core::session::torrent_task::{Control, Reporter, TorrentTask};
+use crate::core::storage::file_storage::{FileStorage, part_path};
+use crate::core::torrent::magnet::magnet_info_hash;
+use crate::core::torrent::torrent::{Info, TorrentFile};
+use crate::core::tracker::tracker::{TrackerRequest, TrackerRequestBuilder};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc, watch};
+use tracing::{debug, info, warn};
+
+//torrents running with the settings of a session, which stop together when it shuts down
+#[derive(Debug)]
+pub struct Session {
+    state: Arc<SessionState>,       //shared with the tasks of the torrents
+    local_addr: Option<SocketAddr>, //address peers connect to, None if the session does not listen
+}
+
+//the part of a session its torrents run with
+#[derive(Debug)]
+pub(crate) struct SessionState {
+    pub config: SessionConfig,          //settings shared by every torrent
+    pub port: u16,                      //port announced to trackers and peers
+    stop: watch::Sender<bool>, //set once the session shuts down, every torrent holds a receiver
+    events: broadcast::Sender<Event>, //what happens to the torrents, for every subscriber
+    torrents: Mutex<Vec<TorrentEntry>>, //torrents of the session in the order they were added
+    lsd: Option<mpsc::UnboundedSender<LsdCommand>>, //local service discovery, None if it is off
+    listening: bool,           //peers can connect to the session
+    incoming: Mutex<HashMap<[u8; 20], mpsc::UnboundedSender<PeerConnection>>>, //torrents that take peer connections
+}
+
+//the peer connections a torrent takes from the listener of its session, until it is dropped
+#[derive(Debug)]
+pub(crate) struct Incoming {
+    state: Arc<SessionState>,
+    info_hash: [u8; 20],
+    connections: mpsc::UnboundedReceiver<PeerConnection>,
+}
+
+//what the local service discovery task of a session is told about its torrents
+#[derive(Debug)]
+enum LsdCommand {
+    //announce the torrent, unless it is private, and send the local peers of it to peers
+    Add {
+        info_hash: [u8; 20],
+        private: bool,
+        peers: mpsc::UnboundedSender<Peer>,
+    },
+    //stop announcing the torrent
+    Remove([u8; 20]),
+}
+
+//a torrent of a session and what it takes to stop it on its own
+#[derive(Debug)]
+struct TorrentEntry {
+    handle: TorrentHandle,
+    torrent_file: Arc<TorrentFile>, //kept to find the data of the torrent when it is removed
+    stop: watch::Sender<bool>,      //set when the torrent is removed, its task holds the receiver
+}
+
+//handed to a running torrent, resolves when its session shuts down or the torrent is removed
+//a torrent stopping for it stops fetching data, saves its resume data with
+//ResumeData::from_storage, which also flushes buffered writes, announces stopped with
+//Tracker::stop, and then drops the signal, which is what Session::shutdown and
+//Session::remove wait for
+#[derive(Debug, Clone)]
+pub struct StopSignal {
+    session: watch::Receiver<bool>,
+    torrent: Option<watch::Receiver<bool>>, //None for signals not tied to a single torrent
+}
+
+impl Session {
+    //create a session whose torrents use config, without listening for incoming connections
+    //the first configured port is announced
+    pub fn new(config: SessionConfig) -> Self {
+        let port = *config.ports.start();
+        Self::with_port(config, port, None)
+    }
+
+    /// Create a session listening on the first free port of `config.ports`, which is then announced.
+    ///
+    /// ```
+    /// use motteseed::{Session, SessionConfig};
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///
+    /// //port 0 lets the system pick one
+    /// let config = SessionConfig {
+    ///     ports: 0..=0,
+    ///     ..SessionConfig::default()
+    /// };
+    /// let session = Session::bind(config).await?;
+    /// println!("listening on port {}", session.port());
+    /// session.shutdown().await;
+    /// # Ok::<(), motteseed::core::session::listen_error::ListenError>(())
+    /// # }).unwrap();
+    /// ```
+    pub async fn bind(config: SessionConfig) -> Result<Self, ListenError> {
+        let listener = bind_listener(config.ports.clone()).await?;
+        let port = listener
+            .local_addr()
+            .map_err(|source| ListenError::NoFreePort {
+                first: *config.ports.start(),
+                last: *config.ports.end(),
+                source,
+            })?;
+        Ok(Self::with_port(config, port.port(), Some(listener)))
+    }
+
+    //local peers can only connect to a session that listens, so only those look for them
+    fn with_port(config: SessionConfig, port: u16, listener: Option<TcpListener>) -> Self {
+        let (stop, _) = watch::channel(false);
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let lsd = (config.lsd && listener.is_some())
+            .then(|| start_lsd(port, stop.subscribe()))
+            .flatten();
+        let local_addr = listener.as_ref().and_then(|l| l.local_addr().ok());
+        let state = Arc::new(SessionState {
+            config,
+            port,
+            stop,
+            events,
+            torrents: Mutex::new(Vec::new()),
+            lsd,
+            listening: listener.is_some(),
+            incoming: Mutex::new(HashMap::new()),
+        });
+        if let Some(listener) = listener {
+            let stop = state.stop.subscribe();
+            tokio::spawn(accept(listener, Arc::downgrade(&state), stop));
+        }
+        Self { state, local_addr }
+    }
+
+    //get the settings shared by the torrents of the session
+    pub fn config(&self) -> &SessionConfig {
+        &self.state.config
+    }
+
+    //get the port the session listens on, the wish of the config if it does not listen
+    pub fn port(&self) -> u16 {
+        self.state.port
+    }
+
+    //get the address peers connect to, None if the session does not listen
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    //start an announce of info_hash to tracker with the peer id, port, address and peer count
+    //of the session, the transfer totals are up to the torrent
+    pub fn announce_request<'a>(
+        &'a self,
+        tracker: &'a [u8],
+        info_hash: &'a [u8; 20],
+    ) -> TrackerRequestBuilder<'a> {
+        self.state.announce_request(tracker, info_hash)
+    }
+
+    /// Start running a torrent with the settings of the session, see [`TorrentHandle`] to follow it.
+    ///
+    /// ```no_run
+    /// use motteseed::{Session, SessionConfig, TorrentFile};
+    /// use std::path::Path;
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///
+    /// let session = Session::new(SessionConfig::default());
+    /// let torrent_file = TorrentFile::from_file(Path::new("debian.iso.torrent"))?;
+    /// let torrent = session.add_torrent(torrent_file)?;
+    /// //runs until it is complete and its seed policy is met
+    /// torrent.wait().await.unwrap()?;
+    /// println!("{}: {:?}", torrent.name(), torrent.progress().state);
+    /// session.shutdown().await;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// # }).unwrap();
+    /// ```
+    pub fn add_torrent(&self, torrent_file: TorrentFile) -> Result<TorrentHandle, SessionError> {
+        self.add_torrent_with(torrent_file, TorrentConfig::default())
+    }
+
+    //start running a torrent with its own settings
+    //a torrent that was added already keeps running and its handle is in the error
+    pub fn add_torrent_with(
+        &self,
+        torrent_file: TorrentFile,
+        torrent_config: TorrentConfig,
+    ) -> Result<TorrentHandle, SessionError> {
+        if self.is_shutting_down() {
+            return Err(SessionError::ShuttingDown);
+        }
+        let mut torrents = self
+            .state
+            .torrents
+            .lock()
+            .expect("torrents are not poisoned");
+        let torrent = torrent_file.torrent();
+        if let Some(entry) = torrents
+            .iter()
+            .find(|entry| entry.handle.info_hash() == &torrent.info_hash)
+        {
+            return Err(SessionError::AlreadyAdded(entry.handle.clone()));
+        }
+
+        let (stop, stop_receiver) = watch::channel(false);
+        let (paused, paused_receiver) = watch::channel(false);
+        let progress = Arc::new(Mutex::new(ProgressTracker::new(
+            torrent.info.total_length(),
+        )));
+        let error = Arc::new(Mutex::new(None));
+        let (info_hash, name) = (torrent.info_hash, torrent.info.name.to_string());
+        let (peers, local_peers) = mpsc::unbounded_channel();
+        if let Some(lsd) = &self.state.lsd {
+            let private = torrent.info.is_private();
+            let _ = lsd.send(LsdCommand::Add {
+                info_hash,
+                private,
+                peers,
+            });
+        }
+        let torrent_file = Arc::new(torrent_file);
+        let task = TorrentTask {
+            session: self.state.clone(),
+            torrent_file: torrent_file.clone(),
+            torrent_config,
+            reporter: Reporter {
+                info_hash,
+                progress: progress.clone(),
+                error: error.clone(),
+                events: self.state.events.clone(),
+            },
+            control: Control {
+                stop: StopSignal {
+                    session: self.state.stop.subscribe(),
+                    torrent: Some(stop_receiver),
+                },
+                paused: paused_receiver,
+            },
+            local_peers,
+        };
+        //sent before the task runs, so it comes before every other event of the torrent
+        let _ = self.state.events.send(Event::TorrentAdded {
+            info_hash,
+            name: name.clone(),
+        });
+        let task = tokio::spawn(task.run());
+        let handle = TorrentHandle::new(info_hash, &name, progress, error, paused, task);
+        torrents.push(TorrentEntry {
+            handle: handle.clone(),
+            torrent_file,
+            stop,
+        });
+        Ok(handle)
+    }
+
+    //start running the torrent of a magnet link (BEP 9)
+    //fetching the metadata from peers is not supported yet, so this only works for torrents whose
+    //torrent file is saved in the resume directory, e.g. by the daemon
+    pub fn add_magnet(&self, link: &str) -> Result<TorrentHandle, SessionError> {
+        let info_hash =
+            magnet_info_hash(link).ok_or_else(|| SessionError::InvalidMagnet(link.to_string()))?;
+        if let Some(handle) = self.get(&info_hash) {
+            return Err(SessionError::AlreadyAdded(handle));
+        }
+        let path = saved_torrent_path(&self.config().resume_dir(), &info_hash);
+        if !path.exists() {
+            return Err(SessionError::NoMetadata(info_hash));
+        }
+        let torrent_file = TorrentFile::from_file(&path)
+            .map_err(|source| SessionError::ReadTorrent { path, source })?;
+        let handle = self.add_torrent(torrent_file)?;
+        let _ = self
+            .state
+            .events
+            .send(Event::MetadataReceived { info_hash });
+        Ok(handle)
+    }
+
+    //get the events of the torrents of the session from now on, see Event
+    //sending never waits for a receiver: one that falls more than EVENT_CAPACITY events behind
+    //gets RecvError::Lagged with the number of events it missed, and goes on with the oldest one kept
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.state.events.subscribe()
+    }
+
+    //get the handle of the torrent with info_hash, None if it is not in the session
+    pub fn get(&self, info_hash: &[u8; 20]) -> Option<TorrentHandle> {
+        self.state
+            .torrents
+            .lock()
+            .expect("torrents are not poisoned")
+            .iter()
+            .find(|entry| entry.handle.info_hash() == info_hash)
+            .map(|entry| entry.handle.clone())
+    }
+
+    //get the handles of the torrents of the session, in the order they were added
+    pub fn list(&self) -> Vec<TorrentHandle> {
+        self.state
+            .torrents
+            .lock()
+            .expect("torrents are not poisoned")
+            .iter()
+            .map(|entry| entry.handle.clone())
+            .collect()
+    }
+
+    //stop the torrent with info_hash and wait until it saved its state and left its swarm,
+    //then forget it, so a restarted session does not run it again
+    //with delete_data its downloaded files and resume data are deleted as well
+    pub async fn remove(
+        &self,
+        info_hash: &[u8; 20],
+        delete_data: bool,
+    ) -> Result<(), SessionError> {
+        let entry = {
+            let mut torrents = self
+                .state
+                .torrents
+                .lock()
+                .expect("torrents are not poisoned");
+            let index = torrents
+                .iter()
+                .position(|entry| entry.handle.info_hash() == info_hash)
+                .ok_or(SessionError::UnknownTorrent(*info_hash))?;
+            torrents.remove(index)
+        };
+        if let Some(lsd) = &self.state.lsd {
+            let _ = lsd.send(LsdCommand::Remove(*info_hash));
+        }
+        entry.stop.send_replace(true);
+        entry.stop.closed().await;
+
+        let resume_dir = self.config().resume_dir();
+        remove_file(&saved_torrent_path(&resume_dir, info_hash))?;
+        //data kept in memory went with the torrent
+        if delete_data && !self.config().in_memory {
+            remove_file(&ResumeData::path(&resume_dir, info_hash))?;
+            delete_data_files(&entry.torrent_file.torrent().info, &self.config().out_dir)?;
+        }
+        Ok(())
+    }
+
+    //get the signal a torrent of the session stops on, held for as long as the torrent runs
+    pub fn stop_signal(&self) -> StopSignal {
+        StopSignal {
+            session: self.state.stop.subscribe(),
+            torrent: None,
+        }
+    }
+
+    //check if the session was asked to shut down
+    pub fn is_shutting_down(&self) -> bool {
+        *self.state.stop.borrow()
+    }
+
+    //ask every torrent to stop, then wait until all of them saved their state and left their swarms
+    //a second call, e.g. from another task, waits for the same torrents
+    pub async fn shutdown(&self) {
+        self.state.stop.send_replace(true);
+        self.state.stop.closed().await;
+    }
+}
+
+impl SessionState {
+    //see Session::announce_request
+    pub fn announce_request<'a>(
+        &'a self,
+        tracker: &'a [u8],
+        info_hash: &'a [u8; 20],
+    ) -> TrackerRequestBuilder<'a> {
+        let mut builder =
+            TrackerRequest::builder(tracker, info_hash, self.config.peer_id.as_bytes())
+                .port(self.port)
+                .numwant(self.config.max_peers);
+        if let Some(ip) = self.config.announce_ip {
+            builder = builder.ip(ip);
+        }
+        builder
+    }
+
+    //check if peers can connect to the session
+    pub fn is_listening(&self) -> bool {
+        self.listening
+    }
+
+    //take the peer connections for info_hash from now on, a session that does not listen has none
+    pub fn incoming(self: &Arc<Self>, info_hash: [u8; 20]) -> Incoming {
+        let (sender, connections) = mpsc::unbounded_channel();
+        if self.listening {
+            self.incoming
+                .lock()
+                .expect("incoming is not poisoned")
+                .insert(info_hash, sender);
+        }
+        Incoming {
+            state: self.clone(),
+            info_hash,
+            connections,
+        }
+    }
+}
+
+impl Incoming {
+    //wait for the next peer that connected for the torrent, None if the session does not listen
+    pub async fn recv(&mut self) -> Option<PeerConnection> {
+        self.connections.recv().await
+    }
+}
+
+impl Drop for Incoming {
+    fn drop(&mut self) {
+        self.state
+            .incoming
+            .lock()
+            .expect("incoming is not poisoned")
+            .remove(&self.info_hash);
+    }
+}
+
+impl StopSignal {
+    //wait until the session shuts down or the torrent is removed, or either is dropped
+    pub async fn stopped(&mut self) {
+        let session = self.session.wait_for(|stop| *stop);
+        match &mut self.torrent {
+            Some(torrent) => {
+                tokio::select! {
+                    _ = session => {}
+                    _ = torrent.wait_for(|stop| *stop) => {}
+                }
+            }
+            None => {
+                let _ = session.await;
+            }
+        }
+    }
+}
+
+//join the local service discovery group announcing port, and run it until stop is set
+//returns None if the group cannot be joined, the session then goes on without it
+fn start_lsd(port: u16, stop: watch::Receiver<bool>) -> Option<mpsc::UnboundedSender<LsdCommand>> {
+    let discovery = LocalDiscovery::new(port)
+        .inspect_err(|e| warn!(error = %e, "local service discovery is off"))
+        .ok()?;
+    let (commands, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(discover(discovery, receiver, stop));
+    Some(commands)
+}
+
+//take the connections of peers to the listener of a session, and hand the ones whose handshake is
+//for a torrent that takes connections to it, answering with our handshake
+async fn accept(listener: TcpListener, state: Weak<SessionState>, mut stop: watch::Receiver<bool>) {
+    loop {
+        let (mut stream, addr) = tokio::select! {
+            _ = stop.wait_for(|stop| *stop) => return,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    debug!(error = %e, "cannot accept a peer");
+                    continue;
+                }
+            },
+        };
+        let state = state.clone();
+        //a slow handshake does not hold up the peers after it
+        tokio::spawn(async move {
+            let theirs = match PeerConnection::read_handshake(&mut stream).await {
+                Ok(handshake) => handshake,
+                Err(e) => {
+                    debug!(peer = %addr, error = %e, "bad handshake");
+                    return;
+                }
+            };
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            let torrent = state
+                .incoming
+                .lock()
+                .expect("incoming is not poisoned")
+                .get(&theirs.info_hash)
+                .cloned();
+            let Some(torrent) = torrent else {
+                debug!(peer = %addr, "peer asked for a torrent we do not serve");
+                return;
+            };
+            let ours = Handshake::new(theirs.info_hash, *state.config.peer_id.as_bytes());
+            match PeerConnection::accept(stream, theirs, &ours).await {
+                Ok(connection) => {
+                    let _ = torrent.send(connection);
+                }
+                Err(e) => debug!(peer = %addr, error = %e, "cannot answer handshake"),
+            }
+        });
+    }
+}
+
+//announce the torrents of a session on the local network and hand them the peers found there
+async fn discover(
+    mut discovery: LocalDiscovery,
+    mut commands: mpsc::UnboundedReceiver<LsdCommand>,
+    mut stop: watch::Receiver<bool>,
+) {
+    let mut torrents: HashMap<[u8; 20], mpsc::UnboundedSender<Peer>> = HashMap::new();
+    loop {
+        tokio::select! {
+            _ = stop.wait_for(|stop| *stop) => return,
+            command = commands.recv() => match command {
+                Some(LsdCommand::Add {
+                    info_hash,
+                    private,
+                    peers,
+                }) => {
+                    if discovery.add_torrent(info_hash, private) {
+                        torrents.insert(info_hash, peers);
+                    }
+                }
+                Some(LsdCommand::Remove(info_hash)) => {
+                    discovery.remove_torrent(&info_hash);
+                    torrents.remove(&info_hash);
+                }
+                None => return,
+            },
+            found = discovery.next_peers() => match found {
+                Ok(found) => {
+                    for local in found {
+                        if let Some(peers) = torrents.get(&local.info_hash) {
+                            let _ = peers.send(local.peer);
+                        }
+                    }
+                }
+                Err(e) => {
+                    info!(error = %e, "local service discovery stopped");
+                    return;
+                }
+            },
+        }
+    }
+}
+
+//delete the file at path, which may be gone already
+fn remove_file(path: &Path) -> Result<(), SessionError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(SessionError::Delete {
+            path: path.to_path_buf(),
+            source: e,
+        }),
+        _ => Ok(()),
+    }
+}
+
+//delete the files of info below dir, and the folders of the torrent that are left empty
+fn delete_data_files(info: &Info, dir: &Path) -> Result<(), SessionError> {
+    let storage = FileStorage::new(info, dir).map_err(SessionError::Storage)?;
+    //a file that was not complete yet is under its part name
+    for (path, _) in storage.files() {
+        remove_file(path)?;
+        remove_file(&part_path(path))?;
+    }
+    for (path, _) in storage.files() {
+        //folders that hold other files are kept, which stops the climb
+        for folder in path.ancestors().skip(1) {
+            if folder == dir || !folder.starts_with(dir) || fs::remove_dir(folder).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::peer::message::{MAX_REQUEST, Message};
+    use crate::core::session::progress::TorrentState;
+    use crate::core::session::resume::ResumeData;
+    use crate::core::session::seed_policy::SeedPolicy;
+    use crate::core::session::torrent_handle::TorrentStatus;
+    use crate::core::storage::memory_storage::MemoryStorage;
+    use crate::core::storage::storage::Storage;
+    use crate::core::torrent::torrent::TorrentFile;
+    use crate::core::tracker::backoff::Backoff;
+    use crate::core::tracker::tracker::{Tracker, TrackerRequest};
+    use crate::core::tracker::tracker_config::TrackerConfig;
+    use crate::util::bencode::bencode_decodable::BencodeDecodable;
+    use crate::util::bencode::parser::parse;
+    use crate::util::test_dir::{temp_dir, temp_path};
+    use crate::util::test_server::{Requests, file_server, http_server, response, serve};
+
+    use sha1::{Digest, Sha1};
+    use std::collections::HashMap;
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn busy_ports_are_skipped_and_the_bound_one_announced() {
+        //another client owns the first port of the default range
+        let _busy = std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, 6881)).unwrap();
+        let session = Session::bind(SessionConfig::default()).await.unwrap();
+        assert_eq!(session.port(), 6882);
+        let addr = session.local_addr().unwrap();
+        assert_eq!(addr.port(), 6882);
+
+        let (port, requests) = http_server(vec![(
+            "HTTP/1.1 200 OK".to_string(),
+            b"d8:intervali1800e5:peers0:e".to_vec(),
+        )])
+        .await;
+        let url = format!("http://127.0.0.1:{}/announce", port);
+        let req = session
+            .announce_request(url.as_bytes(), &[1; 20])
+            .build()
+            .unwrap();
+        Tracker::new(&req).await.unwrap();
+        let requests = requests.lock().unwrap();
+        assert!(
+            requests[0].path.contains("&port=6882&"),
+            "{}",
+            requests[0].path
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_saves_resume_data_and_announces_stopped() {
+        let (port, requests) = http_server(vec![(
+            "HTTP/1.1 200 OK".to_string(),
+            b"d8:intervali1800e5:peers0:e".to_vec(),
+        )])
+        .await;
+        let dir = temp_path("session-resume");
+        let session = Session::new(SessionConfig {
+            out_dir: dir.clone(),
+            ..SessionConfig::default()
+        });
+
+        //a torrent of two pieces that has the first one when the session shuts down
+        let data: Vec<u8> = (0..32).collect();
+        let mut bytes = b"d4:infod6:lengthi32e4:name1:f12:piece lengthi16e6:pieces40:".to_vec();
+        bytes.extend(data.chunks(16).flat_map(Sha1::digest));
+        bytes.extend(b"ee");
+        let torrent_file = TorrentFile::from_bytes(bytes).unwrap();
+        let info_hash = torrent_file.torrent().info_hash;
+
+        let mut stop = session.stop_signal();
+        let resume_dir = session.config().resume_dir();
+        let (started, announced) = oneshot::channel();
+        let torrent = tokio::spawn(async move {
+            let torrent = torrent_file.torrent();
+            let url = format!("http://127.0.0.1:{}/announce", port);
+            let mut req = TrackerRequest::builder(url.as_bytes(), &torrent.info_hash, &[2; 20])
+                .left(16)
+                .build()
+                .unwrap();
+            let mut tracker = Tracker::new(&req).await.unwrap();
+            let mut storage = MemoryStorage::empty(&torrent.info);
+            storage.write_block(0, 0, &data[..16]).unwrap();
+            started.send(()).unwrap();
+
+            stop.stopped().await;
+            ResumeData::from_storage(&torrent.info_hash, &torrent.info, &mut storage)
+                .unwrap()
+                .save(&resume_dir)
+                .unwrap();
+            tracker.stop(&mut req).await.unwrap();
+        });
+
+        //a Ctrl-C calls shutdown the same way
+        announced.await.unwrap();
+        assert!(!session.is_shutting_down());
+        session.shutdown().await;
+        assert!(session.is_shutting_down());
+        torrent.await.unwrap();
+
+        //every torrent has finished stopping once shutdown returns
+        let path = ResumeData::path(&session.config().resume_dir(), &info_hash);
+        let bytes = std::fs::read(&path).unwrap();
+        let resume = ResumeData::decode(&parse(&bytes).unwrap()).unwrap();
+        assert_eq!(resume.pieces, [true, false]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].path.contains("event=started"));
+        assert!(
+            requests[1].path.contains("event=stopped"),
+            "{}",
+            requests[1].path
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    //single file torrent named name holding data in pieces of 16 bytes, served by a web seed at port
+    fn web_seeded(name: &str, data: &[u8], port: u16) -> TorrentFile {
+        tracked(name, data, port, &[])
+    }
+
+    //web seeded torrent that also lists the given trackers, each in a tier of its own
+    fn tracked(name: &str, data: &[u8], port: u16, trackers: &[String]) -> TorrentFile {
+        let url = format!("http://127.0.0.1:{}/", port);
+        let mut bytes = b"d".to_vec();
+        if !trackers.is_empty() {
+            bytes.extend(b"13:announce-listl");
+            for tracker in trackers {
+                bytes.extend(format!("l{}:{}e", tracker.len(), tracker).into_bytes());
+            }
+            bytes.extend(b"e");
+        }
+        bytes.extend(
+            format!(
+                "8:url-list{}:{}4:infod6:lengthi{}e4:name{}:{}12:piece lengthi16e6:pieces{}:",
+                url.len(),
+                url,
+                data.len(),
+                name.len(),
+                name,
+                data.len().div_ceil(16) * 20
+            )
+            .into_bytes(),
+        );
+        bytes.extend(data.chunks(16).flat_map(Sha1::digest));
+        bytes.extend(b"ee");
+        TorrentFile::from_bytes(bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn torrents_are_added_removed_and_shut_down() {
+        let data: Vec<u8> = (0..32).collect();
+        //the second piece of b never arrives, so b is still downloading when it is removed
+        let (port, _) = serve({
+            let data = data.clone();
+            move |request| {
+                let (first, last) = request.range.unwrap();
+                match request.path.as_str() {
+                    "/b" if first > 0 => {
+                        b"HTTP/1.1 206 Partial Content\r\nContent-Length: 16\r\n\r\n".to_vec()
+                    }
+                    _ => response("HTTP/1.1 206 Partial Content", &data[first..=last]),
+                }
+            }
+        })
+        .await;
+        let dir = temp_path("session-torrents");
+        let session = Session::new(SessionConfig {
+            out_dir: dir.clone(),
+            ..SessionConfig::default()
+        });
+
+        let a = session.add_torrent(web_seeded("a", &data, port)).unwrap();
+        let b = session.add_torrent(web_seeded("b", &data, port)).unwrap();
+        let again = session.add_torrent(web_seeded("a", &data, port));
+        assert!(matches!(again, Err(SessionError::AlreadyAdded(handle)) if handle.name() == "a"));
+        let names: Vec<String> = session
+            .list()
+            .iter()
+            .map(|t| t.name().to_string())
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+
+        a.wait().await.unwrap().unwrap();
+        assert_eq!(std::fs::read(dir.join("a")).unwrap(), data);
+        while b.progress().verified_bytes < 16 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        //the removed torrent stopped in order before its data is deleted
+        session.remove(b.info_hash(), true).await.unwrap();
+        assert!(b.is_finished());
+        assert!(!dir.join("b").exists() && !dir.join("b.part").exists());
+        assert!(!ResumeData::path(&session.config().resume_dir(), b.info_hash()).exists());
+        assert!(session.get(b.info_hash()).is_none());
+        assert!(matches!(
+            session.remove(b.info_hash(), true).await,
+            Err(SessionError::UnknownTorrent(_))
+        ));
+
+        session.shutdown().await;
+        assert!(b.wait().await.unwrap().is_ok());
+        assert!(matches!(
+            session.add_torrent(web_seeded("c", &data, port)),
+            Err(SessionError::ShuttingDown)
+        ));
+        assert_eq!(session.list().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    //wait for the next event of the session that matches
+    async fn next_event(events: &mut broadcast::Receiver<Event>, matches: impl Fn(&Event) -> bool) {
+        while !matches(&events.recv().await.unwrap()) {}
+    }
+
+    #[tokio::test]
+    async fn paused_torrents_request_nothing_until_resumed() {
+        let data: Vec<u8> = (0..64).collect();
+        //the first request for the second piece is never answered, so the torrent is paused
+        //while it waits for it
+        let stalled = AtomicBool::new(false);
+        let (port, requests) = serve({
+            let data = data.clone();
+            move |request| {
+                let (first, last) = request.range.unwrap();
+                if first == 16 && !stalled.swap(true, Ordering::Relaxed) {
+                    return b"HTTP/1.1 206 Partial Content\r\nContent-Length: 16\r\n\r\n".to_vec();
+                }
+                response("HTTP/1.1 206 Partial Content", &data[first..=last])
+            }
+        })
+        .await;
+        let dir = temp_path("session-pause");
+        let session = Session::new(SessionConfig {
+            out_dir: dir.clone(),
+            ..SessionConfig::default()
+        });
+
+        let mut events = session.subscribe();
+        let torrent = session.add_torrent(web_seeded("p", &data, port)).unwrap();
+        next_event(&mut events, |event| {
+            matches!(event, Event::PieceVerified { index: 0, .. })
+        })
+        .await;
+        torrent.pause();
+        next_event(&mut events, |event| {
+            matches!(
+                event,
+                Event::StateChanged {
+                    state: TorrentState::Paused,
+                    ..
+                }
+            )
+        })
+        .await;
+        assert_eq!(torrent.status(), TorrentStatus::Paused);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert_eq!(torrent.progress().verified_bytes, 16);
+        //the piece is on disk and in the resume data while the torrent is paused
+        let resume = ResumeData::load(
+            &session.config().resume_dir(),
+            torrent.info_hash(),
+            &web_seeded("p", &data, port).torrent().info,
+        )
+        .unwrap();
+        assert_eq!(resume.pieces, [true, false, false, false]);
+        //and the file keeps its part name until it is complete
+        assert_eq!(std::fs::read(dir.join("p.part")).unwrap()[..16], data[..16]);
+        assert!(!dir.join("p").exists());
+
+        torrent.resume();
+        torrent.wait().await.unwrap().unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 5);
+        assert_eq!(std::fs::read(dir.join("p")).unwrap(), data);
+        assert!(!dir.join("p.part").exists());
+        session.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    //config of a session that keeps its torrents in memory, for tests that are not about storage
+    fn in_memory() -> SessionConfig {
+        SessionConfig {
+            in_memory: true,
+            ..SessionConfig::default()
+        }
+    }
+
+    //session with config that peers can connect to, and so seeds, on a port the system picks
+    async fn listening(config: SessionConfig) -> Session {
+        Session::bind(SessionConfig {
+            ports: 0..=0,
+            lsd: false,
+            ..config
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn events_of_a_download_come_in_order() {
+        let data: Vec<u8> = (0..64).collect();
+        let (port, _) = file_server(HashMap::from([("/e".to_string(), data.clone())]), true).await;
+        let session = listening(in_memory()).await;
+
+        let mut events = session.subscribe();
+        let torrent = session.add_torrent(web_seeded("e", &data, port)).unwrap();
+        torrent.wait().await.unwrap().unwrap();
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            assert_eq!(event.info_hash(), torrent.info_hash());
+            received.push(event);
+        }
+
+        let info_hash = *torrent.info_hash();
+        assert_eq!(
+            received[0],
+            Event::TorrentAdded {
+                info_hash,
+                name: "e".to_string()
+            }
+        );
+        let pieces: Vec<usize> = received
+            .iter()
+            .filter_map(|event| match event {
+                Event::PieceVerified { index, .. } => Some(*index),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pieces, [0, 1, 2, 3]);
+        let position = |wanted: &Event| received.iter().position(|event| event == wanted);
+        let last_piece = position(&Event::PieceVerified {
+            info_hash,
+            index: 3,
+        });
+        let completed = position(&Event::TorrentCompleted { info_hash });
+        let seeding = position(&Event::StateChanged {
+            info_hash,
+            state: TorrentState::Seeding,
+        });
+        assert!(
+            last_piece < completed && completed < seeding,
+            "{:?}",
+            received
+        );
+        session.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn trackers_are_told_of_the_download_and_their_failures_do_not_end_it() {
+        let data: Vec<u8> = (0..64).collect();
+        let (port, _) = file_server(HashMap::from([("/t".to_string(), data.clone())]), true).await;
+        let (broken, _) = http_server(vec![(
+            "HTTP/1.1 500 Internal Server Error".to_string(),
+            Vec::new(),
+        )])
+        .await;
+        let (working, requests) = http_server(vec![(
+            "HTTP/1.1 200 OK".to_string(),
+            b"d8:intervali1800e5:peers0:e".to_vec(),
+        )])
+        .await;
+        let trackers = [
+            format!("http://127.0.0.1:{}/announce", broken),
+            format!("http://127.0.0.1:{}/announce", working),
+        ];
+        let session = listening(SessionConfig {
+            tracker: unramped(),
+            ..in_memory()
+        })
+        .await;
+
+        let mut events = session.subscribe();
+        let torrent = session
+            .add_torrent(tracked("t", &data, port, &trackers))
+            .unwrap();
+        let info_hash = *torrent.info_hash();
+        let seeding = Event::StateChanged {
+            info_hash,
+            state: TorrentState::Seeding,
+        };
+        let mut received = Vec::new();
+        while received.last() != Some(&seeding) {
+            received.push(events.recv().await.unwrap());
+        }
+        assert_eq!(torrent.progress().verified_bytes, data.len() as u64);
+        //the broken tracker is reported, and the torrent goes on seeding with the working one
+        assert!(!torrent.is_finished());
+        assert_eq!(torrent.status(), TorrentStatus::Seeding);
+        assert!(
+            received.iter().any(|event| matches!(
+                event,
+                Event::TrackerError { tracker, .. } if *tracker == trackers[0]
+            )),
+            "{:?}",
+            received
+        );
+        {
+            let requests = requests.lock().unwrap();
+            assert!(requests[0].path.contains("event=started"));
+            assert!(
+                requests[0].path.contains("&left=64&"),
+                "{}",
+                requests[0].path
+            );
+        }
+
+        session.shutdown().await;
+        assert!(torrent.wait().await.unwrap().is_ok());
+        let requests = requests.lock().unwrap();
+        assert!(requests.last().unwrap().path.contains("event=stopped"));
+    }
+
+    #[tokio::test]
+    async fn silent_trackers_hold_up_neither_the_download_nor_a_pause() {
+        let data: Vec<u8> = (0..32).collect();
+        let (port, _) = file_server(HashMap::from([("/h".to_string(), data.clone())]), true).await;
+        //a udp tracker that never answers, which is retried for hours
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tracker = format!("udp://{}/announce", silent.local_addr().unwrap());
+        let session = listening(SessionConfig {
+            tracker: unramped(),
+            ..in_memory()
+        })
+        .await;
+        let mut events = session.subscribe();
+        let torrent = session
+            .add_torrent(tracked("h", &data, port, &[tracker]))
+            .unwrap();
+        let soon = Duration::from_secs(5);
+        tokio::time::timeout(
+            soon,
+            next_event(&mut events, |event| {
+                matches!(event, Event::TorrentCompleted { .. })
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(torrent.progress().verified_bytes, data.len() as u64);
+
+        torrent.pause();
+        tokio::time::timeout(
+            soon,
+            next_event(&mut events, |event| {
+                matches!(
+                    event,
+                    Event::StateChanged {
+                        state: TorrentState::Paused,
+                        ..
+                    }
+                )
+            }),
+        )
+        .await
+        .unwrap();
+        torrent.resume();
+        tokio::time::timeout(soon, session.shutdown())
+            .await
+            .unwrap();
+        torrent.wait().await.unwrap().unwrap();
+        drop(silent);
+    }
+
+    //tracker answering every announce with no peers, allowing the next one right away
+    async fn eager_tracker() -> (String, Requests) {
+        let (port, requests) = http_server(vec![(
+            "HTTP/1.1 200 OK".to_string(),
+            b"d8:intervali1800e12:min intervali0e5:peers0:e".to_vec(),
+        )])
+        .await;
+        (format!("http://127.0.0.1:{}/announce", port), requests)
+    }
+
+    //tracker settings that announce as soon as a torrent starts, not somewhere in the startup ramp
+    fn unramped() -> TrackerConfig {
+        TrackerConfig {
+            startup_ramp: Duration::ZERO,
+            ..TrackerConfig::default()
+        }
+    }
+
+    //wait until the tracker got an announce with event
+    async fn announced(requests: &Requests, event: &str) {
+        let event = format!("event={}", event);
+        while !requests
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|request| request.path.contains(&event))
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn finished_downloads_are_announced_completed_once() {
+        let data: Vec<u8> = (0..32).collect();
+        let (port, _) = file_server(HashMap::from([("/c".to_string(), data.clone())]), true).await;
+        let (tracker, requests) = eager_tracker().await;
+        let session = listening(SessionConfig {
+            tracker: unramped(),
+            ..in_memory()
+        })
+        .await;
+
+        let torrent = session
+            .add_torrent(tracked("c", &data, port, &[tracker]))
+            .unwrap();
+        announced(&requests, "completed").await;
+        assert_eq!(torrent.status(), TorrentStatus::Seeding);
+        session.shutdown().await;
+        torrent.wait().await.unwrap().unwrap();
+
+        let requests = requests.lock().unwrap();
+        let events: Vec<&str> = requests
+            .iter()
+            .map(|request| {
+                let query = &request.path;
+                ["started", "completed", "stopped"]
+                    .into_iter()
+                    .find(|event| query.contains(&format!("event={}", event)))
+                    .unwrap_or("")
+            })
+            .collect();
+        assert_eq!(events, ["started", "completed", "stopped"]);
+        assert!(requests[0].path.contains("&left=32&"));
+        assert!(requests[1].path.contains("&downloaded=32&left=0&"));
+    }
+
+    #[tokio::test]
+    async fn first_announces_wait_for_the_startup_ramp() {
+        let data: Vec<u8> = (0..32).collect();
+        let (port, _) = file_server(HashMap::from([("/w".to_string(), data.clone())]), true).await;
+        let (tracker, requests) = eager_tracker().await;
+        let session = listening(SessionConfig {
+            tracker: TrackerConfig {
+                startup_ramp: Duration::from_secs(86400),
+                ..TrackerConfig::default()
+            },
+            ..in_memory()
+        })
+        .await;
+
+        let mut events = session.subscribe();
+        let torrent = session
+            .add_torrent(tracked("w", &data, port, &[tracker]))
+            .unwrap();
+        let info_hash = *torrent.info_hash();
+        next_event(&mut events, |event| {
+            *event == Event::TorrentCompleted { info_hash }
+        })
+        .await;
+        //the download goes on meanwhile, and a tracker that never heard of us is not told we left
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        session.shutdown().await;
+        torrent.wait().await.unwrap().unwrap();
+        assert_eq!(torrent.progress().verified_bytes, data.len() as u64);
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    //data found on disk is rechecked on a blocking thread, which takes the multi threaded runtime
+    #[tokio::test(flavor = "multi_thread")]
+    async fn torrents_complete_on_disk_are_never_announced_completed() {
+        let data: Vec<u8> = (0..32).collect();
+        let (port, _) = file_server(HashMap::new(), true).await;
+        let (tracker, requests) = eager_tracker().await;
+        let dir = temp_dir("session-seeded");
+        std::fs::write(dir.join("s"), &data).unwrap();
+        let session = listening(SessionConfig {
+            out_dir: dir.clone(),
+            tracker: unramped(),
+            ..SessionConfig::default()
+        })
+        .await;
+
+        let torrent = session
+            .add_torrent(tracked("s", &data, port, &[tracker]))
+            .unwrap();
+        announced(&requests, "started").await;
+        assert_eq!(torrent.status(), TorrentStatus::Seeding);
+        //the tracker would take another announce at once, so a completed one would be sent now
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        session.shutdown().await;
+        torrent.wait().await.unwrap().unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(
+            requests[0].path.contains("&left=0&"),
+            "{}",
+            requests[0].path
+        );
+        assert!(
+            !requests
+                .iter()
+                .any(|request| request.path.contains("event=completed")),
+            "{:?}",
+            requests
+        );
+        assert!(requests.last().unwrap().path.contains("event=stopped"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failing_trackers_are_retried_while_the_torrent_goes_on() {
+        let data: Vec<u8> = (0..32).collect();
+        let (port, _) = file_server(HashMap::from([("/r".to_string(), data.clone())]), true).await;
+        //the tracker is down for the first two announces
+        let failure = ("HTTP/1.1 503 Service Unavailable".to_string(), Vec::new());
+        let (tracker, requests) = http_server(vec![
+            failure.clone(),
+            failure,
+            (
+                "HTTP/1.1 200 OK".to_string(),
+                b"d8:intervali1800e5:peers0:e".to_vec(),
+            ),
+        ])
+        .await;
+        let tracker = format!("http://127.0.0.1:{}/announce", tracker);
+        let retry = Duration::from_millis(50);
+        let session = listening(SessionConfig {
+            tracker: TrackerConfig {
+                backoff: Backoff::new(retry, 2, Duration::from_secs(1), 0.0),
+                ..unramped()
+            },
+            ..in_memory()
+        })
+        .await;
+
+        let mut events = session.subscribe();
+        let started = tokio::time::Instant::now();
+        let torrent = session
+            .add_torrent(tracked("r", &data, port, &[tracker]))
+            .unwrap();
+        let mut announces = Vec::new();
+        while announces.len() < 3 {
+            if let Event::TrackerAnnounce { ok, .. } = events.recv().await.unwrap() {
+                announces.push(ok);
+            }
+        }
+        //50ms after the first failure and 100ms after the second one
+        assert!(started.elapsed() >= retry * 3, "{:?}", started.elapsed());
+        assert_eq!(announces, [false, false, true]);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        assert_eq!(torrent.progress().verified_bytes, data.len() as u64);
+        assert_eq!(torrent.status(), TorrentStatus::Seeding);
+
+        session.shutdown().await;
+        torrent.wait().await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn attributes_are_applied_once_the_download_completes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let data: Vec<u8> = (0..16).collect();
+        let (port, _) =
+            file_server(HashMap::from([("/x/run".to_string(), data.clone())]), true).await;
+        let url = format!("http://127.0.0.1:{}/", port);
+        //an executable file and a link to it
+        let mut bytes = format!(
+            "d8:url-list{}:{}4:infod5:filesld4:attr1:x6:lengthi16e4:pathl3:runeed4:attr1:l\
+            6:lengthi0e4:pathl4:linke12:symlink pathl3:runeee4:name1:x12:piece lengthi16e6:pieces20:",
+            url.len(),
+            url
+        )
+        .into_bytes();
+        bytes.extend(Sha1::digest(&data));
+        bytes.extend(b"ee");
+        let dir = temp_path("session-attrs");
+        let session = Session::new(SessionConfig {
+            out_dir: dir.clone(),
+            ..SessionConfig::default()
+        });
+
+        let torrent = session
+            .add_torrent(TorrentFile::from_bytes(bytes).unwrap())
+            .unwrap();
+        torrent.wait().await.unwrap().unwrap();
+        let mode = std::fs::metadata(dir.join("x/run"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, (mode & 0o444) >> 2);
+        assert_eq!(
+            std::fs::read_link(dir.join("x/link")).unwrap(),
+            std::path::Path::new("run")
+        );
+        assert_eq!(std::fs::read(dir.join("x/link")).unwrap(), data);
+        session.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn http_seeds_serve_what_web_seeds_cannot() {
+        let data: Vec<u8> = (0..48).collect();
+        //the web seed lacks the second piece, which the http seed has
+        let (web, web_requests) = serve({
+            let data = data.clone();
+            move |request| match request.range.unwrap() {
+                (16, _) => response("HTTP/1.1 404 Not Found", b""),
+                (first, last) => response("HTTP/1.1 206 Partial Content", &data[first..=last]),
+            }
+        })
+        .await;
+        let (http, http_requests) = serve({
+            let data = data.clone();
+            move |request| {
+                let (_, piece) = request.path.split_once("&piece=").unwrap();
+                let first = piece.parse::<usize>().unwrap() * 16;
+                response("HTTP/1.1 200 OK", &data[first..first + 16])
+            }
+        })
+        .await;
+        let seed = format!("http://127.0.0.1:{}/seed", http);
+        let url = format!("http://127.0.0.1:{}/", web);
+        let mut bytes = format!(
+            "d9:httpseedsl{}:{}e8:url-list{}:{}4:infod6:lengthi48e4:name1:h12:piece lengthi16e6:pieces60:",
+            seed.len(),
+            seed,
+            url.len(),
+            url
+        )
+        .into_bytes();
+        bytes.extend(data.chunks(16).flat_map(Sha1::digest));
+        bytes.extend(b"ee");
+        let session = Session::new(SessionConfig {
+            http_seed_delay: Duration::from_millis(50),
+            ..in_memory()
+        });
+
+        let started = tokio::time::Instant::now();
+        let torrent = session
+            .add_torrent(TorrentFile::from_bytes(bytes).unwrap())
+            .unwrap();
+        torrent.wait().await.unwrap().unwrap();
+        assert_eq!(torrent.progress().verified_bytes, data.len() as u64);
+        //the http seed was only asked for the piece the web seed did not have, and only later
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(web_requests.lock().unwrap().len(), 3);
+        let asked: Vec<String> = http_requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.path.clone())
+            .collect();
+        assert_eq!(asked.len(), 1);
+        assert!(asked[0].ends_with("&piece=1"), "{}", asked[0]);
+        session.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn peers_on_the_local_network_are_found() {
+        let data: Vec<u8> = (0..32).collect();
+        //the web seed never answers, so both torrents keep running
+        let (port, _) =
+            serve(|_| b"HTTP/1.1 206 Partial Content\r\nContent-Length: 16\r\n\r\n".to_vec()).await;
+        let session = || {
+            Session::bind(SessionConfig {
+                ports: 0..=0,
+                ..in_memory()
+            })
+        };
+        let (first, second) = (session().await.unwrap(), session().await.unwrap());
+
+        let found = first.add_torrent(web_seeded("l", &data, port)).unwrap();
+        let announcing = second.add_torrent(web_seeded("l", &data, port)).unwrap();
+        //the second session announces the torrent once it is added, which the first one hears
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while found.progress().peers == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(found.progress().peers, 1);
+        assert!(!announcing.is_finished());
+
+        first.shutdown().await;
+        second.shutdown().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn peers_download_from_a_seeding_session() {
+        let data: Vec<u8> = (0..48).collect();
+        let (port, _) = file_server(HashMap::new(), true).await;
+        let (tracker, requests) = eager_tracker().await;
+        let dir = temp_dir("session-upload");
+        std::fs::write(dir.join("u"), &data).unwrap();
+        let session = listening(SessionConfig {
+            out_dir: dir.clone(),
+            tracker: unramped(),
+            seed_policy: SeedPolicy::FOREVER,
+            ..SessionConfig::default()
+        })
+        .await;
+        let mut events = session.subscribe();
+        let torrent_file = tracked("u", &data, port, &[tracker]);
+        let info_hash = torrent_file.torrent().info_hash;
+        let torrent = session.add_torrent(torrent_file).unwrap();
+        announced(&requests, "started").await;
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, session.port()));
+        let request = |index, begin, length| Message::Request {
+            index,
+            begin,
+            length,
+        };
+        let reject = |index, begin, length| Message::RejectRequest {
+            index,
+            begin,
+            length,
+        };
+
+        //peers without the Fast extension hear nothing of requests made while choked
+        let plain = Handshake {
+            reserved: [0; 8],
+            ..Handshake::new(info_hash, [8; 20])
+        };
+        let mut peer = PeerConnection::connect(addr, &plain).await.unwrap();
+        assert_eq!(peer.recv().await.unwrap(), Message::Bitfield(vec![0xe0]));
+        peer.send(&request(0, 0, 16)).await.unwrap();
+        peer.send(&Message::Interested).await.unwrap();
+        assert_eq!(peer.recv().await.unwrap(), Message::Unchoke);
+        drop(peer);
+
+        let mut peer = PeerConnection::connect(addr, &Handshake::new(info_hash, [9; 20]))
+            .await
+            .unwrap();
+        assert!(peer.handshake().supports_fast());
+        assert_eq!(peer.recv().await.unwrap(), Message::Bitfield(vec![0xe0]));
+        peer.send(&request(0, 0, 16)).await.unwrap();
+        assert_eq!(peer.recv().await.unwrap(), reject(0, 0, 16));
+        peer.send(&Message::Interested).await.unwrap();
+        assert_eq!(peer.recv().await.unwrap(), Message::Unchoke);
+        //blocks outside the pieces, empty ones and ones longer than MAX_REQUEST are refused
+        for (index, begin, length) in [(3, 0, 16), (2, 8, 16), (0, 0, 0), (0, 0, MAX_REQUEST + 1)] {
+            peer.send(&request(index, begin, length)).await.unwrap();
+            assert_eq!(peer.recv().await.unwrap(), reject(index, begin, length));
+        }
+        let mut downloaded = Vec::new();
+        for index in 0..3 {
+            for begin in [0, 8] {
+                peer.send(&request(index, begin, 8)).await.unwrap();
+                match peer.recv().await.unwrap() {
+                    Message::Piece {
+                        index: i,
+                        begin: b,
+                        data,
+                    } if i == index && b == begin => downloaded.extend(data),
+                    message => panic!("{:?}", message),
+                }
+            }
+        }
+        assert_eq!(downloaded, data);
+        assert_eq!(torrent.progress().uploaded, 48);
+        next_event(&mut events, |event| {
+            matches!(event, Event::PeerConnected { addr, .. } if addr.ip() == Ipv4Addr::LOCALHOST)
+        })
+        .await;
+        drop(peer);
+        next_event(&mut events, |event| {
+            matches!(event, Event::PeerDisconnected { .. })
+        })
+        .await;
+
+        //the trackers hear of the upload
+        session.shutdown().await;
+        torrent.wait().await.unwrap().unwrap();
+        let last = requests.lock().unwrap().last().unwrap().path.clone();
+        assert!(
+            last.contains("event=stopped") && last.contains("uploaded=48"),
+            "{}",
+            last
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    //download every piece of 16 bytes of the torrent with info_hash from the session at port
+    async fn download_from(port: u16, info_hash: [u8; 20], pieces: u32) -> Vec<u8> {
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let mut peer = PeerConnection::connect(addr, &Handshake::new(info_hash, [7; 20]))
+            .await
+            .unwrap();
+        peer.send(&Message::Interested).await.unwrap();
+        while peer.recv().await.unwrap() != Message::Unchoke {}
+        let mut data = Vec::new();
+        for index in 0..pieces {
+            let request = Message::Request {
+                index,
+                begin: 0,
+                length: 16,
+            };
+            peer.send(&request).await.unwrap();
+            match peer.recv().await.unwrap() {
+                Message::Piece { data: block, .. } => data.extend(block),
+                message => panic!("{:?}", message),
+            }
+        }
+        data
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn seeding_stops_once_peers_took_the_ratio() {
+        let data: Vec<u8> = (0..48).collect();
+        let (port, _) = file_server(HashMap::new(), true).await;
+        let (tracker, requests) = eager_tracker().await;
+        let dir = temp_dir("session-ratio");
+        std::fs::write(dir.join("q"), &data).unwrap();
+        //seeds up to a ratio of 1.0 by default
+        let session = listening(SessionConfig {
+            out_dir: dir.clone(),
+            tracker: unramped(),
+            ..SessionConfig::default()
+        })
+        .await;
+        let torrent_file = tracked("q", &data, port, &[tracker]);
+        let info_hash = torrent_file.torrent().info_hash;
+        let torrent = session.add_torrent(torrent_file).unwrap();
+        announced(&requests, "started").await;
+        assert_eq!(torrent.status(), TorrentStatus::Seeding);
+
+        //a complete torrent is measured against its size, so one peer taking all of it is enough
+        assert_eq!(download_from(session.port(), info_hash, 3).await, data);
+        torrent.wait().await.unwrap().unwrap();
+        assert_eq!(torrent.status(), TorrentStatus::Stopped);
+        let last = requests.lock().unwrap().last().unwrap().path.clone();
+        assert!(
+            last.contains("event=stopped") && last.contains("uploaded=48"),
+            "{}",
+            last
+        );
+        //the session goes on without it
+        assert!(!session.is_shutting_down());
+        session.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sessions_without_a_listener_do_not_seed() {
+        let data: Vec<u8> = (0..32).collect();
+        let (port, _) = file_server(HashMap::from([("/n".to_string(), data.clone())]), true).await;
+        let (tracker, requests) = eager_tracker().await;
+        let session = Session::new(SessionConfig {
+            tracker: unramped(),
+            ..in_memory()
+        });
+
+        let mut events = session.subscribe();
+        let torrent = session
+            .add_torrent(tracked("n", &data, port, &[tracker]))
+            .unwrap();
+        //nobody could download from it, so it leaves the swarm once it is complete
+        torrent.wait().await.unwrap().unwrap();
+        assert_eq!(torrent.progress().verified_bytes, data.len() as u64);
+        while let Ok(event) = events.try_recv() {
+            assert!(
+                !matches!(
+                    event,
+                    Event::StateChanged {
+                        state: TorrentState::Seeding,
+                        ..
+                    }
+                ),
+                "{:?}",
+                event
+            );
+        }
+        let last = requests.lock().unwrap().last().unwrap().path.clone();
+        assert!(last.contains("event=stopped&"), "{}", last);
+        assert!(last.contains("&left=0&"), "{}", last);
+        session.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn failed_torrents_tell_why() {
+        let (port, _) = file_server(HashMap::new(), true).await;
+        let session = Session::new(in_memory());
+        let torrent = session
+            .add_torrent(web_seeded("missing", &[1; 16], port))
+            .unwrap();
+        assert!(torrent.wait().await.unwrap().is_err());
+        let TorrentStatus::Error(message) = torrent.status() else {
+            panic!("{:?}", torrent.status());
+        };
+        assert!(
+            message.starts_with("web seed download failed: "),
+            "{}",
+            message
+        );
+        session.shutdown().await;
+    }
+}
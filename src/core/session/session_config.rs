@@ -0,0 +1,76 @@
+use crate::core::peer_id::{PeerId, get_peer_id};
+use crate::core::session::listener::DEFAULT_PORTS;
+use crate::core::session::seed_policy::SeedPolicy;
+use crate::core::storage::write_cache::DEFAULT_WRITE_CACHE;
+use crate::core::tracker::tracker::DEFAULT_NUMWANT;
+use crate::core::tracker::tracker_config::TrackerConfig;
+use crate::core::webseed::httpseed::DEFAULT_FALLBACK_DELAY;
+
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
+
+//directory below the download directory that resume data is kept in
+pub const RESUME_DIR: &str = ".motteseed";
+
+//settings shared by every torrent of a session, filled from the config file,
+//command line flags or by library users
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    pub ports: RangeInclusive<u16>, //ports tried in order for incoming connections, 0..=0 lets the system pick
+    pub out_dir: PathBuf,           //directory downloaded torrents are saved in
+    pub max_peers: u32,             //most peers per torrent, also the number asked from trackers
+    pub seed: bool, //keep seeding a torrent once it is complete, in sessions that listen
+    pub seed_policy: SeedPolicy, //when seeding torrents stop, unless a torrent has its own
+    pub lsd: bool,  //find peers on the local network (BEP 14)
+    pub part_files: bool, //write incomplete files as <name>.part, renamed once all their pieces are in
+    pub in_memory: bool, //keep the data of torrents in memory, nothing is read or written below out_dir
+    pub write_cache: usize, //most bytes of downloaded data a torrent holds before writing it to disk
+    pub http_seed_delay: Duration, //time a piece web seeds could not serve waits for the http seeds
+    pub peer_id: PeerId,    //id sent to trackers and peers
+    pub announce_ip: Option<IpAddr>, //address announced instead of the one trackers see
+    pub tracker: TrackerConfig, //settings for announces and web seeds
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ports: DEFAULT_PORTS,
+            out_dir: PathBuf::from("."),
+            max_peers: DEFAULT_NUMWANT,
+            seed: true,
+            seed_policy: SeedPolicy::default(),
+            lsd: true,
+            part_files: true,
+            in_memory: false,
+            write_cache: DEFAULT_WRITE_CACHE,
+            http_seed_delay: DEFAULT_FALLBACK_DELAY,
+            peer_id: *get_peer_id(),
+            announce_ip: None,
+            tracker: TrackerConfig::default(),
+        }
+    }
+}
+
+impl SessionConfig {
+    //get the directory the resume data of the torrents is saved in
+    pub fn resume_dir(&self) -> PathBuf {
+        self.out_dir.join(RESUME_DIR)
+    }
+}
+
+//settings of a single torrent that replace the ones of its session, None keeps the session's
+#[derive(Debug, Clone, Default)]
+pub struct TorrentConfig {
+    pub seed_policy: Option<SeedPolicy>, //when the torrent stops seeding
+    pub force_recheck: bool,             //hash all data on disk, even with resume data
+    pub overwrite: bool,                 //download over existing files without checking them
+}
+
+impl TorrentConfig {
+    //get the seed policy of the torrent in a session with config
+    pub fn seed_policy(&self, config: &SessionConfig) -> SeedPolicy {
+        self.seed_policy.unwrap_or(config.seed_policy)
+    }
+}
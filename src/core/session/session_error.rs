@@ -0,0 +1,80 @@
+use crate::core::session::torrent_handle::TorrentHandle;
+use crate::core::storage::storage_error::StorageError;
+use crate::core::torrent::torrent_error::ReadTorrentError;
+use crate::core::webseed::webseed_error::WebSeedError;
+use crate::util::hex;
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+//custom error enum for adding and removing the torrents of a session
+#[derive(Error, Debug)]
+pub enum SessionError {
+    //torrent with the same info hash runs already, its handle is kept
+    #[error("torrent '{}' was added already", .0.name())]
+    AlreadyAdded(TorrentHandle),
+
+    //torrent added while the session shuts down, it would stop at once
+    #[error("the session is shutting down")]
+    ShuttingDown,
+
+    //info hash of no torrent of the session
+    #[error("no torrent with info hash {}", hex::encode(.0))]
+    UnknownTorrent([u8; 20]),
+
+    //magnet link without a v1 info hash
+    #[error("invalid magnet link: {0}")]
+    InvalidMagnet(String),
+
+    //magnet link of a torrent whose metadata is not saved, fetching it from peers is not supported yet
+    #[error("no metadata for info hash {}, add its torrent file instead", hex::encode(.0))]
+    NoMetadata([u8; 20]),
+
+    //saved torrent file that could not be read back
+    #[error("cannot read saved torrent '{}'", path.display())]
+    ReadTorrent {
+        path: PathBuf,
+        #[source]
+        source: ReadTorrentError,
+    },
+
+    //files of a removed torrent whose location can not be told from the torrent
+    #[error("cannot find the files of the torrent")]
+    Storage(#[source] StorageError),
+
+    //data or state of a removed torrent that could not be deleted
+    #[error("cannot delete '{}'", path.display())]
+    Delete {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+//custom error enum for a torrent that stopped running because of a failure
+#[derive(Error, Debug)]
+pub enum TorrentError {
+    //download directory that could not be prepared for the torrent
+    #[error("cannot prepare '{}'", dir.display())]
+    Storage {
+        dir: PathBuf,
+        #[source]
+        source: StorageError,
+    },
+
+    //data left by an earlier run that could not be read for checking
+    #[error("cannot check the data")]
+    Verify(#[source] StorageError),
+
+    //web seeds that could not serve the data
+    #[error("web seed download failed")]
+    WebSeed(#[source] WebSeedError),
+
+    //resume data that could not be written
+    #[error("cannot save resume data in '{}'", dir.display())]
+    SaveResume {
+        dir: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
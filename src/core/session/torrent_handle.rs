@@ -0,0 +1,137 @@
+use crate::core::session::progress::{Progress, ProgressTracker, TorrentState};
+use crate::core::session::session_error::TorrentError;
+
+use std::fmt;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+//task running a torrent, which ends with how the torrent ended
+type Task = JoinHandle<Result<(), TorrentError>>;
+
+//what a torrent of a session is doing, as told by its handle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TorrentStatus {
+    Checking,      //hashing data left on disk by an earlier run
+    Downloading,   //fetching the pieces that are missing
+    Seeding,       //complete and serving the data to others
+    Paused,        //held by a handle until it is resumed
+    Stopped,       //done seeding, out of the swarm
+    Error(String), //ended by the failure with this message
+}
+
+impl fmt::Display for TorrentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TorrentStatus::Checking => write!(f, "checking"),
+            TorrentStatus::Downloading => write!(f, "downloading"),
+            TorrentStatus::Seeding => write!(f, "seeding"),
+            TorrentStatus::Paused => write!(f, "paused"),
+            TorrentStatus::Stopped => write!(f, "stopped"),
+            TorrentStatus::Error(message) => write!(f, "error: {}", message),
+        }
+    }
+}
+
+//a torrent running in a session, cloned freely to look at it and control it from several places
+//the task of the torrent is told what to do through channels, so calls return at once
+#[derive(Debug, Clone)]
+pub struct TorrentHandle {
+    info_hash: [u8; 20],
+    name: String,
+    progress: Arc<Mutex<ProgressTracker>>, //counted by the task of the torrent
+    error: Arc<Mutex<Option<String>>>,     //set by the task if the torrent failed
+    paused: watch::Sender<bool>,           //read by the task of the torrent
+    task: Arc<Mutex<Option<Task>>>,        //taken by the first wait
+}
+
+impl TorrentHandle {
+    pub(crate) fn new(
+        info_hash: [u8; 20],
+        name: &str,
+        progress: Arc<Mutex<ProgressTracker>>,
+        error: Arc<Mutex<Option<String>>>,
+        paused: watch::Sender<bool>,
+        task: Task,
+    ) -> Self {
+        Self {
+            info_hash,
+            name: name.to_string(),
+            progress,
+            error,
+            paused,
+            task: Arc::new(Mutex::new(Some(task))),
+        }
+    }
+
+    //get the info hash the torrent is known by in its session
+    pub fn info_hash(&self) -> &[u8; 20] {
+        &self.info_hash
+    }
+
+    //get the name of the torrent
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    //take a snapshot of the progress of the torrent
+    pub fn progress(&self) -> Progress {
+        self.progress
+            .lock()
+            .expect("progress is not poisoned")
+            .snapshot()
+    }
+
+    //get what the torrent is doing, or the failure it ended with
+    pub fn status(&self) -> TorrentStatus {
+        if let Some(message) = self.error.lock().expect("error is not poisoned").clone() {
+            return TorrentStatus::Error(message);
+        }
+        match self.progress().state {
+            TorrentState::Checking => TorrentStatus::Checking,
+            TorrentState::Downloading => TorrentStatus::Downloading,
+            TorrentState::Seeding => TorrentStatus::Seeding,
+            TorrentState::Paused => TorrentStatus::Paused,
+            TorrentState::Stopped => TorrentStatus::Stopped,
+        }
+    }
+
+    //hold the torrent: no more data is requested, what is downloaded is flushed to disk and
+    //saved as resume data, and trackers are told the torrent left the swarm
+    //the torrent keeps its state in memory, a torrent that is checking pauses once it is done
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    //let a paused torrent go on where it was, announcing it to its trackers again
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    //check if the torrent was asked to pause and not resumed since
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    //check if the torrent stopped running, because it is done, failed or was stopped
+    pub fn is_finished(&self) -> bool {
+        self.task
+            .lock()
+            .expect("task is not poisoned")
+            .as_ref()
+            .is_none_or(|task| task.is_finished())
+    }
+
+    //wait until the torrent stops running and get how it ended
+    //only the first call of all clones gets the result, later ones get None at once
+    pub async fn wait(&self) -> Option<Result<(), TorrentError>> {
+        let task = self.task.lock().expect("task is not poisoned").take()?;
+        match task.await {
+            Ok(result) => Some(result),
+            //a torrent that panicked takes its waiter down like a plain function call would
+            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+            Err(_) => None,
+        }
+    }
+}
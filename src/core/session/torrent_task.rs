@@ -0,0 +1,606 @@
+use crate::core::peer::peer::Peer;
+use crate::core::peer::peer_set::PeerSet;
+use crate::core::session::event::Event;
+use crate::core::session::progress::{ProgressTracker, TorrentState};
+use crate::core::session::resume::ResumeData;
+use crate::core::session::session::{SessionState, StopSignal};
+use crate::core::session::session_config::{SessionConfig, TorrentConfig};
+use crate::core::session::session_error::TorrentError;
+use crate::core::session::upload::{UPLOAD_SLOTS, upload};
+use crate::core::storage::file_attributes::apply_attributes;
+use crate::core::storage::file_storage::FileStorage;
+use crate::core::storage::memory_storage::MemoryStorage;
+use crate::core::storage::preflight::{ExistingFiles, SystemDiskSpace, preflight};
+use crate::core::storage::recheck::recheck;
+use crate::core::storage::shared_storage::SharedStorage;
+use crate::core::storage::storage::Storage;
+use crate::core::storage::write_cache::WriteCache;
+use crate::core::torrent::torrent::{Torrent, TorrentFile};
+use crate::core::tracker::multi_tracker::{MultiTracker, TrackerStatus};
+use crate::core::webseed::httpseed::HttpSeeds;
+use crate::core::webseed::webseed::WebSeeds;
+use crate::core::webseed::webseed_error::WebSeedError;
+use crate::util::hex;
+
+use rand::rng;
+use rand::seq::SliceRandom;
+use std::error::Error;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{Semaphore, broadcast, mpsc, watch};
+use tokio::task::JoinSet;
+use tracing::{Instrument, debug, info, info_span, warn};
+
+//time between two looks at the seed policy of a seeding torrent
+const SEED_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+//everything a torrent of a session runs with, moved into its task
+pub(crate) struct TorrentTask {
+    pub session: Arc<SessionState>,
+    pub torrent_file: Arc<TorrentFile>,
+    pub torrent_config: TorrentConfig,
+    pub reporter: Reporter,
+    pub control: Control,
+    pub local_peers: mpsc::UnboundedReceiver<Peer>, //peers of the torrent found on the local network
+}
+
+//counts the progress of a torrent and tells the subscribers of its session what happens to it
+#[derive(Clone)]
+pub(crate) struct Reporter {
+    pub info_hash: [u8; 20],
+    pub progress: Arc<Mutex<ProgressTracker>>, //shared with the handles of the torrent
+    pub error: Arc<Mutex<Option<String>>>,     //message of the error the torrent ended with
+    pub events: broadcast::Sender<Event>,      //events of the session
+}
+
+//what a running torrent is told by its session and its handles
+pub(crate) struct Control {
+    pub stop: StopSignal, //held until the torrent is done, so stopping it waits for it to end in order
+    pub paused: watch::Receiver<bool>, //set while a handle holds the torrent
+}
+
+//why a torrent stopped what it was doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interrupt {
+    Stop,  //the torrent is removed or the session shuts down
+    Pause, //a handle paused the torrent
+}
+
+impl TorrentTask {
+    //run the torrent until it is done, fails or is stopped
+    pub async fn run(self) -> Result<(), TorrentError> {
+        let torrent_file = self.torrent_file.clone();
+        let torrent = torrent_file.torrent();
+        //log events of the torrent are told apart by the start of its info hash
+        let span = info_span!("torrent", info_hash = %&hex::encode(&torrent.info_hash)[..8]);
+        let reporter = self.reporter.clone();
+        let result = self.transfer(torrent).instrument(span).await;
+        if let Err(e) = &result {
+            reporter.failed(e);
+        }
+        result
+    }
+
+    //get the data of a torrent from its web seeds while announcing it to its trackers, counting progress
+    //peers that connect to the session get the pieces we have meanwhile
+    //a complete torrent is seeded until its seed policy is met, as long as trackers can point peers
+    //at it and they can connect to the session
+    //when stop fires the data so far is saved for the next start and the trackers are told we left
+    async fn transfer(mut self, torrent: &Torrent<'_>) -> Result<(), TorrentError> {
+        let config = &self.session.config;
+        let info = &torrent.info;
+        let (storage, earlier) = self.open_storage(torrent)?;
+        //downloaded pieces are gathered in memory and written out in large runs,
+        //peers are served from the same storage while the download goes on
+        let mut storage = SharedStorage::new(WriteCache::new(
+            storage,
+            info.piece_length,
+            info.total_length(),
+            config.write_cache,
+        ));
+        let mut web_seeds = WebSeeds::new(&torrent.url_list, config.tracker.clone());
+        let mut http_seeds = HttpSeeds::new(&torrent.httpseeds, config.tracker.clone());
+        http_seeds.set_fallback_delay(config.http_seed_delay);
+        let has_seeds = !torrent.url_list.is_empty() || !torrent.httpseeds.is_empty();
+        let mut trackers = trackers(&self.session, torrent, &self.reporter);
+        //trackerless torrents rely on DHT nodes, which are not supported yet, so nobody can find them
+        let has_trackers = trackers.next_announce_in().is_some();
+        //peers known from the trackers and the local network
+        let mut swarm = PeerSet::new();
+        let policy = self.torrent_config.seed_policy(config);
+        //peers can only get the data of a session they can connect to, so others do not seed
+        let seed = config.seed && self.session.is_listening();
+        let reporter = &self.reporter;
+        let slots = Arc::new(Semaphore::new(UPLOAD_SLOTS));
+
+        //data that is complete on disk already is seeded right away
+        let mut complete = is_complete(reporter);
+        if complete {
+            finish(reporter, seed);
+        }
+        //a paused torrent leaves the swarm, and joins it again with a started announce once resumed
+        loop {
+            let halt = if complete && (!seed || !has_trackers) {
+                Ok(None)
+            } else if !complete && !has_seeds && !has_trackers {
+                //no web seed to download from and no tracker to find peers with
+                Ok(None)
+            } else {
+                if !complete {
+                    reporter.set_state(TorrentState::Downloading);
+                }
+                //peers are disconnected when the torrent is paused or stopped
+                let mut incoming = self.session.incoming(torrent.info_hash);
+                let mut uploads = JoinSet::new();
+                let mut download_storage = storage.clone();
+                let download = fetch_missing(
+                    &mut web_seeds,
+                    &mut http_seeds,
+                    torrent,
+                    &mut download_storage,
+                    |index, bytes| {
+                        reporter.progress().downloaded(bytes);
+                        reporter.send(Event::PieceVerified {
+                            info_hash: reporter.info_hash,
+                            index,
+                        });
+                    },
+                );
+                tokio::pin!(download);
+                let mut downloading = !complete && has_seeds;
+                //announces run next to the download, so a slow tracker holds up neither it
+                //nor a pause or stop
+                let (completed, completions) = mpsc::unbounded_channel();
+                let (found, mut found_peers) = mpsc::unbounded_channel();
+                let announcing = announce_while_due(&mut trackers, reporter, completions, found);
+                tokio::pin!(announcing);
+                let mut announcing_done = false;
+                loop {
+                    let seeding = reporter.progress().state() == TorrentState::Seeding;
+                    tokio::select! {
+                        biased;
+                        interrupt = self.control.interrupted() => break Ok(Some(interrupt)),
+                        //polled before the download, so the started announce tells the trackers
+                        //what was left when the torrent was started
+                        _ = &mut announcing, if !announcing_done => announcing_done = true,
+                        result = &mut download, if downloading => {
+                            downloading = false;
+                            if let Err(e) = result {
+                                break Err(TorrentError::WebSeed(e));
+                            }
+                            complete = true;
+                            //every file is on disk now, so links and permissions can be set
+                            if !config.in_memory
+                                && let Err(source) = apply_attributes(info, &config.out_dir)
+                            {
+                                break Err(TorrentError::Storage {
+                                    dir: config.out_dir.clone(),
+                                    source,
+                                });
+                            }
+                            //the trackers hear of it with the next announce, those of a torrent
+                            //that is not seeded with the stopped one
+                            let _ = completed.send(reporter.progress().snapshot().downloaded);
+                            finish(reporter, seed);
+                            if !seed || !has_trackers {
+                                break Ok(None);
+                            }
+                        }
+                        Some(connection) = incoming.recv() => {
+                            if uploads.len() < config.max_peers as usize {
+                                uploads.spawn(upload(
+                                    connection,
+                                    storage.clone(),
+                                    self.torrent_file.clone(),
+                                    reporter.clone(),
+                                    slots.clone(),
+                                ));
+                            }
+                        }
+                        Some(_) = uploads.join_next() => {}
+                        Some(peer) = self.local_peers.recv() => {
+                            debug!(peer = %peer.addr(), "local peer");
+                            swarm.merge([peer]);
+                            reporter.progress().set_peers(swarm.len());
+                        }
+                        Some(peers) = found_peers.recv() => {
+                            swarm.merge(peers);
+                            reporter.progress().set_peers(swarm.len());
+                        }
+                        //uploads move the ratio between announces, so it is looked at often
+                        _ = tokio::time::sleep(SEED_CHECK_INTERVAL), if seeding => {
+                            let snapshot = reporter.progress().snapshot();
+                            if policy.is_met(&snapshot) {
+                                info!(ratio = snapshot.ratio(), "seed policy met");
+                                reporter.set_state(TorrentState::Stopped);
+                                break Ok(None);
+                            }
+                        }
+                    }
+                }
+            };
+            //pieces that are done are saved either way, which also flushes them to disk,
+            //so the data of a paused or stopped torrent matches its resume data
+            let downloaded = earlier + reporter.progress().snapshot().downloaded;
+            let saved = save_resume(torrent, &mut storage, config, downloaded);
+            leave(&mut trackers, reporter).await;
+            saved?;
+            match halt? {
+                Some(Interrupt::Pause) if self.control.hold(reporter).await => {}
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    //open the storage of torrent below the download directory and find the pieces it has,
+    //or empty storage in memory for sessions that keep their torrents there
+    //pieces saved as complete by an earlier run are trusted without hashing them again,
+    //other data left by an earlier run is checked, so only missing or damaged pieces are downloaded
+    //returns the storage and the bytes downloaded by earlier runs
+    fn open_storage(
+        &self,
+        torrent: &Torrent<'_>,
+    ) -> Result<(Box<dyn Storage + Send>, u64), TorrentError> {
+        let config = &self.session.config;
+        let torrent_config = &self.torrent_config;
+        let info = &torrent.info;
+        if config.in_memory {
+            return Ok((Box::new(MemoryStorage::empty(info)), 0));
+        }
+        let progress = || self.reporter.progress();
+        let storage_error = |source| TorrentError::Storage {
+            dir: config.out_dir.clone(),
+            source,
+        };
+        let mut storage = FileStorage::new(info, &config.out_dir).map_err(storage_error)?;
+        storage.set_part_files(config.part_files);
+        //pure v2 torrents have no piece hashes to check against
+        let resume = (storage.any_file_exists()
+            && !torrent_config.force_recheck
+            && !torrent_config.overwrite)
+            .then(|| ResumeData::load(&config.resume_dir(), &torrent.info_hash, info))
+            .flatten();
+        //files of another size without resume data are likely another torrent's, which a
+        //recheck would overwrite piece by piece, so that takes a flag
+        let existing = if torrent_config.overwrite {
+            ExistingFiles::Overwrite
+        } else if torrent_config.force_recheck || resume.is_some() {
+            ExistingFiles::Keep
+        } else {
+            ExistingFiles::Refuse
+        };
+        preflight(&storage, &config.out_dir, existing, &SystemDiskSpace).map_err(storage_error)?;
+        if let Some(resume) = &resume {
+            for (index, _) in resume.pieces.iter().enumerate().filter(|(_, have)| **have) {
+                storage.mark_have(index).map_err(storage_error)?;
+                progress().verified(info.piece_len(index).unwrap_or(0));
+            }
+        } else if existing != ExistingFiles::Overwrite
+            && storage.any_file_exists()
+            && info.num_pieces() > 0
+        {
+            self.reporter.set_state(TorrentState::Checking);
+            //hashing blocks, so the other torrents move to other threads meanwhile
+            let result = tokio::task::block_in_place(|| {
+                recheck(info, &mut storage, |checked, _| {
+                    progress().checked(info.piece_len(checked - 1).unwrap_or(0));
+                })
+            })
+            .map_err(TorrentError::Verify)?;
+            for (index, _) in result
+                .pieces
+                .iter()
+                .enumerate()
+                .filter(|(_, valid)| **valid)
+            {
+                storage.mark_have(index).map_err(storage_error)?;
+                progress().verified(info.piece_len(index).unwrap_or(0));
+            }
+        }
+        Ok((
+            Box::new(storage),
+            resume.map_or(0, |resume| resume.downloaded),
+        ))
+    }
+}
+
+//get the trackers of every tier of torrent, each announced to on its own
+//a tracker whose request can not be built is left out, it does not stop the others
+fn trackers<'a>(
+    session: &'a SessionState,
+    torrent: &'a Torrent<'_>,
+    reporter: &Reporter,
+) -> MultiTracker<'a> {
+    let snapshot = reporter.progress().snapshot();
+    let mut urls: Vec<&[u8]> = Vec::new();
+    //urls are shuffled within each tier as BEP 12 requires
+    for mut tier in torrent.trackers() {
+        tier.shuffle(&mut rng());
+        for url in tier {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    let requests = urls
+        .into_iter()
+        .filter_map(|url| {
+            session
+                .announce_request(url, &torrent.info_hash)
+                .left(snapshot.total_bytes - snapshot.verified_bytes)
+                .build()
+                .inspect_err(|e| {
+                    warn!(tracker = %String::from_utf8_lossy(url), error = %e, "skipping tracker")
+                })
+                .ok()
+        })
+        .collect();
+    let mut trackers = MultiTracker::new(requests);
+    //torrents started together spread their first announces, resuming a paused one does not
+    trackers.set_startup_ramp(session.config.tracker.startup_ramp);
+    //failed announces are retried on this schedule while the torrent goes on
+    trackers.set_backoff(session.config.tracker.backoff.clone());
+    trackers.set_config(session.config.tracker.clone());
+    trackers
+}
+
+//tell subscribers every piece is in, and start seeding if seed is set
+fn finish(reporter: &Reporter, seed: bool) {
+    reporter.send(Event::TorrentCompleted {
+        info_hash: reporter.info_hash,
+    });
+    if seed {
+        reporter.set_state(TorrentState::Seeding);
+    }
+}
+
+//check if every piece of the torrent is verified
+fn is_complete(reporter: &Reporter) -> bool {
+    let snapshot = reporter.progress().snapshot();
+    snapshot.verified_bytes == snapshot.total_bytes
+}
+
+//announce to trackers whenever one is due, sending the peers they return to found
+//the bytes downloaded sent to completed tell them the download completed
+//returns once every tracker failed for good, and nothing is left to announce to
+async fn announce_while_due(
+    trackers: &mut MultiTracker<'_>,
+    reporter: &Reporter,
+    mut completed: mpsc::UnboundedReceiver<u64>,
+    found: mpsc::UnboundedSender<Vec<Peer>>,
+) {
+    while let Some(wait) = trackers.next_announce_in() {
+        //a due announce goes out at once, a completed download may make one due earlier
+        if !wait.is_zero() {
+            tokio::select! {
+                Some(downloaded) = completed.recv() => {
+                    trackers.set_completed(downloaded);
+                    continue;
+                }
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+        let _ = found.send(announce(trackers, reporter).await);
+    }
+}
+
+//announce to the trackers that are due with the current counters, telling subscribers what they said
+//returns the peers they sent
+async fn announce(trackers: &mut MultiTracker<'_>, reporter: &Reporter) -> Vec<Peer> {
+    let snapshot = reporter.progress().snapshot();
+    trackers.set_stats(
+        snapshot.uploaded,
+        snapshot.downloaded,
+        snapshot.total_bytes - snapshot.verified_bytes,
+    );
+    let peers = trackers.announce().await;
+    for (tracker, status) in trackers.contacted() {
+        match &status.last_error {
+            None => reporter.announced(tracker, status),
+            Some(message) => reporter.announce_failed(tracker, message),
+        }
+    }
+    debug!(peers = peers.len(), "announced");
+    peers
+}
+
+//tell the trackers we left the swarm
+//a tracker that does not hear from us in time drops us from the swarm on its own
+async fn leave(trackers: &mut MultiTracker<'_>, reporter: &Reporter) {
+    let snapshot = reporter.progress().snapshot();
+    trackers.set_stats(
+        snapshot.uploaded,
+        snapshot.downloaded,
+        snapshot.total_bytes - snapshot.verified_bytes,
+    );
+    trackers.stop().await;
+    for (tracker, status) in trackers.contacted() {
+        if let Some(message) = &status.last_error {
+            reporter.announce_failed(tracker, message);
+        }
+    }
+}
+
+impl Control {
+    //wait until the torrent is stopped or paused, a stop goes first if both happened
+    async fn interrupted(&mut self) -> Interrupt {
+        tokio::select! {
+            biased;
+            _ = self.stop.stopped() => Interrupt::Stop,
+            //handles that are all gone can not pause the torrent anymore
+            Ok(_) = self.paused.wait_for(|paused| *paused) => Interrupt::Pause,
+        }
+    }
+
+    //keep a paused torrent as it is until it is resumed, false if it is stopped meanwhile
+    //the pieces and counters stay in memory, so resuming does not check the data again
+    async fn hold(&mut self, reporter: &Reporter) -> bool {
+        let state = reporter.progress().state();
+        reporter.set_state(TorrentState::Paused);
+        info!("paused");
+        let resumed = tokio::select! {
+            biased;
+            _ = self.stop.stopped() => false,
+            Ok(_) = self.paused.wait_for(|paused| !*paused) => true,
+        };
+        if resumed {
+            info!("resumed");
+            reporter.set_state(state);
+        }
+        resumed
+    }
+}
+
+impl Reporter {
+    //lock the progress of the torrent
+    pub fn progress(&self) -> MutexGuard<'_, ProgressTracker> {
+        self.progress.lock().expect("progress is not poisoned")
+    }
+
+    //send event to the subscribers of the session
+    //the channel never waits for slow subscribers, they lose the oldest events instead
+    pub fn send(&self, event: Event) {
+        //a session without subscribers is fine
+        let _ = self.events.send(event);
+    }
+
+    //switch the torrent to state, telling subscribers if that is a change
+    pub fn set_state(&self, state: TorrentState) {
+        let changed = {
+            let mut progress = self.progress();
+            let changed = progress.state() != state;
+            progress.set_state(state);
+            changed
+        };
+        if changed {
+            self.send(Event::StateChanged {
+                info_hash: self.info_hash,
+                state,
+            });
+        }
+    }
+
+    //tell subscribers what tracker answered to an announce
+    pub fn announced(&self, tracker: &[u8], status: &TrackerStatus) {
+        self.send(Event::TrackerAnnounce {
+            info_hash: self.info_hash,
+            tracker: String::from_utf8_lossy(tracker).into_owned(),
+            ok: true,
+            peers: status.peers_returned,
+            seeders: status.seeders,
+            leechers: status.leechers,
+        });
+    }
+
+    //tell subscribers why an announce to tracker failed
+    pub fn announce_failed(&self, tracker: &[u8], message: &str) {
+        let tracker = String::from_utf8_lossy(tracker).into_owned();
+        self.send(Event::TrackerAnnounce {
+            info_hash: self.info_hash,
+            tracker: tracker.clone(),
+            ok: false,
+            peers: 0,
+            seeders: None,
+            leechers: None,
+        });
+        self.send(Event::TrackerError {
+            info_hash: self.info_hash,
+            tracker,
+            message: message.to_string(),
+        });
+    }
+
+    //record the error the torrent ended with for its handles and subscribers
+    pub fn failed(&self, error: &TorrentError) {
+        let message = error_message(error);
+        *self.error.lock().expect("error is not poisoned") = Some(message.clone());
+        self.send(Event::Error {
+            info_hash: self.info_hash,
+            message,
+        });
+    }
+}
+
+//fetch the pieces missing from storage from web seeds one at a time, then the ones they could not
+//serve from http seeds, calling verified with the index and length of every piece written,
+//and flush them to disk once every piece is in
+async fn fetch_missing(
+    web_seeds: &mut WebSeeds,
+    http_seeds: &mut HttpSeeds,
+    torrent: &Torrent<'_>,
+    storage: &mut impl Storage,
+    mut verified: impl FnMut(usize, u64),
+) -> Result<(), WebSeedError> {
+    let info = &torrent.info;
+    //web seeds first, pieces they cannot serve are left to the http seeds
+    for index in 0..info.num_pieces() {
+        if storage.have(index) || web_seeds.active() == 0 {
+            continue;
+        }
+        match web_seeds.fetch_piece(info, index).await {
+            Ok(data) => {
+                storage.write_block(index, 0, &data)?;
+                verified(index, data.len() as u64);
+            }
+            Err(e) if http_seeds.active() == 0 => return Err(e),
+            Err(_) => {}
+        }
+    }
+    //http seeds are a last resort, asked for a piece once it has been missing for a while
+    while let Some(index) = (0..info.num_pieces()).find(|index| !storage.have(*index)) {
+        if http_seeds.active() == 0 {
+            return Err(WebSeedError::NoWebSeed(index));
+        }
+        http_seeds
+            .download_with_progress(info, &torrent.info_hash, storage, &mut verified)
+            .await?;
+        if let Some(wait) = http_seeds.next_fallback_in() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+    storage.flush()?;
+    Ok(())
+}
+
+//render an error and its sources on one line, e.g. for TorrentStatus::Error
+fn error_message(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(err) = source {
+        //most errors of this crate already show their source in their own message
+        let text = err.to_string();
+        if !message.contains(&text) {
+            message.push_str(&format!(": {}", text));
+        }
+        source = err.source();
+    }
+    message
+}
+
+//save which pieces of torrent are in storage, so the next start does not check all the data again
+fn save_resume(
+    torrent: &Torrent<'_>,
+    storage: &mut impl Storage,
+    config: &SessionConfig,
+    downloaded: u64,
+) -> Result<(), TorrentError> {
+    //data kept in memory is gone with the session, so there is nothing to resume
+    if config.in_memory {
+        return Ok(());
+    }
+    let mut resume =
+        ResumeData::from_storage(&torrent.info_hash, &torrent.info, storage).map_err(|source| {
+            TorrentError::Storage {
+                dir: config.out_dir.clone(),
+                source,
+            }
+        })?;
+    resume.downloaded = downloaded;
+    resume
+        .save(&config.resume_dir())
+        .map_err(|source| TorrentError::SaveResume {
+            dir: config.resume_dir(),
+            source,
+        })?;
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use crate::core::peer::connection::PeerConnection;
+use crate::core::peer::message::{MAX_REQUEST, Message, bitfield};
+use crate::core::peer::peer_error::PeerError;
+use crate::core::session::event::Event;
+use crate::core::session::torrent_task::Reporter;
+use crate::core::storage::storage::Storage;
+use crate::core::torrent::torrent::TorrentFile;
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+use tracing::debug;
+
+//most peers a torrent uploads to at once, the others stay choked until one of them leaves
+pub const UPLOAD_SLOTS: usize = 4;
+
+//time a peer may send nothing, not even a keep-alive, before it is disconnected
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+//serve blocks of the torrent in storage to a peer that connected to us,
+//telling subscribers of the session when the peer comes and goes
+pub(crate) async fn upload<S: Storage>(
+    connection: PeerConnection,
+    storage: S,
+    torrent_file: Arc<TorrentFile>,
+    reporter: Reporter,
+    slots: Arc<Semaphore>,
+) {
+    let Some(addr) = connection.peer_addr() else {
+        return;
+    };
+    reporter.send(Event::PeerConnected {
+        info_hash: reporter.info_hash,
+        addr,
+    });
+    if let Err(e) = serve(connection, storage, &torrent_file, &reporter, slots).await {
+        debug!(peer = %addr, error = %e, "peer disconnected");
+    }
+    reporter.send(Event::PeerDisconnected {
+        info_hash: reporter.info_hash,
+        addr,
+    });
+}
+
+//tell the peer the pieces we have, unchoke it once it is interested and a slot is free,
+//and answer its requests for blocks of those pieces, counting the bytes as uploaded
+//requests of a choked peer, for pieces we do not have or of more than MAX_REQUEST bytes are
+//rejected if the peer supports the Fast extension, and ignored otherwise
+async fn serve<S: Storage>(
+    mut connection: PeerConnection,
+    mut storage: S,
+    torrent_file: &TorrentFile,
+    reporter: &Reporter,
+    slots: Arc<Semaphore>,
+) -> Result<(), PeerError> {
+    let info = &torrent_file.torrent().info;
+    let fast = connection.handshake().supports_fast();
+    //pieces verified from now on are announced to the peer with a have
+    let mut events = reporter.events.subscribe();
+    let bits = bitfield(info.num_pieces(), |index| storage.have(index));
+    connection.send(&Message::Bitfield(bits)).await?;
+    let mut interested = false;
+    let mut slot: Option<OwnedSemaphorePermit> = None; //held while the peer is unchoked
+    loop {
+        tokio::select! {
+            message = timeout(IDLE_TIMEOUT, connection.recv()) => {
+                match message.map_err(|_| PeerError::Timeout)?? {
+                    Message::Interested => interested = true,
+                    Message::NotInterested => {
+                        interested = false;
+                        //the slot goes to a peer that wants something
+                        if slot.take().is_some() {
+                            connection.send(&Message::Choke).await?;
+                        }
+                    }
+                    Message::Request {
+                        index,
+                        begin,
+                        length,
+                    } => {
+                        let piece_len = info.piece_len(index as usize);
+                        let valid = slot.is_some()
+                            && length > 0
+                            && length <= MAX_REQUEST
+                            && piece_len.is_some_and(|piece_len| {
+                                begin as u64 + length as u64 <= piece_len
+                            })
+                            && storage.have(index as usize);
+                        if !valid {
+                            debug!(index, begin, length, "refused request");
+                            if fast {
+                                connection
+                                    .send(&Message::RejectRequest {
+                                        index,
+                                        begin,
+                                        length,
+                                    })
+                                    .await?;
+                            }
+                            continue;
+                        }
+                        let data =
+                            storage.read_block(index as usize, begin as u64, length as usize)?;
+                        connection
+                            .send(&Message::Piece { index, begin, data })
+                            .await?;
+                        reporter.progress().uploaded(length as u64);
+                    }
+                    //blocks are sent as soon as they are asked for, so there is nothing to cancel
+                    _ => {}
+                }
+            }
+            Some(permit) = acquire(&slots), if interested && slot.is_none() => {
+                slot = Some(permit);
+                connection.send(&Message::Unchoke).await?;
+            }
+            event = events.recv() => match event {
+                Ok(Event::PieceVerified { info_hash, index }) if info_hash == reporter.info_hash => {
+                    connection.send(&Message::Have(index as u32)).await?;
+                }
+                //the session is gone
+                Err(RecvError::Closed) => return Ok(()),
+                _ => {}
+            },
+        }
+    }
+}
+
+//wait for a free upload slot, None if there never will be one
+async fn acquire(slots: &Arc<Semaphore>) -> Option<OwnedSemaphorePermit> {
+    slots.clone().acquire_owned().await.ok()
+}
@@ -0,0 +1,212 @@
+use crate::core::torrent::torrent::TorrentFile;
+use crate::util::hex;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+//subfolder of the watch directory picked up torrent files are moved into
+pub const ADDED_DIR: &str = "added";
+//extension of the files in the watch directory that hold a torrent paused, named by its info hash
+pub const PAUSE_EXTENSION: &str = "pause";
+
+//directory that torrent files are dropped into, checked for new ones on every scan
+#[derive(Debug)]
+pub struct WatchDir {
+    dir: PathBuf,                                      //directory that is watched
+    move_added: bool,                                  //move picked up files into ADDED_DIR
+    known: HashSet<[u8; 20]>,                          //info hashes of torrents picked up already
+    seen: HashMap<PathBuf, (u64, Option<SystemTime>)>, //size and modification time of files read
+}
+
+impl WatchDir {
+    //watch dir, moving the torrent files that are picked up into its added folder if move_added is set
+    pub fn new(dir: &Path, move_added: bool) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            move_added,
+            known: HashSet::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    //remember a torrent that runs already, e.g. one restored after a restart,
+    //so dropping its torrent file again does not add it twice
+    pub fn add_known(&mut self, info_hash: [u8; 20]) {
+        self.known.insert(info_hash);
+    }
+
+    //read the torrent files that appeared or changed since the last scan
+    //a file that is not a valid torrent, e.g. because it is still being written, is tried again
+    //once it changes, and torrents that were picked up already are ignored
+    pub fn scan(&mut self) -> io::Result<Vec<TorrentFile>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let is_torrent = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("torrent"));
+            if is_torrent && entry.file_type()?.is_file() {
+                let metadata = entry.metadata()?;
+                paths.push((path, (metadata.len(), metadata.modified().ok())));
+            }
+        }
+        paths.sort();
+        //files that are gone are forgotten, so putting one back is noticed
+        self.seen
+            .retain(|seen, _| paths.iter().any(|(path, _)| path == seen));
+
+        let mut added = Vec::new();
+        for (path, stamp) in paths {
+            if self.seen.insert(path.clone(), stamp) == Some(stamp) {
+                continue;
+            }
+            let torrent_file = match TorrentFile::from_file(&path) {
+                Ok(torrent_file) => torrent_file,
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "cannot read torrent file, trying again when it changes");
+                    continue;
+                }
+            };
+            if !self.known.insert(torrent_file.torrent().info_hash) {
+                info!(path = %path.display(), "ignoring torrent that was added already");
+                continue;
+            }
+            if self.move_added {
+                self.move_to_added(&path);
+            }
+            added.push(torrent_file);
+        }
+        Ok(added)
+    }
+
+    //get the info hashes of the torrents that are paused by a file named like
+    //0123456789abcdef0123456789abcdef01234567.pause in the watch directory
+    pub fn paused(&self) -> io::Result<HashSet<[u8; 20]>> {
+        let mut paused = HashSet::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != PAUSE_EXTENSION) {
+                continue;
+            }
+            let info_hash = path
+                .file_stem()
+                .and_then(|stem| hex::decode(stem.as_encoded_bytes()).ok())
+                .and_then(|bytes| <[u8; 20]>::try_from(bytes).ok());
+            match info_hash {
+                Some(info_hash) => {
+                    paused.insert(info_hash);
+                }
+                None => warn!(path = %path.display(), "pause file is not named by an info hash"),
+            }
+        }
+        Ok(paused)
+    }
+
+    //move a picked up torrent file into the added folder, the torrent runs either way
+    fn move_to_added(&mut self, path: &Path) {
+        let added = self.dir.join(ADDED_DIR);
+        let Some(name) = path.file_name() else {
+            return;
+        };
+        match fs::create_dir_all(&added).and_then(|_| fs::rename(path, added.join(name))) {
+            Ok(()) => {
+                self.seen.remove(path);
+            }
+            Err(e) => warn!(path = %path.display(), error = %e, "cannot move torrent file"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::test_dir::temp_dir;
+
+    use sha1::{Digest, Sha1};
+
+    //bytes of a single file torrent named name holding data
+    fn torrent(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = format!(
+            "d4:infod6:lengthi{}e4:name{}:{}12:piece lengthi16e6:pieces{}:",
+            data.len(),
+            name.len(),
+            name,
+            data.len().div_ceil(16) * 20
+        )
+        .into_bytes();
+        bytes.extend(data.chunks(16).flat_map(Sha1::digest));
+        bytes.extend(b"ee");
+        bytes
+    }
+
+    #[test]
+    fn dropped_torrents_are_picked_up_once() {
+        let dir = temp_dir("watch-once");
+        let mut watch = WatchDir::new(&dir, false);
+        assert!(watch.scan().unwrap().is_empty());
+
+        fs::write(dir.join("a.torrent"), torrent("a", b"first")).unwrap();
+        fs::write(dir.join("notes.txt"), b"not a torrent").unwrap();
+        let added = watch.scan().unwrap();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].torrent().info.name, "a");
+        assert!(watch.scan().unwrap().is_empty());
+
+        //the same torrent under another name is a duplicate
+        fs::write(dir.join("copy.torrent"), torrent("a", b"first")).unwrap();
+        assert!(watch.scan().unwrap().is_empty());
+
+        //a torrent that is still being written is picked up once it is complete
+        let bytes = torrent("b", b"second");
+        fs::write(dir.join("b.torrent"), &bytes[..10]).unwrap();
+        assert!(watch.scan().unwrap().is_empty());
+        fs::write(dir.join("b.torrent"), &bytes).unwrap();
+        let added = watch.scan().unwrap();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].torrent().info.name, "b");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn picked_up_files_can_be_moved() {
+        let dir = temp_dir("watch-move");
+        let mut watch = WatchDir::new(&dir, true);
+        let bytes = torrent("a", b"first");
+        fs::write(dir.join("a.torrent"), &bytes).unwrap();
+        assert_eq!(watch.scan().unwrap().len(), 1);
+        assert!(!dir.join("a.torrent").exists());
+        assert_eq!(
+            fs::read(dir.join(ADDED_DIR).join("a.torrent")).unwrap(),
+            bytes
+        );
+
+        //restored torrents are known before the first scan
+        let mut watch = WatchDir::new(&dir, true);
+        watch.add_known(
+            TorrentFile::from_bytes(bytes.clone())
+                .unwrap()
+                .torrent()
+                .info_hash,
+        );
+        fs::write(dir.join("again.torrent"), &bytes).unwrap();
+        assert!(watch.scan().unwrap().is_empty());
+        assert!(dir.join("again.torrent").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pause_files_name_info_hashes() {
+        let dir = temp_dir("watch-pause");
+        let watch = WatchDir::new(&dir, false);
+        fs::write(dir.join(format!("{}.pause", hex::encode(&[7; 20]))), b"").unwrap();
+        fs::write(dir.join("movie.pause"), b"").unwrap();
+        assert_eq!(watch.paused().unwrap(), HashSet::from([[7; 20]]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
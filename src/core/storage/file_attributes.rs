@@ -0,0 +1,240 @@
+use crate::core::storage::storage_error::StorageError;
+use crate::core::torrent::torrent::{FileDetails, Info};
+
+use std::ffi::OsStr;
+use std::fs;
+use std::iter;
+use std::path::{Component, Path, PathBuf};
+
+//apply the BEP 47 attributes of a multi file torrent downloaded to dir
+//executable files get execute permission on unix and symlinks replace their empty files
+//hidden files need no work on unix, padding files are left alone
+pub fn apply_attributes(info: &Info, dir: &Path) -> Result<(), StorageError> {
+    let FileDetails::MultiFile { files } = &info.file_details else {
+        return Ok(());
+    };
+    let root = dir.join(relative_path(&[info.raw_name])?);
+
+    for file in files {
+        let path = relative_path(&file.path)?;
+        if file.is_symlink() {
+            let target = relative_path(file.symlink_path.as_deref().unwrap_or_default())?;
+            //point the link at the target relative to its own directory, so the download can move
+            let depth = path.components().count() - 1;
+            let target: PathBuf = iter::repeat_n(Component::ParentDir, depth)
+                .map(|c| c.as_os_str())
+                .chain(target.iter())
+                .collect();
+            create_symlink(&target, &root.join(&path))?;
+        } else if file.is_executable() {
+            set_executable(&root.join(&path))?;
+        }
+    }
+
+    Ok(())
+}
+
+//join torrent path components into a relative path that stays below the torrent root
+//components that are empty, "." or "..", or contain a separator are rejected
+//on unix the bytes are used as they are, other systems need them to be UTF-8
+pub fn relative_path(components: &[&[u8]]) -> Result<PathBuf, StorageError> {
+    let unsafe_path = || {
+        let text: Vec<_> = components
+            .iter()
+            .map(|component| String::from_utf8_lossy(component))
+            .collect();
+        StorageError::UnsafePath(text.join("/"))
+    };
+    if components.is_empty() {
+        return Err(unsafe_path());
+    }
+
+    let mut path = PathBuf::new();
+    for component in components {
+        if component.is_empty()
+            || *component == b"."
+            || *component == b".."
+            || component.iter().any(|b| matches!(b, b'/' | b'\\' | b'\0'))
+        {
+            return Err(unsafe_path());
+        }
+        path.push(os_component(component).ok_or_else(unsafe_path)?);
+    }
+
+    Ok(path)
+}
+
+//file names are bytes on unix, so names in another encoding than UTF-8 are kept
+#[cfg(unix)]
+fn os_component(bytes: &[u8]) -> Option<&OsStr> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Some(OsStr::from_bytes(bytes))
+}
+
+//other systems store names as text
+#[cfg(not(unix))]
+fn os_component(bytes: &[u8]) -> Option<&OsStr> {
+    std::str::from_utf8(bytes).ok().map(OsStr::new)
+}
+
+//create a symlink at link pointing to target, replacing a file already there
+fn create_symlink(target: &Path, link: &Path) -> Result<(), StorageError> {
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(link).is_ok() {
+        fs::remove_file(link)?;
+    }
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, link)?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_file(target, link)?;
+
+    Ok(())
+}
+
+//let everyone who may read the file also execute it
+//a file that is not there, e.g. one the user deleted, is left alone
+#[cfg(unix)]
+fn set_executable(path: &Path) -> Result<(), StorageError> {
+    use std::io::ErrorKind;
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = match fs::metadata(path) {
+        Ok(metadata) => metadata.permissions(),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let mode = permissions.mode();
+    permissions.set_mode(mode | (mode & 0o444) >> 2);
+    fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+//other systems have no execute permission to set
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> Result<(), StorageError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::torrent::torrent::TorrentFile;
+
+    //multi file torrent named root with the given bencoded file dicts
+    fn torrent(files: &str) -> TorrentFile {
+        let bytes = format!(
+            "d4:infod5:filesl{files}e4:name4:root12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee"
+        );
+        TorrentFile::from_bytes(bytes.into_bytes()).unwrap()
+    }
+
+    #[test]
+    fn attributes_are_parsed() {
+        let file = torrent(
+            "d4:attr2:xz6:lengthi3e4:pathl3:bineed4:attr1:l6:lengthi0e4:pathl1:d4:linke\
+            12:symlink pathl3:bineed4:attr1:h6:lengthi1e4:pathl4:.hideed4:attr1:p6:lengthi2e\
+            4:pathl4:.pad1:2eed6:lengthi1e4:pathl1:pee",
+        );
+        let FileDetails::MultiFile { files } = &file.torrent().info.file_details else {
+            panic!("{:?}", file.torrent().info.file_details);
+        };
+        //unknown letters are kept
+        assert_eq!(files[0].attr, Some(&b"xz"[..]));
+        assert!(files[0].is_executable() && !files[0].is_symlink());
+        assert!(files[1].is_symlink());
+        assert_eq!(files[1].symlink_path, Some(vec![&b"bin"[..]]));
+        assert!(files[2].is_hidden() && files[3].is_padding());
+        assert!(files[4].attr.is_none() && !files[4].is_executable());
+
+        //a symlink needs a target
+        let bytes = b"d4:infod5:filesld4:attr1:l6:lengthi0e4:pathl1:xeee4:name4:root\
+            12:piece lengthi16384e6:pieces0:ee";
+        assert!(TorrentFile::from_bytes(bytes.to_vec()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn attributes_are_applied_below_the_root() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let file = torrent(
+            "d4:attr1:x6:lengthi3e4:pathl3:bineed4:attr1:l6:lengthi0e4:pathl1:d4:linke\
+            12:symlink pathl3:binee",
+        );
+        let dir = std::env::temp_dir().join(format!("motteseed-attr-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root/d")).unwrap();
+        fs::write(dir.join("root/bin"), b"abc").unwrap();
+        fs::set_permissions(dir.join("root/bin"), fs::Permissions::from_mode(0o640)).unwrap();
+        fs::write(dir.join("root/d/link"), b"").unwrap();
+
+        apply_attributes(&file.torrent().info, &dir).unwrap();
+        //execute permission follows read permission
+        let mode = fs::metadata(dir.join("root/bin"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o750);
+        //links point relative to their own directory
+        assert_eq!(
+            fs::read_link(dir.join("root/d/link")).unwrap(),
+            Path::new("../bin")
+        );
+        assert_eq!(fs::read(dir.join("root/d/link")).unwrap(), b"abc");
+
+        for target in ["l2:..3:bine", "l0:e", "le", "l5:../..e", "l4:/etce"] {
+            let file = torrent(&format!(
+                "d4:attr1:l6:lengthi0e4:pathl1:xe12:symlink path{target}ed6:lengthi1e4:pathl1:zee"
+            ));
+            let result = apply_attributes(&file.torrent().info, &dir);
+            assert!(
+                matches!(result, Err(StorageError::UnsafePath(_))),
+                "{}",
+                target
+            );
+        }
+        assert!(!dir.join("root/x").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_executables_are_skipped() {
+        let file = torrent(
+            "d4:attr1:x6:lengthi0e4:pathl5:emptyeed4:attr1:x6:lengthi1e4:pathl1:zee",
+        );
+        let dir = std::env::temp_dir()
+            .join(format!("motteseed-attr-missing-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root")).unwrap();
+        fs::write(dir.join("root/z"), b"z").unwrap();
+
+        apply_attributes(&file.torrent().info, &dir).unwrap();
+        assert!(!dir.join("root/empty").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn paths_must_stay_below_the_root() {
+        assert_eq!(
+            relative_path(&[b"a", b"b"]).unwrap(),
+            Path::new("a").join("b")
+        );
+        for path in [
+            &[][..],
+            &[&b""[..]],
+            &[b"."],
+            &[b"a", b".."],
+            &[b"a/b"],
+            &[b"a\\b"],
+            &[b"/etc"],
+        ] {
+            assert!(relative_path(path).is_err(), "{:?}", path);
+        }
+        //names that are not UTF-8 can only be written where names are bytes
+        assert_eq!(relative_path(&[b"\xff"]).is_ok(), cfg!(unix));
+    }
+}
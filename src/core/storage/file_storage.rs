@@ -0,0 +1,536 @@
+use crate::core::storage::file_attributes::relative_path;
+use crate::core::storage::storage::Storage;
+use crate::core::storage::storage_error::StorageError;
+use crate::core::storage::written_ranges::WrittenRanges;
+use crate::core::torrent::torrent::{FileDetails, Info};
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+//extension of files that are still being downloaded, when part files are used
+pub const PART_EXTENSION: &str = "part";
+
+//a file of the torrent as laid out in the piece data
+#[derive(Debug)]
+struct StorageFile {
+    start: u64,            //offset of the file in the torrent data
+    length: u64,           //file length in bytes
+    path: Option<PathBuf>, //location on disk, None for padding and symlinks, which are not stored
+    part: Option<PathBuf>, //location while the file is incomplete, None once it has its final name
+}
+
+impl StorageFile {
+    //get where the file is on disk now
+    fn location(&self) -> Option<&Path> {
+        self.part.as_deref().or(self.path.as_deref())
+    }
+}
+
+//storage backend that writes the torrent data into its files below a download directory
+//files are created on first write, padding files are never created and read back as zeros
+//empty files are created once the piece they sit in is complete
+//with part files on, a file is written as <name>.part and renamed once all its pieces are in
+#[derive(Debug)]
+pub struct FileStorage {
+    piece_length: u64,                      //nominal size of each piece in bytes
+    total_length: u64,                      //total size of the torrent data in bytes
+    files: Vec<StorageFile>,                //files in the order they are laid out in the pieces
+    written: HashMap<usize, WrittenRanges>, //ranges written so far, keyed by piece index
+}
+
+impl FileStorage {
+    //create storage for info below dir, multi file torrents get a directory named like the torrent
+    pub fn new(info: &Info, dir: &Path) -> Result<Self, StorageError> {
+        let name = relative_path(&[info.raw_name])?;
+        let mut files = Vec::new();
+        match &info.file_details {
+            FileDetails::SingleFile { length } => files.push(StorageFile {
+                start: 0,
+                length: *length,
+                path: Some(dir.join(name)),
+                part: None,
+            }),
+            FileDetails::MultiFile { files: entries } => {
+                let root = dir.join(name);
+                let mut start = 0u64;
+                for entry in entries {
+                    let path = if entry.is_padding() || entry.is_symlink() {
+                        None
+                    } else {
+                        Some(root.join(relative_path(&entry.path)?))
+                    };
+                    files.push(StorageFile {
+                        start,
+                        length: entry.length,
+                        path,
+                        part: None,
+                    });
+                    start = start.checked_add(entry.length).ok_or_else(|| {
+                        StorageError::OutOfBounds("torrent is larger than u64::MAX bytes".into())
+                    })?;
+                }
+            }
+        }
+
+        Ok(Self {
+            piece_length: info.piece_length,
+            total_length: info.total_length(),
+            files,
+            written: HashMap::new(),
+        })
+    }
+
+    //write files that are not complete under their part path, call it before anything is written
+    //a file found under its final name is complete or was started without part files, it keeps that name
+    //a file found under its part path was started with part files, it stays there until complete
+    pub fn set_part_files(&mut self, enabled: bool) {
+        for file in &mut self.files {
+            file.part = match &file.path {
+                Some(path) if !path.exists() => {
+                    let part = part_path(path);
+                    (enabled || part.exists()).then_some(part)
+                }
+                _ => None,
+            };
+        }
+    }
+
+    //check if path is the part path of a file that is not complete yet
+    pub fn is_partial(&self, path: &Path) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.part.as_deref() == Some(path))
+    }
+
+    //get the location on disk of file index, None for padding files, symlinks and unknown indices
+    pub fn file_path(&self, index: usize) -> Option<&Path> {
+        self.files.get(index)?.location()
+    }
+
+    //get the location on disk and the length of every file that is stored
+    pub fn files(&self) -> impl Iterator<Item = (&Path, u64)> + '_ {
+        self.files
+            .iter()
+            .filter_map(|file| Some((file.location()?, file.length)))
+    }
+
+    //check if any file of the torrent exists on disk, e.g. left by an earlier run
+    pub fn any_file_exists(&self) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.location().is_some_and(|path| path.exists()))
+    }
+
+    //record piece index as present, e.g. after data left by an earlier run passed its hash check
+    //part files that this piece completes get their final names
+    pub fn mark_have(&mut self, index: usize) -> Result<(), StorageError> {
+        if let Some(piece_len) = self.piece_len(index) {
+            self.written.entry(index).or_default().insert(0, piece_len);
+            self.finish_files(index)?;
+        }
+        Ok(())
+    }
+
+    //rename the part files touched by piece index whose pieces are all present
+    //and create the empty files that sit in it, which are never written
+    fn finish_files(&mut self, index: usize) -> Result<(), StorageError> {
+        let piece_start = index as u64 * self.piece_length;
+        let piece_end = piece_start + self.piece_len(index).unwrap_or(0);
+        let last_piece = self.total_length.saturating_sub(1) / self.piece_length;
+        for i in 0..self.files.len() {
+            let file = &self.files[i];
+            if file.length == 0 {
+                //an empty file sits in the piece its offset falls in, or the last one at the end
+                if let Some(path) = &file.path
+                    && (file.start / self.piece_length).min(last_piece) == index as u64
+                {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    File::create(path)?;
+                    self.files[i].part = None;
+                }
+                continue;
+            }
+            let (Some(path), Some(part)) = (&file.path, &file.part) else {
+                continue;
+            };
+            if file.start >= piece_end || file.start + file.length <= piece_start {
+                continue;
+            }
+            let first = file.start / self.piece_length;
+            let last = (file.start + file.length - 1) / self.piece_length;
+            if !(first..=last).all(|piece| self.have(piece as usize)) {
+                continue;
+            }
+            match fs::rename(part, path) {
+                //a file that was never written has nothing to rename
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                result => result?,
+            }
+            self.files[i].part = None;
+        }
+        Ok(())
+    }
+
+    //length of piece index, or None if out of range
+    fn piece_len(&self, index: usize) -> Option<u64> {
+        let start = (index as u64).checked_mul(self.piece_length)?;
+        if start >= self.total_length {
+            return None;
+        }
+        Some((self.total_length - start).min(self.piece_length))
+    }
+
+    //check that [begin, begin + length) lies within piece index
+    fn check_bounds(&self, index: usize, begin: u64, length: usize) -> Result<u64, StorageError> {
+        let piece_len = self
+            .piece_len(index)
+            .ok_or_else(|| StorageError::OutOfBounds(format!("piece {} does not exist", index)))?;
+        if begin + length as u64 > piece_len {
+            return Err(StorageError::OutOfBounds(format!(
+                "block {}+{} does not fit in piece {} of {} bytes",
+                begin, length, index, piece_len
+            )));
+        }
+        Ok(piece_len)
+    }
+
+    //split length bytes at offset of the torrent data into the parts of the files they cover
+    //yields the file, the offset within it, and the start and length of the part within the range
+    fn spans(
+        &self,
+        offset: u64,
+        length: usize,
+    ) -> impl Iterator<Item = (&StorageFile, u64, usize, usize)> + '_ {
+        let end = offset + length as u64;
+        let first = self
+            .files
+            .partition_point(|file| file.start + file.length <= offset);
+
+        self.files[first..]
+            .iter()
+            .take_while(move |file| file.start < end)
+            .filter(|file| file.length > 0)
+            .map(move |file| {
+                let from = offset.max(file.start);
+                let to = end.min(file.start + file.length);
+                (
+                    file,
+                    from - file.start,
+                    (from - offset) as usize,
+                    (to - from) as usize,
+                )
+            })
+    }
+}
+
+impl Storage for FileStorage {
+    fn write_block(&mut self, index: usize, begin: u64, data: &[u8]) -> Result<(), StorageError> {
+        self.check_bounds(index, begin, data.len())?;
+        let offset = index as u64 * self.piece_length + begin;
+
+        for (file, file_offset, at, len) in self.spans(offset, data.len()) {
+            //bytes of padding files are dropped, they are zeros by definition
+            let Some(path) = file.location() else {
+                continue;
+            };
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut handle = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            handle.seek(SeekFrom::Start(file_offset))?;
+            handle.write_all(&data[at..at + len])?;
+        }
+
+        self.written
+            .entry(index)
+            .or_default()
+            .insert(begin, begin + data.len() as u64);
+        if self.have(index) {
+            self.finish_files(index)?;
+        }
+        Ok(())
+    }
+
+    fn read_block(
+        &mut self,
+        index: usize,
+        begin: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.check_bounds(index, begin, length)?;
+        let offset = index as u64 * self.piece_length + begin;
+
+        //padding, files not created yet and their unwritten tails read back as zeros
+        let mut data = vec![0; length];
+        for (file, file_offset, at, len) in self.spans(offset, length) {
+            let Some(path) = file.location() else {
+                continue;
+            };
+
+            let mut handle = match File::open(path) {
+                Ok(handle) => handle,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            handle.seek(SeekFrom::Start(file_offset))?;
+            let mut buf = &mut data[at..at + len];
+            while !buf.is_empty() {
+                match handle.read(buf)? {
+                    0 => break,
+                    read => buf = &mut buf[read..],
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn flush(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn have(&self, index: usize) -> bool {
+        match (self.written.get(&index), self.piece_len(index)) {
+            (Some(written), Some(piece_len)) => written.is_full(piece_len),
+            _ => false,
+        }
+    }
+}
+
+//get the location a file at path is kept at while it is incomplete, path with .part added
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(PART_EXTENSION);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::torrent::torrent::TorrentFile;
+    use crate::util::test_dir::temp_path;
+
+    #[test]
+    fn padding_is_never_written_and_reads_as_zeros() {
+        //x of 10 bytes, padded to the piece boundary before y of 40000 bytes
+        let bytes = format!(
+            "d4:infod5:filesld6:lengthi10e4:pathl1:xeed4:attr1:p6:lengthi16374e\
+            4:pathl4:.pad5:16374eed6:lengthi40000e4:pathl1:yeee4:name3:dir\
+            12:piece lengthi16384e6:pieces80:{}ee",
+            "a".repeat(80)
+        );
+        let file = TorrentFile::from_bytes(bytes.into_bytes()).unwrap();
+        let info = &file.torrent().info;
+        assert_eq!(info.total_length(), 56384);
+        assert_eq!(info.content_length(), 40010);
+
+        let dir = temp_path("fs-padding");
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        let x: Vec<u8> = (1..=10).collect();
+        let y: Vec<u8> = (0..40000u32).map(|i| (i % 250 + 1) as u8).collect();
+        let mut stream = x.clone();
+        stream.extend(vec![0; 16374]);
+        stream.extend_from_slice(&y);
+        //blocks that do not line up with the files
+        for (index, piece) in stream.chunks(16384).enumerate() {
+            assert!(!storage.have(index));
+            for (block, data) in piece.chunks(5000).enumerate() {
+                storage
+                    .write_block(index, block as u64 * 5000, data)
+                    .unwrap();
+            }
+            assert!(storage.have(index));
+        }
+
+        let mut names: Vec<_> = fs::read_dir(dir.join("dir"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(fs::read(dir.join("dir/x")).unwrap(), x);
+        assert_eq!(fs::read(dir.join("dir/y")).unwrap(), y);
+        assert_eq!(storage.read_block(0, 0, 16384).unwrap(), stream[..16384]);
+        assert_eq!(storage.read_block(0, 5, 100).unwrap(), stream[5..105]);
+        let last = 56384 - 3 * 16384;
+        assert_eq!(storage.read_block(3, 0, last).unwrap(), stream[3 * 16384..]);
+        assert!(storage.read_block(3, 0, last + 1).is_err());
+
+        //padding does not need its files
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(storage.read_block(0, 10, 100).unwrap(), vec![0; 100]);
+        //and is left out of the summary
+        let summary = file.torrent().to_string();
+        assert!(summary.contains("(40010 bytes)"), "{}", summary);
+        assert!(summary.contains("Files:        2") && !summary.contains(".pad"));
+    }
+
+    #[test]
+    fn single_files_are_named_like_the_torrent() {
+        let bytes = b"d4:infod6:lengthi5e4:name1:a12:piece lengthi4e\
+            6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaee";
+        let file = TorrentFile::from_bytes(bytes.to_vec()).unwrap();
+        let dir = temp_path("fs-single");
+        let mut storage = FileStorage::new(&file.torrent().info, &dir).unwrap();
+        storage.write_block(1, 0, b"e").unwrap();
+        storage.write_block(0, 0, b"abcd").unwrap();
+        assert_eq!(fs::read(dir.join("a")).unwrap(), b"abcde");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn part_files_get_their_names_with_their_last_piece() {
+        //a is in pieces 0 and 1, b in pieces 1 and 2
+        let bytes = format!(
+            "d4:infod5:filesld6:lengthi6e4:pathl1:aeed6:lengthi5e4:pathl1:beee4:name3:dir\
+            12:piece lengthi4e6:pieces60:{}ee",
+            "a".repeat(60)
+        );
+        let file = TorrentFile::from_bytes(bytes.into_bytes()).unwrap();
+        let info = &file.torrent().info;
+        let dir = temp_path("fs-part");
+        let root = dir.join("dir");
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(true);
+        storage.write_block(0, 0, b"aaaa").unwrap();
+        storage.write_block(2, 0, b"bbb").unwrap();
+        assert!(storage.is_partial(&root.join("a.part")));
+        assert_eq!(storage.file_path(1), Some(root.join("b.part").as_path()));
+
+        //stopping here leaves only part files behind
+        let mut names: Vec<_> = fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.part", "b.part"]);
+
+        //a later run finds the part files and renames them once the pieces are known to be there
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(true);
+        assert!(storage.any_file_exists());
+        storage.mark_have(0).unwrap();
+        storage.mark_have(2).unwrap();
+        assert!(!root.join("a").exists());
+        storage.write_block(1, 0, b"aa").unwrap();
+        assert!(!root.join("a").exists() && !root.join("b").exists());
+        storage.write_block(1, 2, b"bb").unwrap();
+        assert_eq!(fs::read(root.join("a")).unwrap(), b"aaaaaa");
+        assert_eq!(fs::read(root.join("b")).unwrap(), b"bbbbb");
+        assert!(!root.join("a.part").exists() && !root.join("b.part").exists());
+        assert_eq!(storage.read_block(1, 0, 4).unwrap(), b"aabb");
+
+        //complete files keep their names
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(true);
+        assert_eq!(storage.file_path(0), Some(root.join("a").as_path()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_are_found_whether_part_files_are_on_or_off() {
+        //a is in pieces 0 and 1, b in pieces 1 and 2
+        let bytes = format!(
+            "d4:infod5:filesld6:lengthi6e4:pathl1:aeed6:lengthi4e4:pathl1:beee4:name3:dir\
+            12:piece lengthi4e6:pieces60:{}ee",
+            "a".repeat(60)
+        );
+        let file = TorrentFile::from_bytes(bytes.into_bytes()).unwrap();
+        let info = &file.torrent().info;
+        let dir = temp_path("fs-switch");
+        let root = dir.join("dir");
+
+        //a is started with part files, b without them
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(true);
+        storage.write_block(0, 0, b"aaaa").unwrap();
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(false);
+        assert_eq!(storage.file_path(0), Some(root.join("a.part").as_path()));
+        storage.write_block(2, 0, b"bb").unwrap();
+        assert!(root.join("b").exists());
+
+        //with part files on again b keeps its name, with them off a is finished under its own
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(true);
+        assert_eq!(storage.file_path(1), Some(root.join("b").as_path()));
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(false);
+        storage.mark_have(0).unwrap();
+        assert_eq!(storage.read_block(0, 0, 4).unwrap(), b"aaaa");
+        storage.write_block(1, 0, b"aabb").unwrap();
+        assert_eq!(fs::read(root.join("a")).unwrap(), b"aaaaaa");
+        assert!(!root.join("a.part").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_files_are_created_with_the_piece_they_sit_in() {
+        //e between a and b, and f in a subdirectory after the last byte
+        let bytes = format!(
+            "d4:infod5:filesld6:lengthi6e4:pathl1:aeed6:lengthi0e4:pathl1:eeed6:lengthi5e\
+            4:pathl1:beed6:lengthi0e4:pathl1:d1:feee4:name3:dir\
+            12:piece lengthi4e6:pieces60:{}ee",
+            "a".repeat(60)
+        );
+        let file = TorrentFile::from_bytes(bytes.into_bytes()).unwrap();
+        let info = &file.torrent().info;
+        let dir = temp_path("fs-empty");
+        let root = dir.join("dir");
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(true);
+        storage.write_block(0, 0, b"aaaa").unwrap();
+        assert!(!root.join("e").exists());
+        storage.write_block(1, 0, b"aabb").unwrap();
+        assert_eq!(fs::read(root.join("e")).unwrap(), b"");
+        assert!(!root.join("e.part").exists() && !root.join("d").exists());
+        storage.write_block(2, 0, b"bbb").unwrap();
+        assert_eq!(fs::read(root.join("d/f")).unwrap(), b"");
+        assert_eq!(storage.file_path(3), Some(root.join("d/f").as_path()));
+
+        //a later run that finds the data on disk creates them too
+        fs::remove_file(root.join("e")).unwrap();
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        storage.set_part_files(false);
+        storage.mark_have(1).unwrap();
+        assert!(root.join("e").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn latin1_paths_keep_their_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let bytes = b"d4:infod5:filesld6:lengthi5e4:pathl4:caf\xe9eee4:name3:dir\
+            12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let file = TorrentFile::from_bytes(bytes.to_vec()).unwrap();
+        let dir = temp_path("fs-latin1");
+        let storage = FileStorage::new(&file.torrent().info, &dir).unwrap();
+        let path = storage.file_path(0).unwrap();
+        assert_eq!(path.file_name().unwrap().as_bytes(), b"caf\xe9");
+        assert!(!dir.exists());
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn latin1_paths_are_refused() {
+        let bytes = b"d4:infod5:filesld6:lengthi5e4:pathl4:caf\xe9eee4:name3:dir\
+            12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let file = TorrentFile::from_bytes(bytes.to_vec()).unwrap();
+        let dir = temp_path("fs-latin1");
+        let err = FileStorage::new(&file.torrent().info, &dir).unwrap_err();
+        assert_eq!(err.to_string(), "Unsafe path: caf\u{fffd}");
+        assert!(!dir.exists());
+    }
+}
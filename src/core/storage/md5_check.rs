@@ -0,0 +1,175 @@
+use crate::core::storage::storage::Storage;
+use crate::core::storage::storage_error::StorageError;
+use crate::core::torrent::torrent::{FileDetails, Info};
+
+use md5::{Digest, Md5};
+
+//outcome of checking a file against the md5sum in the torrent
+#[derive(Debug, Clone, PartialEq)]
+pub enum Md5Status {
+    Match,                         //file data hashes to the md5sum
+    Mismatch { actual: [u8; 16] }, //file data hashes to something else
+    Incomplete,                    //some pieces of the file are missing, it was not hashed
+}
+
+//result for a single file that carries an md5sum
+#[derive(Debug, Clone)]
+pub struct Md5Check {
+    pub file: usize,        //index of the file in the torrent, 0 for single file torrents
+    pub expected: [u8; 16], //md5sum from the torrent
+    pub status: Md5Status,  //outcome of the check
+}
+
+//hash every complete file that has an md5sum and compare it, files without one are skipped
+//this is an extra check on top of piece verification, only worth it when asked for
+pub fn verify_md5<S: Storage>(info: &Info, storage: &mut S) -> Result<Vec<Md5Check>, StorageError> {
+    if info.piece_length == 0 {
+        return Err(StorageError::OutOfBounds("piece length is 0".into()));
+    }
+
+    //length and md5sum of every file, in the order they are laid out in the pieces
+    let files: Vec<(u64, Option<[u8; 16]>)> = match &info.file_details {
+        FileDetails::SingleFile { length } => vec![(*length, info.md5sum)],
+        FileDetails::MultiFile { files } => files
+            .iter()
+            .map(|file| (file.length, file.md5sum))
+            .collect(),
+    };
+
+    let mut checks = Vec::new();
+    let mut offset = 0;
+    for (file, (length, md5sum)) in files.into_iter().enumerate() {
+        let start = offset;
+        offset += length;
+        let Some(expected) = md5sum else {
+            continue;
+        };
+
+        let status = match hash_range(storage, info.piece_length, start, length)? {
+            None => Md5Status::Incomplete,
+            Some(actual) if actual == expected => Md5Status::Match,
+            Some(actual) => Md5Status::Mismatch { actual },
+        };
+        checks.push(Md5Check {
+            file,
+            expected,
+            status,
+        });
+    }
+
+    Ok(checks)
+}
+
+//MD5 of the length bytes at offset of the torrent data, None if a piece they lie in is missing
+fn hash_range<S: Storage>(
+    storage: &mut S,
+    piece_length: u64,
+    offset: u64,
+    length: u64,
+) -> Result<Option<[u8; 16]>, StorageError> {
+    let end = offset + length;
+    if length > 0 {
+        let first = offset / piece_length;
+        let last = (end - 1) / piece_length;
+        if !(first..=last).all(|index| storage.have(index as usize)) {
+            return Ok(None);
+        }
+    }
+
+    //read piece by piece so large files are never held in memory at once
+    let mut hasher = Md5::new();
+    let mut pos = offset;
+    while pos < end {
+        let index = pos / piece_length;
+        let begin = pos % piece_length;
+        let len = (piece_length - begin).min(end - pos);
+        hasher.update(storage.read_block(index as usize, begin, len as usize)?);
+        pos += len;
+    }
+
+    Ok(Some(hasher.finalize().into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::storage::memory_storage::MemoryStorage;
+    use crate::core::torrent::torrent::TorrentFile;
+    use crate::util::hex;
+
+    //md5 of data
+    fn md5(data: &[u8]) -> [u8; 16] {
+        Md5::digest(data).into()
+    }
+
+    //torrent of files with the given lengths and md5sums, in pieces of 16 bytes
+    fn torrent(files: &[(u64, Option<[u8; 16]>)]) -> TorrentFile {
+        let mut bytes = b"d4:infod5:filesl".to_vec();
+        let mut total = 0;
+        for (n, (length, md5sum)) in files.iter().enumerate() {
+            bytes.extend(format!("d6:lengthi{}e", length).as_bytes());
+            if let Some(md5sum) = md5sum {
+                bytes.extend(format!("6:md5sum32:{}", hex::encode(md5sum)).as_bytes());
+            }
+            bytes.extend(format!("4:pathl1:{}ee", n).as_bytes());
+            total += length;
+        }
+        let pieces = total.div_ceil(16) as usize * 20;
+        bytes.extend(format!("e4:name1:x12:piece lengthi16e6:pieces{}:", pieces).as_bytes());
+        bytes.extend(vec![0; pieces]);
+        bytes.extend(b"ee");
+        TorrentFile::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn files_with_md5sums_are_checked() {
+        let data: Vec<u8> = (0..100).collect();
+        let torrent = torrent(&[
+            (30, Some(md5(&data[..30]))),
+            (0, Some(md5(b""))),
+            (40, Some([0; 16])),
+            (20, None),
+            (10, Some(md5(&data[90..]))),
+        ]);
+        let info = &torrent.torrent().info;
+        let mut storage = MemoryStorage::seeded(info, &data).unwrap();
+        let checks: Vec<(usize, Md5Status)> = verify_md5(info, &mut storage)
+            .unwrap()
+            .into_iter()
+            .map(|check| (check.file, check.status))
+            .collect();
+        let mismatch = Md5Status::Mismatch {
+            actual: md5(&data[30..70]),
+        };
+        assert_eq!(
+            checks,
+            [
+                (0, Md5Status::Match),
+                (1, Md5Status::Match),
+                (2, mismatch),
+                (4, Md5Status::Match)
+            ]
+        );
+    }
+
+    #[test]
+    fn files_with_missing_pieces_are_not_hashed() {
+        let data: Vec<u8> = (0..40).collect();
+        let torrent = torrent(&[(20, Some(md5(&data[..20]))), (20, Some(md5(&data[20..])))]);
+        let info = &torrent.torrent().info;
+        let mut storage = MemoryStorage::empty(info);
+        storage.write_block(0, 0, &data[..16]).unwrap();
+        storage.write_block(2, 0, &data[32..]).unwrap();
+        let checks = verify_md5(info, &mut storage).unwrap();
+        //piece 1 is shared by both files
+        assert!(
+            checks
+                .iter()
+                .all(|check| check.status == Md5Status::Incomplete)
+        );
+        storage.write_block(1, 0, &data[16..32]).unwrap();
+        let checks = verify_md5(info, &mut storage).unwrap();
+        assert!(checks.iter().all(|check| check.status == Md5Status::Match));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::core::storage::storage::Storage;
+use crate::core::storage::storage_error::StorageError;
+use crate::core::storage::written_ranges::WrittenRanges;
+use crate::core::torrent::torrent::Info;
+
+use std::collections::HashMap;
+
+//data held for a single piece
+#[derive(Debug)]
+struct MemoryPiece {
+    data: Box<[u8]>,        //piece bytes, zero where nothing was written
+    written: WrittenRanges, //ranges of the piece that were written
+}
+
+impl MemoryPiece {
+    fn new(length: u64) -> Self {
+        Self {
+            data: vec![0; length as usize].into_boxed_slice(),
+            written: WrittenRanges::default(),
+        }
+    }
+
+    //check if every byte of the piece was written
+    fn is_full(&self) -> bool {
+        self.written.is_full(self.data.len() as u64)
+    }
+}
+
+//storage backend that keeps all piece data in memory, used by tests, benchmarks and
+//sessions that keep their torrents in memory
+#[derive(Debug)]
+pub struct MemoryStorage {
+    piece_length: u64,                   //nominal size of each piece in bytes
+    total_length: u64,                   //total size of the torrent data in bytes
+    pieces: HashMap<usize, MemoryPiece>, //pieces allocated so far, keyed by index
+}
+
+impl MemoryStorage {
+    //create storage for info with no data present
+    pub fn empty(info: &Info) -> Self {
+        Self {
+            piece_length: info.piece_length,
+            total_length: info.total_length(),
+            pieces: HashMap::new(),
+        }
+    }
+
+    //create storage for info already holding the complete torrent data
+    pub fn seeded(info: &Info, data: &[u8]) -> Result<Self, StorageError> {
+        let mut storage = Self::empty(info);
+        if data.len() as u64 != storage.total_length {
+            return Err(StorageError::OutOfBounds(format!(
+                "seed data is {} bytes, torrent is {} bytes",
+                data.len(),
+                storage.total_length
+            )));
+        }
+
+        for (index, chunk) in data.chunks(storage.piece_length as usize).enumerate() {
+            storage.write_block(index, 0, chunk)?;
+        }
+
+        Ok(storage)
+    }
+
+    //number of pieces in the torrent
+    pub fn num_pieces(&self) -> usize {
+        self.total_length.div_ceil(self.piece_length) as usize
+    }
+
+    //length of piece index, or None if out of range
+    fn piece_len(&self, index: usize) -> Option<u64> {
+        let start = (index as u64).checked_mul(self.piece_length)?;
+        if start >= self.total_length {
+            return None;
+        }
+        Some((self.total_length - start).min(self.piece_length))
+    }
+
+    //check that [begin, begin + length) lies within piece index
+    fn check_bounds(&self, index: usize, begin: u64, length: usize) -> Result<u64, StorageError> {
+        let piece_len = self
+            .piece_len(index)
+            .ok_or_else(|| StorageError::OutOfBounds(format!("piece {} does not exist", index)))?;
+        if begin + length as u64 > piece_len {
+            return Err(StorageError::OutOfBounds(format!(
+                "block {}+{} does not fit in piece {} of {} bytes",
+                begin, length, index, piece_len
+            )));
+        }
+        Ok(piece_len)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn write_block(&mut self, index: usize, begin: u64, data: &[u8]) -> Result<(), StorageError> {
+        let piece_len = self.check_bounds(index, begin, data.len())?;
+        let piece = self
+            .pieces
+            .entry(index)
+            .or_insert_with(|| MemoryPiece::new(piece_len));
+
+        let start = begin as usize;
+        piece.data[start..start + data.len()].copy_from_slice(data);
+        piece.written.insert(begin, begin + data.len() as u64);
+
+        Ok(())
+    }
+
+    fn read_block(
+        &mut self,
+        index: usize,
+        begin: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.check_bounds(index, begin, length)?;
+
+        //unwritten pieces read back as zeroes, like a sparse file
+        let start = begin as usize;
+        Ok(match self.pieces.get(&index) {
+            Some(piece) => piece.data[start..start + length].to_vec(),
+            None => vec![0; length],
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn have(&self, index: usize) -> bool {
+        self.pieces.get(&index).is_some_and(MemoryPiece::is_full)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::torrent::torrent::TorrentFile;
+
+    //torrent of 40 bytes in pieces of 16, the hashes are not checked by the storage
+    fn torrent() -> TorrentFile {
+        let mut bytes = b"d4:infod6:lengthi40e4:name1:x12:piece lengthi16e6:pieces60:".to_vec();
+        bytes.extend([0; 60]);
+        bytes.extend(b"ee");
+        TorrentFile::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn pieces_are_had_once_every_byte_is_written() {
+        let torrent = torrent();
+        let mut storage = MemoryStorage::empty(&torrent.torrent().info);
+        assert_eq!(storage.num_pieces(), 3);
+        storage.write_block(0, 8, &[1; 8]).unwrap();
+        storage.write_block(0, 4, &[2; 8]).unwrap();
+        assert!(!storage.have(0));
+        storage.write_block(0, 0, &[3; 4]).unwrap();
+        assert!(storage.have(0));
+        assert_eq!(
+            storage.read_block(0, 2, 8).unwrap(),
+            [3, 3, 2, 2, 2, 2, 2, 2]
+        );
+        //pieces never written read back as zeroes
+        assert_eq!(storage.read_block(1, 0, 4).unwrap(), [0; 4]);
+        assert!(!storage.have(1));
+    }
+
+    #[test]
+    fn seeded_storage_holds_every_piece() {
+        let torrent = torrent();
+        let info = &torrent.torrent().info;
+        let data: Vec<u8> = (0..40).collect();
+        let mut storage = MemoryStorage::seeded(info, &data).unwrap();
+        assert!((0..3).all(|index| storage.have(index)));
+        assert_eq!(storage.read_block(2, 0, 8).unwrap(), data[32..]);
+        assert!(MemoryStorage::seeded(info, &data[1..]).is_err());
+    }
+
+    #[test]
+    fn blocks_outside_pieces_are_refused() {
+        let torrent = torrent();
+        let mut storage = MemoryStorage::empty(&torrent.torrent().info);
+        assert!(storage.read_block(2, 0, 9).is_err());
+        assert!(storage.read_block(3, 0, 1).is_err());
+        assert!(storage.write_block(0, 16, &[1]).is_err());
+    }
+}
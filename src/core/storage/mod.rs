@@ -0,0 +1,11 @@
+pub mod file_attributes;
+pub mod file_storage;
+pub mod md5_check;
+pub mod memory_storage;
+pub mod preflight;
+pub mod recheck;
+pub mod shared_storage;
+pub mod storage;
+pub mod storage_error;
+pub mod write_cache;
+pub mod written_ranges;
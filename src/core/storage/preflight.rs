@@ -0,0 +1,207 @@
+use crate::core::storage::file_storage::FileStorage;
+use crate::core::storage::storage_error::StorageError;
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+
+//what to do about files of a torrent that are on disk before it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExistingFiles {
+    Refuse,    //refuse files of another size, they likely belong to another torrent
+    Keep,      //keep them, resume data or a recheck tells which pieces they hold
+    Overwrite, //download over them, cutting files that are too long to their size
+}
+
+//tells how much space is left for new data below a directory, a trait so tests can fake a full disk
+pub trait DiskSpace {
+    //get the bytes that may still be written below dir
+    fn available(&self, dir: &Path) -> io::Result<u64>;
+}
+
+//asks the file system for the free space
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemDiskSpace;
+
+impl DiskSpace for SystemDiskSpace {
+    #[cfg(unix)]
+    fn available(&self, dir: &Path) -> io::Result<u64> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        //SAFETY: path is a valid C string and stat is only read after statvfs filled it in
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        //blocks available to unprivileged users, the reserved ones can not be used by us
+        Ok((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+    }
+
+    //other systems are not checked, a full disk shows up as a write error instead
+    #[cfg(not(unix))]
+    fn available(&self, _dir: &Path) -> io::Result<u64> {
+        Ok(u64::MAX)
+    }
+}
+
+//get dir ready for the data of storage before anything is written
+//the directory and its parents are created, it has to be writable, existing files are handled as
+//existing says, and the disk needs room for the bytes the existing files do not cover yet
+pub fn preflight(
+    storage: &FileStorage,
+    dir: &Path,
+    existing: ExistingFiles,
+    space: &impl DiskSpace,
+) -> Result<(), StorageError> {
+    fs::create_dir_all(dir)?;
+    check_writable(dir)?;
+
+    let mut needed = 0u64;
+    for (path, length) in storage.files() {
+        let found = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            //a directory where a file belongs is never the torrent's data
+            Ok(_) => {
+                return Err(StorageError::ExistingFileMismatch {
+                    path: path.to_path_buf(),
+                    expected: length,
+                    found: 0,
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                needed = needed.saturating_add(length);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        //a part file is ours and written out of order, so it is shorter until it is complete
+        let partial = storage.is_partial(path) && found <= length;
+        match existing {
+            ExistingFiles::Refuse if found != length && !partial => {
+                return Err(StorageError::ExistingFileMismatch {
+                    path: path.to_path_buf(),
+                    expected: length,
+                    found,
+                });
+            }
+            ExistingFiles::Overwrite if found > length => {
+                OpenOptions::new().write(true).open(path)?.set_len(length)?;
+            }
+            _ => {}
+        }
+        needed = needed.saturating_add(length.saturating_sub(found));
+    }
+
+    let available = space.available(dir)?;
+    if needed > available {
+        return Err(StorageError::InsufficientSpace { needed, available });
+    }
+    Ok(())
+}
+
+//check that files can be created in dir by creating and removing one
+fn check_writable(dir: &Path) -> io::Result<()> {
+    let probe = dir.join(format!(".motteseed-write-test-{}", std::process::id()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)?;
+    fs::remove_file(&probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::torrent::torrent::TorrentFile;
+    use crate::util::test_dir::temp_path;
+
+    //disk with a fixed amount of free space
+    struct FakeDisk(u64);
+
+    impl DiskSpace for FakeDisk {
+        fn available(&self, _dir: &Path) -> io::Result<u64> {
+            Ok(self.0)
+        }
+    }
+
+    //a single file torrent named f of 1000 bytes
+    fn torrent() -> TorrentFile {
+        let mut bytes = b"d4:infod6:lengthi1000e4:name1:f12:piece lengthi1024e6:pieces20:".to_vec();
+        bytes.extend([0; 20]);
+        bytes.extend(b"ee");
+        TorrentFile::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn missing_directories_are_created() {
+        let root = temp_path("preflight-create");
+        let dir = root.join("a").join("b");
+        let torrent_file = torrent();
+        let storage = FileStorage::new(&torrent_file.torrent().info, &dir).unwrap();
+        preflight(&storage, &dir, ExistingFiles::Refuse, &FakeDisk(1000)).unwrap();
+        assert!(dir.is_dir());
+        //the write test leaves nothing behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn a_full_disk_fails_early() {
+        let dir = temp_path("preflight-space");
+        let torrent_file = torrent();
+        let storage = FileStorage::new(&torrent_file.torrent().info, &dir).unwrap();
+        let err = preflight(&storage, &dir, ExistingFiles::Refuse, &FakeDisk(999)).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                StorageError::InsufficientSpace {
+                    needed: 1000,
+                    available: 999
+                }
+            ),
+            "{:?}",
+            err
+        );
+
+        //data that is on disk already needs no more room
+        fs::write(dir.join("f"), [0; 1000]).unwrap();
+        preflight(&storage, &dir, ExistingFiles::Refuse, &FakeDisk(0)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_of_another_size_are_refused() {
+        let dir = temp_path("preflight-mismatch");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("f"), [0; 1500]).unwrap();
+        let torrent_file = torrent();
+        let storage = FileStorage::new(&torrent_file.torrent().info, &dir).unwrap();
+        let disk = FakeDisk(u64::MAX);
+
+        let err = preflight(&storage, &dir, ExistingFiles::Refuse, &disk).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                StorageError::ExistingFileMismatch {
+                    expected: 1000,
+                    found: 1500,
+                    ..
+                }
+            ),
+            "{:?}",
+            err
+        );
+        assert!(err.to_string().contains("--force-recheck"), "{}", err);
+
+        //a recheck sorts out the pieces, an overwrite cuts the file to size
+        preflight(&storage, &dir, ExistingFiles::Keep, &disk).unwrap();
+        assert_eq!(fs::metadata(dir.join("f")).unwrap().len(), 1500);
+        preflight(&storage, &dir, ExistingFiles::Overwrite, &disk).unwrap();
+        assert_eq!(fs::metadata(dir.join("f")).unwrap().len(), 1000);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use crate::core::storage::storage::Storage;
+use crate::core::storage::storage_error::StorageError;
+use crate::core::torrent::torrent::{FileDetails, Info};
+
+use sha1::{Digest, Sha1};
+
+//outcome of hashing the data of a torrent that is already in storage
+#[derive(Debug, Clone)]
+pub struct Recheck {
+    pub pieces: Vec<bool>,     //whether each piece matches its hash, by piece index
+    pub files: Vec<FileCheck>, //files in the order they are laid out in the pieces
+}
+
+//how much of a single file lies in pieces that match their hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCheck {
+    pub length: u64,      //file length in bytes
+    pub valid_bytes: u64, //bytes of the file inside valid pieces
+}
+
+impl Recheck {
+    //number of pieces that match their hash
+    pub fn valid_pieces(&self) -> usize {
+        self.pieces.iter().filter(|&&valid| valid).count()
+    }
+
+    //indices of the pieces that are missing or do not match their hash
+    pub fn bad_pieces(&self) -> Vec<usize> {
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, valid)| !**valid)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    //check if every piece matches its hash
+    pub fn is_complete(&self) -> bool {
+        self.pieces.iter().all(|&valid| valid)
+    }
+}
+
+impl FileCheck {
+    //check if every byte of the file lies in valid pieces
+    pub fn is_complete(&self) -> bool {
+        self.valid_bytes == self.length
+    }
+}
+
+//hash every piece of the torrent in storage and compare it with the hash in the info dict
+//missing data reads back as zeros and fails the check, so a partial download is fine to check
+//progress is called with the pieces checked so far and the piece count after every piece
+pub fn recheck<S: Storage>(
+    info: &Info,
+    storage: &mut S,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<Recheck, StorageError> {
+    if info.piece_length == 0 {
+        return Err(StorageError::OutOfBounds("piece length is 0".into()));
+    }
+    //pure v2 torrents only have per-file merkle trees, which are not checked here
+    let count = info.num_pieces();
+    if count == 0 && info.total_length() > 0 {
+        return Err(StorageError::NoPieceHashes);
+    }
+
+    let mut pieces = Vec::with_capacity(count);
+    for index in 0..count {
+        let valid = match (info.piece_len(index), info.piece_hash(index)) {
+            (Some(len), Some(expected)) => {
+                let data = storage.read_block(index, 0, len as usize)?;
+                Sha1::digest(&data).as_slice() == expected
+            }
+            //hashes past the end of the data are rejected when the torrent is read
+            _ => false,
+        };
+        pieces.push(valid);
+        progress(index + 1, count);
+    }
+
+    //length of every file, in the order they are laid out in the pieces
+    let lengths: Vec<u64> = match &info.file_details {
+        FileDetails::SingleFile { length } => vec![*length],
+        FileDetails::MultiFile { files } => files.iter().map(|file| file.length).collect(),
+    };
+
+    let mut files = Vec::with_capacity(lengths.len());
+    let mut offset = 0;
+    for length in lengths {
+        files.push(FileCheck {
+            length,
+            valid_bytes: valid_bytes(&pieces, info.piece_length, offset, length),
+        });
+        offset += length;
+    }
+
+    Ok(Recheck { pieces, files })
+}
+
+//bytes of the length bytes at offset of the torrent data that lie in valid pieces
+fn valid_bytes(pieces: &[bool], piece_length: u64, offset: u64, length: u64) -> u64 {
+    let end = offset + length;
+    let mut valid = 0;
+    let mut pos = offset;
+    while pos < end {
+        let index = pos / piece_length;
+        let piece_end = ((index + 1) * piece_length).min(end);
+        if pieces.get(index as usize) == Some(&true) {
+            valid += piece_end - pos;
+        }
+        pos = piece_end;
+    }
+    valid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::storage::file_storage::FileStorage;
+    use crate::core::torrent::create::{CreateOptions, create};
+    use crate::core::torrent::torrent::TorrentFile;
+
+    use std::fs;
+
+    #[test]
+    fn damaged_and_missing_data_is_reported_by_piece() {
+        let dir = std::env::temp_dir().join(format!("motteseed-recheck-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("t/s")).unwrap();
+        fs::write(dir.join("t/a"), vec![1; 40_000]).unwrap();
+        fs::write(dir.join("t/s/b"), vec![2; 30_000]).unwrap();
+        let options = CreateOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        };
+        let file = TorrentFile::from_bytes(create(&dir.join("t"), &options).unwrap()).unwrap();
+        let info = &file.torrent().info;
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+
+        let mut calls = 0;
+        let result = recheck(info, &mut storage, |checked, count| {
+            calls += 1;
+            assert_eq!((checked, count), (calls, 5));
+        })
+        .unwrap();
+        assert!(result.is_complete());
+        let complete = |length| FileCheck {
+            length,
+            valid_bytes: length,
+        };
+        assert_eq!(result.files, [complete(40_000), complete(30_000)]);
+
+        //a byte in piece 1 and one in piece 2, which a shares with b
+        let mut a = fs::read(dir.join("t/a")).unwrap();
+        a[20_000] = 9;
+        a[39_999] = 9;
+        fs::write(dir.join("t/a"), a).unwrap();
+        let result = recheck(info, &mut storage, |_, _| {}).unwrap();
+        assert_eq!(result.bad_pieces(), [1, 2]);
+        assert_eq!(result.valid_pieces(), 3);
+        assert_eq!(result.files[0].valid_bytes, 16384);
+        assert_eq!(result.files[1].valid_bytes, 30_000 - (3 * 16384 - 40_000));
+
+        fs::remove_file(dir.join("t/s/b")).unwrap();
+        let mut storage = FileStorage::new(info, &dir).unwrap();
+        let result = recheck(info, &mut storage, |_, _| {}).unwrap();
+        assert_eq!(result.bad_pieces(), [1, 2, 3, 4]);
+        assert_eq!(result.files[1].valid_bytes, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+use crate::core::storage::storage::Storage;
+use crate::core::storage::storage_error::StorageError;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+//storage used by the download of a torrent and the peers it uploads to at the same time
+//clones share the same storage, which is locked for each call
+#[derive(Debug)]
+pub struct SharedStorage<S: Storage> {
+    storage: Arc<Mutex<S>>,
+}
+
+impl<S: Storage> Clone for SharedStorage<S> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+        }
+    }
+}
+
+impl<S: Storage> SharedStorage<S> {
+    //share storage
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage: Arc::new(Mutex::new(storage)),
+        }
+    }
+
+    //lock the storage, e.g. to make several calls no other user comes between
+    pub fn lock(&self) -> MutexGuard<'_, S> {
+        self.storage.lock().expect("storage is not poisoned")
+    }
+}
+
+impl<S: Storage> Storage for SharedStorage<S> {
+    fn write_block(&mut self, index: usize, begin: u64, data: &[u8]) -> Result<(), StorageError> {
+        self.lock().write_block(index, begin, data)
+    }
+
+    fn read_block(
+        &mut self,
+        index: usize,
+        begin: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.lock().read_block(index, begin, length)
+    }
+
+    fn flush(&mut self) -> Result<(), StorageError> {
+        self.lock().flush()
+    }
+
+    fn have(&self, index: usize) -> bool {
+        self.lock().have(index)
+    }
+}
@@ -0,0 +1,45 @@
+use crate::core::storage::storage_error::StorageError;
+
+//backend that keeps piece data for a torrent
+pub trait Storage {
+    //write a block of data at offset begin within piece index
+    fn write_block(&mut self, index: usize, begin: u64, data: &[u8]) -> Result<(), StorageError>;
+
+    //read length bytes at offset begin within piece index
+    fn read_block(
+        &mut self,
+        index: usize,
+        begin: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, StorageError>;
+
+    //push any buffered data down to the backing medium
+    fn flush(&mut self) -> Result<(), StorageError>;
+
+    //check if every byte of piece index has been written
+    fn have(&self, index: usize) -> bool;
+}
+
+//boxed storage, e.g. one of several backends picked at runtime
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn write_block(&mut self, index: usize, begin: u64, data: &[u8]) -> Result<(), StorageError> {
+        (**self).write_block(index, begin, data)
+    }
+
+    fn read_block(
+        &mut self,
+        index: usize,
+        begin: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        (**self).read_block(index, begin, length)
+    }
+
+    fn flush(&mut self) -> Result<(), StorageError> {
+        (**self).flush()
+    }
+
+    fn have(&self, index: usize) -> bool {
+        (**self).have(index)
+    }
+}
@@ -0,0 +1,36 @@
+use crate::util::units::format_bytes;
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+//custom error enum for storage operations
+#[derive(Error, Debug)]
+pub enum StorageError {
+    //block lies outside the torrent data
+    #[error("Out of bounds: {0}")]
+    OutOfBounds(String),
+
+    //io error with a display message
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    //torrent without v1 piece hashes, e.g. a pure v2 one, so its data can not be checked
+    #[error("Torrent has no v1 piece hashes to check the data against")]
+    NoPieceHashes,
+
+    //download directory without room for the data that is still missing
+    #[error("Not enough disk space: need {}, {} available", format_bytes(*needed), format_bytes(*available))]
+    InsufficientSpace { needed: u64, available: u64 },
+
+    //file on disk that has another size than in the torrent, so it likely belongs to another one
+    #[error("Existing file {} is {found} bytes instead of {expected}, use --force-recheck or --overwrite to use it anyway", path.display())]
+    ExistingFileMismatch {
+        path: PathBuf,
+        expected: u64,
+        found: u64,
+    },
+
+    //path from the torrent that could escape the download directory
+    #[error("Unsafe path: {0}")]
+    UnsafePath(String),
+}
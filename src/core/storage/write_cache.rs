@@ -0,0 +1,338 @@
+use crate::core::storage::storage::Storage;
+use crate::core::storage::storage_error::StorageError;
+use crate::core::storage::written_ranges::WrittenRanges;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+//bytes of blocks a torrent holds in memory before they are written to disk
+pub const DEFAULT_WRITE_CACHE: usize = 16 * 1024 * 1024;
+
+//blocks buffered in memory for a single piece
+#[derive(Debug, Default)]
+struct CachedPiece {
+    blocks: BTreeMap<u64, Vec<u8>>, //block data keyed by offset within the piece
+    buffered: u64,                  //total bytes held for this piece
+    written: WrittenRanges,         //ranges of the piece the blocks cover, overlaps counted once
+}
+
+//in-memory write cache that coalesces blocks into whole pieces before they hit storage
+#[derive(Debug)]
+pub struct WriteCache<S: Storage> {
+    storage: S,                          //backend the cache flushes into
+    piece_length: u64,                   //nominal size of each piece in bytes
+    total_length: u64,                   //total size of the torrent data in bytes
+    capacity: usize,                     //maximum number of bytes held in memory
+    used: usize,                         //bytes currently held in memory
+    pieces: HashMap<usize, CachedPiece>, //buffered pieces keyed by index
+    order: VecDeque<usize>,              //piece indices, oldest first
+}
+
+impl<S: Storage> WriteCache<S> {
+    //create a write cache in front of storage holding at most capacity bytes
+    pub fn new(storage: S, piece_length: u64, total_length: u64, capacity: usize) -> Self {
+        Self {
+            storage,
+            piece_length,
+            total_length,
+            capacity,
+            used: 0,
+            pieces: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    //number of bytes currently held in memory
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    //maximum number of bytes held in memory
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    //get the backing storage
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    //get the backing storage to change it, blocks still buffered are not in it yet
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    //flush everything and return the backing storage
+    pub fn into_inner(mut self) -> Result<S, StorageError> {
+        self.flush()?;
+        Ok(self.storage)
+    }
+
+    //length of piece index, accounting for a short final piece
+    fn piece_len(&self, index: usize) -> u64 {
+        let start = index as u64 * self.piece_length;
+        self.total_length
+            .saturating_sub(start)
+            .min(self.piece_length)
+    }
+
+    //check if every byte of a cached piece is buffered
+    fn is_complete(&self, index: usize) -> bool {
+        self.pieces
+            .get(&index)
+            .is_some_and(|piece| piece.written.is_full(self.piece_len(index)))
+    }
+
+    //write a cached piece to storage, joining adjacent blocks into single writes
+    fn flush_piece(&mut self, index: usize) -> Result<(), StorageError> {
+        let Some(piece) = self.pieces.remove(&index) else {
+            return Ok(());
+        };
+        self.order.retain(|&i| i != index);
+        self.used -= piece.buffered as usize;
+
+        let mut run_begin = 0;
+        let mut run: Vec<u8> = Vec::new();
+        for (begin, data) in piece.blocks {
+            if !run.is_empty() && run_begin + run.len() as u64 != begin {
+                self.storage.write_block(index, run_begin, &run)?;
+                run.clear();
+            }
+            if run.is_empty() {
+                run_begin = begin;
+            }
+            run.extend_from_slice(&data);
+        }
+        if !run.is_empty() {
+            self.storage.write_block(index, run_begin, &run)?;
+        }
+
+        Ok(())
+    }
+
+    //flush the oldest complete piece, or the oldest partial piece if none is complete
+    fn evict(&mut self) -> Result<(), StorageError> {
+        let victim = self
+            .order
+            .iter()
+            .copied()
+            .find(|&index| self.is_complete(index))
+            .or_else(|| self.order.front().copied());
+
+        match victim {
+            Some(index) => self.flush_piece(index),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: Storage> Storage for WriteCache<S> {
+    //buffer a block, flushing older pieces first when the memory cap would be exceeded
+    fn write_block(&mut self, index: usize, begin: u64, data: &[u8]) -> Result<(), StorageError> {
+        let end = begin.checked_add(data.len() as u64);
+        if end.is_none_or(|end| end > self.piece_len(index)) {
+            return Err(StorageError::OutOfBounds(format!(
+                "block {}+{} does not fit in piece {}",
+                begin,
+                data.len(),
+                index
+            )));
+        }
+
+        //blocks larger than the whole cache bypass it
+        if data.len() > self.capacity {
+            self.flush_piece(index)?;
+            return self.storage.write_block(index, begin, data);
+        }
+
+        //apply backpressure by flushing until the block fits
+        while self.used + data.len() > self.capacity && !self.order.is_empty() {
+            self.evict()?;
+        }
+
+        let piece = self.pieces.entry(index).or_insert_with(|| {
+            self.order.push_back(index);
+            CachedPiece::default()
+        });
+        if let Some(old) = piece.blocks.insert(begin, data.to_vec()) {
+            //rewrite of an already buffered block
+            piece.buffered -= old.len() as u64;
+            self.used -= old.len();
+        }
+        piece.buffered += data.len() as u64;
+        piece.written.insert(begin, begin + data.len() as u64);
+        self.used += data.len();
+
+        Ok(())
+    }
+
+    //read a block, flushing the piece first if any of it is still buffered
+    fn read_block(
+        &mut self,
+        index: usize,
+        begin: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.flush_piece(index)?;
+        self.storage.read_block(index, begin, length)
+    }
+
+    //drain every buffered piece and flush the backing storage
+    fn flush(&mut self) -> Result<(), StorageError> {
+        while let Some(&index) = self.order.front() {
+            self.flush_piece(index)?;
+        }
+        self.storage.flush()
+    }
+
+    //check if a piece is complete either in the cache or in storage
+    fn have(&self, index: usize) -> bool {
+        self.is_complete(index) || self.storage.have(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::storage::memory_storage::MemoryStorage;
+    use crate::core::torrent::torrent::TorrentFile;
+
+    //storage that records every write it gets
+    #[derive(Debug, Default)]
+    struct Recorder {
+        writes: Vec<(usize, u64, Vec<u8>)>, //index, begin and data of each write, in order
+    }
+
+    impl Storage for Recorder {
+        fn write_block(
+            &mut self,
+            index: usize,
+            begin: u64,
+            data: &[u8],
+        ) -> Result<(), StorageError> {
+            self.writes.push((index, begin, data.to_vec()));
+            Ok(())
+        }
+
+        fn read_block(
+            &mut self,
+            index: usize,
+            begin: u64,
+            length: usize,
+        ) -> Result<Vec<u8>, StorageError> {
+            let mut piece = vec![0; begin as usize + length];
+            for (_, at, data) in self.writes.iter().filter(|write| write.0 == index) {
+                let at = *at as usize;
+                let end = (at + data.len()).min(piece.len());
+                if at < end {
+                    piece[at..end].copy_from_slice(&data[..end - at]);
+                }
+            }
+            Ok(piece.split_off(begin as usize))
+        }
+
+        fn flush(&mut self) -> Result<(), StorageError> {
+            Ok(())
+        }
+
+        fn have(&self, _: usize) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn complete_pieces_are_evicted_first() {
+        let mut cache = WriteCache::new(Recorder::default(), 8, 32, 16);
+        cache.write_block(0, 0, &[1; 4]).unwrap();
+        cache.write_block(1, 0, &[2; 8]).unwrap();
+        //the new piece does not fit, so the complete one is written out, not the older partial one
+        cache.write_block(2, 0, &[3; 8]).unwrap();
+        assert_eq!(cache.storage().writes, [(1, 0, vec![2; 8])]);
+        assert_eq!(cache.used(), 12);
+
+        //a read writes out the piece first, so it sees the buffered data
+        assert_eq!(cache.read_block(2, 2, 4).unwrap(), [3; 4]);
+        assert_eq!(cache.used(), 4);
+    }
+
+    #[test]
+    fn overlapping_blocks_do_not_complete_a_piece() {
+        let mut cache = WriteCache::new(Recorder::default(), 16, 32, 64);
+        cache.write_block(0, 0, &[1; 8]).unwrap();
+        cache.write_block(0, 4, &[2; 8]).unwrap();
+        assert!(!cache.have(0));
+        cache.write_block(0, 8, &[3; 8]).unwrap();
+        assert!(cache.have(0));
+        assert_eq!(
+            cache.read_block(0, 0, 16).unwrap(),
+            [&[1; 4][..], &[2; 4], &[3; 8]].concat()
+        );
+    }
+
+    #[test]
+    fn memory_stays_under_capacity() {
+        let mut cache = WriteCache::new(Recorder::default(), 8, 32, 8);
+        for index in 0..4 {
+            cache.write_block(index, 0, &[index as u8; 4]).unwrap();
+            assert!(cache.used() <= cache.capacity());
+        }
+        //with no complete piece the oldest partial ones are written out
+        let written: Vec<usize> = cache.storage().writes.iter().map(|w| w.0).collect();
+        assert_eq!(written, [0, 1]);
+
+        //a block bigger than the whole cache is written through at once
+        let mut cache = WriteCache::new(Recorder::default(), 8, 32, 4);
+        cache.write_block(3, 0, &[1; 8]).unwrap();
+        assert_eq!(cache.used(), 0);
+        assert_eq!(cache.storage().writes.len(), 1);
+    }
+
+    #[test]
+    fn blocks_outside_pieces_are_refused() {
+        let mut cache = WriteCache::new(Recorder::default(), 8, 20, 64);
+        assert!(cache.write_block(2, 0, &[1; 4]).is_ok());
+        assert!(matches!(
+            cache.write_block(2, 2, &[1; 3]),
+            Err(StorageError::OutOfBounds(_))
+        ));
+        assert!(cache.write_block(3, 0, &[1]).is_err());
+        assert!(cache.write_block(0, u64::MAX, &[1]).is_err());
+    }
+
+    #[test]
+    fn data_survives_eviction_mid_piece() {
+        let bytes = format!(
+            "d4:infod6:lengthi40e4:name1:a12:piece lengthi16e6:pieces60:{}ee",
+            "a".repeat(60)
+        );
+        let file = TorrentFile::from_bytes(bytes.into_bytes()).unwrap();
+        let info = &file.torrent().info;
+        let data: Vec<u8> = (1..=40).collect();
+
+        //second halves first, so the cache has to write out pieces it only holds half of
+        let mut cache = WriteCache::new(MemoryStorage::empty(info), 16, 40, 12);
+        cache.write_block(0, 8, &data[8..16]).unwrap();
+        cache.write_block(1, 8, &data[24..32]).unwrap();
+        assert!(cache.used() <= cache.capacity());
+        assert!(!cache.storage().have(0));
+        assert_eq!(
+            cache.storage_mut().read_block(0, 8, 8).unwrap(),
+            data[8..16]
+        );
+        cache.write_block(2, 0, &data[32..]).unwrap();
+        cache.write_block(1, 0, &data[16..24]).unwrap();
+        cache.write_block(0, 0, &data[..8]).unwrap();
+        assert!(cache.used() <= cache.capacity());
+
+        let mut storage = cache.into_inner().unwrap();
+        for index in 0..3 {
+            assert!(storage.have(index));
+        }
+        let read: Vec<u8> = (0..3)
+            .flat_map(|index| {
+                let length = (40 - index * 16).min(16);
+                storage.read_block(index, 0, length).unwrap()
+            })
+            .collect();
+        assert_eq!(read, data);
+    }
+}
@@ -0,0 +1,27 @@
+//byte ranges of a piece that were written, used to tell when the piece is complete
+#[derive(Debug, Default)]
+pub struct WrittenRanges {
+    ranges: Vec<(u64, u64)>, //sorted, non-overlapping [start, end) ranges that were written
+}
+
+impl WrittenRanges {
+    //record that [start, end) was written, merging with touching ranges
+    pub fn insert(&mut self, start: u64, end: u64) {
+        let (mut start, mut end) = (start, end);
+        self.ranges.retain(|&(s, e)| {
+            if e < start || s > end {
+                return true;
+            }
+            start = start.min(s);
+            end = end.max(e);
+            false
+        });
+        let pos = self.ranges.partition_point(|&(s, _)| s < start);
+        self.ranges.insert(pos, (start, end));
+    }
+
+    //check if every byte of a piece of length bytes was written
+    pub fn is_full(&self, length: u64) -> bool {
+        self.ranges == [(0, length)]
+    }
+}
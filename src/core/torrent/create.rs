@@ -0,0 +1,429 @@
+use crate::core::torrent::torrent_error::CreateTorrentError;
+use crate::util::bencode::bencode_encodable::{BencodeEncodable, encode_dict};
+
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Take, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//number of pieces an automatically chosen piece length aims for
+const TARGET_PIECES: u64 = 1500;
+
+//bounds for automatically chosen piece lengths, also the ones the command line accepts
+pub const MIN_AUTO_PIECE_LENGTH: u64 = 16 * 1024;
+pub const MAX_AUTO_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+
+//pieces each hashing thread gets per batch, bounds the data held in memory
+const PIECES_PER_THREAD: usize = 4;
+
+//written to the created by key of new torrents
+const CREATED_BY: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+//settings for a new torrent
+#[derive(Debug, Clone, Default)]
+pub struct CreateOptions {
+    pub piece_length: Option<u64>, //size of each piece in bytes, None to pick one from the size
+    pub trackers: Vec<String>,     //tracker URLs, the first one becomes the announce key
+    pub comment: Option<String>,   //free-form comment stored in the torrent
+    pub private: bool,             //restrict peers to the embedded trackers (BEP 27)
+    pub source: Option<String>,    //site the torrent is made for, gives it a distinct info hash
+}
+
+//a file to put in the torrent
+#[derive(Debug)]
+struct SourceFile {
+    path: PathBuf,           //location on disk
+    components: Vec<String>, //path inside the torrent, relative to the root directory
+    length: u64,             //file length in bytes
+}
+
+//create a torrent for the file or directory at source and return its bencoded bytes
+//files of a directory are ordered by path so the same content always gives the same info hash
+pub fn create(source: &Path, options: &CreateOptions) -> Result<Vec<u8>, CreateTorrentError> {
+    create_with_progress(source, options, |_, _| {})
+}
+
+//create a torrent like create, calling progress with the bytes hashed so far and the total
+//progress is called after every batch of pieces, so about once per core and few pieces
+pub fn create_with_progress(
+    source: &Path,
+    options: &CreateOptions,
+    progress: impl FnMut(u64, u64),
+) -> Result<Vec<u8>, CreateTorrentError> {
+    let name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| CreateTorrentError::InvalidPath(source.display().to_string()))?
+        .to_string();
+
+    let single_file = fs::metadata(source)?.is_file();
+    let files = if single_file {
+        vec![SourceFile {
+            path: source.to_path_buf(),
+            components: vec![name.clone()],
+            length: fs::metadata(source)?.len(),
+        }]
+    } else {
+        let mut files = Vec::new();
+        walk(source, &mut Vec::new(), &mut files)?;
+        files
+    };
+    if files.is_empty() {
+        return Err(CreateTorrentError::NoFiles(source.display().to_string()));
+    }
+
+    let total_length = files.iter().map(|file| file.length).sum();
+    let piece_length = match options.piece_length {
+        Some(piece_length) if piece_length == 0 || !piece_length.is_power_of_two() => {
+            return Err(CreateTorrentError::InvalidPieceLength(piece_length));
+        }
+        Some(piece_length) => piece_length,
+        None => auto_piece_length(total_length),
+    };
+    let pieces = hash_pieces(&files, piece_length, total_length, progress)?;
+
+    //build the info dict, the encoder writes the keys sorted so the encoding is canonical
+    let mut info: BTreeMap<&str, &dyn BencodeEncodable> = BTreeMap::new();
+    info.insert("name", &name);
+    info.insert("piece length", &piece_length);
+    info.insert("pieces", &pieces);
+    if options.private {
+        info.insert("private", &1u64);
+    }
+    if let Some(source) = &options.source {
+        info.insert("source", source);
+    }
+    if single_file {
+        info.insert("length", &total_length);
+    } else {
+        info.insert("files", &files);
+    }
+
+    //build the outer dict
+    //every tracker gets its own tier, clients try them in order (BEP 12)
+    let tiers: Vec<Vec<&String>> = options.trackers.iter().map(|url| vec![url]).collect();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let mut torrent: BTreeMap<&str, &dyn BencodeEncodable> = BTreeMap::new();
+    if let Some(announce) = options.trackers.first() {
+        torrent.insert("announce", announce);
+    }
+    if tiers.len() > 1 {
+        torrent.insert("announce-list", &tiers);
+    }
+    if let Some(comment) = &options.comment {
+        torrent.insert("comment", comment);
+    }
+    torrent.insert("created by", &CREATED_BY);
+    torrent.insert("creation date", &now);
+    torrent.insert("info", &info);
+
+    Ok(torrent.encode())
+}
+
+//a file entry of the files list of a multi file torrent
+impl BencodeEncodable for SourceFile {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_dict(
+            w,
+            [
+                (b"length".as_slice(), &self.length as &dyn BencodeEncodable),
+                (b"path".as_slice(), &self.components),
+            ],
+        )
+    }
+}
+
+//create a torrent for source and write it to dest
+pub fn create_file(
+    source: &Path,
+    dest: &Path,
+    options: &CreateOptions,
+) -> Result<(), CreateTorrentError> {
+    let bytes = create(source, options)?;
+    fs::write(dest, bytes)?;
+    Ok(())
+}
+
+//pick a power of two piece length giving about TARGET_PIECES pieces
+fn auto_piece_length(total_length: u64) -> u64 {
+    (total_length / TARGET_PIECES)
+        .next_power_of_two()
+        .clamp(MIN_AUTO_PIECE_LENGTH, MAX_AUTO_PIECE_LENGTH)
+}
+
+//collect the files below dir, sorted by name at every level
+//components holds the path of dir inside the torrent
+fn walk(
+    dir: &Path,
+    components: &mut Vec<String>,
+    files: &mut Vec<SourceFile>,
+) -> Result<(), CreateTorrentError> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        //torrent paths are UTF-8 (BEP 3)
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| CreateTorrentError::InvalidPath(path.display().to_string()))?;
+
+        components.push(name);
+        let metadata = fs::metadata(&path)?;
+        if metadata.is_dir() {
+            walk(&path, components, files)?;
+        } else {
+            files.push(SourceFile {
+                path,
+                components: components.clone(),
+                length: metadata.len(),
+            });
+        }
+        components.pop();
+    }
+
+    Ok(())
+}
+
+//read the files as one stream and return the concatenated SHA-1 hashes of its pieces
+//pieces are read in batches on this thread and hashed on one thread per core
+fn hash_pieces(
+    files: &[SourceFile],
+    piece_length: u64,
+    total_length: u64,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<Vec<u8>, CreateTorrentError> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let batch_len = threads * PIECES_PER_THREAD;
+
+    let mut reader = FileStream {
+        files: files.iter(),
+        current: None,
+    };
+
+    let mut hashes = Vec::new();
+    let mut hashed = 0;
+    loop {
+        //read the next batch, the last piece may be short
+        let mut batch = Vec::with_capacity(batch_len);
+        for _ in 0..batch_len {
+            let mut piece = Vec::with_capacity(piece_length as usize);
+            (&mut reader).take(piece_length).read_to_end(&mut piece)?;
+            if piece.is_empty() {
+                break;
+            }
+            batch.push(piece);
+        }
+        if batch.is_empty() {
+            break;
+        }
+
+        let chunk_len = batch.len().div_ceil(threads);
+        let digests: Vec<Vec<u8>> = thread::scope(|scope| {
+            let workers: Vec<_> = batch
+                .chunks(chunk_len)
+                .map(|pieces| {
+                    scope.spawn(move || pieces.iter().flat_map(Sha1::digest).collect::<Vec<u8>>())
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("hashing thread panicked"))
+                .collect()
+        });
+        hashes.extend(digests.concat());
+        hashed += batch.iter().map(|piece| piece.len() as u64).sum::<u64>();
+        progress(hashed, total_length);
+    }
+
+    Ok(hashes)
+}
+
+//reads the files one after another, opening each only when it is reached
+struct FileStream<'a> {
+    files: std::slice::Iter<'a, SourceFile>, //files not opened yet
+    current: Option<Take<File>>,             //file being read, limited to its listed length
+}
+
+impl Read for FileStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let current = match &mut self.current {
+                Some(current) => current,
+                None => match self.files.next() {
+                    Some(file) => self
+                        .current
+                        .insert(File::open(&file.path)?.take(file.length)),
+                    None => return Ok(0),
+                },
+            };
+
+            let read = current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            //a file that shrank since it was listed would shift every later piece
+            if current.limit() > 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file shrank while hashing",
+                ));
+            }
+            self.current = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::torrent::torrent::{FileDetails, TorrentFile};
+    use crate::util::test_dir::temp_path;
+
+    #[test]
+    fn directories_become_multi_file_torrents() {
+        let root = temp_path("create-dir");
+        fs::create_dir_all(root.join("b/c")).unwrap();
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        fs::write(root.join("b/c/z"), &data[..70_000]).unwrap();
+        fs::write(root.join("a"), &data[70_000..70_010]).unwrap();
+        fs::write(root.join("b/e"), &data[70_010..]).unwrap();
+        fs::write(root.join("b/d"), b"").unwrap();
+        let options = CreateOptions {
+            piece_length: Some(16384),
+            trackers: vec!["http://t/a".into(), "udp://u:1".into()],
+            comment: Some("hi".into()),
+            private: true,
+            source: None,
+        };
+
+        let file = TorrentFile::from_bytes(create(&root, &options).unwrap()).unwrap();
+        let torrent = file.torrent();
+        assert_eq!(torrent.announce, Some(&b"http://t/a"[..]));
+        assert_eq!(torrent.announce_list.len(), 2);
+        assert_eq!(torrent.comment.as_deref(), Some("hi"));
+        assert!(torrent.info.private);
+        assert!(torrent.created_by.is_some() && torrent.creation_date.is_some());
+
+        //files are sorted by path and hashed as one stream in that order
+        let FileDetails::MultiFile { files } = &torrent.info.file_details else {
+            panic!("{:?}", torrent.info.file_details);
+        };
+        let paths: Vec<Vec<&[u8]>> = files.iter().map(|file| file.path.clone()).collect();
+        let expected: Vec<Vec<&[u8]>> = vec![
+            vec![b"a"],
+            vec![b"b", b"c", b"z"],
+            vec![b"b", b"d"],
+            vec![b"b", b"e"],
+        ];
+        assert_eq!(paths, expected);
+        let mut stream = data[70_000..70_010].to_vec();
+        stream.extend_from_slice(&data[..70_000]);
+        stream.extend_from_slice(&data[70_010..]);
+        assert_eq!(torrent.info.total_length(), stream.len() as u64);
+        assert_eq!(torrent.info.num_pieces(), stream.len().div_ceil(16384));
+        for (index, piece) in stream.chunks(16384).enumerate() {
+            let hash: [u8; 20] = Sha1::digest(piece).into();
+            assert_eq!(torrent.info.piece_hash(index), Some(&hash));
+        }
+
+        //the same content gives the same info hash
+        let again = TorrentFile::from_bytes(create(&root, &options).unwrap()).unwrap();
+        assert_eq!(again.torrent().info_hash, torrent.info_hash);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn single_files_and_bad_input() {
+        let path = temp_path("create-single");
+        fs::write(&path, vec![7u8; 70_000]).unwrap();
+        let dest = temp_path("create-single.torrent");
+        create_file(&path, &dest, &CreateOptions::default()).unwrap();
+        let file = TorrentFile::from_file(&dest).unwrap();
+        let info = &file.torrent().info;
+        assert_eq!(info.piece_length, MIN_AUTO_PIECE_LENGTH);
+        assert_eq!(info.raw_name, path.file_name().unwrap().as_encoded_bytes());
+        assert!(matches!(
+            info.file_details,
+            FileDetails::SingleFile { length: 70_000 }
+        ));
+        assert!(file.torrent().announce.is_none());
+
+        let options = CreateOptions {
+            piece_length: Some(1000),
+            ..Default::default()
+        };
+        assert!(matches!(
+            create(&path, &options),
+            Err(CreateTorrentError::InvalidPieceLength(1000))
+        ));
+        let empty = temp_path("create-empty");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(matches!(
+            create(&empty, &CreateOptions::default()),
+            Err(CreateTorrentError::NoFiles(_))
+        ));
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&dest).unwrap();
+        fs::remove_dir_all(&empty).unwrap();
+    }
+
+    #[test]
+    fn auto_piece_lengths_are_bounded_powers_of_two() {
+        assert_eq!(auto_piece_length(0), MIN_AUTO_PIECE_LENGTH);
+        assert_eq!(auto_piece_length(1500 * 1024 * 1024), 1024 * 1024);
+        assert_eq!(auto_piece_length(1501 * 1024 * 1024), 2 * 1024 * 1024);
+        assert_eq!(auto_piece_length(u64::MAX / 2), MAX_AUTO_PIECE_LENGTH);
+    }
+
+    #[test]
+    fn source_changes_the_info_hash() {
+        let path = temp_path("create-source");
+        fs::write(&path, b"hello").unwrap();
+        let plain =
+            TorrentFile::from_bytes(create(&path, &CreateOptions::default()).unwrap()).unwrap();
+        assert!(plain.torrent().info.source.is_none());
+        let options = CreateOptions {
+            source: Some("SITE".into()),
+            ..Default::default()
+        };
+        let sourced = TorrentFile::from_bytes(create(&path, &options).unwrap()).unwrap();
+        assert_eq!(sourced.torrent().info.source.as_deref(), Some("SITE"));
+        assert_ne!(sourced.torrent().info_hash, plain.torrent().info_hash);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn progress_is_reported_while_hashing() {
+        let dir = temp_path("create-progress");
+        fs::create_dir_all(dir.join("d")).unwrap();
+        fs::write(dir.join("d/x"), vec![7; 100_000]).unwrap();
+        fs::write(dir.join("a"), b"abc").unwrap();
+        let options = CreateOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        };
+        let mut calls = Vec::new();
+        let bytes =
+            create_with_progress(&dir, &options, |done, total| calls.push((done, total))).unwrap();
+        assert_eq!(calls.last(), Some(&(100_003, 100_003)));
+        assert!(calls.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        //pieces span the files in name order
+        let mut data = b"abc".to_vec();
+        data.extend(vec![7; 100_000]);
+        let pieces: Vec<u8> = data
+            .chunks(16384)
+            .flat_map(|piece| Sha1::digest(piece).to_vec())
+            .collect();
+        let file = TorrentFile::from_bytes(bytes).unwrap();
+        assert_eq!(file.torrent().info.raw_pieces, pieces);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use crate::core::torrent::torrent::Torrent;
+use crate::util::{hex, urlencode};
+
+//optional parts of a generated magnet link
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MagnetOptions {
+    pub length: bool,    //add xl with the total size of the torrent
+    pub web_seeds: bool, //add a ws entry for every BEP 19 web seed
+}
+
+impl Torrent<'_> {
+    //build a magnet link (BEP 9) naming the torrent and its trackers
+    pub fn to_magnet(&self) -> String {
+        self.to_magnet_with(MagnetOptions::default())
+    }
+
+    //build a magnet link with the optional parts chosen in options
+    //hybrid torrents get both the v1 and the v2 exact topic (BEP 52)
+    pub fn to_magnet_with(&self, options: MagnetOptions) -> String {
+        let mut params = Vec::new();
+        if let Some(hash) = &self.info_hash_v1 {
+            params.push(format!("xt=urn:btih:{}", hex::encode(hash)));
+        }
+        if let Some(hash) = &self.info_hash_v2 {
+            //multihash prefix: 0x12 for SHA-256, 0x20 for its 32 byte length
+            params.push(format!("xt=urn:btmh:1220{}", hex::encode(hash)));
+        }
+        params.push(format!("dn={}", urlencode::encode(self.info.raw_name)));
+        if options.length {
+            params.push(format!("xl={}", self.info.total_length()));
+        }
+
+        //announce first, then every announce-list URL not listed yet
+        let mut trackers: Vec<&[u8]> = Vec::new();
+        for url in self
+            .announce
+            .into_iter()
+            .chain(self.announce_list.iter().flatten().copied())
+        {
+            if !trackers.contains(&url) {
+                trackers.push(url);
+            }
+        }
+        for url in trackers {
+            params.push(format!("tr={}", urlencode::encode(url)));
+        }
+
+        if options.web_seeds {
+            for url in &self.url_list {
+                params.push(format!("ws={}", urlencode::encode(url)));
+            }
+        }
+
+        format!("magnet:?{}", params.join("&"))
+    }
+}
+
+//get the v1 info hash of a magnet link, from an xt topic of 40 hex or 32 base32 characters
+pub fn magnet_info_hash(link: &str) -> Option<[u8; 20]> {
+    let query = link.strip_prefix("magnet:?")?;
+    query
+        .split('&')
+        .filter_map(|param| param.strip_prefix("xt=urn:btih:"))
+        .find_map(|hash| match hash.len() {
+            40 => hex::decode(hash.as_bytes()).ok()?.try_into().ok(),
+            32 => decode_base32(hash),
+            _ => None,
+        })
+}
+
+//decode 32 base32 characters (RFC 4648, either case) into the 20 bytes of an info hash
+fn decode_base32(text: &str) -> Option<[u8; 20]> {
+    let mut bytes = [0u8; 20];
+    let mut bits = 0u64;
+    let mut count = 0;
+    let mut len = 0;
+    for c in text.bytes() {
+        let value = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        bits = bits << 5 | u64::from(value);
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            bytes[len] = (bits >> count) as u8;
+            len += 1;
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::torrent::torrent::TorrentFile;
+
+    //decoded key and value pairs of a magnet link, in order
+    fn params(link: &str) -> Vec<(String, Vec<u8>)> {
+        let query = link.strip_prefix("magnet:?").unwrap();
+        query
+            .split('&')
+            .map(|param| {
+                let (key, value) = param.split_once('=').unwrap();
+                (key.to_string(), urlencode::decode(value).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn magnet_links_name_the_torrent_and_its_trackers() {
+        let name = "my file é.txt";
+        let bytes = format!(
+            "d8:announce9:http://x/13:announce-listll9:http://x/el14:udp://y:1/a?b=ee\
+            8:url-list9:http://w/4:infod6:lengthi5e4:name{}:{}12:piece lengthi16384e\
+            6:pieces20:aaaaaaaaaaaaaaaaaaaaee",
+            name.len(),
+            name
+        );
+        let file = TorrentFile::from_bytes(bytes.into_bytes()).unwrap();
+        let torrent = file.torrent();
+        let link = torrent.to_magnet();
+        assert!(link.contains("dn=my%20file%20%C3%A9.txt"), "{}", link);
+        //the announce URL is listed once, in front of the announce-list
+        let expected: Vec<(String, Vec<u8>)> = vec![
+            (
+                "xt".into(),
+                format!("urn:btih:{}", hex::encode(&torrent.info_hash)).into_bytes(),
+            ),
+            ("dn".into(), name.as_bytes().to_vec()),
+            ("tr".into(), b"http://x/".to_vec()),
+            ("tr".into(), b"udp://y:1/a?b=".to_vec()),
+        ];
+        assert_eq!(params(&link), expected);
+
+        let link = torrent.to_magnet_with(MagnetOptions {
+            length: true,
+            web_seeds: true,
+        });
+        let params = params(&link);
+        assert!(params.contains(&("xl".into(), b"5".to_vec())));
+        assert_eq!(params.last(), Some(&("ws".into(), b"http://w/".to_vec())));
+    }
+
+    #[test]
+    fn hybrid_torrents_get_both_topics() {
+        let mut bytes = b"d4:infod9:file treed1:ad0:d6:lengthi5e11:pieces root32:".to_vec();
+        bytes.extend_from_slice(&[7; 32]);
+        bytes.extend_from_slice(b"eee6:lengthi5e12:meta versioni2e4:name1:a");
+        bytes.extend_from_slice(b"12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee");
+        let file = TorrentFile::from_bytes(bytes).unwrap();
+        let torrent = file.torrent();
+
+        let params = params(&torrent.to_magnet());
+        let v1 = format!("urn:btih:{}", hex::encode(&torrent.info_hash));
+        let v2 = format!(
+            "urn:btmh:1220{}",
+            hex::encode(&torrent.info_hash_v2.unwrap())
+        );
+        assert_eq!(params[0], ("xt".into(), v1.into_bytes()));
+        assert_eq!(params[1], ("xt".into(), v2.into_bytes()));
+    }
+
+    #[test]
+    fn info_hashes_are_read_from_links() {
+        let hash: Vec<u8> = (0..20).collect();
+        let link = format!("magnet:?dn=a&xt=urn:btih:{}", hex::encode(&hash));
+        assert_eq!(magnet_info_hash(&link).unwrap(), hash.as_slice());
+        //the same hash in base32
+        let link = "magnet:?xt=urn:btih:AAAQEAYEAUDAOCAJBIFQYDIOB4IBCEQT";
+        assert_eq!(magnet_info_hash(link).unwrap(), hash.as_slice());
+        assert_eq!(magnet_info_hash("magnet:?xt=urn:btih:1234"), None);
+        assert_eq!(magnet_info_hash("http://x/?xt=urn:btih:"), None);
+    }
+}
@@ -1,2 +1,8 @@
+pub mod create;
+pub mod magnet;
+pub mod summary;
 pub mod torrent;
 pub mod torrent_error;
+pub mod torrent_owned;
+#[cfg(feature = "serde")]
+pub mod torrent_serde;
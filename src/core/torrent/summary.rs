@@ -0,0 +1,213 @@
+use crate::core::torrent::torrent::{FileDetails, Torrent};
+use crate::util::hex;
+use crate::util::units::{format_bytes, format_timestamp};
+
+use std::cmp::Reverse;
+use std::fmt;
+
+//most files listed by name, the largest ones are shown
+const MAX_LISTED_FILES: usize = 5;
+
+//human-readable summary of the torrent, one field per line
+//names, paths and URLs that are not UTF-8 are shown lossily
+//the alternate form {:#} lists every file instead of only the largest ones
+impl fmt::Display for Torrent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = &self.info;
+        writeln!(f, "Name:         {}", info.name)?;
+        writeln!(f, "Info hash:    {}", hex::encode(&self.info_hash))?;
+        if let Some(hash) = &self.info_hash_v2 {
+            writeln!(f, "Info hash v2: {}", hex::encode(hash))?;
+        }
+        //padding files are an artifact of the piece layout, not something the user gets
+        let content_length = info.content_length();
+        writeln!(
+            f,
+            "Size:         {} ({} bytes)",
+            format_bytes(content_length),
+            content_length
+        )?;
+        writeln!(f, "Piece length: {}", format_bytes(info.piece_length))?;
+        writeln!(
+            f,
+            "Pieces:       {}",
+            info.total_length().div_ceil(info.piece_length.max(1))
+        )?;
+        writeln!(
+            f,
+            "Private:      {}",
+            if info.private { "yes" } else { "no" }
+        )?;
+        if let Some(source) = &info.source {
+            writeln!(f, "Source:       {}", source)?;
+        }
+        if let Some(date) = self.creation_date {
+            writeln!(f, "Created:      {}", format_timestamp(date))?;
+        }
+        if let Some(created_by) = &self.created_by {
+            writeln!(f, "Created by:   {}", created_by)?;
+        }
+        if let Some(comment) = &self.comment {
+            writeln!(f, "Comment:      {}", comment)?;
+        }
+
+        let trackers = self.trackers();
+        if trackers.is_empty() {
+            writeln!(f, "Trackers:     none")?;
+        } else {
+            writeln!(f, "Trackers:")?;
+            for (tier, urls) in trackers.iter().enumerate() {
+                for url in urls {
+                    writeln!(f, "  tier {}: {}", tier + 1, String::from_utf8_lossy(url))?;
+                }
+            }
+        }
+        for url in self.url_list.iter().chain(&self.httpseeds) {
+            writeln!(f, "Web seed:     {}", String::from_utf8_lossy(url))?;
+        }
+
+        match &info.file_details {
+            FileDetails::SingleFile { .. } => write!(f, "Files:        1"),
+            FileDetails::MultiFile { files } => {
+                let files: Vec<_> = files.iter().filter(|file| !file.is_padding()).collect();
+                write!(f, "Files:        {}", files.len())?;
+
+                //the full list keeps the order of the torrent, the short one shows the largest files
+                //ties keep the order of the torrent
+                let mut listed = files.clone();
+                if !f.alternate() {
+                    listed.sort_by_key(|file| Reverse(file.length));
+                    listed.truncate(MAX_LISTED_FILES);
+                }
+                for file in &listed {
+                    let path: Vec<_> = file
+                        .path
+                        .iter()
+                        .map(|component| String::from_utf8_lossy(component))
+                        .collect();
+                    write!(
+                        f,
+                        "\n  {:>10}  {}",
+                        format_bytes(file.length),
+                        path.join("/")
+                    )?;
+                }
+                if files.len() > listed.len() {
+                    write!(f, "\n  ... and {} more", files.len() - listed.len())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::torrent::torrent::TorrentFile;
+    use crate::util::hex;
+
+    #[test]
+    fn summary_shows_the_largest_files() {
+        let bytes =
+            b"d8:announce9:http://x/13:announce-listll9:http://x/el9:http://y/el5:udp:\xffee\
+            7:comment2:hi10:created by5:mktor13:creation datei1700000000e\
+            4:infod5:filesld6:lengthi3e4:pathl1:d3:\xffxxeed6:lengthi70000e4:pathl1:bee\
+            d6:lengthi1e4:pathl1:ceed6:lengthi1e4:pathl1:eeed6:lengthi1e4:pathl1:fee\
+            d6:lengthi2e4:pathl1:geee4:name2:\xc3\xa912:piece lengthi65536e\
+            6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa7:privatei1eee";
+        let file = TorrentFile::from_bytes(bytes.to_vec()).unwrap();
+        let info_hash = hex::encode(&file.torrent().info_hash);
+        let expected = format!(
+            "Name:         é
+Info hash:    {info_hash}
+Size:         68.4 KiB (70008 bytes)
+Piece length: 64.0 KiB
+Pieces:       2
+Private:      yes
+Created:      2023-11-14 22:13:20 UTC
+Created by:   mktor
+Comment:      hi
+Trackers:
+  tier 1: http://x/
+  tier 2: http://y/
+  tier 3: udp:\u{fffd}
+Files:        6
+    68.4 KiB  b
+         3 B  d/\u{fffd}xx
+         2 B  g
+         1 B  c
+         1 B  e
+  ... and 1 more"
+        );
+        assert_eq!(file.torrent().to_string(), expected);
+
+        //the alternate form lists every file in torrent order
+        let full = format!("{:#}", file.torrent());
+        let files = "Files:        6
+         3 B  d/\u{fffd}xx
+    68.4 KiB  b
+         1 B  c
+         1 B  e
+         1 B  f
+         2 B  g";
+        assert!(full.ends_with(files), "{}", full);
+    }
+
+    #[test]
+    fn summary_lists_web_seeds_and_v2_hashes() {
+        let bytes = b"d8:url-listl8:http://w9:http://w2e9:httpseedsl8:http://he\
+            4:infod6:lengthi3e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let summary = TorrentFile::from_bytes(bytes.to_vec())
+            .unwrap()
+            .torrent()
+            .to_string();
+        let expected = "Trackers:     none
+Web seed:     http://w
+Web seed:     http://w2
+Web seed:     http://h
+Files:        1";
+        assert!(summary.contains(expected), "{}", summary);
+        assert!(!summary.contains("Created:") && !summary.contains("Info hash v2:"));
+
+        let mut bytes = b"d4:infod9:file treed1:ad0:d6:lengthi5e11:pieces root32:".to_vec();
+        bytes.extend_from_slice(&[7; 32]);
+        bytes.extend_from_slice(b"eee12:meta versioni2e4:name1:a12:piece lengthi16384eee");
+        let file = TorrentFile::from_bytes(bytes).unwrap();
+        let summary = file.torrent().to_string();
+        let v2 = hex::encode(&file.torrent().info_hash_v2.unwrap());
+        assert!(
+            summary.contains(&format!("Info hash v2: {v2}\n")),
+            "{}",
+            summary
+        );
+        assert!(summary.contains("Pieces:       1\n"), "{}", summary);
+        assert!(summary.ends_with("Files:        1"), "{}", summary);
+    }
+
+    #[test]
+    fn info_lists_every_file() {
+        //x is padded to the piece boundary before y, the padding file is not listed
+        let bytes = b"d10:created by5:mktor4:infod5:filesld6:lengthi10e4:pathl1:xee\
+            d4:attr1:p6:lengthi16374e4:pathl4:.pad5:16374eed6:lengthi2048e4:pathl3:sub1:yeee\
+            4:name3:dir12:piece lengthi16384e6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\
+            6:source4:sitee8:url-list8:http://we";
+        let file = TorrentFile::from_bytes(bytes.to_vec()).unwrap();
+        let info_hash = hex::encode(&file.torrent().info_hash);
+        let expected = format!(
+            "Name:         dir
+Info hash:    {info_hash}
+Size:         2.0 KiB (2058 bytes)
+Piece length: 16.0 KiB
+Pieces:       2
+Private:      no
+Source:       site
+Created by:   mktor
+Trackers:     none
+Web seed:     http://w
+Files:        2
+        10 B  x
+     2.0 KiB  sub/y"
+        );
+        assert_eq!(format!("{:#}", file.torrent()), expected);
+    }
+}
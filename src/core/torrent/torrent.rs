@@ -1,144 +1,765 @@
-use crate::core::torrent::torrent_error::ReadTorrentError;
+use crate::core::torrent::torrent_error::{InvalidInfoError, ReadTorrentError};
 use crate::util::bencode::bencode_decodable::BencodeDecodable;
-use crate::util::bencode::bencode_decodable_error::BencodeDecodableError;
-use crate::util::errors::BStreamingError;
+use crate::util::bencode::bencode_decodable_error::{BencodeDecodableError, DecodeContext};
+use crate::util::bencode::parser::{Dict, Node, ParseOptions, Value, parse_with};
+use crate::util::hex;
 
-use bencode::util::ByteString;
-use bencode::{Bencode, from_buffer};
-use once_cell::sync::Lazy;
+use self_cell::self_cell;
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
 use std::borrow::Cow;
-use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
-use std::rc::Rc;
+use tokio::io::AsyncReadExt;
 
-//define cached keys
-static LENGTH_KEY: Lazy<ByteString> = Lazy::new(|| ByteString::from_str("length"));
-static PATH_KEY: Lazy<ByteString> = Lazy::new(|| ByteString::from_str("path"));
+//largest torrent file read unless configured otherwise, guards against garbage input
+pub const DEFAULT_MAX_TORRENT_SIZE: u64 = 16 * 1024 * 1024;
+
+//longest name accepted, most file systems limit a single path component to 255 bytes
+pub const MAX_NAME_LENGTH: usize = 255;
 
 #[derive(Debug)]
 pub struct Torrent<'a> {
-    pub announce: &'a [u8],  //tracker URL
-    pub info: Info<'a>,      //main metadata
-    pub info_hash: [u8; 20], //SHA1 encoding of bencode value of info
+    pub announce: Option<&'a [u8]>, //tracker URL, None for trackerless torrents
+    pub announce_list: Vec<Vec<&'a [u8]>>, //tiers of tracker URLs, empty if absent
+    pub creation_date: Option<u64>, //unix timestamp the torrent was created at
+    pub comment: Option<Cow<'a, str>>, //free-form comment from the author
+    pub created_by: Option<Cow<'a, str>>, //program that created the torrent
+    pub encoding: Option<Cow<'a, str>>, //string encoding used in the info dict
+    pub url_list: Vec<&'a [u8]>,    //BEP 19 web seed URLs, empty if absent
+    pub httpseeds: Vec<&'a [u8]>,   //BEP 17 http seed URLs, empty if absent
+    pub nodes: Vec<(String, u16)>,  //DHT bootstrap nodes, host is an IP or a name
+    pub info: Info<'a>,             //main metadata
+    pub piece_layers: HashMap<[u8; 32], &'a [u8]>, //v2 piece hashes by pieces root
+    pub info_hash: [u8; 20],        //v1 hash, or truncated v2 hash for pure v2
+    pub info_hash_v1: Option<[u8; 20]>, //SHA1 of info for v1 and hybrid torrents
+    pub info_hash_v2: Option<[u8; 32]>, //SHA-256 of info for v2 and hybrid torrents
+    raw_info: &'a [u8],             //info dict exactly as encoded, the hashes are taken over it
 }
 
 impl<'a> BencodeDecodable<'a> for Torrent<'a> {
-    fn decode(b: &'a Bencode) -> Result<Self, BencodeDecodableError> {
+    fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError> {
         //get dict from bencode
         let dict = Self::get_struct(b)?;
-        //get announce value
-        let announce = Self::get_str(Self::get_struct_value("announce", dict)?)?;
+        //get optional announce value, trackerless torrents find peers through DHT nodes
+        let announce = Self::get_optional_str("announce", dict)?;
+        //get optional announce list
+        let announce_list = match Self::get_optional_struct_value("announce-list", dict) {
+            Some(b) => Self::decode_announce_list(b).context("announce-list")?,
+            None => Vec::new(),
+        };
+        //get optional metadata, a value of the wrong type is treated as missing
+        //dates before 1970 are clamped to the epoch
+        let creation_date = Self::get_struct_value("creation date", dict)
+            .and_then(Self::get_i64)
+            .map(|date| date.max(0) as u64)
+            .ok();
+        let comment = Self::get_struct_value("comment", dict)
+            .and_then(Self::get_string)
+            .ok();
+        let created_by = Self::get_struct_value("created by", dict)
+            .and_then(Self::get_string)
+            .ok();
+        let encoding = Self::get_struct_value("encoding", dict)
+            .and_then(Self::get_string)
+            .ok();
+        //get optional web seeds, given as a single URL or a list of them
+        let url_list = match Self::get_struct_value("url-list", dict) {
+            Ok(b) => Self::decode_url_list(b),
+            _ => Vec::new(),
+        };
+        //get optional BEP 17 http seeds
+        let httpseeds = match Self::get_struct_value("httpseeds", dict) {
+            Ok(b) => Self::decode_url_list(b),
+            _ => Vec::new(),
+        };
+        //get optional DHT bootstrap nodes
+        let nodes = match Self::get_struct_value("nodes", dict) {
+            Ok(b) => Self::decode_nodes(b),
+            _ => Vec::new(),
+        };
         //get info dict
         let info_dict = Self::get_struct_value("info", dict)?;
         //decode info dict
-        let info = Info::decode(info_dict)?;
-
-        //get raw info bytes to calculate SHA1
-        let info_bytes = info_dict
-            .to_bytes()
-            .map_err(|e| BencodeDecodableError::Other(e.into()))?;
-        //calculate sha1 of info
-        let mut hasher = Sha1::new();
-        hasher.update(&info_bytes);
-        let info_hash = hasher.finalize().into();
+        let info = Info::decode(info_dict).context("info")?;
+        //get v2 piece hashes, only present for files larger than a piece
+        let piece_layers = match Self::get_optional_struct_value("piece layers", dict) {
+            Some(b) => Self::decode_piece_layers(b).context("piece layers")?,
+            None => HashMap::new(),
+        };
 
-        Ok(Self {
+        let mut torrent = Self {
             announce,
+            announce_list,
+            creation_date,
+            comment,
+            created_by,
+            encoding,
+            url_list,
+            httpseeds,
+            nodes,
             info,
-            info_hash,
-        })
+            piece_layers,
+            info_hash: [0; 20],
+            info_hash_v1: None,
+            info_hash_v2: None,
+            //hash the info dict exactly as it appears in the input
+            //re-encoding it only gives the same bytes if the input was encoded canonically
+            raw_info: info_dict.raw,
+        };
+        torrent.set_info_hashes();
+
+        Ok(torrent)
+    }
+}
+
+impl<'a> Torrent<'a> {
+    //compute the info hashes from the bencoded info dict
+    //pure v2 torrents are addressed by their SHA-256 hash truncated to 20 bytes (BEP 52)
+    fn set_info_hashes(&mut self) {
+        let info_bytes = self.raw_info;
+        self.info_hash_v1 = self.info.has_v1().then(|| Sha1::digest(info_bytes).into());
+        self.info_hash_v2 = self
+            .info
+            .has_v2()
+            .then(|| Sha256::digest(info_bytes).into());
+        //every torrent is v1, v2 or both, so one of the hashes is always there
+        self.info_hash = self
+            .info_hash_v1
+            .or(self.info_hash_v2.as_ref().map(truncate_hash))
+            .unwrap_or_default();
+    }
+
+    //get the bencoded info dict, exactly as it appears in the torrent file
+    pub fn raw_info_bytes(&self) -> &'a [u8] {
+        self.raw_info
+    }
+
+    //check if a handshake or announce for hash refers to this torrent
+    //hybrid torrents are reached through both the v1 and the truncated v2 hash
+    pub fn matches_info_hash(&self, hash: &[u8; 20]) -> bool {
+        self.info_hash_v1.as_ref() == Some(hash)
+            || self.info_hash_v2.as_ref().map(truncate_hash).as_ref() == Some(hash)
+    }
+
+    //get every 20 byte hash the torrent can be announced under, v1 first
+    pub fn announce_hashes(&self) -> Vec<[u8; 20]> {
+        self.info_hash_v1
+            .into_iter()
+            .chain(self.info_hash_v2.as_ref().map(truncate_hash))
+            .collect()
+    }
+
+    //decode announce-list tiers in file order, skipping entries that are not byte strings
+    fn decode_announce_list(b: &Node<'a>) -> Result<Vec<Vec<&'a [u8]>>, BencodeDecodableError> {
+        let tier_list = Self::get_list(b)?;
+
+        let mut tiers = Vec::with_capacity(tier_list.len());
+        for tier_item in tier_list {
+            let Ok(url_list) = Self::get_list(tier_item) else {
+                continue;
+            };
+            let tier: Vec<&[u8]> = url_list
+                .iter()
+                .filter_map(|url| Self::get_str(url).ok())
+                .collect();
+            if !tier.is_empty() {
+                tiers.push(tier);
+            }
+        }
+
+        Ok(tiers)
+    }
+
+    //decode url-list, skipping entries that are not byte strings and empty URLs
+    fn decode_url_list(b: &Node<'a>) -> Vec<&'a [u8]> {
+        let urls = match &b.value {
+            Value::List(list) => list
+                .iter()
+                .filter_map(|url| Self::get_str(url).ok())
+                .collect(),
+            _ => Self::get_str(b).map(|url| vec![url]).unwrap_or_default(),
+        };
+        urls.into_iter().filter(|url| !url.is_empty()).collect()
+    }
+
+    //decode piece layers, mapping the pieces root of a file to its concatenated SHA-256 piece hashes
+    fn decode_piece_layers(
+        b: &Node<'a>,
+    ) -> Result<HashMap<[u8; 32], &'a [u8]>, BencodeDecodableError> {
+        let mut piece_layers = HashMap::new();
+        for (root, layer) in Self::get_struct(b)? {
+            let root = <[u8; 32]>::try_from(*root).map_err(|_| {
+                BencodeDecodableError::Other("Invalid pieces root length in piece layers".into())
+            })?;
+            let layer = Self::get_str(layer)?;
+            if layer.len() % 32 != 0 {
+                return Err(BencodeDecodableError::Other(
+                    "Invalid piece layer length".into(),
+                ));
+            }
+            piece_layers.insert(root, layer);
+        }
+        Ok(piece_layers)
+    }
+
+    //decode nodes, a list of [host, port] pairs, skipping malformed entries
+    fn decode_nodes(b: &Node<'a>) -> Vec<(String, u16)> {
+        let Ok(node_list) = Self::get_list(b) else {
+            return Vec::new();
+        };
+
+        node_list
+            .iter()
+            .filter_map(|node| {
+                let [host, port] = Self::get_list(node).ok()? else {
+                    return None;
+                };
+                let host = Self::get_string_strict(host).ok()?;
+                let port = u16::try_from(Self::get_u64(port).ok()?).ok()?;
+                (!host.is_empty() && port != 0).then(|| (host.to_string(), port))
+            })
+            .collect()
+    }
+
+    //get tracker tiers, falling back to announce when there is no announce-list
+    //empty for trackerless torrents
+    pub fn trackers(&self) -> Vec<Vec<&'a [u8]>> {
+        if self.announce_list.is_empty() {
+            self.announce
+                .map(|announce| vec![vec![announce]])
+                .unwrap_or_default()
+        } else {
+            self.announce_list.clone()
+        }
+    }
+
+    //check if the torrent names anywhere to get peers or data from
+    pub fn has_peer_source(&self) -> bool {
+        !self.trackers().is_empty()
+            || !self.nodes.is_empty()
+            || !self.url_list.is_empty()
+            || !self.httpseeds.is_empty()
     }
 }
 
 #[derive(Debug)]
 pub struct Info<'a> {
-    pub name: Cow<'a, str>,            //torrent name/file name
-    pub piece_length: u64,             //size of each piece in bytes
+    pub name: Cow<'a, str>, //torrent name/file name for display, lossy if not UTF-8
+    pub raw_name: &'a [u8], //name exactly as given, for the file system and web seed URLs
+    pub piece_length: u64,  //size of each piece in bytes
     pub raw_pieces: &'a [u8], //raw bytes representing the concatenated SHA-1 hashes of all pieces
     pub file_details: FileDetails<'a>, //single/multi file torrent
+    pub md5sum: Option<[u8; 16]>, //MD5 of the file for single file torrents, rarely present
+    pub private: bool,      //only the embedded trackers may be used to find peers
+    pub source: Option<Cow<'a, str>>, //site the torrent was made for, changes the info hash per site
+    pub meta_version: u64,            //1 for v1 torrents, 2 for v2 and hybrid torrents (BEP 52)
+    pub file_tree: Vec<FileEntry<'a>>, //files of the v2 file tree in path order, empty for v1
+    file_offsets: Vec<u64>, //start of each file in the torrent data, followed by the total length
 }
 
 impl<'a> BencodeDecodable<'a> for Info<'a> {
-    fn decode(b: &'a Bencode) -> Result<Self, BencodeDecodableError> {
+    fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError> {
         //get dict from bencode
         let dict = Self::get_struct(b)?;
-        //get name value
-        let name = Self::get_string(Self::get_struct_value("name", dict)?)?;
+        //get name value, raw bytes are kept since the name does not have to be UTF-8
+        let raw_name = Self::get_str(Self::get_struct_value("name", dict)?).context("name")?;
+        let name = String::from_utf8_lossy(raw_name);
         //get piece length value
-        let piece_length = Self::get_u64(Self::get_struct_value("piece length", dict)?)?;
-        //get raw pieces
-        let raw_pieces = Self::get_str(Self::get_struct_value("pieces", dict)?)?;
+        let piece_length =
+            Self::get_u64(Self::get_struct_value("piece length", dict)?).context("piece length")?;
+        //get meta version, torrents without one are v1
+        let meta_version = Self::get_optional_u64("meta version", dict)?.unwrap_or(1);
+        //get raw pieces, pure v2 torrents have none
+        let raw_pieces = match Self::get_optional_str("pieces", dict)? {
+            Some(pieces) => pieces,
+            None if meta_version >= 2 => &[],
+            None => {
+                return Err(BencodeDecodableError::KeyNotFound(
+                    "Key 'pieces' not found".into(),
+                ));
+            }
+        };
+        //get v2 file tree
+        let mut file_tree = Vec::new();
+        if meta_version >= 2 {
+            Self::decode_file_tree(
+                Self::get_struct_value("file tree", dict)?,
+                &mut Vec::new(),
+                &mut file_tree,
+            )
+            .context("file tree")?;
+        }
 
         //validate that pieces data contains complete SHA-1 hashes (each hash is exactly 20 bytes)
         if raw_pieces.len() % 20 != 0 {
-            return Err(BencodeDecodableError::Other("Invalid pieces length".into()));
+            return Err(InvalidInfoError::PiecesLength(raw_pieces.len()).into());
         }
 
+        //get optional md5sum of a single file torrent
+        let md5sum = decode_md5sum(dict)?;
+        //get private flag, anything other than 1 means a public torrent
+        let private = matches!(
+            Self::get_struct_value("private", dict).and_then(Self::get_u64),
+            Ok(1)
+        );
+        //get optional source, a value of the wrong type is treated as missing
+        let source = Self::get_struct_value("source", dict)
+            .and_then(Self::get_string)
+            .ok();
+
         //get file details
         //get length value. If found, single file. Else multi file
-        let file_details = match Self::get_struct_value("length", dict) {
-            Ok(b) => FileDetails::SingleFile {
-                length: Self::get_u64(b)?,
-            },
-            _ => FileDetails::MultiFile {
+        //pure v2 torrents only describe their files in the file tree
+        let file_details = match (
+            Self::get_optional_u64("length", dict)?,
+            Self::get_optional_struct_value("files", dict),
+        ) {
+            (Some(length), _) => FileDetails::SingleFile { length },
+            (None, Some(file_list)) => FileDetails::MultiFile {
                 //get files details
-                files: {
-                    //get file list value
-                    let file_list = Self::get_list(Self::get_struct_value("files", dict)?)?;
-
-                    let mut files = Vec::with_capacity(file_list.len());
-                    //fill files from file list
-                    for file_item in file_list {
-                        files.push(FileEntry::decode(file_item)?)
-                    }
-
-                    files
-                },
+                files: Self::decode_vec(file_list).context("files")?,
             },
+            (None, None) if meta_version >= 2 => Self::tree_details(raw_name, &file_tree),
+            (None, None) => {
+                return Err(BencodeDecodableError::KeyNotFound(
+                    "Key 'files' not found".into(),
+                ));
+            }
         };
 
-        Ok(Self {
+        let info = Self {
             name,
+            raw_name,
             piece_length,
             raw_pieces,
+            file_offsets: Self::file_offsets(&file_details),
             file_details,
-        })
+            md5sum,
+            private,
+            source,
+            meta_version,
+            file_tree,
+        };
+        info.check_name()?;
+        info.check_files()?;
+        if info.has_v1() && info.has_v2() {
+            info.check_hybrid()?;
+        }
+        info.check_pieces()?;
+
+        Ok(info)
     }
 }
 
+//oddity of an info dict that clients cope with, but that hints at a broken or unusual creator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InfoWarning {
+    PieceLengthNotPowerOfTwo(u64), //piece length in bytes, v2 torrents require a power of two
+}
+
+impl fmt::Display for InfoWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PieceLengthNotPowerOfTwo(length) => {
+                write!(f, "Piece length {} is not a power of two", length)
+            }
+        }
+    }
+}
+
+//part of a range of the torrent data that falls into one file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileSpan {
+    pub file: usize, //index of the file, 0 for single file torrents
+    pub offset: u64, //offset of the span within the file
+    pub length: u64, //length of the span in bytes
+}
+
 #[derive(Debug)]
 pub enum FileDetails<'a> {
     SingleFile { length: u64 }, //file length in bytes for single file torrent
     MultiFile { files: Vec<FileEntry<'a>> }, //list of files for multi file torrent
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileEntry<'a> {
-    pub length: u64,         //file length in bytes
-    pub path: Vec<&'a [u8]>, //path components
+    pub length: u64,                         //file length in bytes
+    pub path: Vec<&'a [u8]>,                 //path components
+    pub md5sum: Option<[u8; 16]>,            //MD5 of the file, rarely present
+    pub pieces_root: Option<[u8; 32]>, //root of the v2 merkle tree of the file, None for v1 and empty files
+    pub attr: Option<&'a [u8]>,        //BEP 47 attribute letters, unknown letters are kept
+    pub symlink_path: Option<Vec<&'a [u8]>>, //target of a symlink, relative to the torrent root
 }
 
 impl<'a> BencodeDecodable<'a> for FileEntry<'a> {
-    fn decode(b: &'a Bencode) -> Result<Self, BencodeDecodableError> {
+    fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError> {
         //get dict from bencode
         let dict = Self::get_struct(b)?;
         //get length value
-        let length = Self::get_u64(Self::get_struct_value_from_bytestring(&LENGTH_KEY, dict)?)?;
-        //get path list value
-        let path_list = Self::get_list(Self::get_struct_value_from_bytestring(&PATH_KEY, dict)?)?;
+        let length = Self::get_length(dict)?;
+        //get file path from path list
+        let path = Self::get_path(dict)?;
+
+        //get optional md5sum
+        let md5sum = decode_md5sum(dict).context("md5sum")?;
+        //get optional attributes
+        let (attr, symlink_path) = Self::decode_attr(dict)?;
 
-        let mut path = Vec::with_capacity(path_list.len());
-        //file path from path list
-        for path_item in path_list {
-            path.push(Self::get_str(path_item)?);
+        Ok(Self {
+            length,
+            path,
+            md5sum,
+            pieces_root: None,
+            attr,
+            symlink_path,
+        })
+    }
+}
+
+impl<'a> FileEntry<'a> {
+    //decode the optional BEP 47 attr and symlink path keys of a file dict
+    #[allow(clippy::type_complexity)]
+    fn decode_attr(
+        dict: &Dict<'a>,
+    ) -> Result<(Option<&'a [u8]>, Option<Vec<&'a [u8]>>), BencodeDecodableError> {
+        let attr = Self::get_optional_str("attr", dict)?;
+        let symlink_path = match Self::get_optional_struct_value("symlink path", dict) {
+            Some(b) => Some(Self::decode_vec_with(b, Self::get_str).context("symlink path")?),
+            None => None,
+        };
+
+        //a symlink without a target cannot be created
+        if attr.is_some_and(|attr| attr.contains(&b'l')) && symlink_path.is_none() {
+            return Err(BencodeDecodableError::KeyNotFound(
+                "Key 'symlink path' not found".into(),
+            ));
         }
+        Ok((attr, symlink_path))
+    }
+
+    //check if attribute letter is set
+    fn has_attr(&self, letter: u8) -> bool {
+        self.attr.is_some_and(|attr| attr.contains(&letter))
+    }
 
-        Ok(Self { length, path })
+    //check if the file should be made executable
+    pub fn is_executable(&self) -> bool {
+        self.has_attr(b'x')
     }
+
+    //check if the file is a symlink to symlink_path, which has no content
+    pub fn is_symlink(&self) -> bool {
+        self.has_attr(b'l')
+    }
+
+    //check if the file should be hidden
+    pub fn is_hidden(&self) -> bool {
+        self.has_attr(b'h')
+    }
+
+    //check if the file only pads the previous file to a piece boundary
+    //padding files hold zeros, are never written to disk and do not count toward the size
+    //older torrents mark them only by placing them in the .pad directory
+    pub fn is_padding(&self) -> bool {
+        self.has_attr(b'p') || self.path.first() == Some(&b".pad".as_slice())
+    }
+}
+
+//decode the optional md5sum key of dict, given as 32 hex digits
+fn decode_md5sum(dict: &Dict) -> Result<Option<[u8; 16]>, BencodeDecodableError> {
+    let Some(node) = dict.get(b"md5sum".as_slice()) else {
+        return Ok(None);
+    };
+    let Value::ByteStr(text) = node.value else {
+        return Err(BencodeDecodableError::WrongType(
+            "expected a ByteString of hex digits".into(),
+        ));
+    };
+
+    let md5sum = hex::decode(text).map_err(|e| BencodeDecodableError::Other(e.into()))?;
+    md5sum.try_into().map(Some).map_err(|md5sum: Vec<u8>| {
+        BencodeDecodableError::Other(
+            format!("md5sum is {} bytes, expected 16", md5sum.len()).into(),
+        )
+    })
 }
 
 impl<'a> Info<'a> {
+    //check if the torrent has v1 piece hashes, true for v1 and hybrid torrents
+    pub fn has_v1(&self) -> bool {
+        self.meta_version < 2 || !self.raw_pieces.is_empty()
+    }
+
+    //check if the torrent has a v2 file tree, true for v2 and hybrid torrents
+    pub fn has_v2(&self) -> bool {
+        self.meta_version >= 2
+    }
+
+    //decode a v2 file tree into its files
+    //keys are path components, an empty key holds the length and pieces root of a file
+    fn decode_file_tree(
+        b: &Node<'a>,
+        path: &mut Vec<&'a [u8]>,
+        files: &mut Vec<FileEntry<'a>>,
+    ) -> Result<(), BencodeDecodableError> {
+        for (&key, node) in Self::get_struct(b)? {
+            if !key.is_empty() {
+                path.push(key);
+                Self::decode_file_tree(node, path, files).context(&String::from_utf8_lossy(key))?;
+                path.pop();
+                continue;
+            }
+
+            let file = Self::get_struct(node)?;
+            //the file dict sits under an empty key, which is left out of error paths
+            let length = Self::get_length(file)?;
+            let pieces_root = match Self::get_optional_struct_value("pieces root", file) {
+                Some(b) => Some(
+                    Self::get_str(b)
+                        .and_then(|root| {
+                            <[u8; 32]>::try_from(root).map_err(|_| {
+                                BencodeDecodableError::Other("Invalid pieces root length".into())
+                            })
+                        })
+                        .context("pieces root")?,
+                ),
+                None => None,
+            };
+            //only empty files have no pieces root
+            if length > 0 && pieces_root.is_none() {
+                return Err(BencodeDecodableError::KeyNotFound(
+                    "Key 'pieces root' not found".into(),
+                ));
+            }
+
+            let (attr, symlink_path) = FileEntry::decode_attr(file)?;
+
+            files.push(FileEntry {
+                length,
+                path: path.clone(),
+                md5sum: None,
+                pieces_root,
+                attr,
+                symlink_path,
+            });
+        }
+
+        Ok(())
+    }
+
+    //check that the v1 file list of a hybrid torrent describes the same files as its v2 file tree
+    //otherwise v1 and v2 peers would download different content for the same torrent
+    fn check_hybrid(&self) -> Result<(), InvalidInfoError> {
+        let name = [self.raw_name];
+        let v1_files: Vec<(&[&[u8]], u64)> = match &self.file_details {
+            FileDetails::SingleFile { length } => vec![(&name, *length)],
+            //padding files only exist in the v1 list, they align files to pieces
+            FileDetails::MultiFile { files } => files
+                .iter()
+                .filter(|file| !file.is_padding())
+                .map(|file| (file.path.as_slice(), file.length))
+                .collect(),
+        };
+        let v2_files = self
+            .file_tree
+            .iter()
+            .map(|file| (file.path.as_slice(), file.length));
+
+        if !v1_files.into_iter().eq(v2_files) {
+            return Err(InvalidInfoError::HybridMismatch);
+        }
+        Ok(())
+    }
+
+    //check that the name can be used as a file or directory name
+    fn check_name(&self) -> Result<(), InvalidInfoError> {
+        if self.raw_name.is_empty() {
+            return Err(InvalidInfoError::EmptyName);
+        }
+        if self.raw_name.len() > MAX_NAME_LENGTH {
+            return Err(InvalidInfoError::NameTooLong(self.raw_name.len()));
+        }
+        Ok(())
+    }
+
+    //check that a multi file torrent lists files with distinct paths and a representable size
+    fn check_files(&self) -> Result<(), InvalidInfoError> {
+        let FileDetails::MultiFile { files } = &self.file_details else {
+            return Ok(());
+        };
+        if files.is_empty() {
+            return Err(InvalidInfoError::NoFiles);
+        }
+        files
+            .iter()
+            .try_fold(0u64, |total, file| total.checked_add(file.length))
+            .ok_or(InvalidInfoError::LengthOverflow)?;
+
+        //padding files may share a path, e.g. two pads of the same size named after it
+        let mut paths = HashSet::with_capacity(files.len());
+        for file in files.iter().filter(|file| !file.is_padding()) {
+            if !paths.insert(file.path.as_slice()) {
+                let path: Vec<_> = file
+                    .path
+                    .iter()
+                    .map(|component| String::from_utf8_lossy(component))
+                    .collect();
+                return Err(InvalidInfoError::DuplicatePath(path.join("/")));
+            }
+        }
+        Ok(())
+    }
+
+    //check that the piece hashes cover exactly the torrent data
+    //a mismatch would later lead to piece indices past the data or data without a hash
+    fn check_pieces(&self) -> Result<(), InvalidInfoError> {
+        if self.piece_length == 0 {
+            return Err(InvalidInfoError::ZeroPieceLength);
+        }
+        //pure v2 torrents hash their pieces per file in the piece layers
+        if !self.has_v1() {
+            return Ok(());
+        }
+
+        let total_length = self.total_length();
+        let num_pieces = self.num_pieces();
+        let expected = total_length.div_ceil(self.piece_length);
+        if num_pieces as u64 != expected {
+            return Err(InvalidInfoError::PieceCount {
+                given: num_pieces,
+                expected,
+                total_length,
+                piece_length: self.piece_length,
+            });
+        }
+        Ok(())
+    }
+
+    //describe the files of a pure v2 torrent the way v1 torrents do
+    //a single file torrent has one file named like the torrent at the root of the tree
+    fn tree_details(name: &[u8], file_tree: &[FileEntry<'a>]) -> FileDetails<'a> {
+        match file_tree {
+            [file] if file.path == [name] => FileDetails::SingleFile {
+                length: file.length,
+            },
+            files => FileDetails::MultiFile {
+                files: files.to_vec(),
+            },
+        }
+    }
+
+    //total size of the torrent data in bytes
+    //saturates rather than wrapping if the file lengths add up to more than u64::MAX
+    pub fn total_length(&self) -> u64 {
+        match &self.file_details {
+            FileDetails::SingleFile { length } => *length,
+            FileDetails::MultiFile { files } => files
+                .iter()
+                .fold(0u64, |total, file| total.saturating_add(file.length)),
+        }
+    }
+
+    //size of the files a user gets, without the padding between them
+    pub fn content_length(&self) -> u64 {
+        match &self.file_details {
+            FileDetails::SingleFile { length } => *length,
+            FileDetails::MultiFile { files } => files
+                .iter()
+                .filter(|file| !file.is_padding())
+                .fold(0u64, |total, file| total.saturating_add(file.length)),
+        }
+    }
+
+    //number of pieces listed in raw_pieces
+    pub fn num_pieces(&self) -> usize {
+        self.raw_pieces.len() / 20
+    }
+
+    //length of piece index, piece_length for all but a shorter last piece, None if out of range
+    pub fn piece_len(&self, index: usize) -> Option<u64> {
+        let total_length = self.total_length();
+        let start = (index as u64).checked_mul(self.piece_length)?;
+        if start >= total_length {
+            return None;
+        }
+        Some((total_length - start).min(self.piece_length))
+    }
+
+    //cumulative file lengths, saturating like total_length
+    fn file_offsets(file_details: &FileDetails) -> Vec<u64> {
+        match file_details {
+            FileDetails::SingleFile { length } => vec![0, *length],
+            FileDetails::MultiFile { files } => {
+                let mut offsets = Vec::with_capacity(files.len() + 1);
+                offsets.push(0);
+                let mut total = 0u64;
+                for file in files {
+                    total = total.saturating_add(file.length);
+                    offsets.push(total);
+                }
+                offsets
+            }
+        }
+    }
+
+    //file that the byte at offset of the torrent data belongs to, and the offset within that file
+    //empty files hold no bytes and are never returned, None if offset is past the end
+    pub fn file_at(&self, offset: u64) -> Option<(usize, u64)> {
+        let total_length = *self.file_offsets.last()?;
+        if offset >= total_length {
+            return None;
+        }
+        //last file starting at or before offset, which skips empty files starting there too
+        let file = self.file_offsets.partition_point(|&start| start <= offset) - 1;
+        Some((file, offset - self.file_offsets[file]))
+    }
+
+    //split length bytes at offset of the torrent data into the parts of the files they cover
+    //the range is cut off at the end of the data, empty files are skipped
+    pub fn files_in_range(&self, offset: u64, length: u64) -> impl Iterator<Item = FileSpan> + '_ {
+        let end = offset
+            .saturating_add(length)
+            .min(self.file_offsets.last().copied().unwrap_or(0));
+        let first = self
+            .file_at(offset)
+            .map_or(self.file_offsets.len(), |(file, _)| file);
+
+        self.file_offsets
+            .windows(2)
+            .enumerate()
+            .skip(first)
+            .take_while(move |(_, bounds)| bounds[0] < end)
+            .filter(|(_, bounds)| bounds[0] < bounds[1])
+            .map(move |(file, bounds)| {
+                let from = offset.max(bounds[0]);
+                let to = end.min(bounds[1]);
+                FileSpan {
+                    file,
+                    offset: from - bounds[0],
+                    length: to - from,
+                }
+            })
+    }
+
+    //oddities that do not stop the torrent from being downloaded
+    pub fn warnings(&self) -> Vec<InfoWarning> {
+        let mut warnings = Vec::new();
+        if !self.piece_length.is_power_of_two() {
+            warnings.push(InfoWarning::PieceLengthNotPowerOfTwo(self.piece_length));
+        }
+        warnings
+    }
+
+    //check if peers may only be found through the embedded trackers (BEP 27)
+    //DHT, peer exchange and local discovery must stay off for private torrents
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
     //get SHA1 of a index from raw_pieces
     pub fn piece_hash(&self, index: usize) -> Option<&[u8; 20]> {
         //compute start and end
@@ -154,42 +775,859 @@ impl<'a> Info<'a> {
     }
 }
 
+//reject torrent data read up to one byte past max_size if it went over
+fn check_size(content: &[u8], max_size: u64) -> Result<(), ReadTorrentError> {
+    if content.len() as u64 > max_size {
+        return Err(ReadTorrentError::TooLarge(max_size));
+    }
+    Ok(())
+}
+
+//truncate a v2 info hash to the 20 bytes used by trackers and the peer handshake
+fn truncate_hash(hash: &[u8; 32]) -> [u8; 20] {
+    let mut truncated = [0; 20];
+    truncated.copy_from_slice(&hash[..20]);
+    truncated
+}
+
+self_cell!(
+    //bytes of a torrent file together with the torrent parsed from them
+    struct ParsedTorrent {
+        owner: Vec<u8>,
+
+        #[covariant]
+        dependent: Torrent,
+    }
+
+    impl {Debug}
+);
+
 #[derive(Debug)]
 pub struct TorrentFile {
-    _data: Rc<Vec<u8>>,            //store data to ensure it stays alive
-    _bencode: Rc<Bencode>,         //store bencode to ensure it stays alive
-    pub torrent: Torrent<'static>, //parsed torrent that references the data
+    parsed: ParsedTorrent, //torrent file bytes and the torrent that references them
 }
 
 impl TorrentFile {
     //create TorrentFile from bytes
     pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, ReadTorrentError> {
-        //create reference-counted data
-        let data = Rc::new(bytes);
-
-        //create a place to store the bencode
-        let bencode_holder = Rc::new(from_buffer(&data).map_err(BStreamingError::from)?);
+        //parse the torrent, borrowing strings from the bytes it is stored with
+        //the parsed value tree is only needed while decoding
+        let parsed = ParsedTorrent::try_new(bytes, |bytes| {
+            let root = parse_with(bytes, &ParseOptions::torrent_file())?;
+            Ok::<_, ReadTorrentError>(Torrent::decode(&root)?)
+        })?;
 
-        //extract the bencode and create a 'static reference
-        //this is safe because we ensure the data lives as long as TorrentFile
-        let bencode_static = unsafe {
-            let bencode_ref = bencode_holder.as_ref();
-            std::mem::transmute::<&Bencode, &'static Bencode>(bencode_ref)
-        };
+        Ok(TorrentFile { parsed })
+    }
 
-        //parse the torrent
-        let torrent = Torrent::decode(bencode_static)?;
+    //get the parsed torrent
+    pub fn torrent(&self) -> &Torrent<'_> {
+        self.parsed.borrow_dependent()
+    }
 
-        Ok(TorrentFile {
-            _data: data,
-            _bencode: bencode_holder,
-            torrent,
-        })
+    //get the bytes the torrent was parsed from
+    pub fn as_bytes(&self) -> &[u8] {
+        self.parsed.borrow_owner()
     }
 
-    //create TorrentFile from file
+    /// Read and parse the torrent file at `file`, the entry point for torrents on disk.
+    ///
+    /// ```
+    /// use motteseed::TorrentFile;
+    /// # let path = std::env::temp_dir().join(format!("motteseed-doc-{}.torrent", std::process::id()));
+    /// # std::fs::write(&path, b"d4:infod6:lengthi3e4:name5:a.txt12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee").unwrap();
+    ///
+    /// let torrent_file = TorrentFile::from_file(&path)?;
+    /// let torrent = torrent_file.torrent();
+    /// println!("{} has {} bytes", torrent.info.name, torrent.info.total_length());
+    /// # assert_eq!(torrent.info.total_length(), 3);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// # Ok::<(), motteseed::core::torrent::torrent_error::ReadTorrentError>(())
+    /// ```
     pub fn from_file(file: &Path) -> Result<Self, ReadTorrentError> {
-        let content = fs::read(file).map_err(ReadTorrentError::IOError)?;
+        let file = File::open(file).map_err(ReadTorrentError::IOError)?;
+        Self::from_reader(file)
+    }
+
+    //create TorrentFile from file without blocking the async runtime
+    pub async fn from_file_async(file: &Path) -> Result<Self, ReadTorrentError> {
+        Self::from_file_async_with_limit(file, DEFAULT_MAX_TORRENT_SIZE).await
+    }
+
+    //create TorrentFile from file without blocking, rejecting files over max_size bytes
+    pub async fn from_file_async_with_limit(
+        file: &Path,
+        max_size: u64,
+    ) -> Result<Self, ReadTorrentError> {
+        let file = tokio::fs::File::open(file).await?;
+        let mut content = Vec::new();
+        //read one byte past the limit to tell a file of exactly max_size from a larger one
+        file.take(max_size.saturating_add(1))
+            .read_to_end(&mut content)
+            .await?;
+        check_size(&content, max_size)?;
         Self::from_bytes(content)
     }
+
+    //create TorrentFile from everything reader yields, e.g. a torrent downloaded over HTTP
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, ReadTorrentError> {
+        Self::from_reader_with_limit(reader, DEFAULT_MAX_TORRENT_SIZE)
+    }
+
+    //create TorrentFile from reader, rejecting input over max_size bytes
+    pub fn from_reader_with_limit<R: Read>(
+        reader: R,
+        max_size: u64,
+    ) -> Result<Self, ReadTorrentError> {
+        let mut content = Vec::new();
+        //read one byte past the limit to tell input of exactly max_size from larger input
+        reader
+            .take(max_size.saturating_add(1))
+            .read_to_end(&mut content)?;
+        check_size(&content, max_size)?;
+        Self::from_bytes(content)
+    }
+}
+
+//torrents are handed to spawned tasks, so TorrentFile must stay Send and Sync
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<TorrentFile>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    //single file torrent announcing to http://x/ with extra top-level keys, which must sort after "announce"
+    fn with_keys(extra: &[u8]) -> TorrentFile {
+        let mut bytes = b"d8:announce9:http://x/".to_vec();
+        bytes.extend_from_slice(extra);
+        bytes.extend_from_slice(
+            b"4:infod6:lengthi5e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee",
+        );
+        TorrentFile::from_bytes(bytes).unwrap()
+    }
+
+    //single file torrent with extra keys at the end of the info dict
+    fn with_info_keys(extra: &[u8]) -> TorrentFile {
+        let mut bytes = b"d8:announce9:http://x/4:infod6:lengthi5e4:name1:a12:piece lengthi16384e\
+            6:pieces20:aaaaaaaaaaaaaaaaaaaa"
+            .to_vec();
+        bytes.extend_from_slice(extra);
+        bytes.extend_from_slice(b"ee");
+        TorrentFile::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn torrent_files_are_shared_between_threads() {
+        let torrent_file = Arc::new(with_keys(b""));
+        let info_hash = torrent_file.torrent().info_hash;
+        //every thread reads the torrent borrowed from the same data
+        let names: Vec<String> = (0..4)
+            .map(|_| {
+                let torrent_file = torrent_file.clone();
+                std::thread::spawn(move || {
+                    let torrent = torrent_file.torrent();
+                    assert_eq!(torrent.info_hash, info_hash);
+                    torrent.info.name.to_string()
+                })
+            })
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        assert_eq!(names, ["a"; 4]);
+        //the data lives on after the other threads let go of it
+        assert_eq!(Arc::strong_count(&torrent_file), 1);
+        assert_eq!(torrent_file.torrent().trackers(), [[&b"http://x/"[..]]]);
+    }
+
+    #[test]
+    fn announce_list_tiers() {
+        let file = with_keys(b"13:announce-listll8:http://a8:http://beleli1eel8:http://c3:urlee");
+        //urls keep their order, empty tiers are dropped
+        let expected: Vec<Vec<&[u8]>> =
+            vec![vec![b"http://a", b"http://b"], vec![b"http://c", b"url"]];
+        assert_eq!(file.torrent().trackers(), expected);
+
+        let file = with_keys(b"13:announce-listl5:http:i1ee");
+        assert!(file.torrent().announce_list.is_empty());
+        let expected: Vec<Vec<&[u8]>> = vec![vec![b"http://x/"]];
+        assert_eq!(file.torrent().trackers(), expected);
+
+        let bytes = b"d8:announce9:http://x/13:announce-list1:x4:infod6:lengthi5e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        assert!(TorrentFile::from_bytes(bytes.to_vec()).is_err());
+    }
+
+    #[test]
+    fn descriptive_fields_are_optional() {
+        let file = with_keys(
+            b"7:comment2:hi10:created by5:mktor13:creation datei1700000000e8:encoding5:UTF-8",
+        );
+        let torrent = &file.torrent();
+        assert_eq!(torrent.creation_date, Some(1700000000));
+        assert_eq!(torrent.comment.as_deref(), Some("hi"));
+        assert_eq!(torrent.created_by.as_deref(), Some("mktor"));
+        assert_eq!(torrent.encoding.as_deref(), Some("UTF-8"));
+
+        let file = with_keys(b"");
+        let torrent = &file.torrent();
+        assert!(torrent.creation_date.is_none() && torrent.comment.is_none());
+        assert!(torrent.created_by.is_none() && torrent.encoding.is_none());
+
+        //bad values are dropped or repaired instead of failing the torrent
+        let file = with_keys(b"7:comment2:\xff\xfe10:created byi3e13:creation date3:abc");
+        let torrent = &file.torrent();
+        assert_eq!(torrent.comment.as_deref(), Some("\u{fffd}\u{fffd}"));
+        assert!(torrent.created_by.is_none() && torrent.creation_date.is_none());
+        assert_eq!(
+            with_keys(b"13:creation datei-5e").torrent().creation_date,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn private_flag_is_part_of_the_info_hash() {
+        let private = with_info_keys(b"7:privatei1e");
+        assert!(private.torrent().info.is_private());
+        for extra in [&b"7:privatei0e"[..], b"7:private1:1", b""] {
+            assert!(!with_info_keys(extra).torrent().info.is_private());
+        }
+        assert_ne!(
+            private.torrent().info_hash,
+            with_info_keys(b"").torrent().info_hash
+        );
+    }
+
+    #[test]
+    fn md5sums_are_parsed_from_hex() {
+        //multi file torrent whose single file has extra keys
+        let parse = |extra: &[u8]| {
+            let mut bytes = b"d4:infod5:filesld6:lengthi3e4:pathl1:xe".to_vec();
+            bytes.extend_from_slice(extra);
+            bytes.extend_from_slice(
+                b"ee4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee",
+            );
+            TorrentFile::from_bytes(bytes)
+        };
+        let md5sum = |file: TorrentFile| match &file.torrent().info.file_details {
+            FileDetails::MultiFile { files } => files[0].md5sum,
+            FileDetails::SingleFile { .. } => unreachable!(),
+        };
+
+        let expected = [
+            0x90, 0x01, 0x50, 0x98, 0x3c, 0xd2, 0x4f, 0xb0, 0xd6, 0x96, 0x3f, 0x7d, 0x28, 0xe1,
+            0x7f, 0x72,
+        ];
+        let file = parse(b"6:md5sum32:900150983CD24FB0d6963f7d28e17f72").unwrap();
+        assert_eq!(md5sum(file), Some(expected));
+        assert_eq!(md5sum(parse(b"").unwrap()), None);
+        for bad in [
+            &b"6:md5sum31:900150983cd24fb0d6963f7d28e17f7"[..],
+            b"6:md5sum32:900150983cd24fb0d6963f7d28e17fzz",
+            b"6:md5sumi5e",
+        ] {
+            assert!(parse(bad).is_err(), "{}", String::from_utf8_lossy(bad));
+        }
+
+        let file = with_info_keys(b"6:md5sum32:900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(file.torrent().info.md5sum, Some(expected));
+    }
+
+    #[test]
+    fn url_list_is_a_string_or_a_list() {
+        let expected: Vec<&[u8]> = vec![b"http://a/b/c"];
+        assert_eq!(
+            with_keys(b"8:url-list12:http://a/b/c").torrent().url_list,
+            expected
+        );
+        //entries that are not strings and empty URLs are skipped
+        let expected: Vec<&[u8]> = vec![b"http:", b"http:2"];
+        let file = with_keys(b"8:url-listl5:http:i1e0:6:http:2e");
+        assert_eq!(file.torrent().url_list, expected);
+        assert!(with_keys(b"").torrent().url_list.is_empty());
+        assert!(with_keys(b"8:url-list0:").torrent().url_list.is_empty());
+    }
+
+    #[test]
+    fn httpseeds_are_parsed() {
+        let expected: Vec<&[u8]> = vec![b"http://a"];
+        assert_eq!(
+            with_keys(b"9:httpseedsl8:http://ae").torrent().httpseeds,
+            expected
+        );
+        assert!(with_keys(b"").torrent().httpseeds.is_empty());
+    }
+
+    #[test]
+    fn nodes_skip_malformed_entries() {
+        let file = with_keys(
+            b"5:nodesll7:1.2.3.4i6881eel18:router.example.orgi6881eel3:badeli1ei2ee\
+              l1:xi70000eel1:xi0eel1:y4:6881el2:\xff\xffi1eel5:[::1]i5ee4:junke",
+        );
+        let expected = [
+            ("1.2.3.4".to_string(), 6881),
+            ("router.example.org".to_string(), 6881),
+            ("[::1]".to_string(), 5),
+        ];
+        assert_eq!(file.torrent().nodes, expected);
+        assert!(with_keys(b"").torrent().nodes.is_empty());
+        assert!(with_keys(b"5:nodes3:abc").torrent().nodes.is_empty());
+    }
+
+    #[test]
+    fn announce_is_optional() {
+        //torrent with the given top-level keys before info and no announce
+        let without_announce = |keys: &[u8]| {
+            let mut bytes = b"d".to_vec();
+            bytes.extend_from_slice(keys);
+            bytes.extend_from_slice(
+                b"4:infod6:lengthi5e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee",
+            );
+            TorrentFile::from_bytes(bytes).unwrap()
+        };
+
+        let file = without_announce(b"5:nodesll7:1.2.3.4i1eee");
+        assert!(file.torrent().announce.is_none());
+        assert!(file.torrent().trackers().is_empty());
+        assert!(file.torrent().has_peer_source());
+        let file = without_announce(b"13:announce-listll8:http://aee");
+        let expected: Vec<Vec<&[u8]>> = vec![vec![b"http://a"]];
+        assert_eq!(file.torrent().trackers(), expected);
+        assert!(!without_announce(b"").torrent().has_peer_source());
+
+        //an announce of the wrong type is still an error
+        let bytes = b"d8:announcei1e4:infod6:lengthi5e4:name1:a12:piece lengthi1e6:pieces0:ee";
+        assert!(TorrentFile::from_bytes(bytes.to_vec()).is_err());
+    }
+
+    #[test]
+    fn info_hash_covers_the_raw_info_bytes() {
+        //keys out of order, re-encoding the info dict would sort them
+        let info: &[u8] =
+            b"d4:name1:a6:lengthi5e12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+        let mut bytes = b"d8:announce1:x4:info".to_vec();
+        bytes.extend_from_slice(info);
+        bytes.push(b'e');
+        let file = TorrentFile::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(file.torrent().raw_info_bytes(), info);
+        assert_eq!(
+            hex::encode(&file.torrent().info_hash),
+            "e63fd68b74c6ba345a2343bf975fc5dd98b2db69"
+        );
+        assert_eq!(
+            file.torrent().info_hash,
+            <[u8; 20]>::from(Sha1::digest(info))
+        );
+
+        //decoding an already parsed value keeps them too
+        let root = crate::util::bencode::parser::parse(&bytes).unwrap();
+        assert_eq!(Torrent::decode(&root).unwrap().raw_info_bytes(), info);
+    }
+
+    #[test]
+    fn v2_torrents_use_the_file_tree() {
+        let root = [7u8; 32];
+        let mut info = b"d9:file treed3:dird1:ad0:d6:lengthi40000e11:pieces root32:".to_vec();
+        info.extend_from_slice(&root);
+        info.extend_from_slice(b"eee5:emptyd0:d6:lengthi0eeee12:meta versioni2e");
+        info.extend_from_slice(b"4:name4:tree12:piece lengthi32768ee");
+        let mut bytes = b"d4:info".to_vec();
+        bytes.extend_from_slice(&info);
+        bytes.extend_from_slice(b"12:piece layersd32:");
+        bytes.extend_from_slice(&root);
+        bytes.extend_from_slice(b"64:");
+        bytes.extend_from_slice(&[1u8; 64]);
+        bytes.extend_from_slice(b"ee");
+
+        let file = TorrentFile::from_bytes(bytes).unwrap();
+        let torrent = file.torrent();
+        assert!(torrent.info.has_v2() && !torrent.info.has_v1());
+        assert!(torrent.info_hash_v1.is_none());
+        let hash: [u8; 32] = Sha256::digest(&info).into();
+        assert_eq!(torrent.info_hash_v2, Some(hash));
+        //pure v2 torrents are addressed by the truncated v2 hash
+        assert_eq!(torrent.info_hash[..], hash[..20]);
+        assert_eq!(torrent.announce_hashes(), vec![torrent.info_hash]);
+        assert_eq!(torrent.piece_layers[&root], &[1u8; 64][..]);
+
+        //files come out in path order, the empty one without a pieces root
+        let tree = &torrent.info.file_tree;
+        let paths: Vec<_> = tree.iter().map(|file| file.path.clone()).collect();
+        let expected: Vec<Vec<&[u8]>> = vec![vec![b"dir", b"a"], vec![b"empty"]];
+        assert_eq!(paths, expected);
+        assert_eq!((tree[0].length, tree[0].pieces_root), (40000, Some(root)));
+        assert_eq!((tree[1].length, tree[1].pieces_root), (0, None));
+        assert_eq!(torrent.info.total_length(), 40000);
+
+        //a non-empty file needs a pieces root
+        let bytes = b"d4:infod9:file treed1:ad0:d6:lengthi5eeee12:meta versioni2e4:name1:a12:piece lengthi16384eee";
+        let err = TorrentFile::from_bytes(bytes.to_vec()).unwrap_err();
+        assert!(err.to_string().contains("pieces root"), "{}", err);
+        //and piece layers hold whole SHA-256 hashes
+        let mut bytes = b"d4:infod9:file treed1:ad0:d6:lengthi0eeee12:meta versioni2e4:name1:a12:piece lengthi16384ee12:piece layersd32:".to_vec();
+        bytes.extend_from_slice(&root);
+        bytes.extend_from_slice(b"3:abcee");
+        assert!(TorrentFile::from_bytes(bytes).is_err());
+    }
+
+    #[test]
+    fn hybrid_torrents_have_both_hashes() {
+        //single file hybrid torrent whose v1 length is v1_length, the file tree says 5
+        let hybrid = |v1_length: u64| {
+            let mut bytes = b"d4:infod9:file treed1:ad0:d6:lengthi5e11:pieces root32:".to_vec();
+            bytes.extend_from_slice(&[7u8; 32]);
+            bytes.extend_from_slice(
+                format!("eee6:lengthi{v1_length}e12:meta versioni2e4:name1:a").as_bytes(),
+            );
+            bytes.extend_from_slice(b"12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee");
+            TorrentFile::from_bytes(bytes)
+        };
+
+        let file = hybrid(5).unwrap();
+        let torrent = file.torrent();
+        assert!(torrent.info.has_v1() && torrent.info.has_v2());
+        let info = torrent.raw_info_bytes();
+        assert_eq!(torrent.info_hash_v1, Some(Sha1::digest(info).into()));
+        assert_eq!(torrent.info_hash_v2, Some(Sha256::digest(info).into()));
+        //v1 peers and trackers keep using the v1 hash
+        assert_eq!(Some(torrent.info_hash), torrent.info_hash_v1);
+        let short = truncate_hash(&torrent.info_hash_v2.unwrap());
+        assert!(torrent.matches_info_hash(&torrent.info_hash));
+        assert!(torrent.matches_info_hash(&short));
+        assert!(!torrent.matches_info_hash(&[0; 20]));
+        assert_eq!(torrent.announce_hashes(), vec![torrent.info_hash, short]);
+
+        //the v1 files and the file tree must describe the same data
+        let err = hybrid(6).unwrap_err();
+        assert!(err.to_string().contains("differ"), "{}", err);
+
+        let file = with_keys(b"");
+        assert!(file.torrent().info_hash_v2.is_none());
+        assert_eq!(
+            file.torrent().announce_hashes(),
+            vec![file.torrent().info_hash]
+        );
+    }
+
+    //file entry of length bytes named f
+    fn file(length: u64) -> FileEntry<'static> {
+        FileEntry {
+            length,
+            path: vec![b"f"],
+            md5sum: None,
+            pieces_root: None,
+            attr: None,
+            symlink_path: None,
+        }
+    }
+
+    //v1 info with pieces of 16 bytes
+    fn info(file_details: FileDetails<'static>, raw_pieces: &'static [u8]) -> Info<'static> {
+        Info {
+            name: "x".into(),
+            raw_name: b"x",
+            piece_length: 16,
+            raw_pieces,
+            file_offsets: Info::file_offsets(&file_details),
+            file_details,
+            md5sum: None,
+            private: false,
+            source: None,
+            meta_version: 1,
+            file_tree: vec![],
+        }
+    }
+
+    //v1 multi file info with files of the given lengths
+    fn files(lengths: &[u64]) -> Info<'static> {
+        info(
+            FileDetails::MultiFile {
+                files: lengths.iter().map(|length| file(*length)).collect(),
+            },
+            &[],
+        )
+    }
+
+    #[test]
+    fn total_length_sums_the_files() {
+        let single = info(FileDetails::SingleFile { length: 5 }, &[0; 40]);
+        assert_eq!((single.total_length(), single.num_pieces()), (5, 2));
+        assert_eq!(files(&[3, 4]).total_length(), 7);
+        assert_eq!(files(&[]).total_length(), 0);
+        assert_eq!(files(&[]).num_pieces(), 0);
+        //saturates instead of wrapping around
+        assert_eq!(files(&[u64::MAX, 4]).total_length(), u64::MAX);
+        assert_eq!(with_keys(b"").torrent().info.total_length(), 5);
+    }
+
+    //single file torrent of length bytes in pieces of piece_length, with count piece hashes
+    fn with_pieces(
+        length: u64,
+        piece_length: u64,
+        count: usize,
+    ) -> Result<TorrentFile, ReadTorrentError> {
+        let mut bytes = format!(
+            "d4:infod6:lengthi{length}e4:name1:a12:piece lengthi{piece_length}e6:pieces{}:",
+            count * 20
+        )
+        .into_bytes();
+        bytes.extend(vec![b'a'; count * 20]);
+        bytes.extend_from_slice(b"ee");
+        TorrentFile::from_bytes(bytes)
+    }
+
+    //the validation error a torrent was refused with
+    fn invalid(result: Result<TorrentFile, ReadTorrentError>) -> InvalidInfoError {
+        let Err(ReadTorrentError::BencodeDecodableError(mut err)) = result else {
+            panic!("{:?}", result.map(|_| ()));
+        };
+        while let BencodeDecodableError::Context { source, .. } = err {
+            err = *source;
+        }
+        match err {
+            BencodeDecodableError::InvalidInfo(err) => err,
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn piece_count_must_match_the_length() {
+        assert!(with_pieces(32, 16, 2).is_ok());
+        assert!(with_pieces(33, 16, 3).is_ok());
+        assert!(with_pieces(0, 16, 0).is_ok());
+
+        let err = invalid(with_pieces(33, 16, 2));
+        assert!(matches!(
+            err,
+            InvalidInfoError::PieceCount {
+                given: 2,
+                expected: 3,
+                total_length: 33,
+                piece_length: 16
+            }
+        ));
+        let message = err.to_string();
+        assert!(
+            message.contains("2 piece hashes") && message.contains("need 3"),
+            "{}",
+            message
+        );
+        assert!(with_pieces(32, 16, 3).is_err());
+        assert!(matches!(
+            invalid(with_pieces(0, 16, 1)),
+            InvalidInfoError::PieceCount { expected: 0, .. }
+        ));
+        assert!(matches!(
+            invalid(with_pieces(32, 0, 0)),
+            InvalidInfoError::ZeroPieceLength
+        ));
+
+        let bytes =
+            b"d4:infod6:lengthi5e4:name1:a12:piece lengthi16e6:pieces19:aaaaaaaaaaaaaaaaaaaee";
+        assert!(matches!(
+            invalid(TorrentFile::from_bytes(bytes.to_vec())),
+            InvalidInfoError::PiecesLength(19)
+        ));
+    }
+
+    #[test]
+    fn last_piece_is_truncated() {
+        //(total length, index, expected) with pieces of 16
+        let table: &[(u64, usize, Option<u64>)] = &[
+            (0, 0, None),
+            (1, 0, Some(1)),
+            (16, 0, Some(16)),
+            (16, 1, None),
+            (17, 0, Some(16)),
+            (17, 1, Some(1)),
+            (17, 2, None),
+            (50, 3, Some(2)),
+            (50, usize::MAX, None),
+        ];
+        for &(length, index, expected) in table {
+            let info = info(FileDetails::SingleFile { length }, &[]);
+            assert_eq!(info.piece_len(index), expected, "{} {}", length, index);
+        }
+        let info = files(&[10, 30]);
+        assert_eq!(
+            (info.piece_len(1), info.piece_len(2), info.piece_len(3)),
+            (Some(16), Some(8), None)
+        );
+    }
+
+    #[test]
+    fn torrent_files_can_move_between_threads() {
+        let file = with_keys(b"7:comment2:hi");
+        let info_hash = file.torrent().info_hash;
+        //moving the file keeps the parsed torrent pointing into its own bytes
+        let file = std::thread::spawn(move || file).join().unwrap();
+        assert_eq!(file.torrent().info_hash, info_hash);
+        assert_eq!(file.torrent().comment.as_deref(), Some("hi"));
+    }
+
+    //reader handing out at most three bytes per read
+    struct Trickle(std::io::Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(3);
+            Read::read(&mut self.0, &mut buf[..len])
+        }
+    }
+
+    const SMALL: &[u8] =
+        b"d8:announce9:http://x/4:infod6:lengthi5e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+
+    #[test]
+    fn readers_are_read_up_to_the_limit() {
+        let trickle = || Trickle(std::io::Cursor::new(SMALL.to_vec()));
+        let file = TorrentFile::from_reader(trickle()).unwrap();
+        assert_eq!(file.torrent().info_hash, with_keys(b"").torrent().info_hash);
+
+        let len = SMALL.len() as u64;
+        assert!(TorrentFile::from_reader_with_limit(trickle(), len).is_ok());
+        assert!(matches!(
+            TorrentFile::from_reader_with_limit(trickle(), len - 1),
+            Err(ReadTorrentError::TooLarge(limit)) if limit == len - 1
+        ));
+        //endless input stops at the default limit
+        assert!(matches!(
+            TorrentFile::from_reader(std::io::repeat(b'a')),
+            Err(ReadTorrentError::TooLarge(DEFAULT_MAX_TORRENT_SIZE))
+        ));
+    }
+
+    #[tokio::test]
+    async fn files_are_read_without_blocking() {
+        let path = std::env::temp_dir().join(format!("motteseed-{}.torrent", std::process::id()));
+        std::fs::write(&path, SMALL).unwrap();
+        assert!(TorrentFile::from_file_async(&path).await.is_ok());
+        assert!(TorrentFile::from_file(&path).is_ok());
+        assert!(matches!(
+            TorrentFile::from_file_async_with_limit(&path, 10).await,
+            Err(ReadTorrentError::TooLarge(10))
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            TorrentFile::from_file_async(&path).await,
+            Err(ReadTorrentError::IOError(_))
+        ));
+    }
+
+    //torrent with the given bencoded info dict and no other keys
+    fn with_info(info: &[u8]) -> Result<TorrentFile, ReadTorrentError> {
+        let mut bytes = b"d4:info".to_vec();
+        bytes.extend_from_slice(info);
+        bytes.push(b'e');
+        TorrentFile::from_bytes(bytes)
+    }
+
+    #[test]
+    fn broken_info_dicts_are_refused() {
+        let long_name = format!(
+            "d6:lengthi5e4:name256:{}12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaae",
+            "n".repeat(256)
+        );
+        let max = i64::MAX;
+        let overflow = format!(
+            "d5:filesld6:lengthi{max}e4:pathl1:aeed6:lengthi{max}e4:pathl1:beed6:lengthi{max}e\
+            4:pathl1:ceee4:name1:d12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaae"
+        );
+
+        let err = invalid(with_info(
+            b"d6:lengthi5e4:name0:12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaae",
+        ));
+        assert!(matches!(err, InvalidInfoError::EmptyName));
+        let err = invalid(with_info(long_name.as_bytes()));
+        assert!(matches!(err, InvalidInfoError::NameTooLong(256)));
+        let err = invalid(with_info(overflow.as_bytes()));
+        assert!(matches!(err, InvalidInfoError::LengthOverflow));
+        let err = invalid(with_info(
+            b"d5:filesle4:name1:d12:piece lengthi16e6:pieces0:e",
+        ));
+        assert!(matches!(err, InvalidInfoError::NoFiles));
+        let err = invalid(with_info(
+            b"d5:filesld6:lengthi3e4:pathl1:s1:aeed6:lengthi4e4:pathl1:s1:aeee4:name1:d\
+            12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaae",
+        ));
+        assert!(matches!(err, InvalidInfoError::DuplicatePath(path) if path == "s/a"));
+
+        //odd piece lengths and padding files sharing a name only warn
+        let file = with_info(
+            b"d6:lengthi30e4:name1:a12:piece lengthi24e\
+            6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaae",
+        )
+        .unwrap();
+        let warnings = file.torrent().info.warnings();
+        assert_eq!(warnings, vec![InfoWarning::PieceLengthNotPowerOfTwo(24)]);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Piece length 24 is not a power of two"
+        );
+        let pads = format!(
+            "d5:filesld6:lengthi1e4:pathl1:aeed4:attr1:p6:lengthi15e4:pathl4:.pad2:15ee\
+            d6:lengthi1e4:pathl1:beed4:attr1:p6:lengthi15e4:pathl4:.pad2:15ee\
+            d6:lengthi1e4:pathl1:ceee4:name1:d12:piece lengthi16e6:pieces60:{}e",
+            "a".repeat(60)
+        );
+        let file = with_info(pads.as_bytes()).unwrap();
+        assert!(file.torrent().info.warnings().is_empty());
+    }
+
+    #[test]
+    fn offsets_map_to_files() {
+        let single = info(FileDetails::SingleFile { length: 10 }, &[]);
+        assert_eq!(single.file_at(0), Some((0, 0)));
+        assert_eq!(single.file_at(9), Some((0, 9)));
+        assert_eq!(single.file_at(10), None);
+
+        //files cover [0, 5), [5, 5), [5, 12) and [12, 20)
+        let multi = files(&[5, 0, 7, 8]);
+        assert_eq!(multi.file_at(0), Some((0, 0)));
+        assert_eq!(multi.file_at(4), Some((0, 4)));
+        //empty files hold no bytes
+        assert_eq!(multi.file_at(5), Some((2, 0)));
+        assert_eq!(multi.file_at(11), Some((2, 6)));
+        assert_eq!(multi.file_at(12), Some((3, 0)));
+        assert_eq!(multi.file_at(19), Some((3, 7)));
+        assert_eq!(multi.file_at(20), None);
+        assert_eq!(files(&[0, 3, 0]).file_at(0), Some((1, 0)));
+        assert_eq!(files(&[0, 0]).file_at(0), None);
+        assert_eq!(files(&[]).file_at(0), None);
+    }
+
+    #[test]
+    fn ranges_are_split_at_file_boundaries() {
+        //span of length bytes at offset of file
+        let span = |file, offset, length| FileSpan {
+            file,
+            offset,
+            length,
+        };
+        let multi = files(&[5, 0, 7, 8]);
+        let spans = |offset, length| multi.files_in_range(offset, length).collect::<Vec<_>>();
+        assert_eq!(spans(0, 5), [span(0, 0, 5)]);
+        assert_eq!(spans(5, 7), [span(2, 0, 7)]);
+        assert_eq!(spans(4, 2), [span(0, 4, 1), span(2, 0, 1)]);
+        assert_eq!(spans(3, 12), [span(0, 3, 2), span(2, 0, 7), span(3, 0, 3)]);
+        //ranges past the end are cut off
+        let all = [span(0, 0, 5), span(2, 0, 7), span(3, 0, 8)];
+        assert_eq!(spans(0, 20), all);
+        assert_eq!(spans(0, 100), all);
+        assert_eq!(spans(19, u64::MAX), [span(3, 7, 1)]);
+        assert!(spans(12, 0).is_empty());
+        assert!(spans(20, 5).is_empty());
+        assert!(spans(u64::MAX, 5).is_empty());
+
+        let single = info(FileDetails::SingleFile { length: 10 }, &[]);
+        let spans: Vec<_> = single.files_in_range(2, 5).collect();
+        assert_eq!(spans, [span(0, 2, 5)]);
+    }
+
+    #[test]
+    fn source_is_part_of_the_info_hash() {
+        let file = with_info_keys(b"6:source3:XYZ");
+        assert_eq!(file.torrent().info.source.as_deref(), Some("XYZ"));
+        assert!(with_info_keys(b"").torrent().info.source.is_none());
+        //a source of the wrong type is treated as missing
+        assert!(
+            with_info_keys(b"6:sourcei1e")
+                .torrent()
+                .info
+                .source
+                .is_none()
+        );
+        assert_ne!(
+            file.torrent().info_hash,
+            with_info_keys(b"").torrent().info_hash
+        );
+        assert!(file.torrent().to_string().contains("Source:       XYZ\n"));
+        assert_eq!(
+            file.torrent().to_owned().info.source.as_deref(),
+            Some("XYZ")
+        );
+    }
+
+    #[test]
+    fn creation_dates_before_1970_are_clamped() {
+        let date = |keys: &[u8]| with_keys(keys).torrent().creation_date;
+        assert_eq!(date(b"13:creation datei-86400e"), Some(0));
+        assert_eq!(date(b"13:creation datei1700000000e"), Some(1700000000));
+        assert_eq!(date(b""), None);
+        //lengths stay unsigned
+        let err =
+            with_info(b"d6:lengthi-5e4:name1:a12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaae")
+                .unwrap_err();
+        assert!(err.to_string().contains("got -5"), "{}", err);
+    }
+
+    #[test]
+    fn errors_name_the_path_to_the_bad_value() {
+        //message of the decoding error the info dict was refused with
+        let error = |info: &[u8]| match with_info(info) {
+            Err(ReadTorrentError::BencodeDecodableError(err)) => err.to_string(),
+            other => panic!("{:?}", other.map(|_| ())),
+        };
+        assert_eq!(
+            error(
+                b"d5:filesld6:lengthi1e4:pathl1:aeed6:lengthi1e4:pathl1:beed6:length1:x\
+                4:pathl1:ceee4:name1:d12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaae"
+            ),
+            "info.files[2].length: expected a Number, found ByteString"
+        );
+        assert_eq!(
+            error(
+                b"d5:filesld6:lengthi1e4:pathl1:ai3eeee4:name1:d12:piece lengthi16e\
+                6:pieces20:aaaaaaaaaaaaaaaaaaaae"
+            ),
+            "info.files[0].path[1]: expected a ByteString, found Number"
+        );
+        assert_eq!(
+            error(b"d6:lengthi5e4:name1:a12:piece length2:166:pieces20:aaaaaaaaaaaaaaaaaaaae"),
+            "info.piece length: expected a Number, found ByteString"
+        );
+        assert_eq!(
+            error(b"d6:lengthi5e4:name1:a12:piece lengthi-16e6:pieces20:aaaaaaaaaaaaaaaaaaaae"),
+            "info.piece length: expected a non-negative number, got -16"
+        );
+        assert_eq!(
+            error(b"d6:lengthi5e4:name1:a12:piece lengthi16e6:piecesi1ee"),
+            "info.pieces: expected a ByteString, found Number"
+        );
+        let message = error(
+            b"d5:filesld6:lengthi1eee4:name1:d12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaae",
+        );
+        assert!(
+            message.starts_with("info.files[0]: Key not found"),
+            "{}",
+            message
+        );
+        //the empty key of a v2 file is left out
+        assert_eq!(
+            error(
+                b"d9:file treed3:dird1:ad0:d6:length1:xeeee12:meta versioni2e4:name3:dir\
+                12:piece lengthi16ee"
+            ),
+            "info.file tree.dir.a.length: expected a Number, found ByteString"
+        );
+        assert_eq!(error(b"i1e"), "info: expected a Dict, found Number");
+    }
+
+    #[test]
+    fn latin1_names_keep_their_raw_bytes() {
+        let file = with_info(
+            b"d5:filesld6:lengthi5e4:pathl4:caf\xe9eee4:name3:\xe9t\xe912:piece lengthi16e\
+            6:pieces20:aaaaaaaaaaaaaaaaaaaae",
+        )
+        .unwrap();
+        let info = &file.torrent().info;
+        assert_eq!(info.name, "\u{fffd}t\u{fffd}");
+        assert_eq!(info.raw_name, b"\xe9t\xe9");
+        let FileDetails::MultiFile { files } = &info.file_details else {
+            panic!("{:?}", info.file_details);
+        };
+        assert_eq!(files[0].path, [b"caf\xe9"]);
+    }
 }
@@ -1,14 +1,14 @@
 use crate::util::bencode::bencode_decodable_error::BencodeDecodableError;
-use crate::util::errors::BStreamingError;
+use crate::util::errors::BencodeParseError;
 
 use thiserror::Error;
 
 //custom error enum for reading torrent operations
 #[derive(Error, Debug)]
 pub enum ReadTorrentError {
-    //variant for streaming errors with a display message
-    #[error("Streaming error: {0}")]
-    StreamingError(#[from] BStreamingError),
+    //input that is not valid bencode
+    #[error("Parse error: {0}")]
+    ParseError(#[from] BencodeParseError),
 
     //key not found error
     #[error("Key not found: {0}")]
@@ -17,4 +17,75 @@ pub enum ReadTorrentError {
     //io error with a display message
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
+
+    //torrent larger than the size limit, with the limit in bytes
+    #[error("Torrent is larger than {0} bytes")]
+    TooLarge(u64),
+}
+
+//info dict whose fields are present and well typed, but that no client can download
+#[derive(Error, Debug)]
+pub enum InvalidInfoError {
+    //name of zero bytes, it would be the file or directory name of the download
+    #[error("Name is empty")]
+    EmptyName,
+
+    //name longer than file systems allow for a single component, with its length in bytes
+    #[error("Name is {0} bytes long")]
+    NameTooLong(usize),
+
+    //piece length of zero, the data can not be split into pieces
+    #[error("Piece length is 0")]
+    ZeroPieceLength,
+
+    //pieces string that is not a whole number of SHA-1 hashes, with its length in bytes
+    #[error("Pieces are {0} bytes long, not a multiple of 20")]
+    PiecesLength(usize),
+
+    //piece hashes that do not cover exactly the torrent data
+    #[error(
+        "{given} piece hashes given, but {total_length} bytes in pieces of {piece_length} bytes need {expected}"
+    )]
+    PieceCount {
+        given: usize,
+        expected: u64,
+        total_length: u64,
+        piece_length: u64,
+    },
+
+    //file lengths that add up to more than u64::MAX
+    #[error("File lengths add up to more than u64::MAX")]
+    LengthOverflow,
+
+    //multi file torrent without any files
+    #[error("Files list is empty")]
+    NoFiles,
+
+    //two files with the same path, with the path joined by '/'
+    #[error("Duplicate file path: {0}")]
+    DuplicatePath(String),
+
+    //hybrid torrent whose v1 file list and v2 file tree describe different files
+    #[error("v1 and v2 file lists of hybrid torrent differ")]
+    HybridMismatch,
+}
+
+//custom error enum for creating torrent files
+#[derive(Error, Debug)]
+pub enum CreateTorrentError {
+    //io error with a display message
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    //path that has no UTF-8 name to put in the torrent
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+
+    //directory without any files to share
+    #[error("No files found in {0}")]
+    NoFiles(String),
+
+    //piece length that is not a power of two
+    #[error("Invalid piece length: {0}")]
+    InvalidPieceLength(u64),
 }
@@ -0,0 +1,219 @@
+use crate::core::torrent::torrent::{FileDetails, FileEntry, Info, Torrent};
+
+use std::collections::HashMap;
+
+//torrent that owns all of its data, for long-lived session state and other threads
+//unlike TorrentFile it does not keep the raw file and its bencode around
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentOwned {
+    pub announce: Option<Vec<u8>>, //tracker URL, None for trackerless torrents
+    pub announce_list: Vec<Vec<Vec<u8>>>, //tiers of tracker URLs, empty if absent
+    pub creation_date: Option<u64>, //unix timestamp the torrent was created at
+    pub comment: Option<String>,   //free-form comment from the author
+    pub created_by: Option<String>, //program that created the torrent
+    pub encoding: Option<String>,  //string encoding used in the info dict
+    pub url_list: Vec<Vec<u8>>,    //BEP 19 web seed URLs, empty if absent
+    pub httpseeds: Vec<Vec<u8>>,   //BEP 17 http seed URLs, empty if absent
+    pub nodes: Vec<(String, u16)>, //DHT bootstrap nodes, host is an IP or a name
+    pub info: InfoOwned,           //main metadata
+    pub piece_layers: HashMap<[u8; 32], Vec<u8>>, //v2 piece hashes by pieces root
+    pub info_hash: [u8; 20],       //v1 hash, or truncated v2 hash for pure v2
+    pub info_hash_v1: Option<[u8; 20]>, //SHA1 of info for v1 and hybrid torrents
+    pub info_hash_v2: Option<[u8; 32]>, //SHA-256 of info for v2 and hybrid torrents
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfoOwned {
+    pub name: String,                   //torrent name/file name
+    pub piece_length: u64,              //size of each piece in bytes
+    pub pieces: Vec<[u8; 20]>,          //SHA-1 hash of every piece, empty for pure v2
+    pub file_details: FileDetailsOwned, //single/multi file torrent
+    pub md5sum: Option<[u8; 16]>,       //MD5 of the file for single file torrents, rarely present
+    pub private: bool,                  //only the embedded trackers may be used to find peers
+    pub source: Option<String>, //site the torrent was made for, changes the info hash per site
+    pub meta_version: u64,      //1 for v1 torrents, 2 for v2 and hybrid torrents (BEP 52)
+    pub file_tree: Vec<FileEntryOwned>, //files of the v2 file tree in path order, empty for v1
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileDetailsOwned {
+    SingleFile { length: u64 }, //file length in bytes for single file torrent
+    MultiFile { files: Vec<FileEntryOwned> }, //list of files for multi file torrent
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntryOwned {
+    pub length: u64,                       //file length in bytes
+    pub path: Vec<String>,                 //path components
+    pub md5sum: Option<[u8; 16]>,          //MD5 of the file, rarely present
+    pub pieces_root: Option<[u8; 32]>, //root of the v2 merkle tree of the file, None for v1 and empty files
+    pub attr: Option<String>,          //BEP 47 attribute letters, unknown letters are kept
+    pub symlink_path: Option<Vec<String>>, //target of a symlink, relative to the torrent root
+}
+
+impl Torrent<'_> {
+    //copy the torrent into one that does not borrow from the parsed file
+    pub fn to_owned(&self) -> TorrentOwned {
+        TorrentOwned::from(self)
+    }
+}
+
+impl From<&Torrent<'_>> for TorrentOwned {
+    fn from(torrent: &Torrent<'_>) -> Self {
+        Self {
+            announce: torrent.announce.map(<[u8]>::to_vec),
+            announce_list: torrent
+                .announce_list
+                .iter()
+                .map(|tier| tier.iter().map(|url| url.to_vec()).collect())
+                .collect(),
+            creation_date: torrent.creation_date,
+            comment: torrent.comment.as_deref().map(str::to_owned),
+            created_by: torrent.created_by.as_deref().map(str::to_owned),
+            encoding: torrent.encoding.as_deref().map(str::to_owned),
+            url_list: torrent.url_list.iter().map(|url| url.to_vec()).collect(),
+            httpseeds: torrent.httpseeds.iter().map(|url| url.to_vec()).collect(),
+            nodes: torrent.nodes.clone(),
+            info: InfoOwned::from(&torrent.info),
+            piece_layers: torrent
+                .piece_layers
+                .iter()
+                .map(|(root, layer)| (*root, layer.to_vec()))
+                .collect(),
+            info_hash: torrent.info_hash,
+            info_hash_v1: torrent.info_hash_v1,
+            info_hash_v2: torrent.info_hash_v2,
+        }
+    }
+}
+
+impl From<&Info<'_>> for InfoOwned {
+    fn from(info: &Info<'_>) -> Self {
+        Self {
+            name: info.name.to_string(),
+            piece_length: info.piece_length,
+            //the parser only accepts whole 20 byte hashes
+            pieces: info
+                .raw_pieces
+                .chunks_exact(20)
+                .map(|hash| hash.try_into().expect("chunk is 20 bytes"))
+                .collect(),
+            file_details: match &info.file_details {
+                FileDetails::SingleFile { length } => {
+                    FileDetailsOwned::SingleFile { length: *length }
+                }
+                FileDetails::MultiFile { files } => FileDetailsOwned::MultiFile {
+                    files: files.iter().map(FileEntryOwned::from).collect(),
+                },
+            },
+            md5sum: info.md5sum,
+            private: info.private,
+            source: info.source.as_deref().map(str::to_owned),
+            meta_version: info.meta_version,
+            file_tree: info.file_tree.iter().map(FileEntryOwned::from).collect(),
+        }
+    }
+}
+
+impl From<&FileEntry<'_>> for FileEntryOwned {
+    //path components are UTF-8 (BEP 3), anything else is replaced with U+FFFD
+    fn from(file: &FileEntry<'_>) -> Self {
+        Self {
+            length: file.length,
+            path: lossy_path(&file.path),
+            md5sum: file.md5sum,
+            pieces_root: file.pieces_root,
+            attr: file
+                .attr
+                .map(|attr| String::from_utf8_lossy(attr).into_owned()),
+            symlink_path: file.symlink_path.as_deref().map(lossy_path),
+        }
+    }
+}
+
+//convert path components to text
+fn lossy_path(path: &[&[u8]]) -> Vec<String> {
+    path.iter()
+        .map(|component| String::from_utf8_lossy(component).into_owned())
+        .collect()
+}
+
+impl InfoOwned {
+    //get SHA1 of a piece
+    pub fn piece_hash(&self, index: usize) -> Option<&[u8; 20]> {
+        self.pieces.get(index)
+    }
+}
+
+//owned torrents are kept in session state shared between tasks
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<TorrentOwned>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::torrent::torrent::TorrentFile;
+
+    #[test]
+    fn owned_torrents_outlive_the_file() {
+        let bytes = b"d8:announce9:http://x/13:announce-listll9:http://x/el9:http://y/ee\
+            7:comment2:hi10:created by1:c13:creation datei7e8:encoding5:UTF-8\
+            9:httpseedsl9:http://h/e\
+            4:infod5:filesld6:lengthi3e6:md5sum32:900150983cd24fb0d6963f7d28e17f72\
+            4:pathl1:d2:\xffxeee4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaa\
+            7:privatei1e6:source3:XYZe5:nodesll9:127.0.0.1i6881eee8:url-list9:http://w/e";
+        let file = TorrentFile::from_bytes(bytes.to_vec()).unwrap();
+        let owned = file.torrent().to_owned();
+        assert_eq!(owned, TorrentOwned::from(file.torrent()));
+        assert_eq!(owned.info_hash, file.torrent().info_hash);
+        drop(file);
+
+        assert_eq!(owned.announce.as_deref(), Some(&b"http://x/"[..]));
+        assert_eq!(owned.announce_list.len(), 2);
+        assert_eq!(owned.comment.as_deref(), Some("hi"));
+        assert_eq!(owned.creation_date, Some(7));
+        assert_eq!(owned.url_list, vec![b"http://w/".to_vec()]);
+        assert_eq!(owned.httpseeds, vec![b"http://h/".to_vec()]);
+        assert_eq!(owned.nodes, vec![("127.0.0.1".to_string(), 6881)]);
+        let info = &owned.info;
+        assert!(info.private);
+        assert_eq!(info.source.as_deref(), Some("XYZ"));
+        assert_eq!(info.pieces, vec![[b'a'; 20]]);
+        assert_eq!(info.piece_hash(0), Some(&[b'a'; 20]));
+        assert_eq!(info.piece_hash(1), None);
+        let FileDetailsOwned::MultiFile { files } = &info.file_details else {
+            panic!("{:?}", info.file_details);
+        };
+        //path components that are not UTF-8 are converted lossily
+        assert_eq!(files[0].path, vec!["d", "\u{fffd}x"]);
+        assert_eq!(files[0].md5sum.unwrap()[0], 0x90);
+
+        //other threads can take it
+        std::thread::spawn(move || assert_eq!(owned.info.name, "a"))
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn v2_files_and_piece_layers_are_copied() {
+        let mut bytes = b"d4:infod9:file treed1:ad0:d6:lengthi40000e11:pieces root32:".to_vec();
+        bytes.extend_from_slice(&[7; 32]);
+        bytes.extend_from_slice(b"eee12:meta versioni2e4:name1:a12:piece lengthi32768ee");
+        bytes.extend_from_slice(b"12:piece layersd32:");
+        bytes.extend_from_slice(&[7; 32]);
+        bytes.extend_from_slice(b"64:");
+        bytes.extend_from_slice(&[1; 64]);
+        bytes.extend_from_slice(b"ee");
+        let file = TorrentFile::from_bytes(bytes).unwrap();
+        let owned = file.torrent().to_owned();
+
+        assert!(owned.info.pieces.is_empty());
+        assert_eq!(owned.info.meta_version, 2);
+        assert_eq!(owned.info.file_tree[0].path, vec!["a"]);
+        assert_eq!(owned.info.file_tree[0].pieces_root, Some([7; 32]));
+        assert_eq!(owned.piece_layers[&[7; 32]], vec![1; 64]);
+        assert_eq!(owned.info_hash_v2, file.torrent().info_hash_v2);
+    }
+}
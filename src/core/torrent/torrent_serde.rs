@@ -0,0 +1,185 @@
+use crate::core::torrent::torrent_owned::{
+    FileDetailsOwned, FileEntryOwned, InfoOwned, TorrentOwned,
+};
+use crate::util::hex;
+
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
+
+//byte string shown as lossy UTF-8 text in human-readable formats like JSON
+//binary formats get the raw bytes, so nothing is lost there
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&String::from_utf8_lossy(self.0))
+        } else {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+//hash shown as lowercase hex in human-readable formats, raw bytes otherwise
+struct Hash<'a>(&'a [u8]);
+
+impl Serialize for Hash<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(self.0))
+        } else {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+//list of byte strings, e.g. tracker or web seed URLs
+struct BytesList<'a>(&'a [Vec<u8>]);
+
+impl Serialize for BytesList<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for bytes in self.0 {
+            seq.serialize_element(&Bytes(bytes))?;
+        }
+        seq.end()
+    }
+}
+
+//tiers of tracker URLs
+struct Tiers<'a>(&'a [Vec<Vec<u8>>]);
+
+impl Serialize for Tiers<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for tier in self.0 {
+            seq.serialize_element(&BytesList(tier))?;
+        }
+        seq.end()
+    }
+}
+
+//torrent metadata without the piece hashes, which are only useful to a client
+impl Serialize for TorrentOwned {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Torrent", 13)?;
+        state.serialize_field("info_hash", &Hash(&self.info_hash))?;
+        state.serialize_field("info_hash_v1", &self.info_hash_v1.as_ref().map(|h| Hash(h)))?;
+        state.serialize_field("info_hash_v2", &self.info_hash_v2.as_ref().map(|h| Hash(h)))?;
+        state.serialize_field("announce", &self.announce.as_deref().map(Bytes))?;
+        state.serialize_field("announce_list", &Tiers(&self.announce_list))?;
+        state.serialize_field("creation_date", &self.creation_date)?;
+        state.serialize_field("comment", &self.comment)?;
+        state.serialize_field("created_by", &self.created_by)?;
+        state.serialize_field("encoding", &self.encoding)?;
+        state.serialize_field("url_list", &BytesList(&self.url_list))?;
+        state.serialize_field("httpseeds", &BytesList(&self.httpseeds))?;
+        state.serialize_field("nodes", &self.nodes)?;
+        state.serialize_field("info", &self.info)?;
+        state.end()
+    }
+}
+
+//files are always listed, a single file torrent has one file named like the torrent
+impl Serialize for InfoOwned {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Info", 9)?;
+        state.serialize_field("name", &self.name)?;
+        state.serialize_field("piece_length", &self.piece_length)?;
+        state.serialize_field("num_pieces", &self.pieces.len())?;
+        state.serialize_field("private", &self.private)?;
+        state.serialize_field("source", &self.source)?;
+        state.serialize_field("meta_version", &self.meta_version)?;
+        state.serialize_field("md5sum", &self.md5sum.as_ref().map(|h| Hash(h)))?;
+        match &self.file_details {
+            FileDetailsOwned::SingleFile { length } => {
+                state.serialize_field("total_length", length)?;
+                let file = FileEntryOwned {
+                    length: *length,
+                    path: vec![self.name.clone()],
+                    md5sum: self.md5sum,
+                    pieces_root: self.file_tree.first().and_then(|file| file.pieces_root),
+                    attr: None,
+                    symlink_path: None,
+                };
+                state.serialize_field("files", &[file])?;
+            }
+            FileDetailsOwned::MultiFile { files } => {
+                let total_length = files
+                    .iter()
+                    .fold(0u64, |total, file| total.saturating_add(file.length));
+                state.serialize_field("total_length", &total_length)?;
+                state.serialize_field("files", files)?;
+            }
+        }
+        state.end()
+    }
+}
+
+//the path is joined with '/' into one string
+impl Serialize for FileEntryOwned {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("FileEntry", 6)?;
+        state.serialize_field("path", &self.path.join("/"))?;
+        state.serialize_field("length", &self.length)?;
+        state.serialize_field("md5sum", &self.md5sum.as_ref().map(|h| Hash(h)))?;
+        state.serialize_field("pieces_root", &self.pieces_root.as_ref().map(|h| Hash(h)))?;
+        state.serialize_field("attr", &self.attr)?;
+        state.serialize_field(
+            "symlink_path",
+            &self.symlink_path.as_ref().map(|path| path.join("/")),
+        )?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::torrent::torrent::TorrentFile;
+    use crate::util::hex;
+
+    #[test]
+    fn torrents_serialize_to_json() {
+        let bytes = b"d8:announce9:http://x/13:announce-listll9:http://x/el5:udp:\xffee\
+            7:comment2:hi13:creation datei7e5:nodesll1:hi1eee\
+            4:infod5:filesld6:lengthi3e6:md5sum32:900150983cd24fb0d6963f7d28e17f72\
+            4:pathl1:d1:xeed6:lengthi4e4:pathl1:yeee4:name1:a12:piece lengthi16384e\
+            6:pieces20:aaaaaaaaaaaaaaaaaaaa7:privatei1eee";
+        let file = TorrentFile::from_bytes(bytes.to_vec()).unwrap();
+        let json = serde_json::to_value(file.torrent().to_owned()).unwrap();
+        assert_eq!(json["info_hash"], hex::encode(&file.torrent().info_hash));
+        assert!(json["info_hash_v2"].is_null());
+        assert_eq!(json["announce"], "http://x/");
+        //byte strings that are not UTF-8 are shown lossily
+        assert_eq!(json["announce_list"][1][0], "udp:\u{fffd}");
+        assert_eq!(json["comment"], "hi");
+        assert_eq!(json["creation_date"], 7);
+        assert_eq!(json["nodes"][0], serde_json::json!(["h", 1]));
+
+        let info = &json["info"];
+        assert_eq!(info["private"], true);
+        assert_eq!(info["num_pieces"], 1);
+        assert_eq!(info["total_length"], 7);
+        assert_eq!(info["files"][0]["path"], "d/x");
+        assert_eq!(
+            info["files"][0]["md5sum"],
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(info["files"][1]["length"], 4);
+    }
+
+    #[test]
+    fn v2_hashes_are_serialized_as_hex() {
+        let mut bytes = b"d4:infod9:file treed1:ad0:d6:lengthi5e11:pieces root32:".to_vec();
+        bytes.extend_from_slice(&[7; 32]);
+        bytes.extend_from_slice(b"eee12:meta versioni2e4:name1:a12:piece lengthi16384eee");
+        let file = TorrentFile::from_bytes(bytes).unwrap();
+        let json = serde_json::to_value(file.torrent().to_owned()).unwrap();
+        let v2 = hex::encode(&file.torrent().info_hash_v2.unwrap());
+        assert_eq!(json["info_hash_v2"], v2);
+        assert_eq!(json["info"]["files"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            json["info"]["files"][0]["pieces_root"],
+            hex::encode(&[7; 32])
+        );
+    }
+}
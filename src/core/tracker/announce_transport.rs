@@ -0,0 +1,120 @@
+use crate::core::tracker::http_transport::HttpTransport;
+use crate::core::tracker::tracker::{AnnounceEvent, TrackerRequest};
+use crate::core::tracker::tracker_config::TrackerConfig;
+use crate::core::tracker::tracker_error::TrackerError;
+use crate::core::tracker::udp_tracker::{UdpAnnounceResponse, UdpTransport};
+
+use http::Uri;
+use std::future::Future;
+
+//answer from a tracker before it is decoded into a response
+#[derive(Debug)]
+pub enum RawResponse {
+    Body(Vec<u8>),            //bencoded body of an HTTP announce
+    Udp(UdpAnnounceResponse), //binary response of a UDP announce, already parsed
+}
+
+//sends announces to a tracker and returns its raw answer
+//transports keep connection state between announces, so they take &mut self
+pub trait AnnounceTransport {
+    //announce the request with the given event, echoing the tracker id if there is one
+    fn announce(
+        &mut self,
+        req: &TrackerRequest<'_>,
+        event: AnnounceEvent,
+        tracker_id: Option<&[u8]>,
+    ) -> impl Future<Output = Result<RawResponse, TrackerError>>;
+}
+
+//get the host and port to connect to for a tracker URL
+//without an explicit port: 80 for http, 443 for https and 6969 for udp
+pub fn connect_target(url: &Uri) -> Result<(&str, u16), TrackerError> {
+    let host = url
+        .host()
+        .ok_or(TrackerError::Other("Missing host in tracker URL".into()))?;
+    let port = match (url.port_u16(), url.scheme_str()) {
+        (Some(port), _) => port,
+        (None, Some("https")) => 443,
+        (None, Some("udp")) => 6969,
+        (None, _) => 80,
+    };
+
+    Ok((host, port))
+}
+
+//picks HTTP or UDP by the scheme of the announce URL
+#[derive(Debug, Default)]
+pub struct DefaultTransport {
+    http: HttpTransport, //used for http:// and https:// trackers
+    udp: UdpTransport,   //used for udp:// trackers
+}
+
+impl DefaultTransport {
+    //create a transport with custom settings for HTTP trackers
+    pub fn new(config: TrackerConfig) -> Self {
+        Self {
+            udp: UdpTransport::new(config.ip_preference, config.request_timeout),
+            http: HttpTransport::new(config),
+        }
+    }
+}
+
+impl AnnounceTransport for DefaultTransport {
+    async fn announce(
+        &mut self,
+        req: &TrackerRequest<'_>,
+        event: AnnounceEvent,
+        tracker_id: Option<&[u8]>,
+    ) -> Result<RawResponse, TrackerError> {
+        let uri = Uri::try_from(req.tracker())?;
+        if uri.scheme_str() == Some("udp") {
+            self.udp.announce(req, event, tracker_id).await
+        } else {
+            self.http.announce(req, event, tracker_id).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_default_by_scheme() {
+        for (url, host, port) in [
+            ("http://t.org/announce", "t.org", 80),
+            ("http://t.org:6969/announce", "t.org", 6969),
+            ("https://t.org/a", "t.org", 443),
+            ("https://t.org:8443/a", "t.org", 8443),
+            ("udp://t.org", "t.org", 6969),
+            ("udp://t.org:1337/announce", "t.org", 1337),
+        ] {
+            let url: Uri = url.parse().unwrap();
+            assert_eq!(connect_target(&url).unwrap(), (host, port), "{}", url);
+        }
+        assert!(connect_target(&Uri::from_static("/announce")).is_err());
+    }
+
+    #[tokio::test]
+    async fn udp_announces_give_up_after_the_request_timeout() {
+        //a tracker that never answers, which the retransmission schedule would wait on for hours
+        let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("udp://{}/announce", silent.local_addr().unwrap());
+        let req = TrackerRequest::builder(url.as_bytes(), &[1; 20], &[2; 20])
+            .build()
+            .unwrap();
+        let request_timeout = std::time::Duration::from_millis(300);
+        let mut transport = DefaultTransport::new(TrackerConfig {
+            request_timeout,
+            ..TrackerConfig::default()
+        });
+
+        let started = std::time::Instant::now();
+        let err = transport
+            .announce(&req, AnnounceEvent::Started, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TrackerError::Timeout(after) if after == request_timeout));
+        assert!(started.elapsed() < request_timeout * 3);
+    }
+}
@@ -0,0 +1,94 @@
+use rand::{Rng, rng};
+use std::time::Duration;
+
+//retry schedule for failed announces: base, base * factor, base * factor^2, ... up to max
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration, //delay before the first retry
+    factor: u32,    //multiplier applied after every failed retry
+    max: Duration,  //longest delay between retries
+    jitter: f64,    //fraction of the delay randomly added or removed
+}
+
+impl Default for Backoff {
+    //15s, 60s, 4m, then every 15m, each +-10%
+    fn default() -> Self {
+        Self {
+            base: Duration::from_secs(15),
+            factor: 4,
+            max: Duration::from_secs(15 * 60),
+            jitter: 0.1,
+        }
+    }
+}
+
+impl Backoff {
+    //create a retry schedule
+    pub fn new(base: Duration, factor: u32, max: Duration, jitter: f64) -> Self {
+        Self {
+            base,
+            factor,
+            max,
+            jitter: jitter.clamp(0.0, 1.0),
+        }
+    }
+
+    //get the delay before retry number attempt, counting from 0
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .factor
+            .checked_pow(attempt)
+            .and_then(|multiplier| self.base.checked_mul(multiplier))
+            .map_or(self.max, |delay| delay.min(self.max));
+
+        jitter(delay, self.jitter, &mut rng())
+    }
+}
+
+//randomly lengthen or shorten delay by up to fraction of itself
+pub fn jitter(delay: Duration, fraction: f64, rng: &mut impl Rng) -> Duration {
+    let fraction = fraction.clamp(0.0, 1.0);
+    if fraction == 0.0 {
+        return delay;
+    }
+    let spread = rng.random_range(-fraction..=fraction);
+    delay.mul_f64(1.0 + spread)
+}
+
+//pick a random delay within ramp, so torrents started together do not all announce at once
+pub fn ramp_delay(ramp: Duration, rng: &mut impl Rng) -> Duration {
+    ramp.mul_f64(rng.random_range(0.0..1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn delays_grow_up_to_the_max() {
+        let backoff = Backoff::new(Duration::from_secs(15), 4, Duration::from_secs(900), 0.0);
+        let delays: Vec<u64> = (0..5).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, [15, 60, 240, 900, 900]);
+        //huge attempts saturate instead of overflowing
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(900));
+    }
+
+    #[test]
+    fn jitter_stays_within_its_fraction() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let base = Duration::from_secs(1800);
+        for _ in 0..1000 {
+            let delay = jitter(base, 0.1, &mut rng);
+            assert!(delay >= Duration::from_secs(1620) && delay <= Duration::from_secs(1980));
+        }
+        assert_eq!(jitter(base, 0.0, &mut rng), base);
+        assert!(jitter(base, 5.0, &mut rng) <= base * 2);
+        for _ in 0..1000 {
+            assert!(ramp_delay(Duration::from_secs(30), &mut rng) < Duration::from_secs(30));
+        }
+        assert_eq!(ramp_delay(Duration::ZERO, &mut rng), Duration::ZERO);
+    }
+}
@@ -0,0 +1,599 @@
+use crate::core::tracker::announce_transport::{AnnounceTransport, RawResponse, connect_target};
+use crate::core::tracker::resolve::{ADDRESS_ATTEMPT_TIMEOUT, connect_any, resolve};
+use crate::core::tracker::tracker::{AnnounceEvent, TrackerRequest};
+use crate::core::tracker::tracker_config::TrackerConfig;
+use crate::core::tracker::tracker_error::TrackerError;
+use crate::util::bencode::pretty;
+
+use http::{Request, Response, StatusCode, Uri};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::{Bytes, Incoming};
+use hyper::client::conn::http1::{SendRequest, handshake};
+use hyper_util::rt::TokioIo;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tracing::info;
+
+//most redirects followed for a single announce
+const MAX_REDIRECTS: usize = 5;
+
+//announces to http:// and https:// trackers
+#[derive(Debug, Default)]
+pub struct HttpTransport {
+    config: TrackerConfig,           //session-wide tracker settings
+    redirect: Option<Vec<u8>>,       //announce URL a permanent redirect pointed us to
+    connection: Option<Connection>,  //connection kept open for the next request
+    remote_addr: Option<SocketAddr>, //address of the tracker we last connected to
+}
+
+//an open HTTP/1.1 connection to a tracker
+#[derive(Debug)]
+struct Connection {
+    origin: String,                    //scheme and authority the connection goes to
+    sender: SendRequest<Empty<Bytes>>, //handle for sending requests on the connection
+}
+
+impl AnnounceTransport for HttpTransport {
+    async fn announce(
+        &mut self,
+        req: &TrackerRequest<'_>,
+        event: AnnounceEvent,
+        tracker_id: Option<&[u8]>,
+    ) -> Result<RawResponse, TrackerError> {
+        //go straight to where a permanent redirect sent us before
+        let tracker = self.redirect.as_deref().unwrap_or(req.tracker());
+        let url = req.build_announce_url(tracker, event, tracker_id)?;
+
+        let request_timeout = self.config.request_timeout;
+        let body = timeout(request_timeout, self.send_request(url, None))
+            .await
+            .map_err(|_| TrackerError::Timeout(request_timeout))??;
+        if self.config.debug_responses {
+            info!(
+                "response from {}:\n{}",
+                String::from_utf8_lossy(req.tracker()),
+                pretty(&body)
+            );
+        }
+
+        Ok(RawResponse::Body(body))
+    }
+}
+
+impl HttpTransport {
+    //create a transport with custom settings
+    pub fn new(config: TrackerConfig) -> Self {
+        Self {
+            config,
+            redirect: None,
+            connection: None,
+            remote_addr: None,
+        }
+    }
+
+    //get the announce URL a permanent redirect pointed us to
+    pub fn redirect(&self) -> Option<&[u8]> {
+        self.redirect.as_deref()
+    }
+
+    //get the address of the tracker we last connected to, the proxy's when using one
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    //download the resource at url, following redirects
+    pub async fn get(&mut self, url: Uri) -> Result<Vec<u8>, TrackerError> {
+        let request_timeout = self.config.request_timeout;
+        timeout(request_timeout, self.send_request(url, None))
+            .await
+            .map_err(|_| TrackerError::Timeout(request_timeout))?
+    }
+
+    //download bytes start..=end of the resource at url, following redirects
+    //used for web seeds, which serve the torrent data from plain HTTP servers
+    pub async fn get_range(
+        &mut self,
+        url: Uri,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<u8>, TrackerError> {
+        let request_timeout = self.config.request_timeout;
+        timeout(request_timeout, self.send_request(url, Some((start, end))))
+            .await
+            .map_err(|_| TrackerError::Timeout(request_timeout))?
+    }
+
+    //send a request to the tracker, following redirects, and return the response body
+    //a permanent redirect on the first hop of an announce is stored for later announces
+    async fn send_request(
+        &mut self,
+        mut url: Uri,
+        range: Option<(u64, u64)>,
+    ) -> Result<Vec<u8>, TrackerError> {
+        let mut visited = HashSet::new();
+
+        for hop in 0..=MAX_REDIRECTS {
+            let res = self.http_get(&url, range).await?;
+            let status = res.status();
+
+            if status.is_redirection() {
+                let location = res
+                    .headers()
+                    .get(hyper::header::LOCATION)
+                    .ok_or_else(|| TrackerError::Redirect("Missing Location header".into()))?
+                    .to_str()
+                    .map_err(|_| TrackerError::Redirect("Invalid Location header".into()))?;
+                let next = Self::resolve_location(&url, location)?;
+
+                //read the body so the connection can take the next request
+                res.into_body().collect().await?;
+
+                if !visited.insert(next.to_string()) {
+                    return Err(TrackerError::Redirect(format!("Redirect loop at {}", next)));
+                }
+                if hop == 0
+                    && range.is_none()
+                    && (status == StatusCode::MOVED_PERMANENTLY
+                        || status == StatusCode::PERMANENT_REDIRECT)
+                {
+                    self.redirect = Some(Self::announce_base(&next).into_bytes());
+                }
+
+                url = next;
+                continue;
+            }
+
+            if !status.is_success() {
+                return Err(TrackerError::HttpStatus(status));
+            }
+
+            let body = res.collect().await?.to_bytes();
+            return match range {
+                //a server that ignores Range sends the whole resource
+                Some((start, end)) if status == StatusCode::OK => body
+                    .get(start as usize..=end as usize)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| {
+                        TrackerError::Other(
+                            format!("Response of {} bytes is too short", body.len()).into(),
+                        )
+                    }),
+                _ => Ok(body.to_vec()),
+            };
+        }
+
+        Err(TrackerError::Redirect(format!(
+            "More than {} redirects",
+            MAX_REDIRECTS
+        )))
+    }
+
+    //resolve a Location header against the URL that returned it
+    fn resolve_location(base: &Uri, location: &str) -> Result<Uri, TrackerError> {
+        let location = Uri::try_from(location)?;
+
+        //relative redirects keep the scheme and host of the current URL
+        if location.scheme().is_none() {
+            let mut parts = base.clone().into_parts();
+            parts.path_and_query = location.path_and_query().cloned();
+            return Ok(Uri::from_parts(parts)?);
+        }
+
+        match location.scheme_str() {
+            Some("http") | Some("https") => Ok(location),
+            _ => Err(TrackerError::Redirect(format!(
+                "Unsupported redirect target {}",
+                location
+            ))),
+        }
+    }
+
+    //announce URL without the query we appended, used to remember a permanent redirect
+    fn announce_base(url: &Uri) -> String {
+        format!(
+            "{}://{}{}",
+            url.scheme_str().unwrap_or("http"),
+            url.authority().map(|a| a.as_str()).unwrap_or_default(),
+            url.path()
+        )
+    }
+
+    //send a single GET request, reusing the open connection to the same origin if possible
+    async fn http_get(
+        &mut self,
+        url: &Uri,
+        range: Option<(u64, u64)>,
+    ) -> Result<Response<Incoming>, TrackerError> {
+        let origin = format!(
+            "{}://{}",
+            url.scheme_str().unwrap_or("http"),
+            url.authority().map(|a| a.as_str()).unwrap_or_default()
+        );
+
+        //a connection the server already closed is dropped here
+        if let Some(mut connection) = self
+            .connection
+            .take_if(|c| c.origin == origin && !c.sender.is_closed())
+            && connection.sender.ready().await.is_ok()
+        {
+            match connection
+                .sender
+                .send_request(self.get_request(url, range)?)
+                .await
+            {
+                Ok(res) => {
+                    self.connection = Some(connection);
+                    return Ok(res);
+                }
+                //the server closed the idle connection as we used it, retry on a new one
+                Err(e) if e.is_closed() || e.is_incomplete_message() => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let (mut sender, remote_addr) = Self::connect(url, &self.config).await?;
+        self.remote_addr = Some(remote_addr);
+        let res = sender.send_request(self.get_request(url, range)?).await?;
+        self.connection = Some(Connection { origin, sender });
+
+        Ok(res)
+    }
+
+    //build a GET request for url, asking for bytes start..=end when range is given
+    fn get_request(
+        &self,
+        url: &Uri,
+        range: Option<(u64, u64)>,
+    ) -> Result<Request<Empty<Bytes>>, TrackerError> {
+        let authority = url
+            .authority()
+            .ok_or(TrackerError::Other("Missing host in tracker URL".into()))?;
+
+        //the request line carries only the path, the host goes in the Host header
+        let target = url.path_and_query().map_or("/", |p| p.as_str());
+        let mut request = Request::builder()
+            .uri(target)
+            .header(hyper::header::HOST, authority.as_str())
+            .header(hyper::header::USER_AGENT, self.config.user_agent.as_str());
+        if let Some((start, end)) = range {
+            request = request.header(hyper::header::RANGE, format!("bytes={}-{}", start, end));
+        }
+
+        Ok(request.body(Empty::<Bytes>::new())?)
+    }
+
+    //open a new connection to the tracker, over TLS for https URLs
+    async fn connect(
+        url: &Uri,
+        config: &TrackerConfig,
+    ) -> Result<(SendRequest<Empty<Bytes>>, SocketAddr), TrackerError> {
+        //set up connection to tracker
+        let (host, port) = connect_target(url)?;
+        let https = url.scheme_str() == Some("https");
+
+        //the connect timeout covers the proxy handshake as well
+        let connect = async {
+            match &config.proxy {
+                Some(proxy) => proxy.connect(host, port).await,
+                None => {
+                    let addrs = resolve(host, port, config.ip_preference).await?;
+                    connect_any(&addrs, ADDRESS_ATTEMPT_TIMEOUT).await
+                }
+            }
+        };
+        let stream = timeout(config.connect_timeout, connect)
+            .await
+            .map_err(|_| TrackerError::Timeout(config.connect_timeout))??;
+        let remote_addr = stream.peer_addr()?;
+
+        if https {
+            //SNI and certificate checks use the host name from the URL
+            let server_name = ServerName::try_from(host.to_owned())
+                .map_err(|e| TrackerError::TlsError(e.to_string()))?;
+            let stream = TlsConnector::from(config.tls.clone())
+                .connect(server_name, stream)
+                .await
+                .map_err(|e| TrackerError::TlsError(e.to_string()))?;
+            Ok((Self::handshake(TokioIo::new(stream)).await?, remote_addr))
+        } else {
+            Ok((Self::handshake(TokioIo::new(stream)).await?, remote_addr))
+        }
+    }
+
+    //perform the HTTP/1.1 handshake on an established stream
+    async fn handshake<T>(io: TokioIo<T>) -> Result<SendRequest<Empty<Bytes>>, TrackerError>
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (sender, conn) = handshake(io).await?;
+
+        //drive the connection until either side closes it
+        //the sender then reports is_closed and the next request reconnects
+        tokio::task::spawn(async move {
+            let _ = conn.await;
+        });
+
+        Ok(sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tracker::tracker_config::DEFAULT_USER_AGENT;
+    use crate::util::test_server::{Requests, http_server, response, serve};
+
+    const PEERS: &[u8] = b"d8:intervali1800e5:peers6:\x0a\x00\x00\x01\x00\x01e";
+
+    //request to url with the default settings
+    fn request(url: &str) -> TrackerRequest<'_> {
+        TrackerRequest::builder(url.as_bytes(), &[1; 20], &[2; 20])
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn temporary_redirects_are_followed_but_not_kept() {
+        //a relative redirect from /announce to /moved, paths are sent in absolute form
+        let (port, seen) = serve(|request| {
+            if request.path.contains("/announce") {
+                response("HTTP/1.1 302 Found\r\nLocation: /moved", b"")
+            } else {
+                response("HTTP/1.1 200 OK", PEERS)
+            }
+        })
+        .await;
+        let tracker = format!("http://127.0.0.1:{}/announce", port);
+        let req = request(&tracker);
+        let mut transport = HttpTransport::default();
+        let Ok(RawResponse::Body(body)) = transport.announce(&req, AnnounceEvent::None, None).await
+        else {
+            panic!("redirect was not followed");
+        };
+        assert_eq!(body, PEERS);
+        assert_eq!(transport.redirect(), None);
+        assert!(seen.lock().unwrap()[1].path.contains("/moved"));
+    }
+
+    #[tokio::test]
+    async fn redirect_loops_and_chains_are_cut() {
+        let announce = |url: String| async move {
+            let req = request(&url);
+            let mut transport = HttpTransport::default();
+            match transport.announce(&req, AnnounceEvent::None, None).await {
+                Err(TrackerError::Redirect(reason)) => reason,
+                other => panic!("expected a redirect error, got {:?}", other),
+            }
+        };
+
+        let (port, _) = http_server(vec![(
+            "HTTP/1.1 302 Found\r\nLocation: /announce".into(),
+            vec![],
+        )])
+        .await;
+        let reason = announce(format!("http://127.0.0.1:{}/announce", port)).await;
+        assert!(reason.starts_with("Redirect loop"), "{}", reason);
+
+        //every hop goes somewhere new, /1 to /2 and so on
+        let (port, seen) = serve(|request| {
+            let hop: usize = request
+                .path
+                .rsplit('/')
+                .next()
+                .unwrap()
+                .split('?')
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap();
+            response(
+                &format!("HTTP/1.1 307 Temporary Redirect\r\nLocation: /{}", hop + 1),
+                b"",
+            )
+        })
+        .await;
+        let reason = announce(format!("http://127.0.0.1:{}/1", port)).await;
+        assert_eq!(reason, format!("More than {} redirects", MAX_REDIRECTS));
+        assert_eq!(seen.lock().unwrap().len(), MAX_REDIRECTS + 1);
+
+        let (port, _) = http_server(vec![(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: ftp://t.example/".into(),
+            vec![],
+        )])
+        .await;
+        let reason = announce(format!("http://127.0.0.1:{}/announce", port)).await;
+        assert!(
+            reason.starts_with("Unsupported redirect target"),
+            "{}",
+            reason
+        );
+
+        let (port, _) = http_server(vec![("HTTP/1.1 302 Found".into(), vec![])]).await;
+        let reason = announce(format!("http://127.0.0.1:{}/announce", port)).await;
+        assert_eq!(reason, "Missing Location header");
+    }
+
+    #[tokio::test]
+    async fn https_trackers_are_verified() {
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+        use tokio_rustls::TlsAcceptor;
+        use tokio_rustls::rustls::crypto::ring::default_provider;
+        use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+        use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+
+        //tracker on loopback with a self-signed certificate for localhost
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert = certified.cert.der().clone();
+        let key = PrivateKeyDer::try_from(certified.signing_key.serialize_der()).unwrap();
+        let server = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    continue;
+                };
+                let _ = stream.read(&mut [0; 4096]).await;
+                let _ = stream.write_all(&response("HTTP/1.1 200 OK", PEERS)).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        let tracker = format!("https://localhost:{}/announce", port);
+        let req = request(&tracker);
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let mut trusting = HttpTransport::new(TrackerConfig::with_root_store(roots));
+        let Ok(RawResponse::Body(body)) = trusting.announce(&req, AnnounceEvent::None, None).await
+        else {
+            panic!("https announce failed");
+        };
+        assert_eq!(body, PEERS);
+
+        //the default roots do not know the certificate
+        let err = HttpTransport::default()
+            .announce(&req, AnnounceEvent::None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TrackerError::TlsError(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn silent_trackers_time_out() {
+        //accept connections but never answer
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok(connection) = listener.accept().await {
+                open.push(connection);
+            }
+        });
+
+        let tracker = format!("http://127.0.0.1:{}/announce", port);
+        let req = request(&tracker);
+        let request_timeout = std::time::Duration::from_millis(300);
+        let config = TrackerConfig {
+            request_timeout,
+            ..Default::default()
+        };
+        let started = std::time::Instant::now();
+        let err = HttpTransport::new(config)
+            .announce(&req, AnnounceEvent::None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TrackerError::Timeout(after) if after == request_timeout));
+        assert!(started.elapsed() >= request_timeout);
+        assert!(started.elapsed() < request_timeout * 3);
+        assert!(err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn user_agent_is_sent_on_every_hop() {
+        let (to, to_seen) = http_server(vec![("HTTP/1.1 200 OK".into(), PEERS.to_vec())]).await;
+        let (from, from_seen) = http_server(vec![(
+            format!("HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:{}/x", to),
+            vec![],
+        )])
+        .await;
+        let tracker = format!("http://127.0.0.1:{}/announce", from);
+        let req = TrackerRequest::builder(tracker.as_bytes(), &[1; 20], &[2; 20])
+            .build()
+            .unwrap();
+        let user_agent = |seen: &Requests, n: usize| {
+            let head = seen.lock().unwrap()[n].head.to_lowercase();
+            head.lines()
+                .find_map(|line| line.strip_prefix("user-agent: ").map(str::to_string))
+        };
+
+        let mut transport = HttpTransport::default();
+        transport
+            .announce(&req, AnnounceEvent::None, None)
+            .await
+            .unwrap();
+        let default = Some(DEFAULT_USER_AGENT.to_lowercase());
+        assert_eq!(user_agent(&from_seen, 0), default);
+        assert_eq!(user_agent(&to_seen, 0), default);
+
+        let config = TrackerConfig {
+            user_agent: "qBittorrent/4.6.0".into(),
+            ..Default::default()
+        };
+        HttpTransport::new(config)
+            .announce(&req, AnnounceEvent::None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            user_agent(&to_seen, 1).as_deref(),
+            Some("qbittorrent/4.6.0")
+        );
+    }
+
+    #[tokio::test]
+    async fn connections_are_reused_until_closed() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        //tracker counting connections, closing each one after its first answer if close is set
+        async fn tracker(close: bool) -> (String, Arc<AtomicUsize>) {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/announce", listener.local_addr().unwrap());
+            let connections = Arc::new(AtomicUsize::new(0));
+            let count = connections.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(async move {
+                        let mut stream = BufReader::new(stream);
+                        let mut line = String::new();
+                        while stream.read_line(&mut line).await.unwrap_or(0) > 0 {
+                            if line.ends_with("\r\n\r\n") {
+                                let answer = response("HTTP/1.1 200 OK", PEERS);
+                                stream.get_mut().write_all(&answer).await.unwrap();
+                                if close {
+                                    let _ = stream.get_mut().shutdown().await;
+                                    return;
+                                }
+                                line.clear();
+                            }
+                        }
+                    });
+                }
+            });
+            (url, connections)
+        }
+
+        for (close, expected) in [(false, 1), (true, 3)] {
+            let (url, connections) = tracker(close).await;
+            let req = TrackerRequest::builder(url.as_bytes(), &[1; 20], &[2; 20])
+                .build()
+                .unwrap();
+            let mut transport = HttpTransport::default();
+            for _ in 0..3 {
+                transport
+                    .announce(&req, AnnounceEvent::None, None)
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(
+                connections.load(Ordering::SeqCst),
+                expected,
+                "close: {}",
+                close
+            );
+        }
+    }
+}
@@ -1,2 +1,11 @@
+pub mod announce_transport;
+pub mod backoff;
+pub mod http_transport;
+pub mod multi_tracker;
+pub mod proxy;
+pub mod resolve;
+pub mod scrape;
 pub mod tracker;
+pub mod tracker_config;
 pub mod tracker_error;
+pub mod udp_tracker;
@@ -0,0 +1,421 @@
+use crate::core::peer::peer::Peer;
+use crate::core::tracker::backoff::{Backoff, ramp_delay};
+use crate::core::tracker::tracker::{AnnounceEvent, Tracker, TrackerRequest};
+use crate::core::tracker::tracker_config::TrackerConfig;
+use crate::core::tracker::tracker_error::TrackerError;
+
+use futures_util::future::join_all;
+use rand::rng;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::info;
+
+//whether a tracker is usable
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum TrackerState {
+    #[default]
+    Working, //last announce succeeded, or none was tried yet
+    Retrying {
+        attempt: u32,          //number of consecutive failures
+        next_attempt: Instant, //time of the next announce attempt
+    },
+    Failed, //tracker refused us, it is not contacted again
+}
+
+//status of a single tracker announced to by a MultiTracker
+#[derive(Debug, Default, Clone)]
+pub struct TrackerStatus {
+    pub state: TrackerState,            //whether the tracker is usable
+    pub last_announce: Option<Instant>, //time of the last successful announce
+    pub last_error: Option<String>,     //error from the last failed announce
+    pub peers_returned: usize,          //number of peers known from this tracker
+    pub seeders: Option<u64>,           //seeders counted by the last answer, if the tracker counts
+    pub leechers: Option<u64>,          //leechers counted by the last answer, if the tracker counts
+}
+
+//a tracker URL together with its request and connection state
+#[derive(Debug)]
+struct TrackerEntry<'a> {
+    request: TrackerRequest<'a>, //request sent to this tracker
+    tracker: Option<Tracker>,    //tracker state, None until the first announce succeeds
+    status: TrackerStatus,       //status shown to callers
+    contacted: bool,             //whether the last announce or stop sent a request to this tracker
+}
+
+impl TrackerEntry<'_> {
+    //announce unless the tracker failed permanently or is waiting to retry
+    //errors are recorded in the status and schedule a retry if they look temporary
+    async fn announce(&mut self, backoff: &Backoff, config: &TrackerConfig) -> Vec<Peer> {
+        self.contacted = false;
+        match self.status.state {
+            TrackerState::Failed => return Vec::new(),
+            TrackerState::Retrying { next_attempt, .. } if Instant::now() < next_attempt => {
+                return Vec::new();
+            }
+            _ => {}
+        }
+
+        match self.try_announce(config).await {
+            Ok(peers) => {
+                self.status.state = TrackerState::Working;
+                peers
+            }
+            Err(e) => {
+                //the status keeps the error, so it is only logged when asked for
+                info!(
+                    tracker = %String::from_utf8_lossy(self.request.tracker()),
+                    error = %e,
+                    retryable = e.is_retryable(),
+                    "announce failed"
+                );
+                self.status.state = if e.is_retryable() {
+                    let attempt = match self.status.state {
+                        TrackerState::Retrying { attempt, .. } => attempt + 1,
+                        _ => 0,
+                    };
+                    TrackerState::Retrying {
+                        attempt,
+                        next_attempt: Instant::now() + backoff.delay(attempt),
+                    }
+                } else {
+                    TrackerState::Failed
+                };
+                self.status.last_error = Some(e.to_string());
+                Vec::new()
+            }
+        }
+    }
+
+    //announce to this tracker if its own interval has passed, the first announce is a started one
+    async fn try_announce(&mut self, config: &TrackerConfig) -> Result<Vec<Peer>, TrackerError> {
+        self.contacted = true;
+        let peers = match &mut self.tracker {
+            Some(tracker) => {
+                let last = tracker.last_announce();
+                let peers = tracker.get_peers(&self.request).await?;
+                self.contacted = tracker.last_announce() != last;
+                peers
+            }
+            None => {
+                let tracker = Tracker::with_config(&self.request, config.clone()).await?;
+                self.tracker.insert(tracker).peers().to_vec()
+            }
+        };
+
+        if let Some(tracker) = &self.tracker {
+            self.status.last_announce = Some(tracker.last_announce());
+            self.status.seeders = tracker.seeders();
+            self.status.leechers = tracker.leechers();
+            //completed is told once, a tracker joined again after a stop must not hear it twice
+            if tracker.completed_sent() && self.request.event() == AnnounceEvent::Completed {
+                self.request.set_event(AnnounceEvent::None);
+            }
+        }
+        self.status.peers_returned = peers.len();
+        self.status.last_error = None;
+
+        Ok(peers)
+    }
+
+    //tell the tracker we leave the swarm, if it knows about us
+    //the next announce joins again with a started event, a completed event not sent yet is kept
+    async fn stop(&mut self) {
+        self.contacted = false;
+        let Some(mut tracker) = self.tracker.take() else {
+            return;
+        };
+        let event = match self.request.event() {
+            AnnounceEvent::Completed if !tracker.completed_sent() => AnnounceEvent::Completed,
+            _ => AnnounceEvent::None,
+        };
+        self.contacted = true;
+        match tracker.stop(&mut self.request).await {
+            Ok(()) => self.status.last_error = None,
+            Err(e) => {
+                info!(
+                    tracker = %String::from_utf8_lossy(self.request.tracker()),
+                    error = %e,
+                    "stopped announce failed"
+                );
+                self.status.last_error = Some(e.to_string());
+            }
+        }
+        self.request.set_event(event);
+    }
+
+    //get the time until the next announce to this tracker is due, None if it failed for good
+    fn next_announce_in(&self, start_at: Instant) -> Option<Duration> {
+        let now = Instant::now();
+        match (&self.status.state, &self.tracker) {
+            (TrackerState::Failed, _) => None,
+            (TrackerState::Retrying { next_attempt, .. }, _) => {
+                Some(next_attempt.saturating_duration_since(now))
+            }
+            (TrackerState::Working, Some(tracker)) => {
+                Some(tracker.next_announce_for(&self.request))
+            }
+            (TrackerState::Working, None) => Some(start_at.saturating_duration_since(now)),
+        }
+    }
+}
+
+//announces to every known tracker at once and merges the peers they return
+#[derive(Debug)]
+pub struct MultiTracker<'a> {
+    entries: Vec<TrackerEntry<'a>>, //one entry per tracker URL
+    backoff: Backoff,               //retry schedule for failing trackers
+    config: TrackerConfig,          //settings every tracker is announced to with
+    start_at: Instant,              //no tracker is contacted before this time
+}
+
+impl<'a> MultiTracker<'a> {
+    //create a multi tracker from one request per tracker URL
+    pub fn new(requests: Vec<TrackerRequest<'a>>) -> Self {
+        Self {
+            entries: requests
+                .into_iter()
+                .map(|request| TrackerEntry {
+                    request,
+                    tracker: None,
+                    status: TrackerStatus::default(),
+                    contacted: false,
+                })
+                .collect(),
+            backoff: Backoff::default(),
+            config: TrackerConfig::default(),
+            start_at: Instant::now(),
+        }
+    }
+
+    //delay the first announce by a random part of ramp
+    //use it when starting many torrents together, so their trackers are not hit in one burst
+    pub fn set_startup_ramp(&mut self, ramp: Duration) {
+        self.start_at = Instant::now() + ramp_delay(ramp, &mut rng());
+    }
+
+    //set the retry schedule for failing trackers
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    //set the settings every tracker is announced to with, e.g. those of the session
+    pub fn set_config(&mut self, config: TrackerConfig) {
+        self.config = config;
+    }
+
+    //update the transfer counters reported by the next announce to every tracker
+    pub fn set_stats(&mut self, uploaded: u64, downloaded: u64, left: u64) {
+        for entry in &mut self.entries {
+            entry.request.set_uploaded(uploaded);
+            entry.request.set_downloaded(downloaded);
+            entry.request.set_left(left);
+        }
+    }
+
+    //report a finished download to every tracker on the next announce
+    //only call this when the last piece verified, not when starting from already complete data
+    pub fn set_completed(&mut self, downloaded: u64) {
+        for entry in &mut self.entries {
+            entry.request.set_downloaded(downloaded);
+            entry.request.set_left(0);
+            entry.request.set_event(AnnounceEvent::Completed);
+        }
+    }
+
+    //announce to all trackers concurrently, each on its own interval
+    //failing trackers are recorded in their status and do not affect the others
+    pub async fn announce(&mut self) -> Vec<Peer> {
+        if Instant::now() < self.start_at {
+            return Vec::new();
+        }
+
+        let (backoff, config) = (&self.backoff, &self.config);
+        let results = join_all(
+            self.entries
+                .iter_mut()
+                .map(|entry| entry.announce(backoff, config)),
+        )
+        .await;
+
+        let mut seen = HashSet::new();
+        results
+            .into_iter()
+            .flatten()
+            .filter(|peer| seen.insert(peer.addr()))
+            .collect()
+    }
+
+    //tell every tracker that knows about us that we leave the swarm, all at once
+    //failures are recorded in the status, the next announce joins the swarm again
+    pub async fn stop(&mut self) {
+        join_all(self.entries.iter_mut().map(TrackerEntry::stop)).await;
+    }
+
+    //get the time until announce has a tracker to contact, None if every tracker failed for good
+    pub fn next_announce_in(&self) -> Option<Duration> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.next_announce_in(self.start_at))
+            .min()
+    }
+
+    //get the status of every tracker, keyed by its URL
+    pub fn status(&self) -> impl Iterator<Item = (&'a [u8], &TrackerStatus)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.request.tracker(), &entry.status))
+    }
+
+    //get the status of the trackers the last announce or stop sent a request to
+    pub fn contacted(&self) -> impl Iterator<Item = (&'a [u8], &TrackerStatus)> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.contacted)
+            .map(|entry| (entry.request.tracker(), &entry.status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::test_server::http_server;
+
+    use std::time::Duration;
+
+    //announce response with a single peer
+    const ANSWER: &[u8] = b"d8:intervali1800e5:peers6:\x0a\x00\x00\x01\x00\x01e";
+
+    //request to url for an empty torrent
+    fn request(url: &str) -> TrackerRequest<'_> {
+        TrackerRequest::builder(url.as_bytes(), &[1; 20], &[2; 20])
+            .left(0)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn peers_of_every_tracker_are_merged() {
+        let (first, _) = http_server(vec![(
+            "HTTP/1.1 200 OK".into(),
+            b"d8:intervali1800e5:peers12:\x0a\x00\x00\x01\x00\x01\x0a\x00\x00\x02\x00\x02e"
+                .to_vec(),
+        )])
+        .await;
+        let (second, _) = http_server(vec![(
+            "HTTP/1.1 200 OK".into(),
+            b"d8:intervali1800e5:peers12:\x0a\x00\x00\x03\x00\x01\x0a\x00\x00\x02\x00\x02e"
+                .to_vec(),
+        )])
+        .await;
+        //a port nobody listens on
+        let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_port = dead.local_addr().unwrap().port();
+        drop(dead);
+        let urls = [first, second, dead_port].map(|port| format!("http://127.0.0.1:{}/a", port));
+
+        let mut trackers = MultiTracker::new(urls.iter().map(|url| request(url)).collect());
+        let mut peers: Vec<String> = trackers
+            .announce()
+            .await
+            .iter()
+            .map(|peer| peer.addr().to_string())
+            .collect();
+        peers.sort();
+        assert_eq!(peers, ["10.0.0.1:1", "10.0.0.2:2", "10.0.0.3:1"]);
+
+        let status: Vec<&TrackerStatus> = trackers.status().map(|(_, status)| status).collect();
+        assert_eq!((status[0].peers_returned, status[1].peers_returned), (2, 2));
+        assert!(status[0].last_error.is_none() && status[0].last_announce.is_some());
+        assert!(status[2].last_error.is_some() && status[2].last_announce.is_none());
+    }
+
+    #[tokio::test]
+    async fn temporary_failures_are_retried_with_backoff() {
+        let (port, requests) = http_server(vec![
+            ("HTTP/1.1 500 Internal Server Error".into(), Vec::new()),
+            ("HTTP/1.1 500 Internal Server Error".into(), Vec::new()),
+            ("HTTP/1.1 200 OK".into(), ANSWER.to_vec()),
+        ])
+        .await;
+        let url = format!("http://127.0.0.1:{}/announce", port);
+        let mut trackers = MultiTracker::new(vec![request(&url)]);
+        let delay = Duration::from_millis(50);
+        trackers.set_backoff(Backoff::new(delay, 2, Duration::from_secs(1), 0.0));
+        let state = |trackers: &MultiTracker| trackers.status().next().unwrap().1.state.clone();
+
+        assert!(trackers.announce().await.is_empty());
+        assert!(matches!(
+            state(&trackers),
+            TrackerState::Retrying { attempt: 0, .. }
+        ));
+        //nothing is sent before the retry is due
+        assert!(trackers.announce().await.is_empty());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        tokio::time::sleep(delay).await;
+        assert!(trackers.announce().await.is_empty());
+        assert!(matches!(
+            state(&trackers),
+            TrackerState::Retrying { attempt: 1, .. }
+        ));
+        tokio::time::sleep(delay * 2).await;
+        assert_eq!(trackers.announce().await.len(), 1);
+        assert_eq!(state(&trackers), TrackerState::Working);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn stopped_trackers_are_joined_again() {
+        let (port, requests) = http_server(vec![("HTTP/1.1 200 OK".into(), ANSWER.to_vec())]).await;
+        let url = format!("http://127.0.0.1:{}/announce", port);
+        let mut trackers = MultiTracker::new(vec![request(&url)]);
+        assert_eq!(trackers.next_announce_in(), Some(Duration::ZERO));
+        assert_eq!(trackers.announce().await.len(), 1);
+        let (_, status) = trackers.contacted().next().unwrap();
+        assert_eq!(status.peers_returned, 1);
+        //nothing is sent before the interval of the tracker has passed
+        assert!(trackers.next_announce_in().unwrap() > Duration::from_secs(1000));
+        assert_eq!(trackers.announce().await.len(), 1);
+        assert_eq!(trackers.contacted().count(), 0);
+
+        trackers.stop().await;
+        assert_eq!(trackers.contacted().count(), 1);
+        assert_eq!(trackers.next_announce_in(), Some(Duration::ZERO));
+        trackers.announce().await;
+        let events: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                request
+                    .path
+                    .split("event=")
+                    .nth(1)
+                    .unwrap()
+                    .split('&')
+                    .next()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(events, ["started", "stopped", "started"]);
+    }
+
+    #[tokio::test]
+    async fn refusing_trackers_are_given_up() {
+        let (port, _) = http_server(vec![(
+            "HTTP/1.1 200 OK".into(),
+            b"d14:failure reason6:bannede".to_vec(),
+        )])
+        .await;
+        let url = format!("http://127.0.0.1:{}/announce", port);
+        let mut trackers = MultiTracker::new(vec![request(&url)]);
+        assert!(trackers.announce().await.is_empty());
+        let (_, status) = trackers.contacted().next().unwrap();
+        assert_eq!(status.state, TrackerState::Failed);
+        assert!(status.last_error.as_ref().unwrap().contains("banned"));
+        assert_eq!(trackers.next_announce_in(), None);
+    }
+}
@@ -0,0 +1,395 @@
+use crate::core::tracker::tracker_error::TrackerError;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::net::IpAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, lookup_host};
+
+//SOCKS5 protocol constants
+const SOCKS_VERSION: u8 = 0x05;
+const SOCKS_AUTH_NONE: u8 = 0x00;
+const SOCKS_AUTH_PASSWORD: u8 = 0x02;
+const SOCKS_AUTH_REJECTED: u8 = 0xFF;
+const SOCKS_CMD_CONNECT: u8 = 0x01;
+const SOCKS_ATYP_IPV4: u8 = 0x01;
+const SOCKS_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS_ATYP_IPV6: u8 = 0x04;
+
+//largest CONNECT response header we accept
+const MAX_CONNECT_RESPONSE: usize = 8192;
+
+//kind of proxy to tunnel tracker connections through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    Http,   //HTTP proxy using the CONNECT method
+    Socks5, //SOCKS5 proxy
+}
+
+//proxy that tracker connections are tunneled through
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub kind: ProxyKind,                       //protocol spoken with the proxy
+    pub host: String,                          //proxy host name or address
+    pub port: u16,                             //proxy port
+    pub credentials: Option<(String, String)>, //username and password, if the proxy needs them
+    pub remote_dns: bool,                      //let the proxy resolve tracker host names
+}
+
+impl ProxyConfig {
+    //create a proxy config without credentials, resolving host names through the proxy
+    pub fn new(kind: ProxyKind, host: impl Into<String>, port: u16) -> Self {
+        Self {
+            kind,
+            host: host.into(),
+            port,
+            credentials: None,
+            remote_dns: true,
+        }
+    }
+
+    //open a connection to host:port tunneled through the proxy
+    pub async fn connect(&self, host: &str, port: u16) -> Result<TcpStream, TrackerError> {
+        //without remote DNS only an address is handed to the proxy
+        let target = match host.parse::<IpAddr>() {
+            Ok(ip) => Target::Ip(ip),
+            Err(_) if self.remote_dns => Target::Domain(host),
+            Err(_) => Target::Ip(
+                lookup_host((host, port))
+                    .await?
+                    .next()
+                    .ok_or_else(|| TrackerError::ProxyError(format!("Could not resolve {}", host)))?
+                    .ip(),
+            ),
+        };
+
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        match self.kind {
+            ProxyKind::Http => self.http_connect(&mut stream, &target, port).await?,
+            ProxyKind::Socks5 => self.socks5_connect(&mut stream, &target, port).await?,
+        }
+
+        Ok(stream)
+    }
+
+    //open a tunnel with an HTTP CONNECT request
+    async fn http_connect(
+        &self,
+        stream: &mut TcpStream,
+        target: &Target<'_>,
+        port: u16,
+    ) -> Result<(), TrackerError> {
+        let authority = match target {
+            Target::Ip(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+            Target::Ip(ip) => format!("{}:{}", ip, port),
+            Target::Domain(domain) => format!("{}:{}", domain, port),
+        };
+
+        let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+        if let Some((username, password)) = &self.credentials {
+            let token = STANDARD.encode(format!("{}:{}", username, password));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        //read the response header byte by byte so no tunneled data is consumed
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= MAX_CONNECT_RESPONSE {
+                return Err(TrackerError::ProxyError("CONNECT response too long".into()));
+            }
+            response.push(stream.read_u8().await?);
+        }
+
+        //status line is "HTTP/1.x 200 ..."
+        let status_line = response.split(|&b| b == b'\r').next().unwrap_or_default();
+        let status = String::from_utf8_lossy(status_line);
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(TrackerError::ProxyError(format!(
+                "CONNECT refused: {}",
+                status
+            ))),
+        }
+    }
+
+    //open a tunnel with the SOCKS5 handshake (RFC 1928, RFC 1929 for passwords)
+    async fn socks5_connect(
+        &self,
+        stream: &mut TcpStream,
+        target: &Target<'_>,
+        port: u16,
+    ) -> Result<(), TrackerError> {
+        //offer password authentication only when we have credentials
+        let method = if self.credentials.is_some() {
+            SOCKS_AUTH_PASSWORD
+        } else {
+            SOCKS_AUTH_NONE
+        };
+        stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != SOCKS_VERSION || reply[1] == SOCKS_AUTH_REJECTED || reply[1] != method {
+            return Err(TrackerError::ProxyError(
+                "SOCKS5 proxy rejected the authentication method".into(),
+            ));
+        }
+
+        if let Some((username, password)) = &self.credentials {
+            let (username, password) = (username.as_bytes(), password.as_bytes());
+            if username.len() > 255 || password.len() > 255 {
+                return Err(TrackerError::ProxyError(
+                    "SOCKS5 username and password must be at most 255 bytes".into(),
+                ));
+            }
+
+            let mut auth = Vec::with_capacity(3 + username.len() + password.len());
+            auth.push(0x01);
+            auth.push(username.len() as u8);
+            auth.extend_from_slice(username);
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password);
+            stream.write_all(&auth).await?;
+
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0x00 {
+                return Err(TrackerError::ProxyError(
+                    "SOCKS5 proxy rejected the credentials".into(),
+                ));
+            }
+        }
+
+        //connect request
+        let mut request = vec![SOCKS_VERSION, SOCKS_CMD_CONNECT, 0x00];
+        match target {
+            Target::Ip(IpAddr::V4(ip)) => {
+                request.push(SOCKS_ATYP_IPV4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Target::Ip(IpAddr::V6(ip)) => {
+                request.push(SOCKS_ATYP_IPV6);
+                request.extend_from_slice(&ip.octets());
+            }
+            Target::Domain(domain) => {
+                let domain = domain.as_bytes();
+                if domain.len() > 255 {
+                    return Err(TrackerError::ProxyError(
+                        "Host name too long for SOCKS5".into(),
+                    ));
+                }
+                request.push(SOCKS_ATYP_DOMAIN);
+                request.push(domain.len() as u8);
+                request.extend_from_slice(domain);
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        //reply is VER REP RSV ATYP followed by the bound address and port
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).await?;
+        if header[0] != SOCKS_VERSION {
+            return Err(TrackerError::ProxyError("Malformed SOCKS5 reply".into()));
+        }
+        if header[1] != 0x00 {
+            return Err(TrackerError::ProxyError(format!(
+                "SOCKS5 connect failed with code {}",
+                header[1]
+            )));
+        }
+
+        let address_len = match header[3] {
+            SOCKS_ATYP_IPV4 => 4,
+            SOCKS_ATYP_IPV6 => 16,
+            SOCKS_ATYP_DOMAIN => stream.read_u8().await? as usize,
+            _ => return Err(TrackerError::ProxyError("Malformed SOCKS5 reply".into())),
+        };
+        let mut bound = vec![0u8; address_len + 2];
+        stream.read_exact(&mut bound).await?;
+
+        Ok(())
+    }
+}
+
+//destination handed to the proxy
+enum Target<'a> {
+    Ip(IpAddr),      //address, resolved locally or given in the URL
+    Domain(&'a str), //host name, resolved by the proxy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    //server on loopback greeting every connection with "hello"
+    async fn target() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(b"hello").await;
+            }
+        });
+        port
+    }
+
+    //start a proxy on loopback, handing every connection to handshake
+    //handshake returns the address to tunnel to, or None to hang up
+    async fn proxy<F, R>(handshake: F) -> u16
+    where
+        F: Fn(TcpStream) -> R + Send + Sync + 'static,
+        R: Future<Output = Option<(TcpStream, u16)>> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handshake = Arc::new(handshake);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handshake = handshake.clone();
+                tokio::spawn(async move {
+                    if let Some((mut stream, port)) = handshake(stream).await {
+                        let mut upstream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+                        let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    //SOCKS5 proxy accepting user:pw if password is set, recording the address type of every request
+    async fn socks5(password: bool, seen: Arc<Mutex<Vec<u8>>>) -> u16 {
+        proxy(move |mut stream| {
+            let seen = seen.clone();
+            async move {
+                let mut greeting = [0u8; 3];
+                stream.read_exact(&mut greeting).await.ok()?;
+                let method = if password {
+                    SOCKS_AUTH_PASSWORD
+                } else {
+                    SOCKS_AUTH_NONE
+                };
+                if greeting[2] != method {
+                    stream
+                        .write_all(&[SOCKS_VERSION, SOCKS_AUTH_REJECTED])
+                        .await
+                        .ok()?;
+                    return None;
+                }
+                stream.write_all(&[SOCKS_VERSION, method]).await.ok()?;
+                if password {
+                    let mut auth = [0u8; 9];
+                    stream.read_exact(&mut auth).await.ok()?;
+                    let accepted = auth == *b"\x01\x04user\x02pw";
+                    stream.write_all(&[1, u8::from(!accepted)]).await.ok()?;
+                    if !accepted {
+                        return None;
+                    }
+                }
+
+                let mut header = [0u8; 4];
+                stream.read_exact(&mut header).await.ok()?;
+                seen.lock().unwrap().push(header[3]);
+                let address_len = match header[3] {
+                    SOCKS_ATYP_IPV4 => 4,
+                    _ => stream.read_u8().await.ok()? as usize,
+                };
+                let mut address = vec![0u8; address_len];
+                stream.read_exact(&mut address).await.ok()?;
+                let port = stream.read_u16().await.ok()?;
+                stream
+                    .write_all(&[SOCKS_VERSION, 0, 0, SOCKS_ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+                    .await
+                    .ok()?;
+                Some((stream, port))
+            }
+        })
+        .await
+    }
+
+    //read what the tunnel delivers
+    async fn greeting(mut stream: TcpStream) -> Vec<u8> {
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).await.unwrap();
+        data
+    }
+
+    #[tokio::test]
+    async fn socks5_tunnels_with_and_without_credentials() {
+        let target = target().await;
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let open = socks5(false, seen.clone()).await;
+
+        let mut config = ProxyConfig::new(ProxyKind::Socks5, "127.0.0.1", open);
+        let stream = config.connect("localhost", target).await.unwrap();
+        assert_eq!(greeting(stream).await, b"hello");
+        //without remote DNS the proxy gets an address
+        config.remote_dns = false;
+        let stream = config.connect("localhost", target).await.unwrap();
+        assert_eq!(greeting(stream).await, b"hello");
+        assert_eq!(*seen.lock().unwrap(), [SOCKS_ATYP_DOMAIN, SOCKS_ATYP_IPV4]);
+
+        let guarded = socks5(true, seen.clone()).await;
+        let mut config = ProxyConfig::new(ProxyKind::Socks5, "127.0.0.1", guarded);
+        config.credentials = Some(("user".into(), "pw".into()));
+        let stream = config.connect("127.0.0.1", target).await.unwrap();
+        assert_eq!(greeting(stream).await, b"hello");
+
+        config.credentials = Some(("user".into(), "no".into()));
+        let err = config.connect("127.0.0.1", target).await.unwrap_err();
+        assert!(matches!(err, TrackerError::ProxyError(reason) if reason.contains("credentials")));
+        config.credentials = None;
+        let err = config.connect("127.0.0.1", target).await.unwrap_err();
+        assert!(matches!(err, TrackerError::ProxyError(reason) if reason.contains("method")));
+    }
+
+    #[tokio::test]
+    async fn http_connect_tunnels_and_reports_refusals() {
+        let target = target().await;
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let port = proxy(move |mut stream| {
+            let seen = seen.clone();
+            async move {
+                let mut head = Vec::new();
+                while !head.ends_with(b"\r\n\r\n") {
+                    head.push(stream.read_u8().await.ok()?);
+                }
+                let head = String::from_utf8(head).unwrap();
+                seen.lock().unwrap().push(head.clone());
+                if !head.contains("Proxy-Authorization") {
+                    let refusal = b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n";
+                    stream.write_all(refusal).await.ok()?;
+                    return None;
+                }
+                let port = head.split([' ', ':']).nth(2)?.parse().ok()?;
+                let reply = b"HTTP/1.1 200 Connection established\r\nVia: test\r\n\r\n";
+                stream.write_all(reply).await.ok()?;
+                Some((stream, port))
+            }
+        })
+        .await;
+
+        let mut config = ProxyConfig::new(ProxyKind::Http, "127.0.0.1", port);
+        config.credentials = Some(("a".into(), "b".into()));
+        let stream = config.connect("localhost", target).await.unwrap();
+        assert_eq!(greeting(stream).await, b"hello");
+        let head = requests.lock().unwrap()[0].clone();
+        assert!(head.starts_with(&format!("CONNECT localhost:{} HTTP/1.1\r\n", target)));
+        assert!(
+            head.contains("\r\nProxy-Authorization: Basic YTpi\r\n"),
+            "{}",
+            head
+        );
+
+        config.credentials = None;
+        let err = config.connect("::1", target).await.unwrap_err();
+        assert!(matches!(err, TrackerError::ProxyError(reason) if reason.contains("407")));
+        assert!(requests.lock().unwrap()[1].starts_with(&format!("CONNECT [::1]:{} ", target)));
+    }
+}
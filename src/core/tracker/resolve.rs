@@ -0,0 +1,157 @@
+use crate::core::tracker::tracker_error::TrackerError;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpStream, lookup_host};
+use tokio::time::timeout;
+
+//time given to each resolved address before trying the next one
+pub const ADDRESS_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(3);
+
+//which address family to use for trackers that resolve to both
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IpPreference {
+    #[default]
+    Auto, //alternate families, starting with the one the resolver returned first
+    PreferV4, //IPv4 addresses first, then IPv6
+    PreferV6, //IPv6 addresses first, then IPv4
+    OnlyV4,   //IPv4 addresses only
+    OnlyV6,   //IPv6 addresses only
+}
+
+impl IpPreference {
+    //order resolved addresses by preference, dropping excluded families
+    pub fn order(self, addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+        let addrs: Vec<SocketAddr> = addrs.into_iter().collect();
+        let v6_first = addrs.first().is_some_and(SocketAddr::is_ipv6);
+        let (v4, v6): (Vec<SocketAddr>, Vec<SocketAddr>) =
+            addrs.into_iter().partition(SocketAddr::is_ipv4);
+
+        match self {
+            IpPreference::OnlyV4 => v4,
+            IpPreference::OnlyV6 => v6,
+            IpPreference::PreferV4 => v4.into_iter().chain(v6).collect(),
+            IpPreference::PreferV6 => v6.into_iter().chain(v4).collect(),
+            IpPreference::Auto => {
+                //keep the resolver's choice of first family, then alternate
+                let (first, second) = if v6_first { (v6, v4) } else { (v4, v6) };
+                let mut ordered = Vec::with_capacity(first.len() + second.len());
+                let (mut first, mut second) = (first.into_iter(), second.into_iter());
+                loop {
+                    match (first.next(), second.next()) {
+                        (None, None) => break,
+                        (a, b) => ordered.extend(a.into_iter().chain(b)),
+                    }
+                }
+                ordered
+            }
+        }
+    }
+}
+
+//resolve host and order the addresses by preference
+pub async fn resolve(
+    host: &str,
+    port: u16,
+    preference: IpPreference,
+) -> Result<Vec<SocketAddr>, TrackerError> {
+    //URL hosts keep the brackets around IPv6 literals
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = preference.order(lookup_host((host, port)).await?);
+    if addrs.is_empty() {
+        return Err(TrackerError::Other(
+            format!("No usable address for {}", host).into(),
+        ));
+    }
+    Ok(addrs)
+}
+
+//connect to the first address that answers, giving each one attempt_timeout
+pub async fn connect_any(
+    addrs: &[SocketAddr],
+    attempt_timeout: Duration,
+) -> Result<TcpStream, TrackerError> {
+    let mut last_error = None;
+    for addr in addrs {
+        match timeout(attempt_timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => last_error = Some(TrackerError::StreamError(e)),
+            Err(_) => last_error = Some(TrackerError::Timeout(attempt_timeout)),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| TrackerError::Other("No address to connect to".into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //parse a list of socket addresses
+    fn addrs(list: &[&str]) -> Vec<SocketAddr> {
+        list.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn addresses_are_ordered_by_preference() {
+        let mixed = addrs(&["[::1]:1", "[::2]:1", "1.1.1.1:1", "2.2.2.2:1", "3.3.3.3:1"]);
+        for (preference, expected) in [
+            (
+                IpPreference::Auto,
+                ["[::1]:1", "1.1.1.1:1", "[::2]:1", "2.2.2.2:1", "3.3.3.3:1"].as_slice(),
+            ),
+            (
+                IpPreference::PreferV4,
+                &["1.1.1.1:1", "2.2.2.2:1", "3.3.3.3:1", "[::1]:1", "[::2]:1"],
+            ),
+            (
+                IpPreference::PreferV6,
+                &["[::1]:1", "[::2]:1", "1.1.1.1:1", "2.2.2.2:1", "3.3.3.3:1"],
+            ),
+            (
+                IpPreference::OnlyV4,
+                &["1.1.1.1:1", "2.2.2.2:1", "3.3.3.3:1"],
+            ),
+            (IpPreference::OnlyV6, &["[::1]:1", "[::2]:1"]),
+        ] {
+            assert_eq!(
+                preference.order(mixed.clone()),
+                addrs(expected),
+                "{:?}",
+                preference
+            );
+        }
+        //auto starts with the family the resolver returned first
+        let v4_first = addrs(&["1.1.1.1:1", "[::1]:1"]);
+        assert_eq!(IpPreference::Auto.order(v4_first.clone()), v4_first);
+    }
+
+    #[tokio::test]
+    async fn literals_resolve_within_their_family() {
+        assert_eq!(
+            resolve("[::1]", 5, IpPreference::Auto).await.unwrap(),
+            addrs(&["[::1]:5"])
+        );
+        assert!(resolve("127.0.0.1", 1, IpPreference::OnlyV6).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn connect_any_skips_dead_addresses() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let alive = listener.local_addr().unwrap();
+        let dead = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let stream = connect_any(&[dead, alive], Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), alive);
+        let err = connect_any(&[dead], Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TrackerError::StreamError(_)), "{:?}", err);
+        assert!(connect_any(&[], Duration::from_millis(200)).await.is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::core::tracker::announce_transport::connect_target;
+use crate::core::tracker::http_transport::HttpTransport;
+use crate::core::tracker::tracker_config::TrackerConfig;
+use crate::core::tracker::tracker_error::TrackerError;
+use crate::core::tracker::udp_tracker::UdpTracker;
+use crate::util::bencode::bencode_decodable::BencodeDecodable;
+use crate::util::bencode::bencode_decodable_error::{BencodeDecodableError, DecodeContext};
+use crate::util::bencode::parser::{Node, parse};
+use crate::util::{hex, urlencode};
+
+use http::Uri;
+use std::collections::HashMap;
+use tokio::time::timeout;
+
+//most info hashes in one scrape request, a UDP scrape packet fits about 74
+pub const MAX_SCRAPE_HASHES: usize = 74;
+
+//swarm counts of one torrent, as reported by a scrape
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScrapeStats {
+    pub seeders: u64,   //peers with the whole torrent
+    pub leechers: u64,  //peers still downloading
+    pub completed: u64, //downloads the tracker has seen finish
+}
+
+//represents the response to a scrape
+#[derive(Debug, Clone, Default)]
+pub struct ScrapeResponse {
+    pub files: HashMap<[u8; 20], ScrapeStats>, //counts by info hash, torrents the tracker does not know are missing
+}
+
+impl<'a> BencodeDecodable<'a> for ScrapeStats {
+    fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError> {
+        let dict = Self::get_struct(b)?;
+
+        //missing counts are 0, negative ones are clamped to 0 like in announces
+        let count = |key: &str| -> Result<u64, BencodeDecodableError> {
+            Ok(Self::get_optional_i64(key, dict)?.map_or(0, |n| n.max(0) as u64))
+        };
+
+        Ok(Self {
+            seeders: count("complete")?,
+            leechers: count("incomplete")?,
+            completed: count("downloaded")?,
+        })
+    }
+}
+
+impl<'a> BencodeDecodable<'a> for ScrapeResponse {
+    fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError> {
+        let dict = Self::get_struct(b)?;
+        let files = Self::get_struct(Self::get_struct_value("files", dict)?).context("files")?;
+
+        //keys are raw info hashes, ones that are not 20 bytes can not be asked for and are skipped
+        let mut response = Self::default();
+        for (key, value) in files {
+            let Ok(info_hash) = <[u8; 20]>::try_from(*key) else {
+                continue;
+            };
+            let stats = ScrapeStats::decode(value)
+                .context(&hex::encode(key))
+                .context("files")?;
+            response.files.insert(info_hash, stats);
+        }
+
+        Ok(response)
+    }
+}
+
+impl ScrapeResponse {
+    //decode a response, turning a tracker-reported failure into TrackerError::Failure
+    fn parse(b: &Node) -> Result<Self, TrackerError> {
+        if let Ok(dict) = Self::get_struct(b)
+            && let Some(reason) = Self::get_optional_string("failure reason", dict)?
+        {
+            return Err(TrackerError::Failure(reason.into_owned()));
+        }
+
+        Ok(Self::decode(b)?)
+    }
+}
+
+//get the scrape URL of an HTTP tracker from its announce URL (BEP 48)
+//the last path component has to start with "announce", which is replaced by "scrape"
+//None if the tracker does not follow the convention and so has no known scrape URL
+pub fn scrape_url(announce: &[u8]) -> Option<Vec<u8>> {
+    //the query, e.g. a passkey, is kept as it is
+    let path_end = announce
+        .iter()
+        .position(|&b| b == b'?')
+        .unwrap_or(announce.len());
+    let path = &announce[..path_end];
+
+    //the slash has to come after the one of "scheme://"
+    let authority_start = path.windows(3).position(|w| w == b"://")? + 3;
+    let slash = path.iter().rposition(|&b| b == b'/')?;
+    if slash < authority_start || !path[slash + 1..].starts_with(b"announce") {
+        return None;
+    }
+
+    let mut url = Vec::with_capacity(announce.len());
+    url.extend_from_slice(&announce[..=slash]);
+    url.extend_from_slice(b"scrape");
+    url.extend_from_slice(&announce[slash + 1 + b"announce".len()..]);
+    Some(url)
+}
+
+//scrape the tracker for the given info hashes, in requests of at most MAX_SCRAPE_HASHES
+//torrents the tracker does not know are missing from the response
+pub async fn scrape(
+    tracker: &[u8],
+    info_hashes: &[[u8; 20]],
+    config: &TrackerConfig,
+) -> Result<ScrapeResponse, TrackerError> {
+    let uri = Uri::try_from(tracker)?;
+    let mut response = ScrapeResponse::default();
+
+    if uri.scheme_str() == Some("udp") {
+        let (host, port) = connect_target(&uri)?;
+        let mut udp = UdpTracker::connect(host, port, config.ip_preference).await?;
+        for batch in info_hashes.chunks(MAX_SCRAPE_HASHES) {
+            //the retransmission schedule runs for hours, a scrape gives up like an HTTP request
+            let stats = timeout(config.request_timeout, udp.scrape(batch))
+                .await
+                .map_err(|_| TrackerError::Timeout(config.request_timeout))??;
+            response.files.extend(batch.iter().copied().zip(stats));
+        }
+        return Ok(response);
+    }
+
+    let base = scrape_url(tracker).ok_or(TrackerError::ScrapeUnsupported)?;
+    let mut http = HttpTransport::new(config.clone());
+    for batch in info_hashes.chunks(MAX_SCRAPE_HASHES) {
+        let body = http.get(build_scrape_url(&base, batch)?).await?;
+        //the response is untrusted, so the default parse limits apply
+        let bencode = parse(&body)?;
+        response
+            .files
+            .extend(ScrapeResponse::parse(&bencode)?.files);
+    }
+
+    Ok(response)
+}
+
+//build the request URL for a scrape of the given info hashes, one info_hash parameter each
+fn build_scrape_url(base: &[u8], info_hashes: &[[u8; 20]]) -> Result<Uri, TrackerError> {
+    let mut url = String::from_utf8_lossy(base).into_owned();
+
+    //add query delimiter, unless the existing query already ends with one
+    if !url.contains('?') {
+        url.push('?');
+    } else if !url.ends_with('?') && !url.ends_with('&') {
+        url.push('&');
+    }
+
+    for (index, info_hash) in info_hashes.iter().enumerate() {
+        if index > 0 {
+            url.push('&');
+        }
+        url.push_str("info_hash=");
+        url.push_str(&urlencode::encode(info_hash));
+    }
+
+    Ok(Uri::try_from(url)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_server::http_server;
+
+    use tokio::net::UdpSocket;
+
+    //fake UDP tracker on loopback, the counts of the n-th info hash of a scrape are n, 10n and n+1
+    async fn fake_udp_tracker() -> u16 {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 2048];
+            loop {
+                let (len, from) = server.recv_from(&mut buffer).await.unwrap();
+                let (action, transaction) = (&buffer[8..12], &buffer[12..16]);
+                let mut answer = action.to_vec();
+                answer.extend(transaction);
+                if action == [0, 0, 0, 0] {
+                    answer.extend(7u64.to_be_bytes());
+                } else {
+                    for n in 0..(len as u32 - 16) / 20 {
+                        for count in [n, n * 10, n + 1] {
+                            answer.extend(count.to_be_bytes());
+                        }
+                    }
+                }
+                server.send_to(&answer, from).await.unwrap();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn scrape_urls_follow_the_announce_url() {
+        let url = |announce: &[u8]| scrape_url(announce).map(|url| String::from_utf8(url).unwrap());
+        assert_eq!(url(b"http://t/announce").unwrap(), "http://t/scrape");
+        assert_eq!(
+            url(b"http://t/x/announce.php?passkey=a/b").unwrap(),
+            "http://t/x/scrape.php?passkey=a/b"
+        );
+        assert_eq!(
+            url(b"http://t/announce?x=1").unwrap(),
+            "http://t/scrape?x=1"
+        );
+        for announce in [
+            &b"http://t/a"[..],
+            b"http://t/announce/x",
+            b"http://announce",
+            b"http://t/",
+        ] {
+            assert_eq!(url(announce), None);
+        }
+    }
+
+    #[test]
+    fn responses_are_decoded() {
+        let body = b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei5e10:downloadedi50e\
+            10:incompletei-1ee3:bad0:ee";
+        let response = ScrapeResponse::parse(&parse(body).unwrap()).unwrap();
+        assert_eq!(response.files.len(), 1);
+        let stats = ScrapeStats {
+            seeders: 5,
+            leechers: 0,
+            completed: 50,
+        };
+        assert_eq!(response.files[&[b'a'; 20]], stats);
+
+        let failure = parse(b"d14:failure reason4:nopee").unwrap();
+        assert!(matches!(
+            ScrapeResponse::parse(&failure),
+            Err(TrackerError::Failure(message)) if message == "nope"
+        ));
+        let mistyped = parse(b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:complete1:xeee").unwrap();
+        let err = ScrapeResponse::parse(&mistyped).unwrap_err();
+        assert!(err.to_string().contains("files"), "{}", err);
+        assert!(ScrapeResponse::parse(&parse(b"de").unwrap()).is_err());
+    }
+
+    #[tokio::test]
+    async fn http_scrapes_are_batched() {
+        let body = b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei1e10:downloadedi2e\
+            10:incompletei3eeee";
+        let (port, requests) = http_server(vec![("HTTP/1.1 200 OK".into(), body.to_vec())]).await;
+        let mut info_hashes: Vec<[u8; 20]> = (0..80).map(|i| [i; 20]).collect();
+        info_hashes[0] = [b'a'; 20];
+        let url = format!("http://127.0.0.1:{}/announce?k=1", port);
+        let response = scrape(url.as_bytes(), &info_hashes, &TrackerConfig::default())
+            .await
+            .unwrap();
+        let stats = ScrapeStats {
+            seeders: 1,
+            leechers: 3,
+            completed: 2,
+        };
+        assert_eq!(response.files[&[b'a'; 20]], stats);
+
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert!(
+            requests[0]
+                .path
+                .starts_with("/scrape?k=1&info_hash=aaaaaaaaaaaaaaaaaaaa&info_hash=%01"),
+            "{}",
+            requests[0].path
+        );
+        assert_eq!(
+            requests[0].path.matches("info_hash=").count(),
+            MAX_SCRAPE_HASHES
+        );
+        assert_eq!(requests[1].path.matches("info_hash=").count(), 6);
+
+        let err = scrape(
+            b"http://127.0.0.1:1/a",
+            &info_hashes,
+            &TrackerConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, TrackerError::ScrapeUnsupported));
+    }
+
+    #[tokio::test]
+    async fn udp_scrapes_are_batched() {
+        let url = format!("udp://127.0.0.1:{}", fake_udp_tracker().await);
+        let info_hashes: Vec<[u8; 20]> = (0..100).map(|i| [i; 20]).collect();
+        let response = scrape(url.as_bytes(), &info_hashes, &TrackerConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(response.files.len(), 100);
+        let stats = |n| ScrapeStats {
+            seeders: n,
+            completed: n * 10,
+            leechers: n + 1,
+        };
+        assert_eq!(response.files[&[1; 20]], stats(1));
+        //the second packet starts over at the 75th info hash
+        assert_eq!(
+            response.files[&[80; 20]],
+            stats(80 - MAX_SCRAPE_HASHES as u64)
+        );
+    }
+}
@@ -1,111 +1,361 @@
 use crate::core::peer::peer::Peer;
+use crate::core::peer::peer_set::PeerSet;
+use crate::core::peer_id::get_tracker_key;
+use crate::core::tracker::announce_transport::{AnnounceTransport, DefaultTransport, RawResponse};
+use crate::core::tracker::backoff::jitter;
+use crate::core::tracker::tracker_config::{DEFAULT_ANNOUNCE_JITTER, TrackerConfig};
 use crate::core::tracker::tracker_error::TrackerError;
+use crate::core::tracker::udp_tracker::UdpAnnounceResponse;
 use crate::util::bencode::bencode_decodable::BencodeDecodable;
-use crate::util::bencode::bencode_decodable_error::BencodeDecodableError;
-use crate::util::errors::BStreamingError;
+use crate::util::bencode::bencode_decodable_error::{BencodeDecodableError, DecodeContext};
+use crate::util::bencode::parser::{Node, Value, parse};
+use crate::util::urlencode;
 
-use bencode::{Bencode, from_buffer};
+use http::Uri;
 use http::uri::PathAndQuery;
-use http::{Request, Uri};
-use http_body_util::{BodyExt, Empty};
-use hyper::body::Bytes;
-use hyper::client::conn::http1::handshake;
-use hyper_util::rt::TokioIo;
 use itoa;
+use rand::rng;
 use std::array::TryFromSliceError;
-use std::rc::Rc;
-use std::time::Instant;
-use tokio::net::TcpStream;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::time::{Instant, timeout};
+use tracing::{debug, warn};
+
+//shortest re-announce interval used when the tracker sends no min interval
+//also the default lower bound for the interval the tracker asks for
+pub const MIN_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+//default upper bound for the interval the tracker asks for
+pub const MAX_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+//re-announce interval used when the tracker sends none or a bogus one
+pub const DEFAULT_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+//number of peers asked from the tracker unless configured otherwise
+pub const DEFAULT_NUMWANT: u32 = 50;
+
+//peers missing from this many consecutive announce intervals are forgotten
+const PEER_EXPIRY_INTERVALS: u32 = 3;
+
+//time a stopped announce may take, so leaving the swarm is not held up by a slow tracker
+pub const STOP_ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+//port announced for incoming connections unless configured otherwise
+pub const DEFAULT_PORT: u16 = 6881;
+
+//event reported to the tracker with an announce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnounceEvent {
+    None,      //regular re-announce
+    Started,   //first announce of a download
+    Completed, //download finished
+    Stopped,   //leaving the swarm
+}
+
+impl AnnounceEvent {
+    //value of the event query parameter, None if it should be omitted
+    pub fn as_str(&self) -> Option<&'static str> {
+        match self {
+            AnnounceEvent::None => None,
+            AnnounceEvent::Started => Some("started"),
+            AnnounceEvent::Completed => Some("completed"),
+            AnnounceEvent::Stopped => Some("stopped"),
+        }
+    }
+}
 
 //represents a request to be sent to a BitTorrent tracker
 #[derive(Debug)]
 pub struct TrackerRequest<'a> {
-    tracker: &'a [u8],     //tracker URL as bytes
-    url_info_hash: String, //URL-encoded info hash
-    url_peer_id: String,   //URL-encoded peer ID
-    port: u16,             //port number for incoming connections
-    uploaded: u64,         //total bytes uploaded
-    downloaded: u64,       //total bytes downloaded
-    left: u64,             //bytes left to download
-    compact: bool,         //whether to request compact peer list
+    tracker: &'a [u8],       //tracker URL as bytes
+    info_hash: &'a [u8; 20], //raw info hash
+    peer_id: &'a [u8; 20],   //raw peer ID
+    url_info_hash: String,   //URL-encoded info hash
+    url_peer_id: String,     //URL-encoded peer ID
+    port: u16,               //port number for incoming connections
+    uploaded: u64,           //total bytes uploaded
+    downloaded: u64,         //total bytes downloaded
+    left: u64,               //bytes left to download
+    compact: bool,           //whether to request compact peer list
+    event: AnnounceEvent,    //event to report with the announce
+    numwant: Option<u32>,    //number of peers wanted, omitted when None
+    key: &'static str,       //per-session key identifying us to the tracker
+    ip: Option<IpAddr>,      //address to register instead of the one the tracker sees
+}
+
+//builds a TrackerRequest, fields not set keep their defaults
+#[derive(Debug)]
+pub struct TrackerRequestBuilder<'a> {
+    tracker: &'a [u8],       //tracker URL as bytes
+    info_hash: &'a [u8; 20], //raw info hash
+    peer_id: &'a [u8; 20],   //raw peer ID
+    port: u16,               //port number for incoming connections
+    uploaded: u64,           //total bytes uploaded
+    downloaded: u64,         //total bytes downloaded
+    left: u64,               //bytes left to download
+    compact: bool,           //whether to request compact peer list
+    event: AnnounceEvent,    //event to report with the announce
+    numwant: Option<u32>,    //number of peers wanted, omitted when None
+    ip: Option<IpAddr>,      //address to register instead of the one the tracker sees
+}
+
+impl<'a> TrackerRequestBuilder<'a> {
+    //set the port number for incoming connections
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    //set the total bytes uploaded
+    pub fn uploaded(mut self, uploaded: u64) -> Self {
+        self.uploaded = uploaded;
+        self
+    }
+
+    //set the total bytes downloaded
+    pub fn downloaded(mut self, downloaded: u64) -> Self {
+        self.downloaded = downloaded;
+        self
+    }
+
+    //set the bytes left to download
+    pub fn left(mut self, left: u64) -> Self {
+        self.left = left;
+        self
+    }
+
+    //set whether to request compact peer list
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    //set the event reported by the first announce
+    pub fn event(mut self, event: AnnounceEvent) -> Self {
+        self.event = event;
+        self
+    }
+
+    //set the number of peers asked from the tracker
+    pub fn numwant(mut self, numwant: u32) -> Self {
+        self.numwant = Some(numwant);
+        self
+    }
+
+    //set the address announced to the tracker, checked when building
+    pub fn ip(mut self, ip: IpAddr) -> Self {
+        self.ip = Some(ip);
+        self
+    }
+
+    //build the request, failing if the announced address is not globally routable
+    pub fn build(self) -> Result<TrackerRequest<'a>, TrackerError> {
+        let mut request = TrackerRequest {
+            tracker: self.tracker,
+            info_hash: self.info_hash,
+            peer_id: self.peer_id,
+            url_info_hash: urlencode::encode(self.info_hash),
+            url_peer_id: urlencode::encode(self.peer_id),
+            port: self.port,
+            uploaded: self.uploaded,
+            downloaded: self.downloaded,
+            left: self.left,
+            compact: self.compact,
+            event: self.event,
+            numwant: self.numwant,
+            key: get_tracker_key(),
+            ip: None,
+        };
+        request.set_ip(self.ip)?;
+
+        Ok(request)
+    }
 }
 
 impl<'a> TrackerRequest<'a> {
-    //create a new tracker request
-    pub fn new(
+    //start building a request for the given tracker, torrent and peer ID
+    pub fn builder(
         tracker: &'a [u8],
         info_hash: &'a [u8; 20],
         peer_id: &'a [u8; 20],
-        port: u16,
-        uploaded: u64,
-        downloaded: u64,
-        left: u64,
-        compact: bool,
-    ) -> Result<Self, TrackerError> {
-        Ok(Self {
+    ) -> TrackerRequestBuilder<'a> {
+        TrackerRequestBuilder {
             tracker,
-            url_info_hash: Self::url_encode(info_hash),
-            url_peer_id: Self::url_encode(peer_id),
-            port,
-            uploaded,
-            downloaded,
-            left,
-            compact,
-        })
+            info_hash,
+            peer_id,
+            port: DEFAULT_PORT,
+            uploaded: 0,
+            downloaded: 0,
+            left: 0,
+            compact: true,
+            event: AnnounceEvent::None,
+            numwant: Some(DEFAULT_NUMWANT),
+            ip: None,
+        }
+    }
+
+    //get tracker URL as bytes
+    pub fn tracker(&self) -> &'a [u8] {
+        self.tracker
+    }
+
+    //get raw info hash
+    pub fn info_hash(&self) -> &'a [u8; 20] {
+        self.info_hash
+    }
+
+    //get raw peer ID
+    pub fn peer_id(&self) -> &'a [u8; 20] {
+        self.peer_id
+    }
+
+    //get port number for incoming connections
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    //get total bytes uploaded
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    //get total bytes downloaded
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    //get bytes left to download
+    pub fn left(&self) -> u64 {
+        self.left
     }
 
-    //URL encodes a 20-byte value for use in tracker requests
-    fn url_encode(bytes: &[u8; 20]) -> String {
-        //pre-allocate capacity - worst case: all bytes need %XX encoding (3 chars each)
-        let mut result = String::with_capacity(bytes.len() * 3);
-
-        for &b in bytes {
-            if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.' || b == b'~' {
-                //direct character push - no allocation
-                result.push(b as char);
-            } else {
-                //add percent encoding without format!
-                result.push('%');
-                //convert byte to hex digits
-                let digit1 = char::from_digit((b >> 4).into(), 16)
-                    .unwrap_or('0')
-                    .to_ascii_uppercase();
-                let digit2 = char::from_digit((b & 0xF).into(), 16)
-                    .unwrap_or('0')
-                    .to_ascii_uppercase();
-                result.push(digit1);
-                result.push(digit2);
+    //get the event reported by the next announce
+    pub fn event(&self) -> AnnounceEvent {
+        self.event
+    }
+
+    //get number of peers wanted
+    pub fn numwant(&self) -> Option<u32> {
+        self.numwant
+    }
+
+    //get per-session tracker key
+    pub fn key(&self) -> &str {
+        self.key
+    }
+
+    //get address announced to the tracker
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.ip
+    }
+
+    //set the address announced to the tracker, which must be globally routable
+    pub fn set_ip(&mut self, ip: Option<IpAddr>) -> Result<(), TrackerError> {
+        if let Some(addr) = ip
+            && !Self::is_global(&addr)
+        {
+            return Err(TrackerError::InvalidIp(addr));
+        }
+        self.ip = ip;
+        Ok(())
+    }
+
+    //check if an address is reachable from the public internet
+    fn is_global(ip: &IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(v4) => {
+                let octets = v4.octets();
+                !(v4.is_private()
+                    || v4.is_loopback()
+                    || v4.is_link_local()
+                    || v4.is_broadcast()
+                    || v4.is_documentation()
+                    || v4.is_unspecified()
+                    || v4.is_multicast()
+                    //shared address space 100.64.0.0/10
+                    || (octets[0] == 100 && (octets[1] & 0xC0) == 64)
+                    //reserved 240.0.0.0/4
+                    || octets[0] >= 240)
+            }
+            IpAddr::V6(v6) => {
+                let segments = v6.segments();
+                !(v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_multicast()
+                    //unique local fc00::/7
+                    || (segments[0] & 0xFE00) == 0xFC00
+                    //link local fe80::/10
+                    || (segments[0] & 0xFFC0) == 0xFE80
+                    //documentation 2001:db8::/32
+                    || (segments[0] == 0x2001 && segments[1] == 0x0DB8))
             }
         }
+    }
+
+    //set the number of peers asked from the tracker, None leaves it to the tracker
+    pub fn set_numwant(&mut self, numwant: Option<u32>) {
+        self.numwant = numwant;
+    }
+
+    //set the event reported by subsequent announces
+    pub fn set_event(&mut self, event: AnnounceEvent) {
+        self.event = event;
+    }
+
+    //set the total bytes uploaded, reported by subsequent announces
+    pub fn set_uploaded(&mut self, uploaded: u64) {
+        self.uploaded = uploaded;
+    }
+
+    //set the total bytes downloaded, reported by subsequent announces
+    pub fn set_downloaded(&mut self, downloaded: u64) {
+        self.downloaded = downloaded;
+    }
 
-        result
+    //set the bytes left to download, reported by subsequent announces
+    pub fn set_left(&mut self, left: u64) {
+        self.left = left;
     }
 
     //build a complete tracker request URL with all required parameters
     pub fn build_url(&'a self) -> Result<Uri, TrackerError> {
+        self.build_announce_url(self.tracker, self.event, None)
+    }
+
+    //build a request URL for the tracker at the given URL, reporting the given event and
+    //echoing the tracker id
+    pub fn build_announce_url(
+        &self,
+        tracker: &[u8],
+        event: AnnounceEvent,
+        tracker_id: Option<&[u8]>,
+    ) -> Result<Uri, TrackerError> {
         //buffer for int to str
         let mut buffer = itoa::Buffer::new();
 
-        let mut uri_parts = Uri::from_maybe_shared(self.tracker.to_vec())?.into_parts();
+        let mut uri_parts = Uri::try_from(tracker)?.into_parts();
 
-        let path = uri_parts
+        //keep any query already in the announce URL, private trackers put passkeys there
+        let base = uri_parts
             .path_and_query
             .as_ref()
-            .map(|p| p.path())
+            .map(|p| p.as_str())
             .unwrap_or("/");
 
         //construct query string with all tracker parameters
-        let approx_query_capacity = path.len() + 100 + (20 * 3) * 2;
+        let approx_query_capacity = base.len() + 200 + (20 * 3) * 2;
         let mut path_and_query = String::with_capacity(approx_query_capacity);
 
-        //start with base path
-        path_and_query.push_str(path);
+        //start with base path and query
+        path_and_query.push_str(base);
 
-        //add query delimiter
-        if path.contains('?') {
-            path_and_query.push('&');
-        } else {
+        //add query delimiter, unless the existing query already ends with one
+        if !base.contains('?') {
             path_and_query.push('?');
+        } else if !base.ends_with('?') && !base.ends_with('&') {
+            path_and_query.push('&');
         }
 
         //build query parameters without intermediate allocations
@@ -130,6 +380,32 @@ impl<'a> TrackerRequest<'a> {
         path_and_query.push_str("&compact=");
         path_and_query.push(if self.compact { '1' } else { '0' });
 
+        //only send event when there is one to report
+        if let Some(event) = event.as_str() {
+            path_and_query.push_str("&event=");
+            path_and_query.push_str(event);
+        }
+
+        path_and_query.push_str("&key=");
+        path_and_query.push_str(self.key);
+
+        if let Some(numwant) = self.numwant {
+            path_and_query.push_str("&numwant=");
+            path_and_query.push_str(buffer.format(numwant));
+        }
+
+        //IPv6 addresses are sent unbracketed, with colons percent-encoded
+        if let Some(ip) = self.ip {
+            path_and_query.push_str("&ip=");
+            path_and_query.push_str(&urlencode::encode(ip.to_string().as_bytes()));
+        }
+
+        //echo the id the tracker handed out earlier
+        if let Some(tracker_id) = tracker_id {
+            path_and_query.push_str("&trackerid=");
+            path_and_query.push_str(&urlencode::encode(tracker_id));
+        }
+
         uri_parts.path_and_query = Some(PathAndQuery::try_from(path_and_query)?);
 
         Ok(Uri::from_parts(uri_parts)?)
@@ -137,23 +413,94 @@ impl<'a> TrackerRequest<'a> {
 }
 
 //represents a reponse sent by a trakcer
-#[derive(Debug)]
-struct TrackerResponse {
-    interval: u64,    //seconds between tracker requests
-    peers: Vec<Peer>, //list of peers received from tracker
+#[derive(Debug, Clone)]
+pub(crate) struct TrackerResponse {
+    pub(crate) interval: Option<u64>, //seconds between tracker requests, None if missing or bogus
+    pub(crate) min_interval: Option<u64>, //seconds the tracker wants between any two requests
+    pub(crate) peers: Vec<Peer>,      //list of peers received from tracker
+    pub(crate) skipped_peers: usize,  //number of malformed peer entries ignored
+    pub(crate) tracker_id: Option<Vec<u8>>, //opaque id to echo back on later announces
+    pub(crate) warning: Option<String>, //non-fatal message from the tracker
+    pub(crate) complete: Option<u64>, //number of seeders in the swarm
+    pub(crate) incomplete: Option<u64>, //number of leechers in the swarm
 }
 
 impl<'a> BencodeDecodable<'a> for TrackerResponse {
-    fn decode(b: &'a Bencode) -> Result<Self, BencodeDecodableError> {
+    fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError> {
         //get dict from bencode
         let dict = Self::get_struct(b)?;
 
-        //get interval value
-        let interval = Self::get_u64(Self::get_struct_value("interval", dict)?)?;
+        //get interval value, a missing, zero, negative or mistyped one falls back to the default
+        let interval = match Self::get_optional_u64("interval", dict) {
+            Ok(Some(interval)) if interval > 0 => Some(interval),
+            Ok(None) => None,
+            _ => {
+                warn!("ignoring invalid tracker interval, using the default");
+                None
+            }
+        };
 
-        //get peers
-        let peers_bytes = Self::get_str(Self::get_struct_value("peers", dict)?)?;
-        if peers_bytes.len() % 6 != 0 {
+        //get optional min interval
+        let min_interval = Self::get_optional_u64("min interval", dict)?;
+
+        //get peers, either compact bytes or a list of dicts
+        let (mut peers, skipped_peers) = match Self::get_struct_value("peers", dict) {
+            Ok(Node {
+                value: Value::List(list),
+                ..
+            }) => Self::decode_dict_peers(list),
+            Ok(b) => (
+                Self::get_str(b)
+                    .and_then(Self::decode_compact_peers)
+                    .context("peers")?,
+                0,
+            ),
+            //a dual-stack tracker may only send peers6
+            Err(_) if dict.contains_key(b"peers6".as_slice()) => (Vec::new(), 0),
+            Err(e) => return Err(e),
+        };
+
+        //get IPv6 peers and merge them in, dropping duplicate addresses
+        if let Some(b) = Self::get_optional_struct_value("peers6", dict) {
+            peers.extend(
+                Self::get_str(b)
+                    .and_then(Self::decode_compact_peers6)
+                    .context("peers6")?,
+            );
+            let mut seen = HashSet::with_capacity(peers.len());
+            peers.retain(|peer| seen.insert(peer.addr()));
+        }
+        if skipped_peers > 0 {
+            warn!(skipped = skipped_peers, "skipped malformed peer entries");
+        }
+
+        //get optional tracker id
+        let tracker_id = Self::get_optional_str("tracker id", dict)?.map(<[u8]>::to_vec);
+
+        //get optional warning message
+        let warning = Self::get_optional_string("warning message", dict)?.map(Cow::into_owned);
+
+        //get optional swarm counts, some trackers send negative ones, which are clamped to 0
+        let complete = Self::get_optional_i64("complete", dict)?.map(|n| n.max(0) as u64);
+        let incomplete = Self::get_optional_i64("incomplete", dict)?.map(|n| n.max(0) as u64);
+
+        Ok(Self {
+            interval,
+            min_interval,
+            peers,
+            skipped_peers,
+            tracker_id,
+            warning,
+            complete,
+            incomplete,
+        })
+    }
+}
+
+impl TrackerResponse {
+    //decode the compact peer format: 4 bytes of ip followed by 2 bytes of port
+    fn decode_compact_peers(peers_bytes: &[u8]) -> Result<Vec<Peer>, BencodeDecodableError> {
+        if !peers_bytes.len().is_multiple_of(6) {
             return Err(BencodeDecodableError::Other(
                 format!(
                     "Peer data length {} is not a multiple of 6.",
@@ -163,7 +510,7 @@ impl<'a> BencodeDecodable<'a> for TrackerResponse {
             ));
         }
 
-        let peers = peers_bytes
+        peers_bytes
             .chunks_exact(6)
             .map(|chunk| {
                 let peer_bytes: [u8; 6] = chunk
@@ -171,90 +518,996 @@ impl<'a> BencodeDecodable<'a> for TrackerResponse {
                     .map_err(|e: TryFromSliceError| BencodeDecodableError::Other(e.into()))?;
                 Peer::decode(&peer_bytes).map_err(|e| BencodeDecodableError::Other(e.into()))
             })
-            .collect::<Result<Vec<_>, _>>()?;
+            .collect()
+    }
 
-        Ok(Self { interval, peers })
+    //decode the compact IPv6 peer format: 16 bytes of ip followed by 2 bytes of port
+    fn decode_compact_peers6(peers_bytes: &[u8]) -> Result<Vec<Peer>, BencodeDecodableError> {
+        if !peers_bytes.len().is_multiple_of(18) {
+            return Err(BencodeDecodableError::Other(
+                format!(
+                    "Peer6 data length {} is not a multiple of 18.",
+                    peers_bytes.len()
+                )
+                .into(),
+            ));
+        }
+
+        peers_bytes
+            .chunks_exact(18)
+            .map(|chunk| {
+                let peer_bytes: [u8; 18] = chunk
+                    .try_into()
+                    .map_err(|e: TryFromSliceError| BencodeDecodableError::Other(e.into()))?;
+                Peer::decode_v6(&peer_bytes).map_err(|e| BencodeDecodableError::Other(e.into()))
+            })
+            .collect()
+    }
+
+    //decode the dictionary peer format, returning the peers and the number of skipped entries
+    fn decode_dict_peers(list: &[Node]) -> (Vec<Peer>, usize) {
+        let mut peers = Vec::with_capacity(list.len());
+        let mut skipped = 0;
+
+        for item in list {
+            match Self::decode_dict_peer(item) {
+                Ok(peer) => peers.push(peer),
+                Err(_) => skipped += 1,
+            }
+        }
+
+        (peers, skipped)
+    }
+
+    //decode a single {ip, port, peer id} entry
+    //hostnames are not resolved here and count as malformed
+    fn decode_dict_peer(b: &Node) -> Result<Peer, BencodeDecodableError> {
+        let dict = Self::get_struct(b)?;
+
+        let ip = Self::get_string_strict(Self::get_struct_value("ip", dict)?)?;
+        let ip = ip
+            .parse::<IpAddr>()
+            .map_err(|e| BencodeDecodableError::Other(e.into()))?;
+
+        let port = Self::get_u64(Self::get_struct_value("port", dict)?)?;
+        let port = u16::try_from(port).map_err(|e| BencodeDecodableError::Other(e.into()))?;
+
+        //peer id is optional and ignored if it is not 20 bytes
+        let peer_id = Self::get_optional_str("peer id", dict)?.and_then(|id| id.try_into().ok());
+
+        Ok(Peer::new(ip, port, peer_id))
+    }
+
+    //decode a response, turning a tracker-reported failure into TrackerError::Failure
+    fn parse(b: &Node) -> Result<Self, TrackerError> {
+        //a rejected announce carries only a failure reason
+        if let Ok(dict) = Self::get_struct(b)
+            && let Some(reason) = Self::get_optional_string("failure reason", dict)?
+        {
+            return Err(TrackerError::Failure(reason.into_owned()));
+        }
+
+        Ok(Self::decode(b)?)
+    }
+}
+
+impl From<UdpAnnounceResponse> for TrackerResponse {
+    fn from(response: UdpAnnounceResponse) -> Self {
+        Self {
+            interval: Some(response.interval.into()).filter(|&interval| interval > 0),
+            min_interval: None,
+            peers: response.peers,
+            skipped_peers: 0,
+            tracker_id: None,
+            warning: None,
+            complete: Some(response.seeders.into()),
+            incomplete: Some(response.leechers.into()),
+        }
     }
 }
 
 //manages communication with a BitTorrent tracker
 #[derive(Debug)]
-pub struct Tracker {
-    last_request: Instant,         //time of last tracker request
-    response_bencode: Rc<Bencode>, //response bencode format
-    response: TrackerResponse,     //response by tracker
+pub struct Tracker<T = DefaultTransport> {
+    last_request: Instant,                 //time of last tracker request
+    response: TrackerResponse,             //response by tracker
+    tracker_id: Option<Vec<u8>>,           //latest tracker id received
+    warning: Option<String>,               //latest warning message received
+    completed_sent: bool,                  //whether the tracker was told the download finished
+    interval_bounds: (Duration, Duration), //range the announce interval is clamped into
+    jitter: f64,                           //fraction of the interval randomly added or removed
+    next_interval: Duration,               //jittered time between the last and the next announce
+    peers: PeerSet,                        //peers merged over all announces
+    transport: T,                          //sends announces, keeping connection state between them
 }
 
-impl<'a> Tracker {
-    //create a new tracker and sends an initial request
+impl Tracker {
+    //create a new tracker and sends an initial started request
     pub async fn new(req: &TrackerRequest<'_>) -> Result<Self, TrackerError> {
-        let response_bencode = Self::send_request(req).await?;
+        Self::with_config(req, TrackerConfig::default()).await
+    }
 
-        //extract the bencode and create a 'static reference
-        //this is safe because we ensure the data lives as long as Tracker
-        let bencode_static = unsafe {
-            let bencode_ref = response_bencode.as_ref();
-            std::mem::transmute::<&Bencode, &'a Bencode>(bencode_ref)
-        };
+    //create a new tracker with custom settings and sends an initial started request
+    pub async fn with_config(
+        req: &TrackerRequest<'_>,
+        config: TrackerConfig,
+    ) -> Result<Self, TrackerError> {
+        let (min, max) = (config.min_announce_interval, config.max_announce_interval);
+        let jitter = config.announce_jitter;
+        let mut tracker = Self::with_transport(req, DefaultTransport::new(config)).await?;
+        tracker.set_interval_bounds(min, max);
+        tracker.set_jitter(jitter);
+        Ok(tracker)
+    }
+}
 
-        Ok(Self {
+impl<T: AnnounceTransport> Tracker<T> {
+    //create a new tracker announcing through transport and sends an initial started request
+    pub async fn with_transport(
+        req: &TrackerRequest<'_>,
+        mut transport: T,
+    ) -> Result<Self, TrackerError> {
+        let response = Self::announce(req, AnnounceEvent::Started, None, &mut transport).await?;
+
+        let mut tracker = Self {
             last_request: Instant::now(),
-            response_bencode,
-            response: TrackerResponse::decode(&bencode_static)?,
-        })
+            tracker_id: response.tracker_id.clone(),
+            warning: None,
+            completed_sent: false,
+            interval_bounds: (MIN_ANNOUNCE_INTERVAL, MAX_ANNOUNCE_INTERVAL),
+            jitter: DEFAULT_ANNOUNCE_JITTER,
+            next_interval: Duration::ZERO,
+            peers: PeerSet::new(),
+            response,
+            transport,
+        };
+        tracker.schedule();
+        tracker.record_warning();
+        tracker.peers.merge(tracker.response.peers.iter().cloned());
+
+        Ok(tracker)
     }
 
-    //send a request to the tracker and processes the response
-    async fn send_request(req: &TrackerRequest<'_>) -> Result<Rc<Bencode>, TrackerError> {
-        let url = req.build_url()?;
+    //get peers merged over all announces, without duplicate addresses
+    pub fn peers(&self) -> &[Peer] {
+        self.peers.peers()
+    }
 
-        //set up connection to tracker
-        let host = url
-            .host()
-            .ok_or(TrackerError::Other("Missing host in tracker URL".into()))?;
-        let port = url.port_u16().unwrap_or(6969);
+    //get the number of peers known from this tracker
+    pub fn peer_count(&self) -> usize {
+        self.peers.len()
+    }
 
-        let stream = TcpStream::connect((host, port)).await?;
-        let io = TokioIo::new(stream);
+    //get the time of the last announce
+    pub fn last_announce(&self) -> Instant {
+        self.last_request
+    }
 
-        let (mut sender, conn) = handshake(io).await?;
+    //get the time left until the next regular announce is due, zero if it is due already
+    pub fn next_announce_in(&self) -> Duration {
+        self.next_interval
+            .saturating_sub(self.last_request.elapsed())
+    }
 
-        //spawn connection handler
-        tokio::task::spawn(async move {
-            if let Err(err) = conn.await {
-                println!("Connection failed: {:?}", err);
+    //get the time left until get_peers announces with req, which is sooner than the regular
+    //announce for a stopped event, or a completed one that was not sent yet once min interval passed
+    pub fn next_announce_for(&self, req: &TrackerRequest<'_>) -> Duration {
+        match self.event_for(req) {
+            AnnounceEvent::Stopped => Duration::ZERO,
+            AnnounceEvent::Completed => self
+                .min_interval()
+                .saturating_sub(self.last_request.elapsed())
+                .min(self.next_announce_in()),
+            _ => self.next_announce_in(),
+        }
+    }
+
+    //get the shortest time allowed between two announces
+    //falls back to a floor when the tracker sent no min interval
+    pub fn min_interval(&self) -> Duration {
+        self.response
+            .min_interval
+            .map_or(MIN_ANNOUNCE_INTERVAL, Duration::from_secs)
+    }
+
+    //get the time between regular announces, clamped into the interval bounds
+    //and never shorter than min_interval unless that exceeds the upper bound
+    pub fn interval(&self) -> Duration {
+        let (min, max) = self.interval_bounds;
+        self.response
+            .interval
+            .map_or(DEFAULT_ANNOUNCE_INTERVAL, Duration::from_secs)
+            .clamp(min, max)
+            .max(self.min_interval().min(max))
+    }
+
+    //set the range the interval sent by the tracker is clamped into
+    pub fn set_interval_bounds(&mut self, min: Duration, max: Duration) {
+        self.interval_bounds = (min, max.max(min));
+        self.schedule();
+    }
+
+    //set the fraction of the interval randomly added or removed, 0 disables jitter
+    pub fn set_jitter(&mut self, jitter: f64) {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self.schedule();
+    }
+
+    //pick the time until the next regular announce from the interval, with jitter
+    //so torrents started together do not keep hitting the tracker at the same instant
+    //it never drops below min_interval, which the tracker asked to be kept between requests
+    fn schedule(&mut self) {
+        let (_, max) = self.interval_bounds;
+        self.next_interval =
+            jitter(self.interval(), self.jitter, &mut rng()).max(self.min_interval().min(max));
+    }
+
+    //get the number of seeders reported by the last announce
+    pub fn seeders(&self) -> Option<u64> {
+        self.response.complete
+    }
+
+    //get the number of leechers reported by the last announce
+    pub fn leechers(&self) -> Option<u64> {
+        self.response.incomplete
+    }
+
+    //get the latest warning message sent by the tracker
+    pub fn last_warning(&self) -> Option<&str> {
+        self.warning.as_deref()
+    }
+
+    //check if a completed event was sent to this tracker
+    pub fn completed_sent(&self) -> bool {
+        self.completed_sent
+    }
+
+    //event to send for req, a completed event is only ever sent once
+    fn event_for(&self, req: &TrackerRequest<'_>) -> AnnounceEvent {
+        match req.event {
+            AnnounceEvent::Completed if self.completed_sent => AnnounceEvent::None,
+            event => event,
+        }
+    }
+
+    //remember and report a warning carried by the current response
+    fn record_warning(&mut self) {
+        if let Some(warning) = &self.response.warning {
+            warn!(warning = %warning, "tracker warning");
+            self.warning = Some(warning.clone());
+        }
+    }
+
+    //announce through the transport and decode its answer
+    async fn announce(
+        req: &TrackerRequest<'_>,
+        event: AnnounceEvent,
+        tracker_id: Option<&[u8]>,
+        transport: &mut T,
+    ) -> Result<TrackerResponse, TrackerError> {
+        let response = match transport.announce(req, event, tracker_id).await? {
+            RawResponse::Body(body) => {
+                //the decoded response owns all its data, the bencode is dropped here
+                //the response is untrusted, so the default parse limits apply
+                let bencode = parse(&body)?;
+                TrackerResponse::parse(&bencode)?
             }
-        });
+            RawResponse::Udp(response) => response.into(),
+        };
+        debug!(
+            tracker = %String::from_utf8_lossy(req.tracker()),
+            ?event,
+            peers = response.peers.len(),
+            skipped_peers = response.skipped_peers,
+            "announced"
+        );
+        Ok(response)
+    }
+
+    /// Get a snapshot of the peers from the tracker, making a new request if needed.
+    ///
+    /// ```no_run
+    /// use motteseed::Tracker;
+    /// use motteseed::core::tracker::tracker::TrackerRequest;
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///
+    /// let (info_hash, peer_id) = ([0xab; 20], *b"-MS0100-123456789012");
+    /// let req = TrackerRequest::builder(b"http://tracker.example.com/announce", &info_hash, &peer_id)
+    ///     .left(1 << 20)
+    ///     .build()?;
+    /// let mut tracker = Tracker::new(&req).await?;
+    /// //calls before the next announce is due return the peers of the last one
+    /// let peers = loop {
+    ///     let peers = tracker.get_peers(&req).await?;
+    ///     if !peers.is_empty() {
+    ///         break peers;
+    ///     }
+    ///     tokio::time::sleep(tracker.next_announce_for(&req)).await;
+    /// };
+    /// for peer in peers {
+    ///     println!("{}", peer.addr());
+    /// }
+    /// # Ok::<(), motteseed::core::tracker::tracker_error::TrackerError>(())
+    /// # }).unwrap();
+    /// ```
+    pub async fn get_peers(&mut self, req: &TrackerRequest<'_>) -> Result<Vec<Peer>, TrackerError> {
+        //request again if interval has passed, reporting the event set on the request
+        //stopped is sent right away, a not yet sent completed event as soon as min interval allows
+        let event = self.event_for(req);
+        let elapsed = self.last_request.elapsed();
+        if elapsed >= self.next_interval
+            || event == AnnounceEvent::Stopped
+            || (event == AnnounceEvent::Completed && elapsed >= self.min_interval())
+        {
+            self.reannounce_now(req).await?;
+        }
+        Ok(self.peers.peers().to_vec())
+    }
+
+    //announce right away, unless the tracker's min interval has not passed yet
+    //a stopped event is always sent
+    pub async fn reannounce(
+        &mut self,
+        req: &TrackerRequest<'_>,
+    ) -> Result<Vec<Peer>, TrackerError> {
+        let elapsed = self.last_request.elapsed();
+        if elapsed < self.min_interval() && req.event != AnnounceEvent::Stopped {
+            return Err(TrackerError::AnnounceTooSoon(self.min_interval() - elapsed));
+        }
+
+        self.reannounce_now(req).await?;
+        Ok(self.peers.peers().to_vec())
+    }
+
+    //tell the tracker we leave the swarm, giving up after STOP_ANNOUNCE_TIMEOUT
+    //the event of req is set to stopped, so later announces with it report the same
+    pub async fn stop(&mut self, req: &mut TrackerRequest<'_>) -> Result<(), TrackerError> {
+        req.set_event(AnnounceEvent::Stopped);
+        timeout(STOP_ANNOUNCE_TIMEOUT, self.reannounce_now(req))
+            .await
+            .map_err(|_| TrackerError::Timeout(STOP_ANNOUNCE_TIMEOUT))?
+    }
+
+    //send an announce and store the response
+    async fn reannounce_now(&mut self, req: &TrackerRequest<'_>) -> Result<(), TrackerError> {
+        let event = self.event_for(req);
+        let response =
+            Self::announce(req, event, self.tracker_id.as_deref(), &mut self.transport).await?;
+        self.response = response;
+        self.last_request = Instant::now();
+        self.schedule();
+        if event == AnnounceEvent::Completed {
+            self.completed_sent = true;
+        }
+
+        //keep the previous tracker id if the tracker did not send a new one
+        if let Some(tracker_id) = &self.response.tracker_id {
+            self.tracker_id = Some(tracker_id.clone());
+        }
+        self.record_warning();
+
+        //merge the new peers and forget ones no longer announced
+        self.peers.merge(self.response.peers.iter().cloned());
+        self.peers.expire(self.interval() * PEER_EXPIRY_INTERVALS);
+
+        Ok(())
+    }
+}
+
+//trackers are driven from spawned tasks, so Tracker and its errors must stay Send
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Tracker>();
+    assert_send::<TrackerError>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::errors::{BencodeParseError, ParseLimit};
+    use crate::util::test_server::http_server;
+
+    use std::collections::VecDeque;
+
+    //request to url for a torrent with 100 bytes left
+    fn request(url: &str) -> TrackerRequest<'_> {
+        TrackerRequest::builder(url.as_bytes(), &[1; 20], &[2; 20])
+            .left(100)
+            .build()
+            .unwrap()
+    }
+
+    //get the query of the announce URL of req
+    fn query(req: &TrackerRequest<'_>) -> String {
+        req.build_url().unwrap().query().unwrap().to_string()
+    }
+
+    //transport answering with canned bodies in turn, recording the event and tracker id of each announce
+    #[derive(Debug, Default)]
+    struct Canned {
+        bodies: VecDeque<Vec<u8>>,                   //bodies left to answer with
+        sent: Vec<(AnnounceEvent, Option<Vec<u8>>)>, //event and tracker id of every announce
+    }
+
+    impl Canned {
+        fn new(bodies: &[&[u8]]) -> Self {
+            Self {
+                bodies: bodies.iter().map(|body| body.to_vec()).collect(),
+                sent: Vec::new(),
+            }
+        }
+    }
+
+    impl AnnounceTransport for Canned {
+        async fn announce(
+            &mut self,
+            _: &TrackerRequest<'_>,
+            event: AnnounceEvent,
+            tracker_id: Option<&[u8]>,
+        ) -> Result<RawResponse, TrackerError> {
+            self.sent.push((event, tracker_id.map(<[u8]>::to_vec)));
+            let body = self.bodies.pop_front().expect("a canned body is left");
+            Ok(RawResponse::Body(body))
+        }
+    }
+
+    //decode a raw announce response
+    fn decode(body: &[u8]) -> Result<TrackerResponse, TrackerError> {
+        TrackerResponse::parse(&parse(body).unwrap())
+    }
+
+    #[test]
+    fn event_is_sent_only_when_there_is_one() {
+        let mut req = request("http://t.example/announce");
+        assert!(!query(&req).contains("event="));
+        for event in [
+            AnnounceEvent::Started,
+            AnnounceEvent::Completed,
+            AnnounceEvent::Stopped,
+        ] {
+            req.set_event(event);
+            let expected = format!("&event={}", event.as_str().unwrap());
+            assert!(query(&req).contains(&expected), "{}", query(&req));
+        }
+        req.set_event(AnnounceEvent::None);
+        assert!(!query(&req).contains("event="));
+    }
+
+    #[test]
+    fn numwant_defaults_and_can_be_left_out() {
+        let mut req = request("http://t.example/announce");
+        assert!(query(&req).ends_with(&format!("&numwant={}", DEFAULT_NUMWANT)));
+        req.set_numwant(Some(200));
+        assert!(query(&req).contains("&numwant=200"));
+        req.set_numwant(None);
+        assert!(!query(&req).contains("numwant"));
+    }
 
-        let authority = url.authority().unwrap().clone();
+    #[test]
+    fn key_is_the_same_for_every_announce() {
+        let first = request("http://t.example/announce");
+        let other = request("http://other.example/announce");
+        assert_eq!(first.key, other.key);
+        assert_eq!(first.key.len(), 8);
+        assert!(first.key.chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(query(&first).contains(&format!("&key={}", first.key)));
+    }
 
-        //build and send HTTP request
-        let req = Request::builder()
-            .uri(url)
-            .header(hyper::header::HOST, authority.as_str())
-            .body(Empty::<Bytes>::new())?;
+    #[test]
+    fn tracker_id_is_parsed_and_echoed() {
+        let with_id = parse(b"d8:intervali1800e10:tracker id2:ab5:peers0:e").unwrap();
+        let response = TrackerResponse::decode(&with_id).unwrap();
+        assert_eq!(response.tracker_id.as_deref(), Some(&b"ab"[..]));
+        let without_id = parse(b"d8:intervali1800e5:peers0:e").unwrap();
+        assert!(
+            TrackerResponse::decode(&without_id)
+                .unwrap()
+                .tracker_id
+                .is_none()
+        );
 
-        let res = sender.send_request(req).await?;
+        let req = request("http://t.example/announce");
+        let url = req
+            .build_announce_url(req.tracker, AnnounceEvent::None, Some(b"a b"))
+            .unwrap();
+        assert!(url.query().unwrap().ends_with("&trackerid=a%20b"));
+        assert!(!query(&req).contains("trackerid"));
+    }
 
-        let body_bytes: &[u8] = &res.collect().await?.to_bytes();
+    #[test]
+    fn only_global_addresses_are_announced() {
+        let mut req = request("http://t.example/announce");
+        assert!(!query(&req).contains("ip="));
+        req.set_ip(Some("203.0.114.7".parse().unwrap())).unwrap();
+        assert!(query(&req).ends_with("&ip=203.0.114.7"));
+        req.set_ip(Some("2606:4700::1".parse().unwrap())).unwrap();
+        assert!(query(&req).ends_with("&ip=2606%3A4700%3A%3A1"));
+
+        for local in [
+            "10.0.0.1",
+            "127.0.0.1",
+            "100.64.0.1",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+        ] {
+            let ip = local.parse().unwrap();
+            assert!(matches!(req.set_ip(Some(ip)), Err(TrackerError::InvalidIp(bad)) if bad == ip));
+        }
+        //a refused address leaves the last good one
+        assert_eq!(req.ip, Some("2606:4700::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn failure_reason_rejects_the_announce() {
+        assert!(matches!(
+            decode(b"d14:failure reason4:nopee"),
+            Err(TrackerError::Failure(reason)) if reason == "nope"
+        ));
+        //the failure wins over whatever else was sent
+        assert!(matches!(
+            decode(b"d8:intervali60e14:failure reason3:bad5:peers0:e"),
+            Err(TrackerError::Failure(reason)) if reason == "bad"
+        ));
+        assert!(decode(b"d14:failure reasoni1ee").is_err());
+    }
+
+    #[test]
+    fn warning_is_decoded_with_the_response() {
+        let response = decode(b"d8:intervali1800e15:warning message4:slow5:peers0:e").unwrap();
+        assert_eq!(response.warning.as_deref(), Some("slow"));
+        assert!(
+            decode(b"d8:intervali1800e5:peers0:e")
+                .unwrap()
+                .warning
+                .is_none()
+        );
+
+        //a mistyped warning is an error rather than no warning
+        assert!(decode(b"d8:intervali60e15:warning messagei1e5:peers0:e").is_err());
+    }
+
+    #[test]
+    fn swarm_counts_are_decoded_when_sent() {
+        let response =
+            decode(b"d8:completei5e10:incompletei12e8:intervali1800e5:peers0:e").unwrap();
+        assert_eq!(
+            (response.complete, response.incomplete),
+            (Some(5), Some(12))
+        );
+        let response = decode(b"d8:intervali1800e5:peers0:e").unwrap();
+        assert_eq!((response.complete, response.incomplete), (None, None));
+
+        //negative counts are clamped, mistyped ones are errors
+        let response = decode(b"d8:completei-1e10:incompletei0e8:intervali60e5:peers0:e").unwrap();
+        assert_eq!((response.complete, response.incomplete), (Some(0), Some(0)));
+        assert!(decode(b"d8:complete1:18:intervali60e5:peers0:e").is_err());
+    }
 
-        //create a place to store the bencode
-        let bencode_holder = Rc::new(from_buffer(body_bytes).map_err(BStreamingError::from)?);
+    #[test]
+    fn dict_peers_are_decoded_and_bad_entries_skipped() {
+        let response = decode(
+            b"d8:intervali1800e5:peersl\
+              d2:ip9:127.0.0.17:peer id20:aaaaaaaaaaaaaaaaaaaa4:porti6881ee\
+              d2:ip8:host.com4:porti1ee\
+              d2:ip3:::14:porti2ee\
+              d2:ip8:10.0.0.14:porti70000ee\
+              i5e\
+              ee",
+        )
+        .unwrap();
+        let addrs: Vec<String> = response
+            .peers
+            .iter()
+            .map(|p| p.addr().to_string())
+            .collect();
+        assert_eq!(addrs, ["127.0.0.1:6881", "[::1]:2"]);
+        assert_eq!(response.skipped_peers, 3);
+        assert_eq!(response.peers[0].peer_id(), Some(&[b'a'; 20]));
+        assert_eq!(response.peers[1].peer_id(), None);
 
-        Ok(bencode_holder)
+        //the compact format is still understood
+        let response = decode(b"d8:intervali1800e5:peers6:\x7f\x00\x00\x01\x1a\xe1e").unwrap();
+        assert_eq!(response.peers[0].addr().to_string(), "127.0.0.1:6881");
+        assert!(decode(b"d8:intervali1800e5:peers5:abcdee").is_err());
     }
 
-    //get peers from tracker, making a new request if needed
-    pub async fn get_peers(
-        &'a mut self,
-        req: &'a TrackerRequest<'a>,
-    ) -> Result<&'a Vec<Peer>, TrackerError> {
-        //request again if interval has passed
-        if self.last_request.elapsed().as_secs() > self.response.interval {
-            self.response_bencode = Self::send_request(req).await?;
-            self.response = TrackerResponse::decode(self.response_bencode.as_ref())?;
-            self.last_request = Instant::now();
+    #[test]
+    fn peers6_are_merged_with_peers() {
+        //compact IPv6 peer ::1 on port 0x0105
+        let peer6 = [&[0; 15][..], &[1, 1, 5]].concat();
+
+        let mut body = b"d8:intervali1800e5:peers6:\x7f\x00\x00\x01\x1a\xe16:peers636:".to_vec();
+        body.extend(peer6.repeat(2));
+        body.push(b'e');
+        let addrs: Vec<String> = decode(&body)
+            .unwrap()
+            .peers
+            .iter()
+            .map(|p| p.addr().to_string())
+            .collect();
+        //the duplicate address is dropped
+        assert_eq!(addrs, ["127.0.0.1:6881", "[::1]:261"]);
+
+        //a tracker may send only peers6
+        let mut body = b"d8:intervali1800e6:peers618:".to_vec();
+        body.extend(&peer6);
+        body.push(b'e');
+        assert_eq!(decode(&body).unwrap().peers.len(), 1);
+
+        assert!(decode(b"d8:intervali1800e6:peers65:abcdee").is_err());
+        assert!(decode(b"d8:intervali1800ee").is_err());
+    }
+
+    #[tokio::test]
+    async fn min_interval_holds_back_reannounces() {
+        let (port, requests) = http_server(vec![
+            (
+                "HTTP/1.1 200 OK".into(),
+                b"d8:intervali1800e12:min intervali900e5:peers0:e".to_vec(),
+            ),
+            (
+                "HTTP/1.1 200 OK".into(),
+                b"d8:intervali1800e5:peers0:e".to_vec(),
+            ),
+        ])
+        .await;
+        let url = format!("http://127.0.0.1:{}/announce", port);
+        let mut req = request(&url);
+        let mut tracker = Tracker::new(&req).await.unwrap();
+        assert_eq!(tracker.min_interval(), Duration::from_secs(900));
+        let Err(TrackerError::AnnounceTooSoon(wait)) = tracker.reannounce(&req).await else {
+            panic!("reannounced before the min interval");
+        };
+        assert!(wait > Duration::from_secs(890) && wait <= Duration::from_secs(900));
+
+        //leaving is never held back
+        req.set_event(AnnounceEvent::Stopped);
+        tracker.reannounce(&req).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        //without a min interval the floor applies
+        assert_eq!(tracker.min_interval(), MIN_ANNOUNCE_INTERVAL);
+        assert!(decode(b"d8:intervali60e12:min interval2:305:peers0:e").is_err());
+    }
+
+    #[test]
+    fn announce_url_keeps_the_tracker_query() {
+        for (tracker, start) in [
+            (
+                "http://t.example/announce?passkey=abc",
+                "/announce?passkey=abc&info_hash=",
+            ),
+            (
+                "http://t.example/a/announce?uk=x&y=1",
+                "/a/announce?uk=x&y=1&info_hash=",
+            ),
+            ("http://t.example/announce?", "/announce?info_hash="),
+            ("http://t.example/announce", "/announce?info_hash="),
+            ("http://t.example", "/?info_hash="),
+        ] {
+            let url = request(tracker).build_url().unwrap();
+            let path = url.path_and_query().unwrap().as_str();
+            assert!(path.starts_with(start), "{} -> {}", tracker, path);
+            assert_eq!(url.host(), Some("t.example"));
         }
-        Ok(&self.response.peers)
+    }
+
+    #[tokio::test]
+    async fn any_transport_drives_the_tracker() {
+        let mut req = request("http://t.example/announce");
+        let transport = Canned::new(&[
+            b"d8:intervali10e5:peers6:\x0a\x00\x00\x01\x00\x01e",
+            b"d14:failure reason4:nopee",
+        ]);
+        let mut tracker = Tracker::with_transport(&req, transport).await.unwrap();
+        //the interval is clamped to the floor
+        assert_eq!(tracker.interval(), MIN_ANNOUNCE_INTERVAL);
+        assert_eq!(tracker.peers()[0].addr().to_string(), "10.0.0.1:1");
+
+        req.set_event(AnnounceEvent::Stopped);
+        let err = tracker.get_peers(&req).await.unwrap_err();
+        assert!(matches!(err, TrackerError::Failure(reason) if reason == "nope"));
+        //a failed announce keeps what the last one brought
+        assert_eq!(tracker.peer_count(), 1);
+        let events: Vec<AnnounceEvent> = tracker.transport.sent.iter().map(|(e, _)| *e).collect();
+        assert_eq!(events, [AnnounceEvent::Started, AnnounceEvent::Stopped]);
+    }
+
+    #[tokio::test]
+    async fn responses_are_kept_without_their_bytes() {
+        let req = request("http://t.example/announce");
+        let transport = Canned::new(&[b"d8:completei5e10:incompletei3e8:intervali1800e\
+            5:peers6:\x0a\x00\x00\x01\x00\x0110:tracker id2:ab15:warning message4:slowe"]);
+        let tracker = Tracker::with_transport(&req, transport).await.unwrap();
+        //the body and its bencode are dropped once decoded, the tracker holds all it needs
+        assert_eq!(tracker.last_warning(), Some("slow"));
+        assert_eq!((tracker.seeders(), tracker.leechers()), (Some(5), Some(3)));
+        assert_eq!(tracker.tracker_id.as_deref(), Some(&b"ab"[..]));
+        assert_eq!(tracker.peers()[0].addr().to_string(), "10.0.0.1:1");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn trackers_announce_from_spawned_tasks() {
+        let (port, requests) = http_server(vec![(
+            "HTTP/1.1 200 OK".to_string(),
+            b"d8:intervali1800e5:peers6:\x0a\x00\x00\x01\x00\x01e".to_vec(),
+        )])
+        .await;
+        //the tracker is created on one task and stopped on another, which may run on another thread
+        let url = format!("http://127.0.0.1:{}/announce", port);
+        let tracker = tokio::spawn({
+            let url = url.clone();
+            async move { Tracker::new(&request(&url)).await.unwrap() }
+        })
+        .await
+        .unwrap();
+        let peers = tokio::spawn(async move {
+            let mut tracker = tracker;
+            tracker.stop(&mut request(&url)).await.unwrap();
+            tracker.peer_count()
+        })
+        .await
+        .unwrap();
+        assert_eq!(peers, 1);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn tracker_id_is_echoed_until_replaced() {
+        let mut req = request("http://t.example/announce");
+        let transport = Canned::new(&[
+            b"d8:intervali1800e10:tracker id2:ab5:peers0:e",
+            b"d8:intervali1800e5:peers0:e",
+            b"d8:intervali1800e10:tracker id2:cd5:peers0:e",
+            b"d8:intervali1800e5:peers0:e",
+        ]);
+        let mut tracker = Tracker::with_transport(&req, transport).await.unwrap();
+        //stopped events are sent right away, whatever the interval
+        req.set_event(AnnounceEvent::Stopped);
+        for _ in 0..3 {
+            tracker.get_peers(&req).await.unwrap();
+        }
+        let ids: Vec<Option<&[u8]>> = tracker
+            .transport
+            .sent
+            .iter()
+            .map(|(_, id)| id.as_deref())
+            .collect();
+        assert_eq!(ids, [None, Some(&b"ab"[..]), Some(b"ab"), Some(b"cd")]);
+    }
+
+    #[test]
+    fn transfer_statistics_can_be_updated() {
+        let mut req = TrackerRequest::builder(b"http://t.example/a", &[1; 20], &[2; 20])
+            .port(7000)
+            .left(100)
+            .build()
+            .unwrap();
+        let before = query(&req);
+        assert!(before.contains("&port=7000&uploaded=0&downloaded=0&left=100&"));
+
+        req.set_uploaded(5);
+        req.set_downloaded(60);
+        req.set_left(40);
+        assert_eq!((req.uploaded(), req.downloaded(), req.left()), (5, 60, 40));
+        let after = query(&req);
+        assert!(
+            after.contains("&port=7000&uploaded=5&downloaded=60&left=40&"),
+            "{}",
+            after
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn peers_are_merged_across_announces() {
+        let req = request("http://t.example/announce");
+        let only_third: &[u8] = b"d8:intervali100e5:peers6:\x0a\x00\x00\x03\x00\x03e";
+        let transport = Canned::new(&[
+            b"d8:intervali100e5:peers12:\x0a\x00\x00\x01\x00\x01\x0a\x00\x00\x02\x00\x02e",
+            b"d8:intervali100e5:peers12:\x0a\x00\x00\x02\x00\x02\x0a\x00\x00\x03\x00\x03e",
+            only_third,
+            only_third,
+            only_third,
+            only_third,
+        ]);
+        let mut tracker = Tracker::with_transport(&req, transport).await.unwrap();
+        tracker.set_jitter(0.0);
+        let addrs = |peers: &[Peer]| -> Vec<String> {
+            peers.iter().map(|peer| peer.addr().to_string()).collect()
+        };
+
+        tokio::time::advance(Duration::from_secs(100)).await;
+        let peers = tracker.get_peers(&req).await.unwrap();
+        assert_eq!(addrs(&peers), ["10.0.0.1:1", "10.0.0.2:2", "10.0.0.3:3"]);
+        //peers not announced for more than three intervals are forgotten
+        for _ in 0..4 {
+            tokio::time::advance(Duration::from_secs(100)).await;
+            tracker.get_peers(&req).await.unwrap();
+        }
+        assert_eq!(addrs(tracker.peers()), ["10.0.0.3:3"]);
+    }
+
+    #[tokio::test]
+    async fn get_peers_returns_a_snapshot() {
+        let mut req = request("http://t.example/announce");
+        let transport = Canned::new(&[
+            b"d8:intervali1800e5:peers6:\x0a\x00\x00\x01\x00\x01e",
+            b"d8:intervali1800e5:peers6:\x0a\x00\x00\x02\x00\x02e",
+        ]);
+        let mut tracker = Tracker::with_transport(&req, transport).await.unwrap();
+        //not due yet, so nothing is sent
+        let before = tracker.get_peers(&req).await.unwrap();
+        assert_eq!(tracker.transport.sent.len(), 1);
+
+        req.set_event(AnnounceEvent::Stopped);
+        let after = tracker.get_peers(&req).await.unwrap();
+        assert_eq!(before.len(), 1);
+        assert_eq!(after.len(), 2);
+        assert_eq!(tracker.peer_count(), after.len());
+    }
+
+    #[tokio::test]
+    async fn missing_and_absurd_intervals_are_fixed_up() {
+        assert_eq!(decode(b"d5:peers0:e").unwrap().interval, None);
+        assert_eq!(decode(b"d8:intervali0e5:peers0:e").unwrap().interval, None);
+        assert_eq!(decode(b"d8:intervali-5e5:peers0:e").unwrap().interval, None);
+        assert_eq!(decode(b"d8:interval2:605:peers0:e").unwrap().interval, None);
+        assert_eq!(
+            decode(b"d8:intervali5e5:peers0:e").unwrap().interval,
+            Some(5)
+        );
+
+        let req = request("http://t.example/announce");
+        for (body, interval) in [
+            (&b"d5:peers0:e"[..], DEFAULT_ANNOUNCE_INTERVAL),
+            (b"d8:intervali0e5:peers0:e", DEFAULT_ANNOUNCE_INTERVAL),
+            (b"d8:intervali5e5:peers0:e", MIN_ANNOUNCE_INTERVAL),
+            (b"d8:intervali99999999e5:peers0:e", MAX_ANNOUNCE_INTERVAL),
+            (
+                b"d8:intervali900e12:min intervali1200e5:peers0:e",
+                Duration::from_secs(1200),
+            ),
+        ] {
+            let tracker = Tracker::with_transport(&req, Canned::new(&[body]))
+                .await
+                .unwrap();
+            assert_eq!(
+                tracker.interval(),
+                interval,
+                "{}",
+                String::from_utf8_lossy(body)
+            );
+        }
+
+        let transport = Canned::new(&[b"d8:intervali5e5:peers0:e"]);
+        let mut tracker = Tracker::with_transport(&req, transport).await.unwrap();
+        tracker.set_interval_bounds(Duration::from_secs(1), Duration::from_secs(100));
+        //the min interval floor still applies
+        assert_eq!(tracker.interval(), MIN_ANNOUNCE_INTERVAL);
+        tracker.set_interval_bounds(Duration::from_secs(10), Duration::from_secs(20));
+        assert_eq!(tracker.interval(), Duration::from_secs(20));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn status_getters_follow_the_schedule() {
+        let req = request("http://t.example/announce");
+        let body: &[u8] = b"d8:completei3e10:incompletei4e8:intervali120e\
+            5:peers6:\x0a\x00\x00\x01\x00\x0115:warning message2:hie";
+        let mut tracker = Tracker::with_transport(&req, Canned::new(&[body, body]))
+            .await
+            .unwrap();
+        tracker.set_jitter(0.0);
+        let started = tracker.last_announce();
+        assert_eq!(tracker.next_announce_in(), Duration::from_secs(120));
+
+        tokio::time::advance(Duration::from_secs(119)).await;
+        assert_eq!(tracker.next_announce_in(), Duration::from_secs(1));
+        tracker.get_peers(&req).await.unwrap();
+        assert_eq!(tracker.transport.sent.len(), 1);
+        tokio::time::advance(Duration::from_secs(100)).await;
+        assert_eq!(tracker.next_announce_in(), Duration::ZERO);
+
+        tracker.get_peers(&req).await.unwrap();
+        assert_eq!(tracker.transport.sent.len(), 2);
+        assert_eq!(tracker.last_announce() - started, Duration::from_secs(219));
+        assert_eq!(tracker.next_announce_in(), Duration::from_secs(120));
+        assert_eq!(tracker.peer_count(), 1);
+        assert_eq!((tracker.seeders(), tracker.leechers()), (Some(3), Some(4)));
+        assert_eq!(tracker.last_warning(), Some("hi"));
+        assert!(!tracker.completed_sent());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn completed_waits_for_min_interval() {
+        let mut req = request("http://t.example/announce");
+        let body: &[u8] = b"d8:intervali1800e12:min intervali60e5:peers0:e";
+        let mut tracker = Tracker::with_transport(&req, Canned::new(&[body; 2]))
+            .await
+            .unwrap();
+        tokio::time::advance(Duration::from_secs(10)).await;
+        req.set_event(AnnounceEvent::Completed);
+        tracker.get_peers(&req).await.unwrap();
+        assert_eq!(tracker.transport.sent.len(), 1);
+        assert_eq!(tracker.next_announce_for(&req), Duration::from_secs(50));
+
+        tokio::time::advance(Duration::from_secs(50)).await;
+        tracker.get_peers(&req).await.unwrap();
+        assert_eq!(tracker.transport.sent[1].0, AnnounceEvent::Completed);
+        assert!(tracker.completed_sent());
+        //it is sent once, later announces keep the regular interval
+        tracker.get_peers(&req).await.unwrap();
+        assert_eq!(tracker.transport.sent.len(), 2);
+        assert_eq!(tracker.next_announce_for(&req), tracker.next_announce_in());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reannounces_are_jittered_but_not_before_min_interval() {
+        let req = request("http://t.example/announce");
+        let body: &[u8] = b"d8:intervali1000e12:min intervali950e5:peers0:e";
+        let mut tracker = Tracker::with_transport(&req, Canned::new(&[body; 41]))
+            .await
+            .unwrap();
+        let mut waits = HashSet::new();
+        for _ in 0..40 {
+            let wait = tracker.next_announce_in();
+            assert!(wait >= Duration::from_secs(950) && wait <= Duration::from_secs(1100));
+            waits.insert(wait);
+            tokio::time::advance(wait).await;
+            tracker.get_peers(&req).await.unwrap();
+        }
+        assert!(waits.len() > 10);
+        assert_eq!(tracker.transport.sent.len(), 41);
+
+        tracker.set_jitter(0.0);
+        assert_eq!(tracker.next_announce_in(), Duration::from_secs(1000));
+    }
+
+    #[test]
+    fn response_errors_name_the_bad_key() {
+        let err = decode(b"d8:intervali60e12:min interval2:305:peers0:e").unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("min interval: expected a Number, found ByteString"),
+            "{}",
+            message
+        );
+        let err = decode(b"d8:intervali60e5:peers5:abcdee").unwrap_err();
+        assert!(err.to_string().contains("peers: "), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn hostile_responses_hit_the_parse_limits() {
+        let req = request("http://t.example/announce");
+        //error the tracker gives up with on body
+        async fn refused(req: &TrackerRequest<'_>, body: Vec<u8>) -> TrackerError {
+            let transport = Canned::new(&[&body]);
+            Tracker::with_transport(req, transport).await.unwrap_err()
+        }
+
+        let deep = [&b"d5:peers"[..], &vec![b'l'; 100_000]].concat();
+        let err = refused(&req, deep).await;
+        assert!(
+            matches!(
+                err,
+                TrackerError::ParseError(BencodeParseError::LimitExceeded {
+                    limit: ParseLimit::Depth,
+                    ..
+                })
+            ),
+            "{}",
+            err
+        );
+        let wide = [
+            &b"d8:intervali10e5:peersl"[..],
+            &b"de".repeat(200_000),
+            b"ee",
+        ]
+        .concat();
+        let err = refused(&req, wide).await;
+        assert!(err.to_string().contains("Element count limit"), "{}", err);
+        let long = [&b"d5:peers2000000:"[..], &vec![0; 2_000_000], b"e"].concat();
+        let err = refused(&req, long).await;
+        assert!(err.to_string().contains("String length limit"), "{}", err);
     }
 }
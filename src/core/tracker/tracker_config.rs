@@ -0,0 +1,78 @@
+use crate::core::tracker::backoff::Backoff;
+use crate::core::tracker::proxy::ProxyConfig;
+use crate::core::tracker::resolve::IpPreference;
+use crate::core::tracker::tracker::{MAX_ANNOUNCE_INTERVAL, MIN_ANNOUNCE_INTERVAL};
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+//time allowed to open a connection to an HTTP tracker
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+//time allowed for a whole announce, HTTP redirects and UDP retransmits included
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+//fraction of the announce interval randomly added or removed, so torrents drift apart
+pub const DEFAULT_ANNOUNCE_JITTER: f64 = 0.1;
+
+//window the first announces of torrents started together are spread over
+pub const DEFAULT_STARTUP_RAMP: Duration = Duration::from_secs(30);
+
+//User-Agent header sent to HTTP trackers unless configured otherwise
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+//settings shared by every announce of a session
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    pub tls: Arc<ClientConfig>,          //TLS settings used for https trackers
+    pub connect_timeout: Duration,       //deadline for opening a connection
+    pub request_timeout: Duration,       //deadline for a whole announce
+    pub proxy: Option<ProxyConfig>,      //proxy HTTP tracker connections are tunneled through
+    pub user_agent: String,              //User-Agent header sent to HTTP trackers
+    pub min_announce_interval: Duration, //lower bound for the interval trackers ask for
+    pub max_announce_interval: Duration, //upper bound for the interval trackers ask for
+    pub ip_preference: IpPreference,     //address family used for trackers that have both
+    pub announce_jitter: f64,            //fraction of the interval randomly added or removed
+    pub startup_ramp: Duration, //window first announces of several torrents are spread over
+    pub debug_responses: bool,  //log the body of every HTTP announce response at info level
+    pub backoff: Backoff,       //retry schedule for trackers whose announce failed
+}
+
+impl Default for TrackerConfig {
+    //verify https trackers against the webpki root certificates
+    fn default() -> Self {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Self::with_root_store(roots)
+    }
+}
+
+impl TrackerConfig {
+    //verify https trackers against the given root certificates
+    pub fn with_root_store(roots: RootCertStore) -> Self {
+        let tls = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("ring provider supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        Self {
+            tls: Arc::new(tls),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            proxy: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            min_announce_interval: MIN_ANNOUNCE_INTERVAL,
+            max_announce_interval: MAX_ANNOUNCE_INTERVAL,
+            ip_preference: IpPreference::default(),
+            announce_jitter: DEFAULT_ANNOUNCE_JITTER,
+            startup_ramp: DEFAULT_STARTUP_RAMP,
+            debug_responses: false,
+            backoff: Backoff::default(),
+        }
+    }
+}
@@ -1,13 +1,20 @@
 use crate::util::bencode::bencode_decodable_error::BencodeDecodableError;
-use crate::util::errors::BStreamingError;
+use crate::util::errors::BencodeParseError;
 
+use http::StatusCode;
 use http::uri::{InvalidUri, InvalidUriParts};
+use std::net::IpAddr;
 use std::str::Utf8Error;
+use std::time::Duration;
 use thiserror::Error;
 
 //custom error enum for tracker operations
 #[derive(Error, Debug)]
 pub enum TrackerError {
+    //tracker rejected the announce, retrying will not help
+    #[error("Tracker failure: {0}")]
+    Failure(String),
+
     #[error("Invalid Uri: {0}")]
     InvalidUri(#[from] InvalidUri),
 
@@ -29,9 +36,51 @@ pub enum TrackerError {
     #[error("Bencode Error: {0}")]
     BencodeError(#[from] BencodeDecodableError),
 
-    #[error("Streaming error: {0}")]
-    StreamingError(#[from] BStreamingError),
+    #[error("Parse error: {0}")]
+    ParseError(#[from] BencodeParseError),
+
+    #[error("Invalid IP address: {0} is not a global address")]
+    InvalidIp(IpAddr),
+
+    #[error("Announce too soon, min interval ends in {0:?}")]
+    AnnounceTooSoon(Duration),
+
+    #[error("UDP tracker error: {0}")]
+    UdpError(String),
+
+    #[error("HTTP status: {0}")]
+    HttpStatus(StatusCode),
+
+    #[error("Redirect error: {0}")]
+    Redirect(String),
+
+    #[error("TLS error: {0}")]
+    TlsError(String),
+
+    #[error("Tracker timed out after {0:?}")]
+    Timeout(Duration),
+
+    //HTTP tracker whose announce URL does not lead to a scrape URL (BEP 48)
+    #[error("Tracker does not support scrape")]
+    ScrapeUnsupported,
+
+    #[error("Proxy error: {0}")]
+    ProxyError(String),
 
     #[error("Error: {0}")]
-    Other(#[from] Box<dyn std::error::Error>),
+    Other(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl TrackerError {
+    //check if the error is likely temporary, so the announce is worth retrying
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TrackerError::StreamError(_)
+            | TrackerError::HyperError(_)
+            | TrackerError::UdpError(_)
+            | TrackerError::Timeout(_) => true,
+            TrackerError::HttpStatus(status) => status.is_server_error(),
+            _ => false,
+        }
+    }
 }
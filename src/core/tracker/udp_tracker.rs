@@ -0,0 +1,456 @@
+use crate::core::peer::peer::Peer;
+use crate::core::tracker::announce_transport::{AnnounceTransport, RawResponse, connect_target};
+use crate::core::tracker::resolve::{IpPreference, resolve};
+use crate::core::tracker::scrape::ScrapeStats;
+use crate::core::tracker::tracker::{AnnounceEvent, TrackerRequest};
+use crate::core::tracker::tracker_config::DEFAULT_REQUEST_TIMEOUT;
+use crate::core::tracker::tracker_error::TrackerError;
+
+use http::Uri;
+use rand::{Rng, rng};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, timeout, timeout_at};
+
+//magic constant identifying the UDP tracker protocol
+const PROTOCOL_ID: u64 = 0x41727101980;
+
+//actions understood by UDP trackers
+const ACTION_CONNECT: u32 = 0;
+const ACTION_ANNOUNCE: u32 = 1;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+
+//a connection id may be used for one minute after it was received
+const CONNECTION_ID_LIFETIME: Duration = Duration::from_secs(60);
+
+//first retransmission timeout, doubled after every attempt
+pub const DEFAULT_BASE_TIMEOUT: Duration = Duration::from_secs(15);
+
+//highest n in the 15 * 2^n retransmission schedule
+const MAX_RETRANSMITS: u32 = 8;
+
+//largest datagram we expect from a tracker
+const MAX_PACKET_SIZE: usize = 2048;
+
+//represents the response to a UDP announce
+#[derive(Debug)]
+pub struct UdpAnnounceResponse {
+    pub interval: u32,    //seconds between tracker requests
+    pub leechers: u32,    //number of leechers in the swarm
+    pub seeders: u32,     //number of seeders in the swarm
+    pub peers: Vec<Peer>, //list of peers received from tracker
+}
+
+//manages communication with a UDP tracker (BEP 15)
+#[derive(Debug)]
+pub struct UdpTracker {
+    socket: UdpSocket,                  //socket connected to the tracker
+    connection: Option<(u64, Instant)>, //connection id and when it was received
+    base_timeout: Duration,             //first retransmission timeout
+}
+
+impl UdpTracker {
+    //resolve the tracker and bind a socket of the matching address family
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        preference: IpPreference,
+    ) -> Result<Self, TrackerError> {
+        let addr = resolve(host, port, preference).await?[0];
+
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+
+        Ok(Self {
+            socket,
+            connection: None,
+            base_timeout: DEFAULT_BASE_TIMEOUT,
+        })
+    }
+
+    //set the first retransmission timeout
+    pub fn set_base_timeout(&mut self, base_timeout: Duration) {
+        self.base_timeout = base_timeout;
+    }
+
+    //announce to the tracker, retransmitting on the 15 * 2^n schedule
+    pub async fn announce(
+        &mut self,
+        req: &TrackerRequest<'_>,
+        event: AnnounceEvent,
+    ) -> Result<UdpAnnounceResponse, TrackerError> {
+        let response = self
+            .request(|connection_id, transaction_id| {
+                Self::announce_packet(req, event, connection_id, transaction_id)
+            })
+            .await?;
+        self.parse_announce(&response)
+    }
+
+    //scrape the tracker for the given info hashes, retransmitting on the 15 * 2^n schedule
+    //the counts are in the order of info_hashes, at most about 74 fit in one packet
+    pub async fn scrape(
+        &mut self,
+        info_hashes: &[[u8; 20]],
+    ) -> Result<Vec<ScrapeStats>, TrackerError> {
+        let response = self
+            .request(|connection_id, transaction_id| {
+                let mut packet = Vec::with_capacity(16 + 20 * info_hashes.len());
+                packet.extend_from_slice(&connection_id.to_be_bytes());
+                packet.extend_from_slice(&ACTION_SCRAPE.to_be_bytes());
+                packet.extend_from_slice(&transaction_id.to_be_bytes());
+                for info_hash in info_hashes {
+                    packet.extend_from_slice(info_hash);
+                }
+                packet
+            })
+            .await?;
+        Self::parse_scrape(&response, info_hashes.len())
+    }
+
+    //send the packet build makes from a connection id and a transaction id, and return the answer
+    //the connection id is renewed when it expires, packets are retransmitted on the 15 * 2^n schedule
+    async fn request(
+        &mut self,
+        build: impl Fn(u64, u32) -> Vec<u8>,
+    ) -> Result<Vec<u8>, TrackerError> {
+        for n in 0..=MAX_RETRANSMITS {
+            let wait = self.base_timeout * 2u32.pow(n);
+
+            //get a fresh connection id if the old one expired
+            let connection_id = match self.connection {
+                Some((id, received)) if received.elapsed() < CONNECTION_ID_LIFETIME => id,
+                _ => match self.request_connection_id(wait).await? {
+                    Some(id) => id,
+                    None => continue,
+                },
+            };
+
+            let transaction_id: u32 = rng().random();
+            let packet = build(connection_id, transaction_id);
+            if let Some(response) = self.transact(&packet, transaction_id, wait).await? {
+                return Ok(response);
+            }
+        }
+
+        Err(TrackerError::UdpError(format!(
+            "No response after {} attempts",
+            MAX_RETRANSMITS + 1
+        )))
+    }
+
+    //send a connect request, returning None if the tracker did not answer in time
+    async fn request_connection_id(&mut self, wait: Duration) -> Result<Option<u64>, TrackerError> {
+        let transaction_id: u32 = rng().random();
+
+        let mut packet = Vec::with_capacity(16);
+        packet.extend_from_slice(&PROTOCOL_ID.to_be_bytes());
+        packet.extend_from_slice(&ACTION_CONNECT.to_be_bytes());
+        packet.extend_from_slice(&transaction_id.to_be_bytes());
+
+        let Some(response) = self.transact(&packet, transaction_id, wait).await? else {
+            return Ok(None);
+        };
+        if response.len() < 16 || read_u32(&response, 0) != ACTION_CONNECT {
+            return Err(TrackerError::UdpError("Malformed connect response".into()));
+        }
+
+        let connection_id = u64::from_be_bytes(response[8..16].try_into().unwrap_or_default());
+        self.connection = Some((connection_id, Instant::now()));
+
+        Ok(Some(connection_id))
+    }
+
+    //build an announce packet from the request
+    fn announce_packet(
+        req: &TrackerRequest<'_>,
+        event: AnnounceEvent,
+        connection_id: u64,
+        transaction_id: u32,
+    ) -> Vec<u8> {
+        let event: u32 = match event {
+            AnnounceEvent::None => 0,
+            AnnounceEvent::Completed => 1,
+            AnnounceEvent::Started => 2,
+            AnnounceEvent::Stopped => 3,
+        };
+        //only an IPv4 address fits in the packet, 0 lets the tracker use the sender address
+        let ip = match req.ip() {
+            Some(IpAddr::V4(ip)) => u32::from(ip),
+            _ => 0,
+        };
+        let key = u32::from_str_radix(req.key(), 16).unwrap_or_default();
+        let numwant = req.numwant().map_or(-1, |n| n.min(i32::MAX as u32) as i32);
+
+        let mut packet = Vec::with_capacity(98);
+        packet.extend_from_slice(&connection_id.to_be_bytes());
+        packet.extend_from_slice(&ACTION_ANNOUNCE.to_be_bytes());
+        packet.extend_from_slice(&transaction_id.to_be_bytes());
+        packet.extend_from_slice(req.info_hash());
+        packet.extend_from_slice(req.peer_id());
+        packet.extend_from_slice(&req.downloaded().to_be_bytes());
+        packet.extend_from_slice(&req.left().to_be_bytes());
+        packet.extend_from_slice(&req.uploaded().to_be_bytes());
+        packet.extend_from_slice(&event.to_be_bytes());
+        packet.extend_from_slice(&ip.to_be_bytes());
+        packet.extend_from_slice(&key.to_be_bytes());
+        packet.extend_from_slice(&numwant.to_be_bytes());
+        packet.extend_from_slice(&req.port().to_be_bytes());
+
+        packet
+    }
+
+    //parse an announce response, peers are 6 bytes over IPv4 and 18 bytes over IPv6
+    fn parse_announce(&self, response: &[u8]) -> Result<UdpAnnounceResponse, TrackerError> {
+        if response.len() < 20 || read_u32(response, 0) != ACTION_ANNOUNCE {
+            return Err(TrackerError::UdpError("Malformed announce response".into()));
+        }
+
+        let peers_bytes = &response[20..];
+        let ipv6 = self.socket.peer_addr()?.is_ipv6();
+        let peers = if ipv6 {
+            peers_bytes
+                .chunks_exact(18)
+                .filter_map(|chunk| Peer::decode_v6(chunk.try_into().ok()?).ok())
+                .collect()
+        } else {
+            peers_bytes
+                .chunks_exact(6)
+                .filter_map(|chunk| Peer::decode(chunk.try_into().ok()?).ok())
+                .collect()
+        };
+
+        Ok(UdpAnnounceResponse {
+            interval: read_u32(response, 8),
+            leechers: read_u32(response, 12),
+            seeders: read_u32(response, 16),
+            peers,
+        })
+    }
+
+    //parse a scrape response, seeders, completed and leechers for each info hash asked for
+    fn parse_scrape(response: &[u8], count: usize) -> Result<Vec<ScrapeStats>, TrackerError> {
+        if response.len() < 8 + 12 * count || read_u32(response, 0) != ACTION_SCRAPE {
+            return Err(TrackerError::UdpError("Malformed scrape response".into()));
+        }
+
+        Ok(response[8..8 + 12 * count]
+            .chunks_exact(12)
+            .map(|entry| ScrapeStats {
+                seeders: read_u32(entry, 0).into(),
+                completed: read_u32(entry, 4).into(),
+                leechers: read_u32(entry, 8).into(),
+            })
+            .collect())
+    }
+
+    //send a packet once and wait for the response with the same transaction id
+    //returns None if nothing arrived before the timeout
+    async fn transact(
+        &self,
+        packet: &[u8],
+        transaction_id: u32,
+        wait: Duration,
+    ) -> Result<Option<Vec<u8>>, TrackerError> {
+        self.socket.send(packet).await?;
+
+        let deadline = Instant::now() + wait;
+        let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+        loop {
+            let len = match timeout_at(deadline, self.socket.recv(&mut buffer)).await {
+                Ok(len) => len?,
+                Err(_) => return Ok(None),
+            };
+
+            //ignore late answers to earlier transactions
+            if len < 8 || read_u32(&buffer, 4) != transaction_id {
+                continue;
+            }
+
+            if read_u32(&buffer, 0) == ACTION_ERROR {
+                let message = String::from_utf8_lossy(&buffer[8..len]).into_owned();
+                return Err(TrackerError::Failure(message));
+            }
+
+            buffer.truncate(len);
+            return Ok(Some(buffer));
+        }
+    }
+}
+
+//announces to udp:// trackers, connecting on the first announce
+#[derive(Debug)]
+pub struct UdpTransport {
+    tracker: Option<UdpTracker>, //connection kept for later announces
+    ip_preference: IpPreference, //address family used if the tracker has both
+    request_timeout: Duration,   //deadline for a whole announce, retransmits included
+}
+
+impl Default for UdpTransport {
+    fn default() -> Self {
+        Self::new(IpPreference::default(), DEFAULT_REQUEST_TIMEOUT)
+    }
+}
+
+impl UdpTransport {
+    //create a transport resolving trackers with the given address family preference,
+    //giving up on an announce after request_timeout
+    pub fn new(ip_preference: IpPreference, request_timeout: Duration) -> Self {
+        Self {
+            tracker: None,
+            ip_preference,
+            request_timeout,
+        }
+    }
+}
+
+impl AnnounceTransport for UdpTransport {
+    async fn announce(
+        &mut self,
+        req: &TrackerRequest<'_>,
+        event: AnnounceEvent,
+        _tracker_id: Option<&[u8]>,
+    ) -> Result<RawResponse, TrackerError> {
+        //the retransmission schedule runs for hours, an announce gives up like an HTTP request
+        let request_timeout = self.request_timeout;
+        let ip_preference = self.ip_preference;
+        let announce = async {
+            //connect once and keep the socket for later announces
+            let tracker = match &mut self.tracker {
+                Some(tracker) => tracker,
+                None => {
+                    let uri = Uri::try_from(req.tracker())?;
+                    let (host, port) = connect_target(&uri)?;
+                    let tracker = UdpTracker::connect(host, port, ip_preference).await?;
+                    self.tracker.insert(tracker)
+                }
+            };
+            tracker.announce(req, event).await
+        };
+
+        let response = timeout(request_timeout, announce)
+            .await
+            .map_err(|_| TrackerError::Timeout(request_timeout))??;
+        Ok(RawResponse::Udp(response))
+    }
+}
+
+//read a big-endian u32 at offset, callers check the length beforehand
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //fake tracker on loopback handing out connection id 7
+    //the first announce is dropped to force a retransmit, stopped announces are answered with an error
+    async fn fake_tracker() -> u16 {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; MAX_PACKET_SIZE];
+            let mut dropped = false;
+            loop {
+                let (len, from) = server.recv_from(&mut buffer).await.unwrap();
+                let packet = &buffer[..len];
+                let transaction = &packet[12..16];
+                let mut answer = Vec::new();
+                match read_u32(packet, 8) {
+                    ACTION_CONNECT => {
+                        assert_eq!(packet[..8], PROTOCOL_ID.to_be_bytes());
+                        answer.extend(ACTION_CONNECT.to_be_bytes());
+                        answer.extend(transaction);
+                        answer.extend(7u64.to_be_bytes());
+                    }
+                    ACTION_ANNOUNCE if !dropped => {
+                        dropped = true;
+                        continue;
+                    }
+                    ACTION_ANNOUNCE if read_u32(packet, 80) == 2 => {
+                        assert_eq!(len, 98);
+                        assert_eq!(packet[..8], 7u64.to_be_bytes());
+                        assert_eq!(packet[16..36], [1; 20]);
+                        answer.extend(ACTION_ANNOUNCE.to_be_bytes());
+                        answer.extend(transaction);
+                        for n in [900u32, 3, 4] {
+                            answer.extend(n.to_be_bytes());
+                        }
+                        answer.extend([10, 0, 0, 1, 0x1a, 0xe1]);
+                    }
+                    _ => {
+                        answer.extend(ACTION_ERROR.to_be_bytes());
+                        answer.extend(transaction);
+                        answer.extend(b"no stops");
+                    }
+                }
+                server.send_to(&answer, from).await.unwrap();
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn announce_over_loopback() {
+        let port = fake_tracker().await;
+        let url = format!("udp://127.0.0.1:{}/announce", port);
+        let req = TrackerRequest::builder(url.as_bytes(), &[1; 20], &[2; 20])
+            .left(10)
+            .build()
+            .unwrap();
+        let mut udp = UdpTracker::connect("127.0.0.1", port, IpPreference::Auto)
+            .await
+            .unwrap();
+        udp.set_base_timeout(Duration::from_millis(100));
+
+        let response = udp.announce(&req, AnnounceEvent::Started).await.unwrap();
+        assert_eq!(response.interval, 900);
+        assert_eq!((response.seeders, response.leechers), (4, 3));
+        assert_eq!(response.peers[0].addr().to_string(), "10.0.0.1:6881");
+
+        let err = udp
+            .announce(&req, AnnounceEvent::Stopped)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TrackerError::Failure(message) if message == "no stops"));
+    }
+
+    #[test]
+    fn announce_packet_layout() {
+        let mut req = TrackerRequest::builder(b"udp://t.example:80", &[1; 20], &[2; 20])
+            .port(6882)
+            .uploaded(3)
+            .downloaded(4)
+            .left(5)
+            .numwant(30)
+            .build()
+            .unwrap();
+        let packet = UdpTracker::announce_packet(&req, AnnounceEvent::Stopped, 7, 9);
+        assert_eq!(packet.len(), 98);
+        assert_eq!(packet[..8], 7u64.to_be_bytes());
+        assert_eq!(read_u32(&packet, 12), 9);
+        assert_eq!(packet[36..56], [2; 20]);
+        assert_eq!(packet[56..64], 4u64.to_be_bytes());
+        assert_eq!(packet[64..72], 5u64.to_be_bytes());
+        assert_eq!(packet[72..80], 3u64.to_be_bytes());
+        assert_eq!(read_u32(&packet, 80), 3);
+        assert_eq!(read_u32(&packet, 84), 0);
+        assert_eq!(format!("{:08X}", read_u32(&packet, 88)), req.key());
+        assert_eq!(read_u32(&packet, 92), 30);
+        assert_eq!(packet[96..], 6882u16.to_be_bytes());
+
+        req.set_numwant(None);
+        req.set_ip(Some("203.0.114.7".parse().unwrap())).unwrap();
+        let packet = UdpTracker::announce_packet(&req, AnnounceEvent::None, 7, 9);
+        assert_eq!(packet[84..88], [203, 0, 114, 7]);
+        assert_eq!(read_u32(&packet, 92), u32::MAX); //-1, as many as the tracker likes
+    }
+}
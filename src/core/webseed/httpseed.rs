@@ -0,0 +1,284 @@
+use crate::core::storage::storage::Storage;
+use crate::core::torrent::torrent::Info;
+use crate::core::tracker::http_transport::HttpTransport;
+use crate::core::tracker::tracker_config::TrackerConfig;
+use crate::core::webseed::webseed::MAX_WEBSEED_FAILURES;
+use crate::core::webseed::webseed_error::WebSeedError;
+use crate::util::urlencode;
+
+use http::Uri;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+//time a piece must stay missing before http seeds are asked for it
+pub const DEFAULT_FALLBACK_DELAY: Duration = Duration::from_secs(60);
+
+//an HTTP seed from the httpseeds list (BEP 17), serving whole pieces by index
+#[derive(Debug)]
+struct HttpSeed {
+    url: String, //seed URL, the info hash and piece are added as query parameters
+    transport: HttpTransport, //connection to the server, reused between requests
+    failures: u32, //consecutive failed or bad downloads
+}
+
+impl HttpSeed {
+    //check if the seed failed too often to be used again
+    fn is_disabled(&self) -> bool {
+        self.failures >= MAX_WEBSEED_FAILURES
+    }
+
+    //get the URL asking for piece index of the torrent with info_hash
+    fn piece_url(&self, info_hash: &[u8; 20], index: usize) -> Result<Uri, WebSeedError> {
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+        let url = format!(
+            "{}{}info_hash={}&piece={}",
+            self.url,
+            separator,
+            urlencode::encode(info_hash),
+            index
+        );
+        Uri::try_from(url.as_str()).map_err(|_| WebSeedError::InvalidUrl(url))
+    }
+}
+
+//downloads pieces from the http seeds of a torrent
+//they are a last resort, only pieces peers and web seeds could not deliver for a while are fetched
+#[derive(Debug)]
+pub struct HttpSeeds {
+    seeds: Vec<HttpSeed>,                   //http seeds in list order
+    fallback_delay: Duration, //time a piece must stay missing before it is fetched here
+    missing_since: HashMap<usize, Instant>, //time each missing piece was first seen missing
+}
+
+impl HttpSeeds {
+    //create a downloader for the httpseeds list of a torrent, URLs that are not UTF-8 are skipped
+    pub fn new(urls: &[&[u8]], config: TrackerConfig) -> Self {
+        Self {
+            seeds: urls
+                .iter()
+                .filter_map(|url| std::str::from_utf8(url).ok())
+                .map(|url| HttpSeed {
+                    url: url.to_string(),
+                    transport: HttpTransport::new(config.clone()),
+                    failures: 0,
+                })
+                .collect(),
+            fallback_delay: DEFAULT_FALLBACK_DELAY,
+            missing_since: HashMap::new(),
+        }
+    }
+
+    //set the time a piece must stay missing before http seeds are asked for it
+    pub fn set_fallback_delay(&mut self, delay: Duration) {
+        self.fallback_delay = delay;
+    }
+
+    //get the number of http seeds that are still used
+    pub fn active(&self) -> usize {
+        self.seeds.iter().filter(|seed| !seed.is_disabled()).count()
+    }
+
+    //download and verify a piece, trying every usable http seed in turn
+    //a seed that fails or sends bad data too often in a row is disabled
+    pub async fn fetch_piece(
+        &mut self,
+        info: &Info<'_>,
+        info_hash: &[u8; 20],
+        index: usize,
+    ) -> Result<Vec<u8>, WebSeedError> {
+        let expected = info
+            .piece_hash(index)
+            .ok_or(WebSeedError::NoWebSeed(index))?;
+
+        for seed in self.seeds.iter_mut().filter(|seed| !seed.is_disabled()) {
+            let result = match seed.piece_url(info_hash, index) {
+                Ok(url) => seed.transport.get(url).await.map_err(WebSeedError::from),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(data) if Sha1::digest(&data).as_slice() == expected => {
+                    debug!(url = %seed.url, piece = index, "piece verified");
+                    seed.failures = 0;
+                    return Ok(data);
+                }
+                Ok(_) => {
+                    warn!(url = %seed.url, error = %WebSeedError::HashMismatch(index), "HTTP seed failed");
+                    seed.failures += 1;
+                }
+                Err(e) => {
+                    warn!(url = %seed.url, error = %e, "HTTP seed failed");
+                    seed.failures += 1;
+                }
+            }
+        }
+
+        Err(WebSeedError::NoWebSeed(index))
+    }
+
+    //get the time until a piece seen missing is due to be fetched, None if none is waiting
+    pub fn next_fallback_in(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.missing_since
+            .values()
+            .map(|since| (*since + self.fallback_delay).saturating_duration_since(now))
+            .min()
+    }
+
+    //download the pieces that have been missing from storage for longer than the fallback delay
+    //returns the number of pieces downloaded, pieces no seed could serve are tried again next call
+    pub async fn download<S: Storage>(
+        &mut self,
+        info: &Info<'_>,
+        info_hash: &[u8; 20],
+        storage: &mut S,
+    ) -> Result<usize, WebSeedError> {
+        self.download_with_progress(info, info_hash, storage, |_, _| {})
+            .await
+    }
+
+    //download like download, calling progress with the index and length of every piece written
+    pub async fn download_with_progress<S: Storage>(
+        &mut self,
+        info: &Info<'_>,
+        info_hash: &[u8; 20],
+        storage: &mut S,
+        mut progress: impl FnMut(usize, u64),
+    ) -> Result<usize, WebSeedError> {
+        let now = Instant::now();
+        let mut downloaded = 0;
+        for index in 0..info.num_pieces() {
+            if storage.have(index) {
+                self.missing_since.remove(&index);
+                continue;
+            }
+            let since = *self.missing_since.entry(index).or_insert(now);
+            if now.duration_since(since) < self.fallback_delay {
+                continue;
+            }
+
+            match self.fetch_piece(info, info_hash, index).await {
+                Ok(data) => {
+                    storage.write_block(index, 0, &data)?;
+                    self.missing_since.remove(&index);
+                    progress(index, data.len() as u64);
+                    downloaded += 1;
+                }
+                //every seed is disabled, nothing more can be fetched
+                Err(e) if self.active() == 0 => return Err(e),
+                Err(_) => {}
+            }
+        }
+        storage.flush()?;
+
+        Ok(downloaded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::storage::memory_storage::MemoryStorage;
+    use crate::core::torrent::torrent::TorrentFile;
+    use crate::util::test_server::file_server;
+
+    //single file torrent holding data in pieces of 16 bytes
+    fn torrent(data: &[u8]) -> TorrentFile {
+        let pieces: Vec<u8> = data.chunks(16).flat_map(Sha1::digest).collect();
+        let mut bytes = format!(
+            "d4:infod6:lengthi{}e4:name1:f12:piece lengthi16e6:pieces{}:",
+            data.len(),
+            pieces.len()
+        )
+        .into_bytes();
+        bytes.extend(pieces);
+        bytes.extend(b"ee");
+        TorrentFile::from_bytes(bytes).unwrap()
+    }
+
+    //files served for the pieces of data, by the path and query asking for them
+    fn pieces(data: &[u8]) -> HashMap<String, Vec<u8>> {
+        let info_hash = urlencode::encode(&[0xff; 20]);
+        data.chunks(16)
+            .enumerate()
+            .map(|(index, piece)| {
+                let path = format!("/seed?k=1&info_hash={}&piece={}", info_hash, index);
+                (path, piece.to_vec())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn overdue_pieces_are_fetched_by_index() {
+        let data: Vec<u8> = (0..40).collect();
+        let torrent = torrent(&data);
+        let info = &torrent.torrent().info;
+        let (port, requests) = file_server(pieces(&data), false).await;
+        let url = format!("http://127.0.0.1:{}/seed?k=1", port);
+        let mut seeds = HttpSeeds::new(&[url.as_bytes()], TrackerConfig::default());
+        let mut storage = MemoryStorage::empty(info);
+
+        //pieces have not been missing long enough yet
+        assert_eq!(
+            seeds
+                .download(info, &[0xff; 20], &mut storage)
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(requests.lock().unwrap().is_empty());
+        seeds.set_fallback_delay(Duration::ZERO);
+        assert_eq!(
+            seeds
+                .download(info, &[0xff; 20], &mut storage)
+                .await
+                .unwrap(),
+            3
+        );
+        assert!((0..3).all(|index| storage.have(index)));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn seeds_sending_bad_pieces_are_disabled() {
+        let data: Vec<u8> = (0..40).collect();
+        let torrent = torrent(&data);
+        let info = &torrent.torrent().info;
+        let mut served = pieces(&data);
+        for (path, piece) in served.iter_mut() {
+            if path.ends_with("piece=1") {
+                piece[0] ^= 1;
+            }
+        }
+        let (port, _) = file_server(served, false).await;
+        let url = format!("http://127.0.0.1:{}/seed?k=1", port);
+        let mut seeds = HttpSeeds::new(&[url.as_bytes()], TrackerConfig::default());
+        seeds.set_fallback_delay(Duration::ZERO);
+        let mut storage = MemoryStorage::empty(info);
+
+        assert_eq!(
+            seeds
+                .download(info, &[0xff; 20], &mut storage)
+                .await
+                .unwrap(),
+            2
+        );
+        assert!(!storage.have(1));
+        //the good piece after it reset the count of failures in a row
+        for _ in 0..MAX_WEBSEED_FAILURES {
+            assert_eq!(seeds.active(), 1);
+            let err = seeds.fetch_piece(info, &[0xff; 20], 1).await.unwrap_err();
+            assert!(matches!(err, WebSeedError::NoWebSeed(1)));
+        }
+        assert_eq!(seeds.active(), 0);
+        assert!(
+            seeds
+                .download(info, &[0xff; 20], &mut storage)
+                .await
+                .is_err()
+        );
+    }
+}
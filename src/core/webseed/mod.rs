@@ -0,0 +1,3 @@
+pub mod httpseed;
+pub mod webseed;
+pub mod webseed_error;
@@ -0,0 +1,374 @@
+use crate::core::storage::storage::Storage;
+use crate::core::torrent::torrent::{FileDetails, Info};
+use crate::core::tracker::http_transport::HttpTransport;
+use crate::core::tracker::tracker_config::TrackerConfig;
+use crate::core::webseed::webseed_error::WebSeedError;
+use crate::util::urlencode;
+
+use http::Uri;
+use sha1::{Digest, Sha1};
+use tracing::{debug, warn};
+
+//consecutive failures after which a web seed is no longer used
+pub const MAX_WEBSEED_FAILURES: u32 = 3;
+
+//an HTTP server from url-list serving the torrent data (BEP 19)
+#[derive(Debug)]
+struct WebSeed {
+    url: String,              //base URL from url-list
+    transport: HttpTransport, //connection to the server, reused between requests
+    failures: u32,            //consecutive failed or bad downloads
+}
+
+//part of a piece that lies within a single file
+#[derive(Debug)]
+struct Segment<'a> {
+    path: Option<&'a [&'a [u8]]>, //path components of the file, None for single file torrents
+    offset: u64,                  //offset of the segment within the file
+    length: u64,                  //length of the segment in bytes
+}
+
+impl WebSeed {
+    //check if the web seed failed too often to be used again
+    fn is_disabled(&self) -> bool {
+        self.failures >= MAX_WEBSEED_FAILURES
+    }
+
+    //get the URL of a file of the torrent
+    //a base URL ending in '/' names a directory, so the torrent name and file path are appended
+    //multi file torrents always append them, single file torrents may point at the file itself
+    fn file_url(&self, name: &[u8], path: Option<&[&[u8]]>) -> Result<Uri, WebSeedError> {
+        let mut url = self.url.clone();
+        match path {
+            None if !url.ends_with('/') => {}
+            None => url.push_str(&urlencode::encode(name)),
+            Some(path) => {
+                if !url.ends_with('/') {
+                    url.push('/');
+                }
+                url.push_str(&urlencode::encode(name));
+                for component in path {
+                    url.push('/');
+                    url.push_str(&urlencode::encode(component));
+                }
+            }
+        }
+
+        Uri::try_from(url.as_str()).map_err(|_| WebSeedError::InvalidUrl(url))
+    }
+
+    //download piece data segment by segment
+    async fn fetch(
+        &mut self,
+        name: &[u8],
+        segments: &[Segment<'_>],
+    ) -> Result<Vec<u8>, WebSeedError> {
+        let mut data = Vec::new();
+        for segment in segments {
+            let url = self.file_url(name, segment.path)?;
+            let end = segment.offset + segment.length - 1;
+            data.extend(self.transport.get_range(url, segment.offset, end).await?);
+        }
+        Ok(data)
+    }
+}
+
+//downloads pieces from the web seeds of a torrent
+#[derive(Debug)]
+pub struct WebSeeds {
+    seeds: Vec<WebSeed>, //web seeds in url-list order
+}
+
+impl WebSeeds {
+    //create a downloader for the url-list of a torrent, URLs that are not UTF-8 are skipped
+    pub fn new(urls: &[&[u8]], config: TrackerConfig) -> Self {
+        Self {
+            seeds: urls
+                .iter()
+                .filter_map(|url| std::str::from_utf8(url).ok())
+                .map(|url| WebSeed {
+                    url: url.to_string(),
+                    transport: HttpTransport::new(config.clone()),
+                    failures: 0,
+                })
+                .collect(),
+        }
+    }
+
+    //get the number of web seeds that are still used
+    pub fn active(&self) -> usize {
+        self.seeds.iter().filter(|seed| !seed.is_disabled()).count()
+    }
+
+    //download and verify a piece, trying every usable web seed in turn
+    //a web seed that fails or sends bad data too often in a row is disabled
+    pub async fn fetch_piece(
+        &mut self,
+        info: &Info<'_>,
+        index: usize,
+    ) -> Result<Vec<u8>, WebSeedError> {
+        let expected = info
+            .piece_hash(index)
+            .ok_or(WebSeedError::NoWebSeed(index))?;
+        let segments = segments(info, index);
+
+        for seed in self.seeds.iter_mut().filter(|seed| !seed.is_disabled()) {
+            match seed.fetch(info.raw_name, &segments).await {
+                Ok(data) if Sha1::digest(&data).as_slice() == expected => {
+                    debug!(url = %seed.url, piece = index, "piece verified");
+                    seed.failures = 0;
+                    return Ok(data);
+                }
+                Ok(_) => {
+                    warn!(url = %seed.url, error = %WebSeedError::HashMismatch(index), "web seed failed");
+                    seed.failures += 1;
+                }
+                Err(e) => {
+                    warn!(url = %seed.url, error = %e, "web seed failed");
+                    seed.failures += 1;
+                }
+            }
+        }
+
+        Err(WebSeedError::NoWebSeed(index))
+    }
+
+    //download every piece missing from storage, returns the number of pieces downloaded
+    //pieces go through the same hash check as data from peers before they are written
+    pub async fn download<S: Storage>(
+        &mut self,
+        info: &Info<'_>,
+        storage: &mut S,
+    ) -> Result<usize, WebSeedError> {
+        self.download_with_progress(info, storage, |_, _| {}).await
+    }
+
+    //download like download, calling progress with the index and length of every piece written
+    pub async fn download_with_progress<S: Storage>(
+        &mut self,
+        info: &Info<'_>,
+        storage: &mut S,
+        mut progress: impl FnMut(usize, u64),
+    ) -> Result<usize, WebSeedError> {
+        let mut downloaded = 0;
+        for index in 0..info.num_pieces() {
+            if storage.have(index) {
+                continue;
+            }
+            let data = self.fetch_piece(info, index).await?;
+            storage.write_block(index, 0, &data)?;
+            progress(index, data.len() as u64);
+            downloaded += 1;
+        }
+        storage.flush()?;
+
+        Ok(downloaded)
+    }
+}
+
+//split piece index into the parts of the files it covers
+fn segments<'a>(info: &'a Info<'_>, index: usize) -> Vec<Segment<'a>> {
+    let files: Vec<(Option<&[&[u8]]>, u64)> = match &info.file_details {
+        FileDetails::SingleFile { length } => vec![(None, *length)],
+        FileDetails::MultiFile { files } => files
+            .iter()
+            .map(|file| (Some(file.path.as_slice()), file.length))
+            .collect(),
+    };
+    let piece_start = index as u64 * info.piece_length;
+    let piece_end = (piece_start + info.piece_length).min(info.total_length());
+
+    let mut segments = Vec::new();
+    let mut file_start = 0;
+    for (path, length) in files {
+        let file_end = file_start + length;
+        let start = piece_start.max(file_start);
+        let end = piece_end.min(file_end);
+        if start < end {
+            segments.push(Segment {
+                path,
+                offset: start - file_start,
+                length: end - start,
+            });
+        }
+        file_start = file_end;
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::storage::memory_storage::MemoryStorage;
+    use crate::core::torrent::torrent::TorrentFile;
+    use crate::util::test_server::file_server;
+
+    use std::collections::HashMap;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    //torrent named name holding data in pieces of 16 bytes
+    //files are given as path components and length, None makes a single file torrent
+    fn torrent(name: &str, files: Option<&[(&[&str], u64)]>, data: &[u8]) -> TorrentFile {
+        let mut bytes = b"d4:infod".to_vec();
+        match files {
+            None => bytes.extend(format!("6:lengthi{}e", data.len()).as_bytes()),
+            Some(files) => {
+                bytes.extend(b"5:filesl");
+                for (path, length) in files {
+                    bytes.extend(format!("d6:lengthi{}e4:pathl", length).as_bytes());
+                    for component in *path {
+                        bytes.extend(format!("{}:{}", component.len(), component).as_bytes());
+                    }
+                    bytes.extend(b"ee");
+                }
+                bytes.push(b'e');
+            }
+        }
+        let pieces: Vec<u8> = data.chunks(16).flat_map(Sha1::digest).collect();
+        bytes.extend(format!("4:name{}:{}12:piece lengthi16e", name.len(), name).as_bytes());
+        bytes.extend(format!("6:pieces{}:", pieces.len()).as_bytes());
+        bytes.extend(pieces);
+        bytes.extend(b"ee");
+        TorrentFile::from_bytes(bytes).unwrap()
+    }
+
+    //read every piece back from storage
+    fn contents(storage: &mut MemoryStorage, info: &Info<'_>) -> Vec<u8> {
+        (0..info.raw_pieces.len() / 20)
+            .flat_map(|index| {
+                let length = segments(info, index).iter().map(|s| s.length).sum::<u64>() as usize;
+                storage.read_block(index, 0, length).unwrap()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn multi_file_pieces_are_fetched_by_range() {
+        let data: Vec<u8> = (0..100).collect();
+        let files: &[(&[&str], u64)] = &[(&["dir", "a b"], 30), (&["empty"], 0), (&["c"], 70)];
+        let torrent = torrent("tor", Some(files), &data);
+        let info = &torrent.torrent().info;
+        let served = HashMap::from([
+            ("/seed/tor/dir/a%20b".to_string(), data[..30].to_vec()),
+            ("/seed/tor/c".to_string(), data[30..].to_vec()),
+        ]);
+        let (good, requests) = file_server(served, true).await;
+        let (empty, empty_requests) = file_server(HashMap::new(), true).await;
+        let urls = [
+            format!("http://127.0.0.1:{}/seed/", empty),
+            format!("http://127.0.0.1:{}/seed", good),
+        ];
+        let urls: Vec<&[u8]> = urls.iter().map(|url| url.as_bytes()).collect();
+        let mut seeds = WebSeeds::new(&urls, TrackerConfig::default());
+
+        let mut storage = MemoryStorage::empty(info);
+        assert_eq!(seeds.download(info, &mut storage).await.unwrap(), 7);
+        assert_eq!(contents(&mut storage, info), data);
+        //the seed without the files was given up on
+        assert_eq!(
+            empty_requests.lock().unwrap().len(),
+            MAX_WEBSEED_FAILURES as usize
+        );
+        assert_eq!(seeds.active(), 1);
+        //piece 1 spans both files, with the empty one in between
+        let requests = requests.lock().unwrap().clone();
+        let asked = |path: &str, range| {
+            requests
+                .iter()
+                .any(|request| request.path == path && request.range == Some(range))
+        };
+        assert!(asked("/seed/tor/dir/a%20b", (16, 29)));
+        assert!(asked("/seed/tor/c", (0, 1)));
+        assert!(
+            !requests
+                .iter()
+                .any(|request| request.path.ends_with("empty"))
+        );
+        drop(requests);
+
+        assert_eq!(seeds.download(info, &mut storage).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn single_file_seeds_and_bad_data() {
+        let data: Vec<u8> = (0..40).collect();
+        let torrent = torrent("f.bin", None, &data);
+        let info = &torrent.torrent().info;
+
+        //a server ignoring Range sends the whole file for every piece
+        let served = HashMap::from([("/dir/f.bin".to_string(), data.clone())]);
+        let (port, _) = file_server(served, false).await;
+        let url = format!("http://127.0.0.1:{}/dir/", port);
+        let mut seeds = WebSeeds::new(&[url.as_bytes()], TrackerConfig::default());
+        let mut storage = MemoryStorage::empty(info);
+        assert_eq!(seeds.download(info, &mut storage).await.unwrap(), 3);
+        assert_eq!(contents(&mut storage, info), data);
+
+        //a URL not ending in '/' names the file itself
+        let mut corrupt = data.clone();
+        corrupt[0] ^= 1;
+        let (port, _) = file_server(HashMap::from([("/x".to_string(), corrupt)]), true).await;
+        let url = format!("http://127.0.0.1:{}/x", port);
+        let mut seeds = WebSeeds::new(&[url.as_bytes()], TrackerConfig::default());
+        for _ in 0..MAX_WEBSEED_FAILURES {
+            let err = seeds.fetch_piece(info, 0).await.unwrap_err();
+            assert!(matches!(err, WebSeedError::NoWebSeed(0)));
+        }
+        assert_eq!(seeds.active(), 0);
+        let mut storage = MemoryStorage::empty(info);
+        assert!(seeds.download(info, &mut storage).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn verified_pieces_and_failures_are_logged() {
+        //log lines written while the guard below is held
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let data: Vec<u8> = (0..20).collect();
+        let torrent = torrent("f", None, &data);
+        let info = &torrent.torrent().info;
+        let mut corrupt = data.clone();
+        corrupt[0] ^= 1;
+        let (bad, _) = file_server(HashMap::from([("/f".to_string(), corrupt)]), true).await;
+        let (good, _) = file_server(HashMap::from([("/f".to_string(), data)]), true).await;
+        let urls = [
+            format!("http://127.0.0.1:{}/f", bad),
+            format!("http://127.0.0.1:{}/f", good),
+        ];
+        let urls: Vec<&[u8]> = urls.iter().map(|url| url.as_bytes()).collect();
+        let mut seeds = WebSeeds::new(&urls, TrackerConfig::default());
+        seeds.fetch_piece(info, 0).await.unwrap();
+
+        let log = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", log);
+        assert!(lines[0].contains("WARN") && lines[0].contains("web seed failed"));
+        assert!(lines[0].contains(&format!("url=http://127.0.0.1:{}/f", bad)));
+        assert!(lines[1].contains("DEBUG") && lines[1].contains("piece verified"));
+        assert!(lines[1].contains("piece=0"), "{}", lines[1]);
+    }
+}
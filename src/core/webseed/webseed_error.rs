@@ -0,0 +1,26 @@
+use crate::core::storage::storage_error::StorageError;
+use crate::core::tracker::tracker_error::TrackerError;
+
+use thiserror::Error;
+
+//custom error enum for web seed downloads
+#[derive(Error, Debug)]
+pub enum WebSeedError {
+    #[error("Invalid web seed URL: {0}")]
+    InvalidUrl(String),
+
+    //request to the web seed failed, including 404 and other HTTP errors
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] TrackerError),
+
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+
+    //data sent by the web seed does not match the piece hash
+    #[error("Piece {0} failed the hash check")]
+    HashMismatch(usize),
+
+    //every web seed failed or is disabled
+    #[error("No web seed could serve piece {0}")]
+    NoWebSeed(usize),
+}
@@ -0,0 +1,11 @@
+//BitTorrent library behind the motteseed command line client
+//core holds the protocol and the session that runs torrents, util the encodings they share
+pub mod core;
+pub mod util;
+
+//the types most programs start from
+pub use crate::core::session::session::Session;
+pub use crate::core::session::session_config::SessionConfig;
+pub use crate::core::session::torrent_handle::TorrentHandle;
+pub use crate::core::torrent::torrent::TorrentFile;
+pub use crate::core::tracker::tracker::Tracker;
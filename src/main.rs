@@ -1,30 +1,162 @@
-mod core;
-mod util;
+mod cli;
 
-use core::peer_id::get_peer_id;
-use core::torrent::torrent::TorrentFile;
+use cli::args::{Cli, Command};
+use cli::cli_error::{CliError, EXIT_INTERRUPTED, EXIT_USAGE, report};
+use cli::commands::{create, info, scrape, verify};
+use cli::config_file::ConfigFile;
+use cli::download::{Output, daemon, download};
+use cli::logging;
+use motteseed::core::session::session::Session;
+use motteseed::core::session::session_config::SessionConfig;
 
-use core::tracker::tracker::{Tracker, TrackerRequest};
-use std::env;
-use std::path::Path;
+use clap::Parser;
+use futures_util::future::join_all;
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use std::io;
+use std::process::{self, ExitCode};
+use std::sync::Arc;
+use tokio::signal;
+use tracing::warn;
 
 #[tokio::main]
-async fn main() {
-    let args: Vec<String> = env::args().collect();
-    let file_path = args[1].clone();
-    let torrent_file = TorrentFile::from_file(&Path::new(&file_path)).unwrap();
-    let peer_id = &get_peer_id();
-    let tracker_request = TrackerRequest::new(
-        torrent_file.torrent.announce,
-        &torrent_file.torrent.info_hash,
-        peer_id,
-        6881,
-        0,
-        0,
-        0,
-        true,
-    )
-    .unwrap();
-    let tracker = Tracker::new(&tracker_request).await.unwrap();
-    println!("{:?}", tracker);
+async fn main() -> ExitCode {
+    //unknown flags or missing arguments print the usage, --help and --version are not failures
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            if e.use_stderr() {
+                return ExitCode::from(EXIT_USAGE);
+            }
+            return ExitCode::SUCCESS;
+        }
+    };
+    let verbose = cli.verbose;
+    let config_path = cli.config.clone();
+    let command = cli.into_command();
+    //bars are redrawn in place, which only works on a terminal
+    let bars = match &command {
+        Command::Download(args) if args.draws_bars() => {
+            Some(MultiProgress::with_draw_target(ProgressDrawTarget::stdout()))
+        }
+        _ => None,
+    };
+    let debug_responses = matches!(&command, Command::Download(args) if args.debug_response);
+    logging::init(verbose, debug_responses, bars.clone());
+
+    //settings come from the flags, then the config file, then the defaults
+    let load_config = || ConfigFile::load(config_path.as_deref());
+
+    match command {
+        Command::Download(args) => {
+            let session =
+                async { start_session(args.session_config(load_config()?.session_config())).await };
+            let session = match session.await {
+                Ok(session) => session,
+                Err(e) => return report(Err(e)),
+            };
+            //torrents run side by side, one that fails does not stop the others
+            let output = Output {
+                bars: bars.as_ref(),
+                tagged: args.paths.len() > 1,
+                #[cfg(feature = "serde")]
+                json: args.json,
+            };
+            let results = join_all(
+                args.paths
+                    .iter()
+                    .map(|path| async { report(download(path, &args, &session, output).await) }),
+            )
+            .await;
+            //the first failure in command line order sets the exit code
+            results
+                .into_iter()
+                .find(|code| *code != ExitCode::SUCCESS)
+                .unwrap_or(ExitCode::SUCCESS)
+        }
+        Command::Info(args) => report(info(&args)),
+        Command::Verify(args) => verify(&args).unwrap_or_else(|e| report(Err(e))),
+        Command::Scrape(args) => match load_config() {
+            Ok(file) => scrape(&args, &file.session_config().tracker)
+                .await
+                .unwrap_or_else(|e| report(Err(e))),
+            Err(e) => report(Err(e)),
+        },
+        //create a torrent instead of downloading
+        Command::Create(args) => report(create(&args)),
+        Command::Daemon(args) => {
+            let session =
+                async { start_session(args.session_config(load_config()?.session_config())).await };
+            let session = match session.await {
+                Ok(session) => session,
+                Err(e) => return report(Err(e)),
+            };
+            report(daemon(&args, session).await)
+        }
+    }
+}
+
+//start a session listening for peers with config, which shuts down on Ctrl-C
+async fn start_session(config: SessionConfig) -> Result<Arc<Session>, CliError> {
+    let session = Arc::new(Session::bind(config).await?);
+    tokio::spawn(stop_on_signal(session.clone()));
+    #[cfg(unix)]
+    tokio::spawn(pause_on_signal(session.clone()));
+    Ok(session)
+}
+
+//pause every torrent of the session on SIGUSR1 and resume them on SIGUSR2
+#[cfg(unix)]
+async fn pause_on_signal(session: Arc<Session>) {
+    use signal::unix::{SignalKind, signal};
+    let (Ok(mut pause), Ok(mut resume)) = (
+        signal(SignalKind::user_defined1()),
+        signal(SignalKind::user_defined2()),
+    ) else {
+        warn!("cannot listen for SIGUSR1 and SIGUSR2, torrents can not be paused");
+        return;
+    };
+    loop {
+        let pausing = tokio::select! {
+            Some(()) = pause.recv() => true,
+            Some(()) = resume.recv() => false,
+            else => return,
+        };
+        let torrents = session.list();
+        for torrent in &torrents {
+            match pausing {
+                true => torrent.pause(),
+                false => torrent.resume(),
+            }
+        }
+        tracing::info!(torrents = torrents.len(), pausing, "pause signal");
+    }
+}
+
+//shut the session down on Ctrl-C or SIGTERM, so torrents save their state and leave their swarms
+//the torrents then end without an error, a second signal exits at once
+async fn stop_on_signal(session: Arc<Session>) {
+    if let Err(e) = stop_requested().await {
+        warn!(error = %e, "cannot listen for Ctrl-C, stopping only works by killing the process");
+        return;
+    }
+    warn!("stopping, press Ctrl-C again to quit at once");
+    tokio::select! {
+        _ = session.shutdown() => {}
+        _ = stop_requested() => process::exit(EXIT_INTERRUPTED.into()),
+    }
+}
+
+//wait for Ctrl-C, or for SIGTERM on unix
+async fn stop_requested() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    signal::ctrl_c().await
 }
@@ -1,76 +1,348 @@
-use crate::util::bencode::bencode_decodable_error::BencodeDecodableError;
+use crate::util::bencode::bencode_decodable_error::{BencodeDecodableError, DecodeContext};
+use crate::util::bencode::parser::{Dict, Node, Value};
 
-use bencode::Bencode;
-use bencode::util::ByteString;
 use std::borrow::Cow;
-use std::collections::BTreeMap;
 
 //a trait for decoding Bencode data into Rust types
 pub trait BencodeDecodable<'a>: Sized {
     //decode Bencode into Self
-    fn decode(b: &'a Bencode) -> Result<Self, BencodeDecodableError>;
-
-    //extract u64 value from a Bencode Number variant
-    fn get_u64(b: &'a Bencode) -> Result<u64, BencodeDecodableError> {
-        match b {
-            Bencode::Number(num) => Ok((*num)
-                .try_into()
-                .map_err(|_| BencodeDecodableError::WrongType("Expected a Number".into()))?),
-            _ => Err(BencodeDecodableError::WrongType("Expected a Number".into())),
+    //the node may be dropped afterwards, only the input it was parsed from has to outlive Self
+    fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError>;
+
+    //extract signed i64 value from an Int value
+    fn get_i64(b: &Node<'a>) -> Result<i64, BencodeDecodableError> {
+        match &b.value {
+            Value::Int(num) => Ok(*num),
+            _ => Err(wrong_type("a Number", b)),
         }
     }
 
-    //extract raw bytes from a Bencode ByteString variant
-    fn get_str(b: &'a Bencode) -> Result<&'a [u8], BencodeDecodableError> {
-        match b {
-            Bencode::ByteString(bytes) => Ok(bytes),
-            _ => Err(BencodeDecodableError::WrongType(
-                "Expected a ByteString".into(),
-            )),
+    //extract u64 value from an Int value, negative numbers are rejected
+    fn get_u64(b: &Node<'a>) -> Result<u64, BencodeDecodableError> {
+        let num = Self::get_i64(b)?;
+        u64::try_from(num).map_err(|_| {
+            BencodeDecodableError::WrongType(format!("expected a non-negative number, got {}", num))
+        })
+    }
+
+    //extract raw bytes from a ByteStr value
+    fn get_str(b: &Node<'a>) -> Result<&'a [u8], BencodeDecodableError> {
+        match &b.value {
+            Value::ByteStr(bytes) => Ok(*bytes),
+            _ => Err(wrong_type("a ByteString", b)),
         }
     }
 
-    //extract string from a Bencode ByteString variant
-    fn get_string(b: &'a Bencode) -> Result<Cow<'a, str>, BencodeDecodableError> {
+    //extract string from a ByteStr value, invalid UTF-8 is replaced with U+FFFD
+    //only for text that is shown, not for values that have to round-trip
+    fn get_string(b: &Node<'a>) -> Result<Cow<'a, str>, BencodeDecodableError> {
         let bytes = Self::get_str(b)?;
         Ok(String::from_utf8_lossy(bytes))
     }
 
-    //extract dictionary from a Bencode Dict variant
-    fn get_struct(
-        b: &'a Bencode,
-    ) -> Result<&'a BTreeMap<ByteString, Bencode>, BencodeDecodableError> {
-        match b {
-            Bencode::Dict(dict_map) => Ok(dict_map),
-            _ => Err(BencodeDecodableError::WrongType(
-                "Expected a dictionary".into(),
-            )),
+    //extract string from a ByteStr value, failing on invalid UTF-8
+    //the error has the offset of the first invalid byte in the input
+    fn get_string_strict(b: &Node<'a>) -> Result<&'a str, BencodeDecodableError> {
+        let bytes = Self::get_str(b)?;
+        std::str::from_utf8(bytes).map_err(|e| {
+            //the contents are at the end of the value, after the length prefix
+            BencodeDecodableError::InvalidUtf8(b.span.end - bytes.len() + e.valid_up_to())
+        })
+    }
+
+    //extract dictionary from a Dict value
+    fn get_struct<'n>(b: &'n Node<'a>) -> Result<&'n Dict<'a>, BencodeDecodableError> {
+        match &b.value {
+            Value::Dict(dict_map) => Ok(dict_map),
+            _ => Err(wrong_type("a Dict", b)),
         }
     }
 
     //retrieve value from a Bencode dictionary by key
-    fn get_struct_value_from_bytestring(
-        key: &ByteString,
-        dict_map: &'a BTreeMap<ByteString, Bencode>,
-    ) -> Result<&'a Bencode, BencodeDecodableError> {
-        dict_map
-            .get(key)
+    fn get_struct_value<'n>(
+        key: &str,
+        dict_map: &'n Dict<'a>,
+    ) -> Result<&'n Node<'a>, BencodeDecodableError> {
+        Self::get_optional_struct_value(key, dict_map)
             .ok_or_else(|| BencodeDecodableError::KeyNotFound(format!("Key '{}' not found", key)))
     }
 
-    //retrieve value from a Bencode dictionary by key
-    fn get_struct_value(
+    //retrieve an optional value from a Bencode dictionary by key, None if the key is missing
+    fn get_optional_struct_value<'n>(key: &str, dict_map: &'n Dict<'a>) -> Option<&'n Node<'a>> {
+        dict_map.get(key.as_bytes())
+    }
+
+    //extract an optional u64 value, a present value of the wrong type is still an error
+    fn get_optional_u64(
+        key: &str,
+        dict_map: &Dict<'a>,
+    ) -> Result<Option<u64>, BencodeDecodableError> {
+        Self::get_optional_struct_value(key, dict_map)
+            .map(Self::get_u64)
+            .transpose()
+            .context(key)
+    }
+
+    //extract an optional i64 value, a present value of the wrong type is still an error
+    fn get_optional_i64(
+        key: &str,
+        dict_map: &Dict<'a>,
+    ) -> Result<Option<i64>, BencodeDecodableError> {
+        Self::get_optional_struct_value(key, dict_map)
+            .map(Self::get_i64)
+            .transpose()
+            .context(key)
+    }
+
+    //extract optional raw bytes, a present value of the wrong type is still an error
+    fn get_optional_str(
         key: &str,
-        dict_map: &'a BTreeMap<ByteString, Bencode>,
-    ) -> Result<&'a Bencode, BencodeDecodableError> {
-        Self::get_struct_value_from_bytestring(&ByteString::from_str(key), dict_map)
+        dict_map: &Dict<'a>,
+    ) -> Result<Option<&'a [u8]>, BencodeDecodableError> {
+        Self::get_optional_struct_value(key, dict_map)
+            .map(Self::get_str)
+            .transpose()
+            .context(key)
+    }
+
+    //extract an optional string, a present value of the wrong type is still an error
+    fn get_optional_string(
+        key: &str,
+        dict_map: &Dict<'a>,
+    ) -> Result<Option<Cow<'a, str>>, BencodeDecodableError> {
+        Self::get_optional_struct_value(key, dict_map)
+            .map(Self::get_string)
+            .transpose()
+            .context(key)
+    }
+
+    //extract an optional list, a present value of the wrong type is still an error
+    fn get_optional_list<'n>(
+        key: &str,
+        dict_map: &'n Dict<'a>,
+    ) -> Result<Option<&'n [Node<'a>]>, BencodeDecodableError> {
+        Self::get_optional_struct_value(key, dict_map)
+            .map(Self::get_list)
+            .transpose()
+            .context(key)
+    }
+
+    //extract the length key of a file dict
+    fn get_length(dict_map: &Dict<'a>) -> Result<u64, BencodeDecodableError> {
+        Self::get_u64(Self::get_struct_value("length", dict_map)?).context("length")
+    }
+
+    //extract the path key of a file dict, a list of byte string components
+    fn get_path(dict_map: &Dict<'a>) -> Result<Vec<&'a [u8]>, BencodeDecodableError> {
+        Self::decode_vec_with(Self::get_struct_value("path", dict_map)?, Self::get_str)
+            .context("path")
+    }
+
+    //decode every item of a List value, errors name the index of the item they happened in
+    fn decode_vec<T: BencodeDecodable<'a>>(b: &Node<'a>) -> Result<Vec<T>, BencodeDecodableError> {
+        Self::decode_vec_with(b, T::decode)
+    }
+
+    //decode every item of a List value with decode_item, for items without their own impl
+    //such as plain byte strings
+    fn decode_vec_with<T>(
+        b: &Node<'a>,
+        mut decode_item: impl FnMut(&Node<'a>) -> Result<T, BencodeDecodableError>,
+    ) -> Result<Vec<T>, BencodeDecodableError> {
+        Self::get_list(b)?
+            .iter()
+            .enumerate()
+            .map(|(index, item)| decode_item(item).context_index(index))
+            .collect()
+    }
+
+    //extracts a list from a List value
+    fn get_list<'n>(b: &'n Node<'a>) -> Result<&'n [Node<'a>], BencodeDecodableError> {
+        match &b.value {
+            Value::List(list) => Ok(list),
+            _ => Err(wrong_type("a List", b)),
+        }
     }
+}
+
+//error for a value that is not of the expected type
+fn wrong_type(expected: &str, found: &Node) -> BencodeDecodableError {
+    let found = match found.value {
+        Value::Int(_) => "Number",
+        Value::ByteStr(_) => "ByteString",
+        Value::List(_) => "List",
+        Value::Dict(_) => "Dict",
+    };
+    BencodeDecodableError::WrongType(format!("expected {}, found {}", expected, found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::bencode::parser::parse;
 
-    //extracts a list from a Bencode List variant
-    fn get_list(b: &'a Bencode) -> Result<&'a Vec<Bencode>, BencodeDecodableError> {
-        match b {
-            Bencode::List(list) => Ok(list),
-            _ => Err(BencodeDecodableError::WrongType("Expected a list".into())),
+    //decodes anything, to reach the provided helpers
+    struct Any;
+
+    impl<'a> BencodeDecodable<'a> for Any {
+        fn decode(_: &Node<'a>) -> Result<Self, BencodeDecodableError> {
+            Ok(Any)
         }
     }
+
+    //check that a helper failed on a value of the wrong type
+    fn is_wrong_type<T>(result: Result<T, BencodeDecodableError>) -> bool {
+        matches!(
+            result.map(|_| ()).unwrap_err().root_cause(),
+            BencodeDecodableError::WrongType(_)
+        )
+    }
+
+    #[test]
+    fn optional_keys_may_be_missing_but_not_mistyped() {
+        let node = parse(b"d1:ai5e1:b2:hi1:cli1ee1:di-1ee").unwrap();
+        let Value::Dict(dict) = &node.value else {
+            panic!("{:?}", node.value);
+        };
+        assert!(Any::get_optional_struct_value("a", dict).is_some());
+        assert!(Any::get_optional_struct_value("z", dict).is_none());
+
+        assert_eq!(Any::get_optional_u64("a", dict).unwrap(), Some(5));
+        assert_eq!(Any::get_optional_u64("z", dict).unwrap(), None);
+        assert!(is_wrong_type(Any::get_optional_u64("b", dict)));
+        assert!(is_wrong_type(Any::get_optional_u64("d", dict)));
+
+        assert_eq!(Any::get_optional_str("b", dict).unwrap(), Some(&b"hi"[..]));
+        assert_eq!(Any::get_optional_str("z", dict).unwrap(), None);
+        assert!(is_wrong_type(Any::get_optional_str("a", dict)));
+        assert_eq!(
+            Any::get_optional_string("b", dict).unwrap().as_deref(),
+            Some("hi")
+        );
+        assert!(is_wrong_type(Any::get_optional_string("c", dict)));
+
+        let list = Any::get_optional_list("c", dict).unwrap();
+        assert_eq!(list.map(<[_]>::len), Some(1));
+        assert_eq!(Any::get_optional_list("z", dict).unwrap(), None);
+        assert!(is_wrong_type(Any::get_optional_list("b", dict)));
+    }
+
+    #[test]
+    fn signed_and_unsigned_numbers() {
+        //node holding value, not backed by any input
+        let node = |value| Node {
+            value,
+            span: 0..0,
+            raw: b"",
+        };
+        let int = |n: i64| node(Value::Int(n));
+        assert_eq!(Any::get_i64(&int(-5)).unwrap(), -5);
+        assert_eq!(Any::get_i64(&int(i64::MIN)).unwrap(), i64::MIN);
+        assert!(is_wrong_type(Any::get_i64(&node(Value::ByteStr(b"5")))));
+
+        assert_eq!(Any::get_u64(&int(0)).unwrap(), 0);
+        assert_eq!(Any::get_u64(&int(i64::MAX)).unwrap(), i64::MAX as u64);
+        let err = Any::get_u64(&int(-5)).unwrap_err();
+        assert_eq!(err.to_string(), "expected a non-negative number, got -5");
+        let err = Any::get_u64(&node(Value::List(vec![]))).unwrap_err();
+        assert_eq!(err.to_string(), "expected a Number, found List");
+
+        let node = parse(b"d1:ai-3e1:b1:xe").unwrap();
+        let Value::Dict(dict) = &node.value else {
+            panic!("{:?}", node.value);
+        };
+        assert_eq!(Any::get_optional_i64("a", dict).unwrap(), Some(-3));
+        assert_eq!(Any::get_optional_i64("z", dict).unwrap(), None);
+        assert!(is_wrong_type(Any::get_optional_i64("b", dict)));
+    }
+
+    #[test]
+    fn file_length_and_path() {
+        //dict the file entry node holds
+        let dict = |node| Any::get_struct(node).unwrap();
+
+        let node = parse(b"d6:lengthi7e4:pathl1:a2:bcee").unwrap();
+        assert_eq!(Any::get_length(dict(&node)).unwrap(), 7);
+        assert_eq!(Any::get_path(dict(&node)).unwrap(), vec![&b"a"[..], b"bc"]);
+
+        let node = parse(b"d6:length1:x4:pathl1:ai1eee").unwrap();
+        let err = Any::get_length(dict(&node)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "length: expected a Number, found ByteString"
+        );
+        let err = Any::get_path(dict(&node)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "path[1]: expected a ByteString, found Number"
+        );
+
+        let node = parse(b"de").unwrap();
+        assert!(matches!(
+            Any::get_length(dict(&node)),
+            Err(BencodeDecodableError::KeyNotFound(_))
+        ));
+        assert!(matches!(
+            Any::get_path(dict(&node)),
+            Err(BencodeDecodableError::KeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn strict_strings_point_at_the_bad_byte() {
+        //"café" in UTF-8 for a, in latin-1 for b
+        let node = parse(b"d1:a5:caf\xc3\xa91:b4:caf\xe91:ci1ee").unwrap();
+        let dict = Any::get_struct(&node).unwrap();
+        assert_eq!(
+            Any::get_string_strict(&dict[&b"a"[..]]).unwrap(),
+            "caf\u{e9}"
+        );
+        assert_eq!(Any::get_string(&dict[&b"b"[..]]).unwrap(), "caf\u{fffd}");
+        //the contents of b start at 16, its \xe9 is at 19
+        let err = Any::get_string_strict(&dict[&b"b"[..]]).unwrap_err();
+        assert!(matches!(err, BencodeDecodableError::InvalidUtf8(19)));
+        assert_eq!(err.to_string(), "Invalid UTF-8 at offset 19");
+        assert!(is_wrong_type(Any::get_string_strict(&dict[&b"c"[..]])));
+        assert_eq!(Any::get_string_strict(&parse(b"0:").unwrap()).unwrap(), "");
+    }
+
+    #[test]
+    fn list_items_are_decoded_in_order() {
+        //a Number value, decoded through its own impl
+        #[derive(Debug, PartialEq)]
+        struct Number(i64);
+
+        impl<'a> BencodeDecodable<'a> for Number {
+            fn decode(b: &Node<'a>) -> Result<Self, BencodeDecodableError> {
+                Ok(Number(Self::get_i64(b)?))
+            }
+        }
+
+        let node = parse(b"li1ei2ei3ee").unwrap();
+        let numbers: Vec<Number> = Any::decode_vec(&node).unwrap();
+        assert_eq!(numbers, [Number(1), Number(2), Number(3)]);
+        assert!(
+            Any::decode_vec::<Number>(&parse(b"le").unwrap())
+                .unwrap()
+                .is_empty()
+        );
+        let err = Any::decode_vec::<Number>(&parse(b"li1e1:xi3ee").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "[1]: expected a Number, found ByteString");
+        assert!(is_wrong_type(Any::decode_vec::<Number>(
+            &parse(b"i1e").unwrap()
+        )));
+
+        let node = parse(b"l1:a0:e").unwrap();
+        assert_eq!(
+            Any::decode_vec_with(&node, Any::get_str).unwrap(),
+            [&b"a"[..], b""]
+        );
+        let mut calls = 0;
+        let node = parse(b"li1ei2ee").unwrap();
+        let numbers = Any::decode_vec_with(&node, |item| {
+            calls += 1;
+            Any::get_i64(item)
+        });
+        assert_eq!((numbers.unwrap(), calls), (vec![1, 2], 2));
+    }
 }
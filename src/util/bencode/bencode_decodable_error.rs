@@ -1,3 +1,7 @@
+use crate::core::torrent::torrent_error::InvalidInfoError;
+use crate::util::errors::BencodeParseError;
+
+use std::fmt;
 use thiserror::Error;
 
 //custom error enum for reading torrent operations
@@ -7,10 +11,137 @@ pub enum BencodeDecodableError {
     #[error("Key not found: {0}")]
     KeyNotFound(String),
 
-    //wrong tyep error
-    #[error("Found wrong type: {0}")]
+    //wrong type error, naming the expected and the found type
+    #[error("{0}")]
     WrongType(String),
 
+    //byte string that has to be text but is not UTF-8, with the offset of the first bad byte
+    #[error("Invalid UTF-8 at offset {0}")]
+    InvalidUtf8(usize),
+
+    //bencode that could not be parsed
+    #[error("Malformed bencode: {0}")]
+    Malformed(#[from] BencodeParseError),
+
+    //info dict that decoded but fails a sanity check
+    #[error("Invalid info: {0}")]
+    InvalidInfo(#[from] InvalidInfoError),
+
+    //error inside a nested value, with the keys and indices leading to it
+    #[error("{path}: {source}")]
+    Context {
+        path: KeyPath,
+        source: Box<BencodeDecodableError>,
+    },
+
     #[error("Error: {0}")]
-    Other(#[from] Box<dyn std::error::Error>),
+    Other(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+//step from a bencode value into one nested in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Key(String),  //value of a dictionary key
+    Index(usize), //item of a list
+}
+
+//keys and indices from the outermost value to the one an error happened in
+//shown like info.files[2].length
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyPath(pub Vec<PathSegment>);
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) if i == 0 => write!(f, "{}", key)?,
+                PathSegment::Key(key) => write!(f, ".{}", key)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BencodeDecodableError {
+    //prepend a step to the path of the error, called as the error bubbles up to the root
+    fn within(self, segment: PathSegment) -> Self {
+        match self {
+            Self::Context { mut path, source } => {
+                path.0.insert(0, segment);
+                Self::Context { path, source }
+            }
+            error => Self::Context {
+                path: KeyPath(vec![segment]),
+                source: Box::new(error),
+            },
+        }
+    }
+
+    //path to the value the error happened in, empty for errors at the root
+    pub fn path(&self) -> KeyPath {
+        match self {
+            Self::Context { path, .. } => path.clone(),
+            _ => KeyPath::default(),
+        }
+    }
+
+    //the error without its path
+    pub fn root_cause(&self) -> &Self {
+        match self {
+            Self::Context { source, .. } => source,
+            error => error,
+        }
+    }
+}
+
+//add the location of a nested value to errors from decoding it
+pub trait DecodeContext<T> {
+    //the error happened in the value of key
+    fn context(self, key: &str) -> Result<T, BencodeDecodableError>;
+
+    //the error happened in item index of a list
+    fn context_index(self, index: usize) -> Result<T, BencodeDecodableError>;
+}
+
+impl<T> DecodeContext<T> for Result<T, BencodeDecodableError> {
+    fn context(self, key: &str) -> Result<T, BencodeDecodableError> {
+        self.map_err(|e| e.within(PathSegment::Key(key.to_string())))
+    }
+
+    fn context_index(self, index: usize) -> Result<T, BencodeDecodableError> {
+        self.map_err(|e| e.within(PathSegment::Index(index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_built_from_the_inside_out() {
+        let err: Result<(), _> = Err(BencodeDecodableError::WrongType("expected a Number".into()));
+        let err = err
+            .context("length")
+            .context_index(2)
+            .context("files")
+            .context("info");
+        let err = err.unwrap_err();
+        assert_eq!(err.path().to_string(), "info.files[2].length");
+        assert_eq!(err.to_string(), "info.files[2].length: expected a Number");
+        assert!(matches!(
+            err.root_cause(),
+            BencodeDecodableError::WrongType(_)
+        ));
+
+        //errors at the root have no path
+        let err = BencodeDecodableError::KeyNotFound("Key 'info' not found".into());
+        assert!(err.path().0.is_empty());
+        assert!(matches!(
+            err.root_cause(),
+            BencodeDecodableError::KeyNotFound(_)
+        ));
+        let path = KeyPath(vec![PathSegment::Index(0), PathSegment::Key("a".into())]);
+        assert_eq!(path.to_string(), "[0].a");
+    }
 }
@@ -0,0 +1,218 @@
+use crate::util::bencode::parser::{Node, Value};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+//a trait for encoding Rust types into Bencode data, the counterpart of BencodeDecodable
+//dictionary keys are written sorted by their raw bytes, so the output is canonical
+pub trait BencodeEncodable {
+    //write the Bencode of self into w
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()>;
+
+    //encode self into a new buffer
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_to(&mut buf)
+            .expect("writing to a Vec does not fail");
+        buf
+    }
+}
+
+//write an integer as i<decimal>e
+pub fn encode_int(w: &mut dyn Write, n: impl itoa::Integer) -> io::Result<()> {
+    w.write_all(b"i")?;
+    w.write_all(itoa::Buffer::new().format(n).as_bytes())?;
+    w.write_all(b"e")
+}
+
+//write a byte string as <length>:<bytes>
+pub fn encode_bytes(w: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+    w.write_all(itoa::Buffer::new().format(bytes.len()).as_bytes())?;
+    w.write_all(b":")?;
+    w.write_all(bytes)
+}
+
+//write a list of items as l<items>e
+pub fn encode_list<'a, T: BencodeEncodable + 'a>(
+    w: &mut dyn Write,
+    items: impl IntoIterator<Item = &'a T>,
+) -> io::Result<()> {
+    w.write_all(b"l")?;
+    for item in items {
+        item.encode_to(w)?;
+    }
+    w.write_all(b"e")
+}
+
+//write a dictionary as d<key><value>...e, sorting the keys by their raw bytes
+pub fn encode_dict<'a>(
+    w: &mut dyn Write,
+    entries: impl IntoIterator<Item = (&'a [u8], &'a dyn BencodeEncodable)>,
+) -> io::Result<()> {
+    let mut entries: Vec<_> = entries.into_iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+
+    w.write_all(b"d")?;
+    for (key, value) in entries {
+        encode_bytes(w, key)?;
+        value.encode_to(w)?;
+    }
+    w.write_all(b"e")
+}
+
+//integers, u8 and i8 are left out so that byte slices encode as byte strings
+macro_rules! impl_int {
+    ($($t:ty),*) => {
+        $(impl BencodeEncodable for $t {
+            fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+                encode_int(w, *self)
+            }
+        })*
+    };
+}
+
+impl_int!(i16, i32, i64, isize, u16, u32, u64, usize);
+
+impl BencodeEncodable for [u8] {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_bytes(w, self)
+    }
+}
+
+impl BencodeEncodable for Vec<u8> {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_bytes(w, self)
+    }
+}
+
+impl<const N: usize> BencodeEncodable for [u8; N] {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_bytes(w, self)
+    }
+}
+
+impl BencodeEncodable for str {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_bytes(w, self.as_bytes())
+    }
+}
+
+impl BencodeEncodable for String {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_bytes(w, self.as_bytes())
+    }
+}
+
+impl<T: BencodeEncodable + ?Sized> BencodeEncodable for &T {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        (**self).encode_to(w)
+    }
+}
+
+impl<T: BencodeEncodable> BencodeEncodable for [T] {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_list(w, self)
+    }
+}
+
+impl<T: BencodeEncodable> BencodeEncodable for Vec<T> {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_list(w, self)
+    }
+}
+
+impl<K: AsRef<[u8]>, V: BencodeEncodable> BencodeEncodable for BTreeMap<K, V> {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        //K may order differently than its bytes, so the keys are sorted again
+        encode_dict(
+            w,
+            self.iter()
+                .map(|(key, value)| (key.as_ref(), value as &dyn BencodeEncodable)),
+        )
+    }
+}
+
+impl<K: AsRef<[u8]>, V: BencodeEncodable, S> BencodeEncodable for HashMap<K, V, S> {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        encode_dict(
+            w,
+            self.iter()
+                .map(|(key, value)| (key.as_ref(), value as &dyn BencodeEncodable)),
+        )
+    }
+}
+
+//parsed values, dictionaries come out sorted whatever the input order
+impl BencodeEncodable for Value<'_> {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        match self {
+            Value::Int(n) => encode_int(w, *n),
+            Value::ByteStr(bytes) => encode_bytes(w, bytes),
+            Value::List(list) => encode_list(w, list),
+            Value::Dict(dict) => encode_dict(
+                w,
+                dict.iter()
+                    .map(|(key, value)| (*key, value as &dyn BencodeEncodable)),
+            ),
+        }
+    }
+}
+
+//re-encodes the value rather than copying raw, so the output is canonical
+impl BencodeEncodable for Node<'_> {
+    fn encode_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        self.value.encode_to(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitives_and_lists() {
+        assert_eq!(42u64.encode(), b"i42e");
+        assert_eq!((-7i64).encode(), b"i-7e");
+        assert_eq!(0i32.encode(), b"i0e");
+        assert_eq!(u64::MAX.encode(), b"i18446744073709551615e");
+        assert_eq!("spam".encode(), b"4:spam");
+        assert_eq!(String::new().encode(), b"0:");
+        assert_eq!(b"ab"[..].encode(), b"2:ab");
+        assert_eq!(b"ab".encode(), b"2:ab");
+        //byte vectors are strings, other vectors lists
+        assert_eq!(vec![1u8, 2].encode(), b"2:\x01\x02");
+        assert_eq!(vec![1u32, 2].encode(), b"li1ei2ee");
+        assert_eq!(vec!["a", "bc"].encode(), b"l1:a2:bce");
+        assert_eq!(Vec::<u32>::new().encode(), b"le");
+        assert_eq!(vec![vec![1u16], vec![]].encode(), b"lli1eelee");
+
+        let mut w = Vec::new();
+        42u64.encode_to(&mut w).unwrap();
+        "x".encode_to(&mut w).unwrap();
+        assert_eq!(w, b"i42e1:x");
+    }
+
+    #[test]
+    fn dict_keys_are_sorted_by_raw_bytes() {
+        let keys = ["b", "a", "ab", "", "B", "a\u{e9}", "aa", "\u{7f}"];
+        let map: HashMap<String, usize> = keys.iter().map(|k| (k.to_string(), k.len())).collect();
+        assert_eq!(
+            map.encode(),
+            "d0:i0e1:Bi1e1:ai1e2:aai2e2:abi2e3:a\u{e9}i3e1:bi1e1:\u{7f}i1ee".as_bytes()
+        );
+
+        let raw: BTreeMap<Vec<u8>, &str> = BTreeMap::from([
+            (vec![0xff], "x"),
+            (vec![0x00, 0x01], "y"),
+            (vec![0x00], "z"),
+        ]);
+        assert_eq!(raw.encode(), b"d1:\x001:z2:\x00\x011:y1:\xff1:xe");
+
+        //entries of different types are sorted too
+        let entries: [(&[u8], &dyn BencodeEncodable); 3] =
+            [(b"zz", &1u32), (b"a", &"v"), (b"m", &vec![2u32])];
+        let mut w = Vec::new();
+        encode_dict(&mut w, entries).unwrap();
+        assert_eq!(w, b"d1:a1:v1:mli2ee2:zzi1ee");
+    }
+}
@@ -1,2 +1,8 @@
 pub mod bencode_decodable;
 pub mod bencode_decodable_error;
+pub mod bencode_encodable;
+pub mod parser;
+pub mod pretty;
+pub mod span;
+
+pub use pretty::pretty;
@@ -0,0 +1,615 @@
+use crate::util::errors::{BencodeParseError, ParseLimit};
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+//limits for untrusted network input such as tracker responses
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+pub const DEFAULT_MAX_ELEMENTS: usize = 100_000;
+pub const DEFAULT_MAX_STRING_LENGTH: usize = 1024 * 1024;
+
+//limits for torrent files, whose size is already capped when they are read
+//large multi file torrents have millions of values and a pieces string of several MiB
+pub const TORRENT_FILE_MAX_DEPTH: usize = 64;
+pub const TORRENT_FILE_MAX_ELEMENTS: usize = 4_000_000;
+
+//dictionary of a decoded value, keys are the raw bytes of the input
+pub type Dict<'a> = BTreeMap<&'a [u8], Node<'a>>;
+
+//bencode value, byte strings and keys borrow from the input instead of being copied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value<'a> {
+    Int(i64),            //integer, i<decimal>e
+    ByteStr(&'a [u8]),   //byte string, <length>:<bytes>
+    List(Vec<Node<'a>>), //list, l<values>e
+    Dict(Dict<'a>),      //dictionary, d<key><value>...e
+}
+
+//a decoded value together with where it was found in the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node<'a> {
+    pub value: Value<'a>,
+    pub span: Range<usize>, //position of the value in the input
+    pub raw: &'a [u8],      //the value exactly as it was encoded, e.g. to hash an info dict
+}
+
+impl<'a> Node<'a> {
+    //get the value under key, None if this is not a dict or the key is missing
+    pub fn get(&self, key: &str) -> Option<&Node<'a>> {
+        match &self.value {
+            Value::Dict(dict) => dict.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+}
+
+//how closely the input has to follow the bencode spec, and how large it may get
+//the defaults suit untrusted network input
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    //only accept the canonical encoding: sorted unique keys, no leading zeros or negative zero,
+    //nothing after the value. Otherwise the last of duplicate keys wins
+    pub strict: bool,
+    pub max_depth: usize, //deepest nesting of lists and dicts, the parser recurses this far
+    pub max_elements: usize, //most values in the input, dict keys included
+    pub max_string_length: usize, //longest byte string in bytes
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_elements: DEFAULT_MAX_ELEMENTS,
+            max_string_length: DEFAULT_MAX_STRING_LENGTH,
+        }
+    }
+}
+
+impl ParseOptions {
+    //lenient options with limits generous enough for large torrent files
+    pub fn torrent_file() -> Self {
+        Self {
+            max_depth: TORRENT_FILE_MAX_DEPTH,
+            max_elements: TORRENT_FILE_MAX_ELEMENTS,
+            max_string_length: usize::MAX,
+            ..Self::default()
+        }
+    }
+}
+
+//parse the bencode value in buf leniently, as most clients do, with the default limits
+pub fn parse(buf: &[u8]) -> Result<Node<'_>, BencodeParseError> {
+    parse_with(buf, &ParseOptions::default())
+}
+
+//parse the bencode value in buf, rejecting anything but the canonical encoding
+pub fn parse_strict(buf: &[u8]) -> Result<Node<'_>, BencodeParseError> {
+    parse_with(
+        buf,
+        &ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        },
+    )
+}
+
+//parse the bencode value at the start of buf
+pub fn parse_with<'a>(
+    buf: &'a [u8],
+    options: &ParseOptions,
+) -> Result<Node<'a>, BencodeParseError> {
+    let mut parser = Parser {
+        buf,
+        pos: 0,
+        options,
+        depth: 0,
+        elements: 0,
+    };
+    let node = parser.value()?;
+    if options.strict && parser.pos < buf.len() {
+        return Err(BencodeParseError::TrailingData(parser.pos));
+    }
+    Ok(node)
+}
+
+struct Parser<'a, 'o> {
+    buf: &'a [u8],             //input
+    pos: usize,                //offset of the next byte to read
+    options: &'o ParseOptions, //strictness and limits
+    depth: usize,              //lists and dicts entered but not yet closed
+    elements: usize,           //values parsed so far, dict keys included
+}
+
+impl<'a> Parser<'a, '_> {
+    //get the next byte without consuming it
+    fn peek(&self) -> Result<u8, BencodeParseError> {
+        self.buf
+            .get(self.pos)
+            .copied()
+            .ok_or(BencodeParseError::UnexpectedEnd(self.pos))
+    }
+
+    //count one more value, dict keys included
+    fn count_element(&mut self, pos: usize) -> Result<(), BencodeParseError> {
+        self.elements += 1;
+        check_limit(
+            ParseLimit::Elements,
+            self.elements,
+            self.options.max_elements,
+            pos,
+        )
+    }
+
+    //parse the value starting at pos
+    fn value(&mut self) -> Result<Node<'a>, BencodeParseError> {
+        let start = self.pos;
+        self.count_element(start)?;
+        let value = match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                let int = self.int()?;
+                self.expect(b'e', BencodeParseError::InvalidInteger(start))?;
+                Value::Int(int)
+            }
+            b'l' => {
+                self.enter(start)?;
+                let mut list = Vec::new();
+                while self.peek()? != b'e' {
+                    list.push(self.value()?);
+                }
+                self.leave();
+                Value::List(list)
+            }
+            b'd' => {
+                self.enter(start)?;
+                let dict = self.dict()?;
+                self.leave();
+                Value::Dict(dict)
+            }
+            b'0'..=b'9' => Value::ByteStr(self.bytes()?),
+            _ => return Err(BencodeParseError::InvalidByte(start)),
+        };
+
+        Ok(Node {
+            value,
+            span: start..self.pos,
+            raw: &self.buf[start..self.pos],
+        })
+    }
+
+    //step into the list or dict starting at pos
+    fn enter(&mut self, pos: usize) -> Result<(), BencodeParseError> {
+        self.depth += 1;
+        check_limit(ParseLimit::Depth, self.depth, self.options.max_depth, pos)?;
+        self.pos += 1;
+        Ok(())
+    }
+
+    //step out of a list or dict over its closing e
+    fn leave(&mut self) {
+        self.depth -= 1;
+        self.pos += 1;
+    }
+
+    //parse the entries of a dict up to its closing e
+    fn dict(&mut self) -> Result<Dict<'a>, BencodeParseError> {
+        let mut dict = BTreeMap::new();
+        let mut last_key: Option<&[u8]> = None;
+        while self.peek()? != b'e' {
+            let key_start = self.pos;
+            //keys are always byte strings
+            if !self.peek()?.is_ascii_digit() {
+                return Err(BencodeParseError::InvalidByte(key_start));
+            }
+            self.count_element(key_start)?;
+            let key = self.bytes()?;
+            if self.options.strict
+                && let Some(last_key) = last_key
+            {
+                if key == last_key {
+                    return Err(BencodeParseError::DuplicateKey(key_start));
+                }
+                if key < last_key {
+                    return Err(BencodeParseError::UnsortedKey(key_start));
+                }
+            }
+            last_key = Some(key);
+
+            let value = self.value()?;
+            dict.insert(key, value);
+        }
+        Ok(dict)
+    }
+
+    //parse a byte string, returning its contents
+    fn bytes(&mut self) -> Result<&'a [u8], BencodeParseError> {
+        let start = self.pos;
+        let digits = self.digits();
+        let length = self
+            .number(digits, start)?
+            .and_then(|length| usize::try_from(length).ok())
+            .ok_or(BencodeParseError::InvalidLength(start))?;
+        self.expect(b':', BencodeParseError::InvalidLength(start))?;
+        check_limit(
+            ParseLimit::StringLength,
+            length,
+            self.options.max_string_length,
+            start,
+        )?;
+
+        let end = self
+            .pos
+            .checked_add(length)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or(BencodeParseError::UnexpectedEnd(self.buf.len()))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    //parse the text of an integer, without the surrounding i and e
+    fn int(&mut self) -> Result<i64, BencodeParseError> {
+        let start = self.pos - 1;
+        let negative = self.buf.get(self.pos) == Some(&b'-');
+        if negative {
+            self.pos += 1;
+        }
+        let digits = self.digits();
+        let magnitude = self
+            .number(digits, start)?
+            .ok_or(BencodeParseError::InvalidInteger(start))?;
+
+        if negative {
+            if self.options.strict && magnitude == 0 {
+                return Err(BencodeParseError::NonCanonical(start));
+            }
+            //i64::MIN has no positive counterpart, so it is built from the negated magnitude
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+        .ok_or(BencodeParseError::InvalidInteger(start))
+    }
+
+    //consume a run of ASCII digits
+    fn digits(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while self.buf.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        &self.buf[start..self.pos]
+    }
+
+    //value of a run of digits, None if it is empty or does not fit in a u64
+    //leading zeros are an error in strict mode
+    fn number(&self, digits: &[u8], start: usize) -> Result<Option<u64>, BencodeParseError> {
+        if self.options.strict && digits.len() > 1 && digits[0] == b'0' {
+            return Err(BencodeParseError::NonCanonical(start));
+        }
+        if digits.is_empty() {
+            return Ok(None);
+        }
+        Ok(digits.iter().try_fold(0u64, |n, &digit| {
+            n.checked_mul(10)?.checked_add(u64::from(digit - b'0'))
+        }))
+    }
+
+    //consume byte, failing with error if the input has something else there
+    fn expect(&mut self, byte: u8, error: BencodeParseError) -> Result<(), BencodeParseError> {
+        match self.buf.get(self.pos) {
+            Some(&b) if b == byte => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(error),
+            None => Err(BencodeParseError::UnexpectedEnd(self.pos)),
+        }
+    }
+}
+
+//fail with limit if value went over max, pos is where the input went over it
+fn check_limit(
+    limit: ParseLimit,
+    value: usize,
+    max: usize,
+    pos: usize,
+) -> Result<(), BencodeParseError> {
+    if value > max {
+        return Err(BencodeParseError::LimitExceeded { limit, max, pos });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::bencode::bencode_encodable::BencodeEncodable;
+    use BencodeParseError as E;
+
+    #[test]
+    fn values_borrow_from_the_input() {
+        let input: &[u8] = b"d3:numi-42e3:strl3:abc0:e1:zdee";
+        let root = parse(input).unwrap();
+        assert_eq!((root.span.clone(), root.raw), (0..input.len(), input));
+        let num = root.get("num").unwrap();
+        assert_eq!(num.value, Value::Int(-42));
+        assert_eq!((num.span.clone(), num.raw), (6..11, &b"i-42e"[..]));
+        let Value::List(list) = &root.get("str").unwrap().value else {
+            panic!("{:?}", root);
+        };
+        assert_eq!(list[0].value, Value::ByteStr(b"abc"));
+        assert_eq!(list[0].raw, b"3:abc");
+        assert_eq!(list[1].value, Value::ByteStr(b""));
+        assert_eq!(root.get("z").unwrap().value, Value::Dict(Dict::new()));
+        assert!(root.get("missing").is_none());
+        assert!(num.get("x").is_none());
+
+        let Value::ByteStr(abc) = list[0].value else {
+            panic!("{:?}", list[0]);
+        };
+        assert_eq!(abc.as_ptr(), input[19..].as_ptr());
+    }
+
+    #[test]
+    fn integers() {
+        //value of input, or where parsing failed
+        let int = |input| parse(input).map(|node| node.value);
+        assert_eq!(int(b"i0e"), Ok(Value::Int(0)));
+        assert_eq!(int(b"i9223372036854775807e"), Ok(Value::Int(i64::MAX)));
+        assert_eq!(int(b"i-9223372036854775808e"), Ok(Value::Int(i64::MIN)));
+        for bad in [
+            &b"i9223372036854775808e"[..],
+            b"i-9223372036854775809e",
+            b"ie",
+            b"i-e",
+            b"i+1e",
+            b"i1.5e",
+            b"i 1e",
+            b"i",
+        ] {
+            assert_eq!(int(bad), Err(E::InvalidInteger(0)), "{:?}", bad);
+        }
+        assert_eq!(int(b"i12"), Err(E::UnexpectedEnd(3)));
+
+        //only strict parsing rejects non-canonical numbers
+        assert_eq!(int(b"i007e"), Ok(Value::Int(7)));
+        assert_eq!(int(b"i-0e"), Ok(Value::Int(0)));
+        assert_eq!(parse_strict(b"i007e"), Err(E::NonCanonical(0)));
+        assert_eq!(parse_strict(b"i-0e"), Err(E::NonCanonical(0)));
+        assert_eq!(parse_strict(b"i-01e"), Err(E::NonCanonical(0)));
+        assert_eq!(parse_strict(b"i-10e").unwrap().value, Value::Int(-10));
+    }
+
+    #[test]
+    fn byte_strings() {
+        assert_eq!(parse(b"4:spam").unwrap().value, Value::ByteStr(b"spam"));
+        assert_eq!(parse(b"0:").unwrap().value, Value::ByteStr(b""));
+        assert_eq!(parse(b"5:spam"), Err(E::UnexpectedEnd(6)));
+        assert_eq!(parse(b"4spam"), Err(E::InvalidLength(0)));
+        assert_eq!(parse(b"4"), Err(E::UnexpectedEnd(1)));
+        assert_eq!(
+            parse(b"99999999999999999999999:a"),
+            Err(E::InvalidLength(0))
+        );
+        //lengths near usize::MAX must not wrap around
+        let unlimited = ParseOptions {
+            max_string_length: usize::MAX,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_with(b"18446744073709551615:a", &unlimited),
+            Err(E::UnexpectedEnd(22))
+        );
+        assert_eq!(parse(b"04:spam").unwrap().value, Value::ByteStr(b"spam"));
+        assert_eq!(parse_strict(b"04:spam"), Err(E::NonCanonical(0)));
+    }
+
+    #[test]
+    fn structure_errors() {
+        assert_eq!(parse(b""), Err(E::UnexpectedEnd(0)));
+        assert_eq!(parse(b"x"), Err(E::InvalidByte(0)));
+        assert_eq!(parse(b"l"), Err(E::UnexpectedEnd(1)));
+        assert_eq!(parse(b"li1e"), Err(E::UnexpectedEnd(4)));
+        assert_eq!(parse(b"d1:a"), Err(E::UnexpectedEnd(4)));
+        //keys are byte strings and every key has a value
+        assert_eq!(parse(b"di1ei2ee"), Err(E::InvalidByte(1)));
+        assert_eq!(parse(b"d1:ae"), Err(E::InvalidByte(4)));
+        assert_eq!(parse(b"e"), Err(E::InvalidByte(0)));
+        assert_eq!(E::InvalidByte(3).to_string(), "Unexpected byte at offset 3");
+    }
+
+    #[test]
+    fn dict_keys_in_lenient_and_strict_mode() {
+        //any order is read leniently, the last duplicate wins
+        let root = parse(b"d1:bi1e1:ai2e1:bi3ee").unwrap();
+        assert_eq!(root.get("a").unwrap().value, Value::Int(2));
+        assert_eq!(root.get("b").unwrap().value, Value::Int(3));
+        assert_eq!(root.get("b").unwrap().span, 16..19);
+
+        assert_eq!(parse_strict(b"d1:bi1e1:ai2ee"), Err(E::UnsortedKey(7)));
+        assert_eq!(parse_strict(b"d1:ai1e1:ai2ee"), Err(E::DuplicateKey(7)));
+        //keys are compared as raw bytes, nested dicts too
+        assert!(parse_strict(b"d1:Bi1e1:ai1e2:aai1e1:\xffi1ee").is_ok());
+        assert_eq!(parse_strict(b"d2:aai1e1:ai1ee"), Err(E::UnsortedKey(8)));
+        assert_eq!(
+            parse_strict(b"d1:ad1:bi1e1:ai1eee"),
+            Err(E::UnsortedKey(11))
+        );
+    }
+
+    #[test]
+    fn trailing_data_is_only_refused_when_strict() {
+        assert_eq!(parse(b"i1ejunk").unwrap().span, 0..3);
+        assert_eq!(parse_strict(b"i1ejunk"), Err(E::TrailingData(3)));
+        assert_eq!(parse_strict(b"i1e\n"), Err(E::TrailingData(3)));
+    }
+
+    #[test]
+    fn parsed_values_encode_canonically() {
+        let torrent: &[u8] = b"d8:announce9:http://x/4:infod5:filesld6:lengthi3e4:pathl1:aeee\
+            4:name1:d12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let root = parse_strict(torrent).unwrap();
+        assert_eq!(root, parse(torrent).unwrap());
+        assert_eq!(root.encode(), torrent);
+        assert_eq!(root.value.encode(), torrent);
+        assert_eq!(Value::Int(-3).encode(), b"i-3e");
+        //lenient input comes out sorted, without the replaced duplicate
+        assert_eq!(
+            parse(b"d1:bi1e1:ai2e1:bi3ee").unwrap().encode(),
+            b"d1:ai2e1:bi3ee"
+        );
+    }
+
+    #[test]
+    fn nesting_depth_is_limited() {
+        //n lists nested in each other
+        let nested = |n: usize| [vec![b'l'; n], vec![b'e'; n]].concat();
+        assert!(parse(&nested(DEFAULT_MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(&nested(DEFAULT_MAX_DEPTH + 1)),
+            Err(E::LimitExceeded {
+                limit: ParseLimit::Depth,
+                max: DEFAULT_MAX_DEPTH,
+                pos: 32
+            })
+        );
+        //unterminated nesting hits the limit long before the stack runs out
+        assert!(matches!(
+            parse(&vec![b'l'; 10_000_000]),
+            Err(E::LimitExceeded {
+                limit: ParseLimit::Depth,
+                ..
+            })
+        ));
+        let mut dicts = b"d1:a".repeat(40);
+        dicts.extend_from_slice(b"i1e");
+        dicts.extend(vec![b'e'; 40]);
+        assert!(matches!(
+            parse(&dicts),
+            Err(E::LimitExceeded {
+                limit: ParseLimit::Depth,
+                pos: 128,
+                ..
+            })
+        ));
+        assert!(parse_with(&dicts, &ParseOptions::torrent_file()).is_ok());
+        assert!(
+            parse_with(
+                &nested(TORRENT_FILE_MAX_DEPTH),
+                &ParseOptions::torrent_file()
+            )
+            .is_ok()
+        );
+        assert!(
+            parse_with(
+                &nested(TORRENT_FILE_MAX_DEPTH + 1),
+                &ParseOptions::torrent_file()
+            )
+            .is_err()
+        );
+
+        //siblings do not add up
+        let options = ParseOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+        assert!(parse_with(b"llelelee", &options).is_ok());
+        assert!(parse_with(b"lllleeee", &options).is_err());
+        let options = ParseOptions {
+            max_depth: 0,
+            ..Default::default()
+        };
+        assert!(parse_with(b"i1e", &options).is_ok());
+        assert!(parse_with(b"le", &options).is_err());
+    }
+
+    #[test]
+    fn element_count_is_limited() {
+        //list of n zeros
+        let list = |n: usize| [&b"l"[..], &b"i0e".repeat(n), b"e"].concat();
+        //the list itself counts too
+        assert!(parse(&list(DEFAULT_MAX_ELEMENTS - 1)).is_ok());
+        assert!(matches!(
+            parse(&list(DEFAULT_MAX_ELEMENTS)),
+            Err(E::LimitExceeded {
+                limit: ParseLimit::Elements,
+                max: DEFAULT_MAX_ELEMENTS,
+                ..
+            })
+        ));
+        assert!(parse_with(&list(DEFAULT_MAX_ELEMENTS), &ParseOptions::torrent_file()).is_ok());
+
+        //and so do dict keys
+        let options = ParseOptions {
+            max_elements: 5,
+            ..Default::default()
+        };
+        assert!(parse_with(b"d1:ai1e1:bi2ee", &options).is_ok());
+        assert_eq!(
+            parse_with(b"d1:ai1e1:bi2e1:ci3ee", &options),
+            Err(E::LimitExceeded {
+                limit: ParseLimit::Elements,
+                max: 5,
+                pos: 13
+            })
+        );
+    }
+
+    #[test]
+    fn string_length_is_limited() {
+        //byte string of n bytes
+        let string = |n: usize| [format!("{}:", n).into_bytes(), vec![b'x'; n]].concat();
+        assert!(parse(&string(DEFAULT_MAX_STRING_LENGTH)).is_ok());
+        assert_eq!(
+            parse(&string(DEFAULT_MAX_STRING_LENGTH + 1)),
+            Err(E::LimitExceeded {
+                limit: ParseLimit::StringLength,
+                max: DEFAULT_MAX_STRING_LENGTH,
+                pos: 0
+            })
+        );
+        assert!(
+            parse_with(
+                &string(DEFAULT_MAX_STRING_LENGTH + 1),
+                &ParseOptions::torrent_file()
+            )
+            .is_ok()
+        );
+
+        //a huge claimed length fails on the limit before the missing bytes
+        assert!(matches!(
+            parse(b"l999999999999:xe"),
+            Err(E::LimitExceeded {
+                limit: ParseLimit::StringLength,
+                pos: 1,
+                ..
+            })
+        ));
+        assert_eq!(
+            parse_with(b"l999999999999:xe", &ParseOptions::torrent_file()),
+            Err(E::UnexpectedEnd(16))
+        );
+        let options = ParseOptions {
+            max_string_length: 2,
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse_with(b"d3:abci1ee", &options),
+            Err(E::LimitExceeded {
+                limit: ParseLimit::StringLength,
+                pos: 1,
+                ..
+            })
+        ));
+        let err = E::LimitExceeded {
+            limit: ParseLimit::Depth,
+            max: 32,
+            pos: 7,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Nesting depth limit of 32 exceeded at offset 7"
+        );
+    }
+}
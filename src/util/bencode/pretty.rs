@@ -0,0 +1,187 @@
+use crate::util::bencode::parser::{Node, ParseOptions, Value, parse_with};
+use crate::util::hex;
+
+use sha1::{Digest, Sha1};
+
+//binary strings up to this many bytes are shown as hex in full
+const MAX_FULL_HEX: usize = 32;
+
+//binary strings up to this many bytes are shown as truncated hex, longer ones are summarized
+const MAX_TRUNCATED_HEX: usize = 256;
+
+//spaces per nesting level
+const INDENT: usize = 2;
+
+//render bencode as indented, JSON-ish text for debugging
+//byte strings are quoted when they are printable text and shown as hex otherwise
+//long binary strings such as pieces are summarized by their length and SHA-1
+pub fn pretty(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    //torrent files are rendered too, so their larger limits are used
+    match parse_with(bytes, &ParseOptions::torrent_file()) {
+        Ok(node) => {
+            write_node(&mut out, &node, 0);
+            if node.span.end < bytes.len() {
+                out.push_str(&format!(
+                    "\n<{} trailing bytes>",
+                    bytes.len() - node.span.end
+                ));
+            }
+        }
+        Err(e) => out.push_str(&format!("<malformed bencode: {}>", e)),
+    }
+    out
+}
+
+//append node to out, nested values indented one level deeper than depth
+fn write_node(out: &mut String, node: &Node, depth: usize) {
+    match &node.value {
+        Value::Int(num) => out.push_str(&num.to_string()),
+        Value::ByteStr(bytes) => write_bytes(out, bytes),
+        Value::List(list) if list.is_empty() => out.push_str("[]"),
+        Value::List(list) => {
+            out.push('[');
+            for (index, item) in list.iter().enumerate() {
+                new_line(out, depth + 1, index);
+                write_node(out, item, depth + 1);
+            }
+            new_line(out, depth, 0);
+            out.push(']');
+        }
+        Value::Dict(dict) if dict.is_empty() => out.push_str("{}"),
+        Value::Dict(dict) => {
+            out.push('{');
+            for (index, (key, value)) in dict.iter().enumerate() {
+                new_line(out, depth + 1, index);
+                write_bytes(out, key);
+                out.push_str(": ");
+                write_node(out, value, depth + 1);
+            }
+            new_line(out, depth, 0);
+            out.push('}');
+        }
+    }
+}
+
+//start a new line indented to depth, ending the previous item with a comma unless it is the first
+fn new_line(out: &mut String, depth: usize, index: usize) {
+    if index > 0 {
+        out.push(',');
+    }
+    out.push('\n');
+    out.extend(std::iter::repeat_n(' ', depth * INDENT));
+}
+
+//append a byte string, quoted if it is printable text
+fn write_bytes(out: &mut String, bytes: &[u8]) {
+    //line breaks and tabs are common in messages and are escaped, other control characters are not text
+    if let Ok(text) = std::str::from_utf8(bytes)
+        && !text
+            .chars()
+            .any(|c| c.is_control() && !c.is_ascii_whitespace())
+    {
+        out.push_str(&format!("{:?}", text));
+    } else if bytes.len() <= MAX_FULL_HEX {
+        out.push_str(&format!("<hex:{}>", hex::encode(bytes)));
+    } else if bytes.len() <= MAX_TRUNCATED_HEX {
+        out.push_str(&format!(
+            "<{} bytes, hex:{}…>",
+            bytes.len(),
+            hex::encode(&bytes[..MAX_FULL_HEX])
+        ));
+    } else {
+        let digest = hex::encode(&Sha1::digest(bytes));
+        out.push_str(&format!("<{} bytes, sha1:{}…>", bytes.len(), &digest[..8]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker_response() {
+        let body = b"d8:completei5e10:incompletei2e8:intervali1800e\
+            5:peers12:\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x1a\xe2\
+            6:peers6ld2:ip9:127.0.0.17:peer id20:-MS0100-abcdefghijkl4:porti6881eee\
+            15:warning message7:be nice10:tracker id0:e";
+        let expected = r#"{
+  "complete": 5,
+  "incomplete": 2,
+  "interval": 1800,
+  "peers": <hex:7f0000011ae10a0000021ae2>,
+  "peers6": [
+    {
+      "ip": "127.0.0.1",
+      "peer id": "-MS0100-abcdefghijkl",
+      "port": 6881
+    }
+  ],
+  "tracker id": "",
+  "warning message": "be nice"
+}"#;
+        assert_eq!(pretty(body), expected);
+    }
+
+    #[test]
+    fn torrent_file() {
+        let mut bytes = b"d8:announce10:http://t/a4:infod5:filesld6:lengthi20e\
+            4:pathl6:m\xfasica5:a.txteee4:name4:caf\xe912:piece lengthi16384e6:pieces20:"
+            .to_vec();
+        bytes.extend([7; 20]);
+        bytes.extend(b"ee");
+        let expected = r#"{
+  "announce": "http://t/a",
+  "info": {
+    "files": [
+      {
+        "length": 20,
+        "path": [
+          <hex:6dfa73696361>,
+          "a.txt"
+        ]
+      }
+    ],
+    "name": <hex:636166e9>,
+    "piece length": 16384,
+    "pieces": <hex:0707070707070707070707070707070707070707>
+  }
+}"#;
+        assert_eq!(pretty(&bytes), expected);
+    }
+
+    #[test]
+    fn long_binary_strings_are_summarized() {
+        let mut bytes = b"40:".to_vec();
+        bytes.extend([0; 40]);
+        assert_eq!(
+            pretty(&bytes),
+            format!("<40 bytes, hex:{}…>", "00".repeat(32))
+        );
+        let mut bytes = b"3340:".to_vec();
+        bytes.extend([0; 3340]);
+        let out = pretty(&bytes);
+        assert!(out.starts_with("<3340 bytes, sha1:"), "{}", out);
+        assert!(out.ends_with("…>"), "{}", out);
+        assert_eq!(out.len(), "<3340 bytes, sha1:12345678…>".len());
+
+        //long text is kept whole
+        let text = format!("300:{}", "a".repeat(300));
+        assert_eq!(pretty(text.as_bytes()), format!("{:?}", "a".repeat(300)));
+    }
+
+    #[test]
+    fn small_and_broken_values() {
+        assert_eq!(pretty(b"le"), "[]");
+        assert_eq!(pretty(b"de"), "{}");
+        assert_eq!(pretty(b"i-3e"), "-3");
+        assert_eq!(pretty(b"5:a\"b\nc"), r#""a\"b\nc""#);
+        assert_eq!(pretty(b"2:a\x01"), "<hex:6101>");
+        assert_eq!(pretty(b"ld1:xi1eee"), "[\n  {\n    \"x\": 1\n  }\n]");
+        assert_eq!(pretty(b"i1exx"), "1\n<2 trailing bytes>");
+        assert_eq!(
+            pretty(b"l"),
+            "<malformed bencode: Unexpected end of bencode at offset 1>"
+        );
+    }
+}
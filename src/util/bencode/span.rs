@@ -0,0 +1,55 @@
+use crate::util::bencode::bencode_decodable_error::{BencodeDecodableError, PathSegment};
+use crate::util::bencode::parser::{Node, Value, parse};
+
+use std::ops::Range;
+
+//find the bytes of the value at path, starting from the value at the start of buf
+//keys step into dicts and indices into lists, None if the path leads nowhere
+//returns the span exactly as it appears in buf, so hashing it does not depend on re-encoding
+pub fn raw_span(
+    buf: &[u8],
+    path: &[PathSegment],
+) -> Result<Option<Range<usize>>, BencodeDecodableError> {
+    let root = parse(buf)?;
+    Ok(find(&root, path).map(|node| node.span.clone()))
+}
+
+//get the span of the value starting at pos
+pub fn value_span(buf: &[u8], pos: usize) -> Result<Range<usize>, BencodeDecodableError> {
+    let node = parse(buf.get(pos..).unwrap_or_default())?;
+    Ok(pos + node.span.start..pos + node.span.end)
+}
+
+//get the node at path below node, None if the path leads nowhere
+pub fn find<'n, 'a>(node: &'n Node<'a>, path: &[PathSegment]) -> Option<&'n Node<'a>> {
+    path.iter()
+        .try_fold(node, |node, segment| match (&node.value, segment) {
+            (Value::Dict(dict), PathSegment::Key(key)) => dict.get(key.as_bytes()),
+            (Value::List(list), PathSegment::Index(index)) => list.get(*index),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_values_keep_their_bytes() {
+        use PathSegment::{Index, Key};
+
+        let input: &[u8] = b"d5:emptyd0:i1ee4:listli-12e3:abcd1:kleee3:numi42e3:str5:helloe";
+        //bytes of the value at path
+        let get = |path: &[PathSegment]| raw_span(input, path).unwrap().map(|span| &input[span]);
+        let key = |key: &str| Key(key.into());
+        assert_eq!(get(&[]), Some(input));
+        assert_eq!(get(&[key("num")]), Some(&b"i42e"[..]));
+        assert_eq!(get(&[key("str")]), Some(&b"5:hello"[..]));
+        assert_eq!(get(&[key("list"), Index(2)]), Some(&b"d1:klee"[..]));
+        assert_eq!(get(&[key("list"), Index(2), key("k")]), Some(&b"le"[..]));
+        assert_eq!(get(&[key("empty"), key("")]), Some(&b"i1e"[..]));
+        assert_eq!(get(&[key("list"), Index(3)]), None);
+        assert_eq!(get(&[key("num"), Index(0)]), None);
+        assert_eq!(get(&[key("missing")]), None);
+    }
+}
@@ -1,19 +1,84 @@
-use bencode::streaming::Error as BencStreamingError;
+use std::fmt;
+use thiserror::Error;
 
-//wrapper struct for streaming::Error
-#[derive(Debug)]
-pub struct BStreamingError(BencStreamingError);
+//error for malformed bencode, with the byte offset the problem was found at
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BencodeParseError {
+    //input ends in the middle of a value
+    #[error("Unexpected end of bencode at offset {0}")]
+    UnexpectedEnd(usize),
 
-impl std::fmt::Display for BStreamingError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self.0)
-    }
+    //byte that can not start a value
+    #[error("Unexpected byte at offset {0}")]
+    InvalidByte(usize),
+
+    //integer that is empty, has stray characters or does not fit in an i64
+    #[error("Invalid integer at offset {0}")]
+    InvalidInteger(usize),
+
+    //byte string length that is not a number or does not fit in usize
+    #[error("Invalid string length at offset {0}")]
+    InvalidLength(usize),
+
+    //number with leading zeros or a negative zero, only rejected in strict mode
+    #[error("Non-canonical number at offset {0}")]
+    NonCanonical(usize),
+
+    //dictionary key that is not greater than the one before it, only rejected in strict mode
+    #[error("Unsorted dictionary key at offset {0}")]
+    UnsortedKey(usize),
+
+    //dictionary key that appears twice, only rejected in strict mode
+    #[error("Duplicate dictionary key at offset {0}")]
+    DuplicateKey(usize),
+
+    //bytes left after the value, only rejected in strict mode
+    #[error("Trailing data at offset {0}")]
+    TrailingData(usize),
+
+    //input that goes over one of the parse limits, with the limit and where it was hit
+    #[error("{limit} limit of {max} exceeded at offset {pos}")]
+    LimitExceeded {
+        limit: ParseLimit,
+        max: usize,
+        pos: usize,
+    },
 }
 
-impl std::error::Error for BStreamingError {}
+//limit on the shape of bencode input, guards against input built to exhaust stack or memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseLimit {
+    Depth,        //lists and dicts nested in each other
+    Elements,     //values in the whole input, dict keys included
+    StringLength, //bytes in a single byte string
+}
 
-impl From<BencStreamingError> for BStreamingError {
-    fn from(err: BencStreamingError) -> Self {
-        BStreamingError(err)
+impl fmt::Display for ParseLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Depth => write!(f, "Nesting depth"),
+            Self::Elements => write!(f, "Element count"),
+            Self::StringLength => write!(f, "String length"),
+        }
     }
 }
+
+//error for malformed percent-encoded text
+#[derive(Error, Debug)]
+pub enum UrlDecodeError {
+    //'%' not followed by two hex digits, at the given byte offset
+    #[error("Invalid percent escape at offset {0}")]
+    InvalidEscape(usize),
+}
+
+//error for malformed hex text
+#[derive(Error, Debug)]
+pub enum HexDecodeError {
+    //hex text must have two digits per byte
+    #[error("Odd number of hex digits: {0}")]
+    OddLength(usize),
+
+    //character that is not a hex digit, at the given byte offset
+    #[error("Invalid hex digit at offset {0}")]
+    InvalidDigit(usize),
+}
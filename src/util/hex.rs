@@ -0,0 +1,32 @@
+use crate::util::errors::HexDecodeError;
+
+//encode bytes as lowercase hex digits
+pub fn encode(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len() * 2);
+
+    for &b in bytes {
+        result.push(char::from_digit((b >> 4).into(), 16).unwrap_or('0'));
+        result.push(char::from_digit((b & 0xF).into(), 16).unwrap_or('0'));
+    }
+
+    result
+}
+
+//decode hex digits of either case into bytes
+pub fn decode(text: &[u8]) -> Result<Vec<u8>, HexDecodeError> {
+    if !text.len().is_multiple_of(2) {
+        return Err(HexDecodeError::OddLength(text.len()));
+    }
+
+    text.chunks_exact(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let high = (pair[0] as char).to_digit(16);
+            let low = (pair[1] as char).to_digit(16);
+            match (high, low) {
+                (Some(high), Some(low)) => Ok((high * 16 + low) as u8),
+                _ => Err(HexDecodeError::InvalidDigit(i * 2)),
+            }
+        })
+        .collect()
+}
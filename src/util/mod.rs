@@ -1,2 +1,10 @@
 pub mod bencode;
 pub mod errors;
+pub mod hex;
+pub mod units;
+pub mod urlencode;
+
+#[cfg(test)]
+pub(crate) mod test_dir;
+#[cfg(test)]
+pub(crate) mod test_server;
@@ -0,0 +1,20 @@
+//paths in the temp directory for tests that need the file system
+//also included by the integration tests, so it only uses std
+use std::fs;
+use std::path::PathBuf;
+
+//fresh path in the temp directory, nothing exists there yet
+//name has to be unique among the tests that run in one process
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("motteseed-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+//fresh empty directory in the temp directory
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = temp_path(name);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
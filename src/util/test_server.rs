@@ -0,0 +1,111 @@
+//small HTTP/1.1 servers on the loopback interface, for the tests of the HTTP clients
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+//a request received by a test server
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    pub(crate) head: String,                  //request line and headers as sent
+    pub(crate) path: String,                  //path and query from the request line
+    pub(crate) range: Option<(usize, usize)>, //first and last byte asked for with a Range header
+}
+
+//requests a test server received, in order
+pub(crate) type Requests = Arc<Mutex<Vec<Request>>>;
+
+//start a server on a free loopback port, answering every request with the bytes handler returns
+//connections are kept open, so clients can send several requests on one
+pub(crate) async fn serve<F>(handler: F) -> (u16, Requests)
+where
+    F: Fn(&Request) -> Vec<u8> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let requests = Requests::default();
+    let handler = Arc::new(handler);
+
+    let log = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let (handler, log) = (handler.clone(), log.clone());
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut reader = BufReader::new(reader);
+                while let Some(request) = read_request(&mut reader).await {
+                    log.lock().unwrap().push(request.clone());
+                    if writer.write_all(&handler(&request)).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    (port, requests)
+}
+
+//serve the given responses in turn, starting over after the last one
+//a response is a status line, optionally followed by header lines, and a body
+pub(crate) async fn http_server(responses: Vec<(String, Vec<u8>)>) -> (u16, Requests) {
+    let next = AtomicUsize::new(0);
+    serve(move |_| {
+        let (head, body) = &responses[next.fetch_add(1, Ordering::Relaxed) % responses.len()];
+        response(head, body)
+    })
+    .await
+}
+
+//serve files by path, answering Range requests with 206 if honor_range is set
+//and with the whole file otherwise
+pub(crate) async fn file_server(
+    files: HashMap<String, Vec<u8>>,
+    honor_range: bool,
+) -> (u16, Requests) {
+    serve(
+        move |request| match (files.get(&request.path), request.range) {
+            (None, _) => response("HTTP/1.1 404 Not Found", b""),
+            (Some(data), Some((first, last))) if honor_range => {
+                response("HTTP/1.1 206 Partial Content", &data[first..=last])
+            }
+            (Some(data), _) => response("HTTP/1.1 200 OK", data),
+        },
+    )
+    .await
+}
+
+//build a response with the given status line and extra headers, adding the Content-Length
+pub(crate) fn response(head: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!("{}\r\nContent-Length: {}\r\n\r\n", head, body.len()).into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+//read the request line and headers of the next request, None once the client is gone
+async fn read_request<R>(reader: &mut R) -> Option<Request>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    let mut head = String::new();
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        if let Some(bytes) = line.to_lowercase().strip_prefix("range: bytes=") {
+            let (first, last) = bytes.trim().split_once('-')?;
+            range = Some((first.parse().ok()?, last.parse().ok()?));
+        }
+        head.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+
+    let path = head.split(' ').nth(1)?.to_string();
+    Some(Request { head, path, range })
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+//binary unit suffixes, each 1024 times the previous one
+const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+//format a byte count in the largest unit that keeps it at least 1, e.g. "1.5 MiB"
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+//parse a byte count such as "512", "16k", "4MiB" or "1 GB", the units are binary either way
+//None if the text is not a count or the count does not fit in u64
+pub fn parse_bytes(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    let number: u64 = text[..digits].parse().ok()?;
+
+    //the unit letter may be followed by "iB" or "B"
+    let unit = text[digits..].trim_start().to_ascii_lowercase();
+    let letter = unit
+        .strip_suffix("ib")
+        .or(unit.strip_suffix('b'))
+        .unwrap_or(&unit);
+    let exponent = match letter {
+        "" => 0,
+        _ => UNITS
+            .iter()
+            .position(|u| u[..1].eq_ignore_ascii_case(letter))?,
+    };
+
+    number.checked_mul(1u64.checked_shl(10 * exponent as u32)?)
+}
+
+//format a unix timestamp as a UTC date and time, e.g. "2023-11-14 22:13:20 UTC"
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    //convert days since 1970-01-01 to a civil date (Howard Hinnant's days_from_civil inverse)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+//format a duration in its two largest units, e.g. "45s", "3m 20s" or "2h 05m"
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else if secs < 86400 {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{}d {:02}h", secs / 86400, secs % 86400 / 3600)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_and_timestamps_are_formatted() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(u64::MAX), "16.0 EiB");
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(4102444799), "2099-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn byte_counts_are_parsed() {
+        assert_eq!(parse_bytes("512"), Some(512));
+        assert_eq!(parse_bytes("16k"), Some(16384));
+        assert_eq!(parse_bytes("16K"), Some(16384));
+        assert_eq!(parse_bytes("4MiB"), Some(4 << 20));
+        assert_eq!(parse_bytes("1 GB"), Some(1 << 30));
+        assert_eq!(parse_bytes("3b"), Some(3));
+        for text in ["16E", "1x", "k", "-1", "1.5m", ""] {
+            assert_eq!(parse_bytes(text), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::util::errors::UrlDecodeError;
+
+//percent-encode opaque bytes, leaving only unreserved characters as they are
+pub fn encode(bytes: &[u8]) -> String {
+    //pre-allocate capacity - worst case: all bytes need %XX encoding (3 chars each)
+    let mut result = String::with_capacity(bytes.len() * 3);
+
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.' || b == b'~' {
+            //direct character push - no allocation
+            result.push(b as char);
+        } else {
+            //add percent encoding without format!
+            result.push('%');
+            //convert byte to hex digits
+            let digit1 = char::from_digit((b >> 4).into(), 16)
+                .unwrap_or('0')
+                .to_ascii_uppercase();
+            let digit2 = char::from_digit((b & 0xF).into(), 16)
+                .unwrap_or('0')
+                .to_ascii_uppercase();
+            result.push(digit1);
+            result.push(digit2);
+        }
+    }
+
+    result
+}
+
+//decode a percent-encoded query value, '+' is read as a space
+pub fn decode(text: &str) -> Result<Vec<u8>, UrlDecodeError> {
+    let bytes = text.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                //both hex digits must be present
+                let high = bytes.get(i + 1).and_then(|&d| (d as char).to_digit(16));
+                let low = bytes.get(i + 2).and_then(|&d| (d as char).to_digit(16));
+                match (high, low) {
+                    (Some(high), Some(low)) => result.push((high * 16 + low) as u8),
+                    _ => return Err(UrlDecodeError::InvalidEscape(i)),
+                }
+                i += 3;
+            }
+            b'+' => {
+                result.push(b' ');
+                i += 1;
+            }
+            b => {
+                result.push(b);
+                i += 1;
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_of_any_length_roundtrip() {
+        let all: Vec<u8> = (0..=255).collect();
+        for len in [0, 1, 20, 32, 256] {
+            let bytes = &all[..len];
+            let encoded = encode(bytes);
+            assert!(
+                encoded
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-_.~%".contains(&b))
+            );
+            assert_eq!(decode(&encoded).unwrap(), bytes);
+        }
+        assert_eq!(encode(b"azAZ09-_.~"), "azAZ09-_.~");
+        assert_eq!(encode(&[0x12, 0xab, b' ']), "%12%AB%20");
+    }
+
+    #[test]
+    fn plus_and_bad_escapes_are_decoded() {
+        assert_eq!(decode("a+b%2fc").unwrap(), b"a b/c");
+        assert!(matches!(
+            decode("ab%4"),
+            Err(UrlDecodeError::InvalidEscape(2))
+        ));
+        assert!(matches!(
+            decode("%zz"),
+            Err(UrlDecodeError::InvalidEscape(0))
+        ));
+    }
+}
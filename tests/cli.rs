@@ -0,0 +1,292 @@
+//runs the motteseed binary the way users do and checks what it prints and exits with
+
+#![cfg(feature = "cli")]
+
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::process::{Command, Output};
+use std::thread;
+
+//the temp directory helper the unit tests use
+#[path = "../src/util/test_dir.rs"]
+mod test_dir;
+
+use test_dir::temp_dir;
+
+//run the binary with args, which may be paths
+fn motteseed<S: AsRef<OsStr>>(args: &[S]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_MotteSeed"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+//single file torrent named f holding data in pieces of 16 bytes, with the given md5sum
+fn torrent(data: &[u8], md5sum: [u8; 16]) -> Vec<u8> {
+    let md5sum: String = md5sum.iter().map(|b| format!("{:02x}", b)).collect();
+    let mut bytes = format!(
+        "d4:infod6:lengthi{}e6:md5sum32:{}4:name1:f12:piece lengthi16e6:pieces{}:",
+        data.len(),
+        md5sum,
+        data.len().div_ceil(16) * 20
+    )
+    .into_bytes();
+    bytes.extend(data.chunks(16).flat_map(Sha1::digest));
+    bytes.extend(b"ee");
+    bytes
+}
+
+//serve data as the file of every web seeded torrent, answering Range requests on a thread
+//returns the port it listens on
+fn web_seed(data: Vec<u8>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let data = data.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                //one request after another on the same connection
+                loop {
+                    let mut range = None;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        if let Some(bytes) = line.to_lowercase().strip_prefix("range: bytes=") {
+                            let (first, last) = bytes.trim().split_once('-').unwrap();
+                            range = Some((first.parse().unwrap(), last.parse().unwrap()));
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    let (first, last): (usize, usize) = range.unwrap_or((0, data.len() - 1));
+                    let body = &data[first..=last];
+                    let head = format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\r\n",
+                        body.len()
+                    );
+                    if writer.write_all(head.as_bytes()).is_err() || writer.write_all(body).is_err()
+                    {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+//single file torrent named name holding data in pieces of 16 bytes, served by the web seed at port
+fn web_seeded(name: &str, data: &[u8], port: u16) -> Vec<u8> {
+    let url = format!("http://127.0.0.1:{}/", port);
+    let mut bytes = format!(
+        "d8:url-list{}:{}4:infod6:lengthi{}e4:name{}:{}12:piece lengthi16e6:pieces{}:",
+        url.len(),
+        url,
+        data.len(),
+        name.len(),
+        name,
+        data.len().div_ceil(16) * 20
+    )
+    .into_bytes();
+    bytes.extend(data.chunks(16).flat_map(Sha1::digest));
+    bytes.extend(b"ee");
+    bytes
+}
+
+#[test]
+fn verify_checks_md5sums_when_asked() {
+    let dir = temp_dir("cli-md5");
+    let data: Vec<u8> = (0..32).collect();
+    fs::write(dir.join("f"), &data).unwrap();
+    let good = dir.join("good.torrent");
+    fs::write(&good, torrent(&data, Md5::digest(&data).into())).unwrap();
+    //pieces that match do not make up for a file that hashes to another md5sum
+    let bad = dir.join("bad.torrent");
+    fs::write(&bad, torrent(&data, [0; 16])).unwrap();
+
+    let output = motteseed(&[
+        "verify".as_ref(),
+        good.as_os_str(),
+        "--data".as_ref(),
+        dir.as_os_str(),
+        "--md5".as_ref(),
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "2/2 pieces OK\n1/1 md5sums OK\n");
+
+    let output = motteseed(&[
+        "verify".as_ref(),
+        bad.as_os_str(),
+        "--data".as_ref(),
+        dir.as_os_str(),
+        "--md5".as_ref(),
+    ]);
+    assert_eq!(output.status.code(), Some(6));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "2/2 pieces OK\nf md5 mismatch\n0/1 md5sums OK\n");
+
+    //md5sums are only looked at with --md5
+    let output = motteseed(&[
+        "verify".as_ref(),
+        bad.as_os_str(),
+        "--data".as_ref(),
+        dir.as_os_str(),
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_files_exit_with_the_io_code() {
+    let dir = temp_dir("cli-missing");
+    let path = dir.join("nope.torrent");
+    //downloading is the default action
+    let output = motteseed(&[&path]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with(&format!("error: cannot read '{}': ", path.display())),
+        "{}",
+        stderr
+    );
+    assert_eq!(stderr.lines().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn malformed_torrents_exit_with_the_parse_code() {
+    let dir = temp_dir("cli-malformed");
+    let path = dir.join("bad.torrent");
+    fs::write(&path, b"garbage").unwrap();
+    let output = motteseed(&["info".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        format!(
+            "error: invalid torrent '{}': Parse error: Unexpected byte at offset 0\n",
+            path.display()
+        )
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn valid_torrents_exit_with_success() {
+    let dir = temp_dir("cli-valid");
+    let path = dir.join("f.torrent");
+    let data: Vec<u8> = (0..32).collect();
+    fs::write(&path, torrent(&data, Md5::digest(&data).into())).unwrap();
+    let output = motteseed(&["info".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+    assert!(!output.stdout.is_empty());
+
+    //a mistyped flag is a usage error
+    let output = motteseed(&["info", "--bogus"]);
+    assert_eq!(output.status.code(), Some(2));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn torrents_given_together_download_side_by_side() {
+    let dir = temp_dir("cli-several");
+    let (first, second): (Vec<u8>, Vec<u8>) = ((0..48).collect(), (100..164).collect());
+    let paths = [dir.join("a.torrent"), dir.join("b.torrent")];
+    fs::write(&paths[0], web_seeded("a", &first, web_seed(first.clone()))).unwrap();
+    fs::write(
+        &paths[1],
+        web_seeded("b", &second, web_seed(second.clone())),
+    )
+    .unwrap();
+    //an empty config file keeps the settings of the user out of the test
+    let config = dir.join("config.toml");
+    fs::write(&config, b"").unwrap();
+    let out = dir.join("out");
+
+    let output = motteseed(&[
+        paths[0].as_os_str(),
+        paths[1].as_os_str(),
+        "--out-dir".as_ref(),
+        out.as_os_str(),
+        "--no-seed".as_ref(),
+        "--config".as_ref(),
+        config.as_os_str(),
+    ]);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(fs::read(out.join("a")).unwrap(), first);
+    assert_eq!(fs::read(out.join("b")).unwrap(), second);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_events_of_a_download_come_in_order() {
+    let dir = temp_dir("cli-json");
+    let data: Vec<u8> = (0..48).collect();
+    let path = dir.join("j.torrent");
+    fs::write(&path, web_seeded("j", &data, web_seed(data.clone()))).unwrap();
+    let config = dir.join("config.toml");
+    fs::write(&config, b"").unwrap();
+    let out = dir.join("out");
+
+    let output = motteseed(&[
+        "--json".as_ref(),
+        path.as_os_str(),
+        "--out-dir".as_ref(),
+        out.as_os_str(),
+        "--no-seed".as_ref(),
+        "--config".as_ref(),
+        config.as_os_str(),
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    //every line is an event, progress snapshots may come in between the others
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect(line))
+        .filter(|event: &serde_json::Value| event["event"] != "progress")
+        .collect();
+    let names: Vec<&str> = events
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "torrent_added",
+            "piece_verified",
+            "piece_verified",
+            "piece_verified",
+            "completed"
+        ]
+    );
+    let info_hash = events[0]["info_hash"].as_str().unwrap();
+    assert_eq!(info_hash.len(), 40);
+    assert!(events.iter().all(|event| event["info_hash"] == info_hash));
+    assert_eq!(events[0]["name"], "j");
+    assert_eq!(events[0]["total_bytes"], 48);
+    assert_eq!(events[0]["pieces"], 3);
+    let pieces: Vec<u64> = events[1..4]
+        .iter()
+        .map(|event| event["index"].as_u64().unwrap())
+        .collect();
+    assert_eq!(pieces, [0, 1, 2]);
+    assert_eq!(events[4]["verified_bytes"], 48);
+    fs::remove_dir_all(&dir).unwrap();
+}